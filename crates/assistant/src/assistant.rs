@@ -4,6 +4,7 @@ mod completion_provider;
 mod context_store;
 mod inline_assistant;
 mod model_selector;
+mod project_index_status;
 mod prompt_library;
 mod prompts;
 mod search;
@@ -11,6 +12,7 @@ mod slash_command;
 mod streaming_diff;
 
 pub use assistant_panel::AssistantPanel;
+pub use project_index_status::{IndexProject, ProjectIndexStatus};
 
 use assistant_settings::{AnthropicModel, AssistantSettings, CloudModel, OllamaModel, OpenAiModel};
 use assistant_slash_command::SlashCommandRegistry;
@@ -23,7 +25,9 @@ pub(crate) use inline_assistant::*;
 pub(crate) use model_selector::*;
 use paths::EMBEDDINGS_DIR;
 use rustdoc::RustdocStore;
-use semantic_index::{CloudEmbeddingProvider, SemanticIndex};
+use semantic_index::{
+    CloudEmbeddingProvider, CloudSummaryProvider, SemanticIndex, VectorStoreSettings,
+};
 use serde::{Deserialize, Serialize};
 use settings::{Settings, SettingsStore};
 use slash_command::{
@@ -262,6 +266,20 @@ impl Assistant {
     }
 }
 
+/// The provider of the embedding model chosen in the settings, which frames chunks with
+/// the template the settings give for the model, if any.
+fn cloud_embedding_provider(
+    client: &Arc<Client>,
+    settings: &VectorStoreSettings,
+) -> CloudEmbeddingProvider {
+    let embedding_provider =
+        CloudEmbeddingProvider::new(client.clone()).with_model(settings.embedding_model.clone());
+    match settings.document_template(&settings.embedding_model) {
+        Some(document_template) => embedding_provider.with_document_template(document_template),
+        None => embedding_provider,
+    }
+}
+
 pub fn init(client: Arc<Client>, cx: &mut AppContext) {
     cx.set_global(Assistant::default());
     AssistantSettings::register(cx);
@@ -269,17 +287,50 @@ pub fn init(client: Arc<Client>, cx: &mut AppContext) {
     cx.spawn(|mut cx| {
         let client = client.clone();
         async move {
-            let embedding_provider = CloudEmbeddingProvider::new(client.clone());
-            let semantic_index = SemanticIndex::new(
+            let (mut embedding_model, mut document_template, embedding_provider) =
+                cx.update(|cx| {
+                    let settings = VectorStoreSettings::get_global(cx);
+                    (
+                        settings.embedding_model.clone(),
+                        settings.document_template(&settings.embedding_model),
+                        cloud_embedding_provider(&client, settings),
+                    )
+                })?;
+            let mut semantic_index = SemanticIndex::new(
                 EMBEDDINGS_DIR.join("semantic-index-db.0.mdb"),
                 Arc::new(embedding_provider),
                 &mut cx,
             )
             .await?;
-            cx.update(|cx| cx.set_global(semantic_index))
+            cx.update(|cx| {
+                semantic_index
+                    .set_summary_provider(Arc::new(CloudSummaryProvider::new(client.clone())), cx);
+                cx.set_global(semantic_index);
+
+                // Projects are migrated to the new model or template in the background.
+                cx.observe_global::<SettingsStore>(move |cx| {
+                    let settings = VectorStoreSettings::get_global(cx);
+                    let new_document_template =
+                        settings.document_template(&settings.embedding_model);
+                    if settings.embedding_model == embedding_model
+                        && new_document_template == document_template
+                    {
+                        return;
+                    }
+                    embedding_model = settings.embedding_model.clone();
+                    document_template = new_document_template;
+                    let embedding_provider = cloud_embedding_provider(&client, settings);
+                    SemanticIndex::update_global(cx, |semantic_index, cx| {
+                        semantic_index.set_embedding_provider(Arc::new(embedding_provider), cx)
+                    });
+                })
+                .detach();
+            })
         }
     })
     .detach();
+    semantic_index::init(&client, cx);
+    project_index_status::init(cx);
 
     prompt_library::init(cx);
     completion_provider::init(client.clone(), cx);
@@ -16,7 +16,7 @@ use assistant_slash_command::{SlashCommand, SlashCommandOutput, SlashCommandOutp
 use client::telemetry::Telemetry;
 use collections::{BTreeSet, HashMap, HashSet};
 use editor::{
-    actions::{FoldAt, MoveToEndOfLine, Newline, ShowCompletions, UnfoldAt},
+    actions::{FoldAt, MoveToEnd, MoveToEndOfLine, Newline, ShowCompletions, UnfoldAt},
     display_map::{BlockDisposition, BlockId, BlockProperties, BlockStyle, Crease, ToDisplayPoint},
     scroll::{Autoscroll, AutoscrollStrategy},
     Anchor, Editor, EditorEvent, RowExt, ToOffset as _, ToPoint,
@@ -65,7 +65,7 @@ use workspace::NewFile;
 use workspace::{
     dock::{DockPosition, Panel, PanelEvent},
     searchable::Direction,
-    Save, ToggleZoom, Toolbar, Workspace,
+    Save, SemanticSearchInDirectory, ToggleZoom, Toolbar, Workspace,
 };
 
 pub fn init(cx: &mut AppContext) {
@@ -81,7 +81,8 @@ pub fn init(cx: &mut AppContext) {
                     workspace.toggle_panel_focus::<AssistantPanel>(cx);
                 })
                 .register_action(AssistantPanel::inline_assist)
-                .register_action(ContextEditor::quote_selection);
+                .register_action(ContextEditor::quote_selection)
+                .register_action(ContextEditor::semantic_search_in_directory);
         },
     )
     .detach();
@@ -2904,6 +2905,45 @@ impl ContextEditor {
         }
     }
 
+    /// Starts a `/search` command restricted to the action's directory, for the user to
+    /// type the query of.
+    fn semantic_search_in_directory(
+        workspace: &mut Workspace,
+        action: &SemanticSearchInDirectory,
+        cx: &mut ViewContext<Workspace>,
+    ) {
+        let Some(panel) = workspace.panel::<AssistantPanel>(cx) else {
+            return;
+        };
+
+        if !panel.focus_handle(cx).contains_focused(cx) {
+            workspace.toggle_panel_focus::<AssistantPanel>(cx);
+        }
+
+        let text = format!("/search --path=\"{}\" ", action.directory.to_string_lossy());
+        panel.update(cx, |_, cx| {
+            // Wait to create a new context until the workspace is no longer
+            // being updated.
+            cx.defer(move |panel, cx| {
+                if let Some(context) = panel
+                    .active_context_editor()
+                    .cloned()
+                    .or_else(|| panel.new_context(cx))
+                {
+                    context.update(cx, |context, cx| {
+                        context.editor.update(cx, |editor, cx| {
+                            editor.move_to_end(&MoveToEnd, cx);
+                            if editor.selections.newest::<Point>(cx).head().column > 0 {
+                                editor.newline(&Newline, cx);
+                            }
+                            editor.insert(&text, cx);
+                        })
+                    });
+                };
+            });
+        });
+    }
+
     fn copy(&mut self, _: &editor::actions::Copy, cx: &mut ViewContext<Self>) {
         let editor = self.editor.read(cx);
         let context = self.context.read(cx);
@@ -0,0 +1,187 @@
+use gpui::{
+    actions, div, AppContext, IntoElement, Model, ParentElement, Render, Subscription, Task,
+    ViewContext,
+};
+use project::Project;
+use semantic_index::{ProjectIndex, SemanticIndex, Status, VectorStoreSettings};
+use settings::Settings;
+use std::time::{Duration, SystemTime};
+use ui::{Button, ButtonCommon, Clickable, FluentBuilder, LabelSize, Tooltip};
+use workspace::{item::ItemHandle, StatusItemView, Workspace};
+
+actions!(semantic_index, [IndexProject]);
+
+/// How often the status bar item re-renders, so that the age of the index stays accurate.
+const ELAPSED_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+pub(crate) fn init(cx: &mut AppContext) {
+    cx.observe_new_views(|workspace: &mut Workspace, cx| {
+        workspace.register_action(index_project);
+        auto_index_project(workspace, cx);
+    })
+    .detach();
+}
+
+fn index_project(workspace: &mut Workspace, _: &IndexProject, cx: &mut ViewContext<Workspace>) {
+    if !cx.has_global::<SemanticIndex>() {
+        return;
+    }
+
+    let project = workspace.project().clone();
+    let project_index =
+        cx.update_global(|index: &mut SemanticIndex, cx| index.project_index(project, cx));
+    project_index.read(cx).refresh(cx);
+}
+
+/// Indexes the workspace's project once it has been open for `auto_index_delay_ms` and
+/// its worktrees have been scanned, if `auto_index` is enabled.
+fn auto_index_project(workspace: &mut Workspace, cx: &mut ViewContext<Workspace>) {
+    let settings = VectorStoreSettings::get_global(cx);
+    if !settings.auto_index {
+        return;
+    }
+
+    let delay = settings.auto_index_delay();
+    let project = workspace.project().downgrade();
+    cx.spawn(|_, mut cx| async move {
+        cx.background_executor().timer(delay).await;
+        let scans = project.update(&mut cx, |project, cx| {
+            project
+                .worktrees()
+                .filter_map(|worktree| Some(worktree.read(cx).as_local()?.scan_complete()))
+                .collect::<Vec<_>>()
+        })?;
+        futures::future::join_all(scans).await;
+
+        cx.update(|cx| {
+            let Some(project) = project.upgrade() else {
+                return;
+            };
+            if !VectorStoreSettings::get_global(cx).auto_index
+                || !cx.has_global::<SemanticIndex>()
+                || project.read(cx).is_remote()
+            {
+                return;
+            }
+            // Creating the project's index indexes every file that changed since the
+            // project was last open.
+            cx.update_global(|index: &mut SemanticIndex, cx| index.project_index(project, cx));
+        })
+    })
+    .detach_and_log_err(cx);
+}
+
+/// A status bar item showing how up to date the project's index is, which can be
+/// clicked to index the project.
+pub struct ProjectIndexStatus {
+    project: Model<Project>,
+    project_index: Option<Model<ProjectIndex>>,
+    _project_index_subscription: Option<Subscription>,
+    _observe_semantic_index: Subscription,
+    _refresh_elapsed: Task<()>,
+}
+
+impl ProjectIndexStatus {
+    pub fn new(workspace: &Workspace, cx: &mut ViewContext<Self>) -> Self {
+        let mut this = Self {
+            project: workspace.project().clone(),
+            project_index: None,
+            _project_index_subscription: None,
+            _observe_semantic_index: cx
+                .observe_global::<SemanticIndex>(|this, cx| this.update_project_index(cx)),
+            _refresh_elapsed: cx.spawn(|this, mut cx| async move {
+                loop {
+                    cx.background_executor()
+                        .timer(ELAPSED_REFRESH_INTERVAL)
+                        .await;
+                    if this.update(&mut cx, |_, cx| cx.notify()).is_err() {
+                        break;
+                    }
+                }
+            }),
+        };
+        this.update_project_index(cx);
+        this
+    }
+
+    fn update_project_index(&mut self, cx: &mut ViewContext<Self>) {
+        if self.project_index.is_some() || !cx.has_global::<SemanticIndex>() {
+            return;
+        }
+
+        let Some(project_index) = cx
+            .global::<SemanticIndex>()
+            .existing_project_index(&self.project)
+        else {
+            return;
+        };
+
+        self._project_index_subscription =
+            Some(cx.subscribe(&project_index, |_, _, _: &Status, cx| cx.notify()));
+        self.project_index = Some(project_index);
+        cx.notify();
+    }
+
+    fn label(&self, cx: &ViewContext<Self>) -> String {
+        let Some(project_index) = self.project_index.as_ref() else {
+            return "Index Project".into();
+        };
+
+        let project_index = project_index.read(cx);
+        let label = match project_index.status() {
+            Status::Loading => "Loading Index…".into(),
+            Status::Scanning { remaining_count } => format!("Indexing {remaining_count} files…"),
+            Status::Idle => match project_index.last_indexed_at() {
+                Some(indexed_at) => format!("Indexed {}", format_elapsed(indexed_at)),
+                None => "Index Project".into(),
+            },
+        };
+
+        let label = match project_index.indexing_errors(cx).len() {
+            0 => label,
+            1 => format!("{label} (1 error)"),
+            error_count => format!("{label} ({error_count} errors)"),
+        };
+        if project_index.embedding_provider_error().is_some() {
+            format!("{label}, Embeddings Unavailable")
+        } else {
+            label
+        }
+    }
+}
+
+fn format_elapsed(time: SystemTime) -> String {
+    let elapsed = SystemTime::now()
+        .duration_since(time)
+        .unwrap_or_default()
+        .as_secs();
+    match elapsed {
+        0..=59 => "just now".into(),
+        60..=3599 => format!("{}m ago", elapsed / 60),
+        3600..=86399 => format!("{}h ago", elapsed / 3600),
+        _ => format!("{}d ago", elapsed / 86400),
+    }
+}
+
+impl Render for ProjectIndexStatus {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let has_semantic_index = cx.has_global::<SemanticIndex>();
+        div().when(has_semantic_index, |el| {
+            el.child(
+                Button::new("project-index-status", self.label(cx))
+                    .label_size(LabelSize::Small)
+                    .on_click(|_, cx| cx.dispatch_action(Box::new(IndexProject)))
+                    .tooltip(|cx| Tooltip::for_action("Index Project", &IndexProject, cx)),
+            )
+        })
+    }
+}
+
+impl StatusItemView for ProjectIndexStatus {
+    fn set_active_pane_item(
+        &mut self,
+        _active_pane_item: Option<&dyn ItemHandle>,
+        _cx: &mut ViewContext<Self>,
+    ) {
+    }
+}
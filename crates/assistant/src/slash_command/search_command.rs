@@ -4,18 +4,24 @@ use super::{
 };
 use anyhow::Result;
 use assistant_slash_command::SlashCommandOutputSection;
+use collections::HashSet;
 use gpui::{AppContext, Task, WeakView};
 use language::{CodeLabel, HighlightId, LineEnding, LspAdapterDelegate};
-use semantic_index::SemanticIndex;
+use project::{Project, ProjectPath, Worktree};
+use semantic_index::{SearchResultSource, SemanticIndex};
 use std::{
     fmt::Write,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{atomic::AtomicBool, Arc},
 };
 use ui::{prelude::*, IconName};
 use util::ResultExt;
 use workspace::Workspace;
 
+/// How much the search results favor covering different parts of the project over
+/// ranking purely by relevance, so that they aren't all near-identical excerpts.
+const SEARCH_DIVERSITY: f32 = 0.3;
+
 pub(crate) struct SearchSlashCommand;
 
 impl SlashCommand for SearchSlashCommand {
@@ -70,52 +76,89 @@ impl SlashCommand for SearchSlashCommand {
             return Task::ready(Err(anyhow::anyhow!("missing search query")));
         };
 
-        let mut limit = None;
-        let mut query = String::new();
-        for part in argument.split(' ') {
-            if let Some(parameter) = part.strip_prefix("--") {
-                if let Ok(count) = parameter.parse::<usize>() {
-                    limit = Some(count);
-                    continue;
-                }
-            }
-
-            query.push_str(part);
-            query.push(' ');
-        }
-        query.pop();
-
+        let (limit, directory, query) = match parse_argument(argument) {
+            Ok(parsed) => parsed,
+            Err(error) => return Task::ready(Err(error)),
+        };
         if query.is_empty() {
             return Task::ready(Err(anyhow::anyhow!("missing search query")));
         }
 
         let project = workspace.read(cx).project().clone();
-        let fs = project.read(cx).fs().clone();
+        let directory = match directory {
+            Some(directory) => match project_path_for_directory(project.read(cx), &directory, cx) {
+                Some(directory) => Some(directory),
+                None => {
+                    return Task::ready(Err(anyhow::anyhow!(
+                        "no directory {directory:?} in the project"
+                    )))
+                }
+            },
+            None => None,
+        };
         let project_index =
             cx.update_global(|index: &mut SemanticIndex, cx| index.project_index(project, cx));
 
         cx.spawn(|cx| async move {
             let results = project_index
                 .read_with(&cx, |project_index, cx| {
-                    project_index.search(query.clone(), limit.unwrap_or(5), cx)
+                    let limit = limit.unwrap_or(5);
+                    match directory {
+                        Some(directory) => project_index.search_in_directory(
+                            directory,
+                            query.clone(),
+                            limit,
+                            None,
+                            SEARCH_DIVERSITY,
+                            cx,
+                        ),
+                        None => {
+                            project_index.search(query.clone(), limit, None, SEARCH_DIVERSITY, cx)
+                        }
+                    }
                 })?
                 .await?;
 
             let mut loaded_results = Vec::new();
             for result in results {
-                let (full_path, file_content) =
-                    result.worktree.read_with(&cx, |worktree, _cx| {
-                        let entry_abs_path = worktree.abs_path().join(&result.path);
-                        let mut entry_full_path = PathBuf::from(worktree.root_name());
-                        entry_full_path.push(&result.path);
-                        let file_content = async {
-                            let entry_abs_path = entry_abs_path;
-                            fs.load(&entry_abs_path).await
-                        };
-                        (entry_full_path, file_content)
-                    })?;
-                if let Some(file_content) = file_content.await.log_err() {
-                    loaded_results.push((result, full_path, file_content));
+                // The results' ranges refer to the text they were scored on, which is the
+                // buffer's rather than the file's when the buffer has unsaved changes.
+                let content = project_index
+                    .read_with(&cx, |project_index, cx| {
+                        project_index.result_text(&result, cx)
+                    })?
+                    .await;
+                let Some(Some(content)) = content.log_err() else {
+                    continue;
+                };
+                match &result.source {
+                    SearchResultSource::File { worktree, path } => {
+                        let full_path = worktree.read_with(&cx, |worktree, _cx| {
+                            let mut entry_full_path = PathBuf::from(worktree.root_name());
+                            entry_full_path.push(path);
+                            entry_full_path
+                        })?;
+                        // Summaries describe what the file is for, to ground the answer.
+                        let summary = project_index
+                            .read_with(&cx, |project_index, cx| {
+                                project_index.file_summary(worktree, path, cx)
+                            })?
+                            .await
+                            .log_err()
+                            .flatten();
+                        let path = path.to_path_buf();
+                        loaded_results.push((result, path, full_path, content, summary));
+                    }
+                    SearchResultSource::Document(id) => {
+                        let document_path = PathBuf::from(id.key.as_ref());
+                        loaded_results.push((
+                            result,
+                            document_path.clone(),
+                            document_path,
+                            content,
+                            None,
+                        ));
+                    }
                 }
             }
 
@@ -124,9 +167,17 @@ impl SlashCommand for SearchSlashCommand {
                 .spawn(async move {
                     let mut text = format!("Search results for {query}:\n");
                     let mut sections = Vec::new();
-                    for (result, full_path, file_content) in loaded_results {
-                        let range_start = result.range.start.min(file_content.len());
-                        let range_end = result.range.end.min(file_content.len());
+                    let mut summarized_paths = HashSet::default();
+                    for (result, path, full_path, file_content, summary) in loaded_results {
+                        if let Some(summary) = summary {
+                            if summarized_paths.insert(full_path.clone()) {
+                                writeln!(text, "Summary of {}: {}", full_path.display(), summary)
+                                    .unwrap();
+                            }
+                        }
+                        let range_start = floor_char_boundary(&file_content, result.range.start);
+                        let range_end =
+                            floor_char_boundary(&file_content, result.range.end).max(range_start);
 
                         let start_row = file_content[0..range_start].matches('\n').count() as u32;
                         let end_row = file_content[0..range_end].matches('\n').count() as u32;
@@ -141,7 +192,7 @@ impl SlashCommand for SearchSlashCommand {
 
                         let section_start_ix = text.len();
                         text.push_str(&codeblock_fence_for_path(
-                            Some(&result.path),
+                            Some(&path),
                             Some(start_row..end_row),
                         ));
 
@@ -178,3 +229,169 @@ impl SlashCommand for SearchSlashCommand {
         })
     }
 }
+
+/// Splits the command's argument into the number of results (`--5`), the directory to
+/// search in (`--path=src` or `--path="path with spaces"`), and the query.
+///
+/// Within a quoted path, `\"` stands for a quote and `\\` for a backslash.
+fn parse_argument(argument: &str) -> Result<(Option<usize>, Option<PathBuf>, String)> {
+    let mut limit = None;
+    let mut directory = None;
+    let mut query = String::new();
+    let mut rest = argument.trim_start();
+    while !rest.is_empty() {
+        if let Some(path) = rest.strip_prefix("--path=") {
+            if directory.is_some() {
+                return Err(anyhow::anyhow!("only one --path can be given"));
+            }
+            let (path, remaining) = match path.strip_prefix('"') {
+                Some(quoted) => parse_quoted(quoted)?,
+                None => {
+                    let (path, remaining) = path.split_once(' ').unwrap_or((path, ""));
+                    (path.to_string(), remaining)
+                }
+            };
+            directory = Some(PathBuf::from(path));
+            rest = remaining.trim_start();
+            continue;
+        }
+
+        let (part, remaining) = rest.split_once(' ').unwrap_or((rest, ""));
+        rest = remaining.trim_start();
+        if let Some(count) = part
+            .strip_prefix("--")
+            .and_then(|count| count.parse::<usize>().ok())
+        {
+            limit = Some(count);
+            continue;
+        }
+
+        query.push_str(part);
+        query.push(' ');
+    }
+    query.pop();
+    Ok((limit, directory, query))
+}
+
+/// Reads a quoted value up to its closing quote, returning it unescaped along with the
+/// text after it. The closing quote must end the argument or be followed by a space, so
+/// that it's clear where the value ends.
+fn parse_quoted(quoted: &str) -> Result<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((ix, ch)) = chars.next() {
+        match ch {
+            '\\' => match chars.next() {
+                Some((_, escaped @ ('"' | '\\'))) => value.push(escaped),
+                // Other backslashes are kept, as they separate Windows paths.
+                Some((_, other)) => {
+                    value.push('\\');
+                    value.push(other);
+                }
+                None => value.push('\\'),
+            },
+            '"' => {
+                let remaining = &quoted[ix + 1..];
+                if !remaining.is_empty() && !remaining.starts_with(' ') {
+                    return Err(anyhow::anyhow!(
+                        "expected a space after the quoted path \"{value}\""
+                    ));
+                }
+                return Ok((value, remaining));
+            }
+            _ => value.push(ch),
+        }
+    }
+    Err(anyhow::anyhow!(
+        "missing closing quote in --path=\"{quoted}"
+    ))
+}
+
+/// The largest char boundary of `text` at or before `offset`, so that a range that's out
+/// of date with the text can still be sliced from it.
+fn floor_char_boundary(text: &str, offset: usize) -> usize {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+/// Finds a directory given by its path in the project, which starts with the name of its
+/// worktree, or is relative to the only worktree of the project.
+fn project_path_for_directory(
+    project: &Project,
+    directory: &Path,
+    cx: &AppContext,
+) -> Option<ProjectPath> {
+    let worktrees = project.visible_worktrees(cx).collect::<Vec<_>>();
+    let is_directory = |worktree: &Worktree, path: &Path| {
+        worktree
+            .entry_for_path(path)
+            .map_or(false, |entry| entry.is_dir())
+    };
+    for worktree in &worktrees {
+        let worktree = worktree.read(cx);
+        if let Ok(path) = directory.strip_prefix(worktree.root_name()) {
+            if is_directory(worktree, path) {
+                return Some(ProjectPath {
+                    worktree_id: worktree.id(),
+                    path: path.into(),
+                });
+            }
+        }
+    }
+    if let [worktree] = worktrees.as_slice() {
+        let worktree = worktree.read(cx);
+        if is_directory(worktree, directory) {
+            return Some(ProjectPath {
+                worktree_id: worktree.id(),
+                path: directory.into(),
+            });
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_argument() {
+        assert_eq!(
+            parse_argument("--3 --path=src/db how are rows stored").unwrap(),
+            (
+                Some(3),
+                Some(PathBuf::from("src/db")),
+                "how are rows stored".to_string()
+            )
+        );
+        assert_eq!(
+            parse_argument("--path=\"my project/src dir\" parsing  errors").unwrap(),
+            (
+                None,
+                Some(PathBuf::from("my project/src dir")),
+                "parsing errors".to_string()
+            )
+        );
+        assert_eq!(
+            parse_argument(r#"--path="say \"hi\"\\ dir" greeting"#).unwrap(),
+            (
+                None,
+                Some(PathBuf::from(r#"say "hi"\ dir"#)),
+                "greeting".to_string()
+            )
+        );
+        assert_eq!(
+            parse_argument(r#"--path="src\db" rows"#).unwrap(),
+            (None, Some(PathBuf::from(r"src\db")), "rows".to_string())
+        );
+
+        // Paths whose end is unclear are rejected rather than guessed at.
+        assert!(parse_argument("--path=\"unterminated dir").is_err());
+        assert!(parse_argument(r#"--path="escaped quote\" dir"#).is_err());
+        assert!(parse_argument("--path=\"src\"db rows").is_err());
+        assert!(parse_argument("--path=src --path=lib rows").is_err());
+    }
+}
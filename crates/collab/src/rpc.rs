@@ -467,6 +467,12 @@ impl Server {
             .add_request_handler(user_handler(
                 forward_read_only_project_request::<proto::SearchProject>,
             ))
+            .add_request_handler(user_handler(
+                forward_read_only_project_request::<proto::SearchProjectIndex>,
+            ))
+            .add_request_handler(user_handler(
+                forward_read_only_project_request::<proto::RefreshProjectIndex>,
+            ))
             .add_request_handler(user_handler(
                 forward_read_only_project_request::<proto::GetDocumentHighlights>,
             ))
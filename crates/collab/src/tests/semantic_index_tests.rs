@@ -0,0 +1,96 @@
+use crate::tests::TestServer;
+use call::ActiveCall;
+use gpui::{BackgroundExecutor, BorrowAppContext, TestAppContext};
+use semantic_index::{FakeEmbeddingProvider, SearchResultSource, SemanticIndex};
+use serde_json::json;
+use std::{path::Path, sync::Arc};
+
+#[gpui::test]
+async fn test_search_shared_project_index(
+    executor: BackgroundExecutor,
+    cx_a: &mut TestAppContext,
+    cx_b: &mut TestAppContext,
+) {
+    let mut server = TestServer::start(executor.clone()).await;
+    let client_a = server.create_client(cx_a, "user_a").await;
+    let client_b = server.create_client(cx_b, "user_b").await;
+    server
+        .create_room(&mut [(&client_a, cx_a), (&client_b, cx_b)])
+        .await;
+    let active_call_a = cx_a.read(ActiveCall::global);
+
+    let db_dir_a = tempfile::tempdir().unwrap();
+    let db_dir_b = tempfile::tempdir().unwrap();
+    for (client, db_dir, cx) in [
+        (&client_a, &db_dir_a, &mut *cx_a),
+        (&client_b, &db_dir_b, &mut *cx_b),
+    ] {
+        cx.update(|cx| semantic_index::init(client.client(), cx));
+        let semantic_index = SemanticIndex::new(
+            db_dir.path().into(),
+            Arc::new(FakeEmbeddingProvider::new()),
+            &mut cx.to_async(),
+        )
+        .await
+        .unwrap();
+        cx.update(|cx| cx.set_global(semantic_index));
+    }
+
+    client_a
+        .fs()
+        .insert_tree(
+            "/root",
+            json!({
+                "needle.txt": "a needle in a haystack",
+                "hay.txt": "nothing but hay",
+            }),
+        )
+        .await;
+    let (project_a, _) = client_a.build_local_project("/root", cx_a).await;
+    let project_id = active_call_a
+        .update(cx_a, |call, cx| call.share_project(project_a.clone(), cx))
+        .await
+        .unwrap();
+    let project_b = client_b.build_dev_server_project(project_id, cx_b).await;
+    let project_index_b = cx_b.update(|cx| {
+        cx.update_global::<SemanticIndex, _>(|semantic_index, cx| {
+            semantic_index.project_index(project_b.clone(), cx)
+        })
+    });
+    let search_b = |cx: &mut TestAppContext| {
+        cx.update(|cx| {
+            project_index_b
+                .read(cx)
+                .search("needle".into(), 1000, None, 0., cx)
+        })
+    };
+
+    // Guests can't make the host index a project it didn't index.
+    assert!(search_b(cx_b).await.is_err());
+    executor.run_until_parked();
+    assert!(cx_a.update(|cx| {
+        cx.global::<SemanticIndex>()
+            .existing_project_index(&project_a)
+            .is_none()
+    }));
+
+    let project_index_a = cx_a.update(|cx| {
+        cx.update_global::<SemanticIndex, _>(|semantic_index, cx| {
+            semantic_index.project_index(project_a.clone(), cx)
+        })
+    });
+    executor.run_until_parked();
+    let results = search_b(cx_b).await.unwrap();
+    assert_eq!(results.len(), 2);
+    let SearchResultSource::File { path, .. } = &results[0].source else {
+        panic!("expected a file result");
+    };
+    assert_eq!(path.as_ref(), Path::new("needle.txt"));
+
+    // Guests' queries stay out of the host's search history.
+    let history = cx_a
+        .update(|cx| project_index_a.read(cx).search_history("", 10, cx))
+        .await
+        .unwrap();
+    assert!(history.is_empty(), "{history:?}");
+}
@@ -18,7 +18,7 @@ use project::{PathMatchCandidateSet, Project, ProjectPath, WorktreeId};
 use settings::Settings;
 use std::{
     cmp,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
     sync::{
         atomic::{self, AtomicBool},
         Arc,
@@ -660,9 +660,15 @@ impl FileFinderDelegate {
         (file_name, file_name_positions, full_path, path_positions)
     }
 
+    /// Shows the file at `query_path` if it's in the project. When it doesn't exist and
+    /// `fall_back_to_search` is set, e.g. because the path was resolved against a
+    /// terminal's directory but named a file of the project, the query is searched for
+    /// instead.
     fn lookup_absolute_path(
         &self,
         query: PathLikeWithPosition<FileSearchQuery>,
+        query_path: PathBuf,
+        fall_back_to_search: bool,
         cx: &mut ViewContext<'_, Picker<Self>>,
     ) -> Task<()> {
         cx.spawn(|picker, mut cx| async move {
@@ -676,14 +682,13 @@ impl FileFinderDelegate {
                 return;
             };
 
-            let query_path = Path::new(query.path_like.path_query());
             let mut path_matches = Vec::new();
-            match fs.metadata(query_path).await.log_err() {
+            match fs.metadata(&query_path).await.log_err() {
                 Some(Some(_metadata)) => {
                     let update_result = project
                         .update(&mut cx, |project, cx| {
                             if let Some((worktree, relative_path)) =
-                                project.find_local_worktree(query_path, cx)
+                                project.find_local_worktree(&query_path, cx)
                             {
                                 path_matches.push(ProjectPanelOrdMatch(PathMatch {
                                     score: 1.0,
@@ -700,6 +705,17 @@ impl FileFinderDelegate {
                         return;
                     }
                 }
+                Some(None) if fall_back_to_search => {
+                    if let Some(search) = picker
+                        .update(&mut cx, |picker, cx| {
+                            picker.delegate.spawn_search(query, cx)
+                        })
+                        .log_err()
+                    {
+                        search.await;
+                    }
+                    return;
+                }
                 Some(None) => {}
                 None => return,
             }
@@ -812,8 +828,21 @@ impl PickerDelegate for FileFinderDelegate {
             })
             .expect("infallible");
 
-            if Path::new(query.path_like.path_query()).is_absolute() {
-                self.lookup_absolute_path(query, cx)
+            let query_path = Path::new(query.path_like.path_query());
+            let is_absolute = query_path.is_absolute();
+            let resolved_path = if is_absolute {
+                Some(query_path.to_path_buf())
+            } else {
+                self.project
+                    .read(cx)
+                    .recent_terminal_directories()
+                    .next()
+                    .and_then(|directory| resolve_in_directory(directory, query_path))
+            };
+            if let Some(resolved_path) = resolved_path {
+                // A relative path that isn't in the terminal's directory may still name a
+                // file of the project.
+                self.lookup_absolute_path(query, resolved_path, !is_absolute, cx)
             } else {
                 self.spawn_search(query, cx)
             }
@@ -989,10 +1018,46 @@ impl PickerDelegate for FileFinderDelegate {
     }
 }
 
+/// Resolves a path starting with `./` or `../`, e.g. one copied from a terminal, against
+/// `directory`. Other relative paths are searched for in the project instead.
+fn resolve_in_directory(directory: &Path, path: &Path) -> Option<PathBuf> {
+    if !matches!(
+        path.components().next(),
+        Some(Component::CurDir | Component::ParentDir)
+    ) {
+        return None;
+    }
+    let mut resolved = directory.to_path_buf();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            component => resolved.push(component),
+        }
+    }
+    Some(resolved)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_resolve_in_directory() {
+        let directory = Path::new("/root/project/src");
+        assert_eq!(
+            resolve_in_directory(directory, Path::new("./main.rs")),
+            Some(PathBuf::from("/root/project/src/main.rs"))
+        );
+        assert_eq!(
+            resolve_in_directory(directory, Path::new("../tests/./a.rs")),
+            Some(PathBuf::from("/root/project/tests/a.rs"))
+        );
+        assert_eq!(resolve_in_directory(directory, Path::new("main.rs")), None);
+    }
+
     #[test]
     fn test_custom_project_search_ordering_in_file_finder() {
         let mut file_finder_sorted_output = vec![
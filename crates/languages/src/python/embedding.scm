@@ -7,3 +7,16 @@
     "async"? @context
     "def" @context
     name: (_) @name) @item
+
+(decorated_definition
+    (decorator)+ @context
+    definition: (class_definition
+        "class" @context
+        name: (identifier) @name) @item)
+
+(decorated_definition
+    (decorator)+ @context
+    definition: (function_definition
+        "async"? @context
+        "def" @context
+        name: (_) @name) @item)
@@ -1,5 +1,5 @@
 (
-    [(line_comment) (attribute_item)]* @context
+    [(line_comment) (block_comment) (attribute_item)]* @context
     .
     [
 
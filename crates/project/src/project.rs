@@ -766,6 +766,7 @@ impl Project {
                 nonce: StdRng::from_entropy().gen(),
                 terminals: Terminals {
                     local_handles: Vec::new(),
+                    recent_directories: VecDeque::new(),
                 },
                 current_lsp_settings: ProjectSettings::get_global(cx).lsp.clone(),
                 node: Some(node),
@@ -925,6 +926,7 @@ impl Project {
                 nonce: StdRng::from_entropy().gen(),
                 terminals: Terminals {
                     local_handles: Vec::new(),
+                    recent_directories: VecDeque::new(),
                 },
                 current_lsp_settings: ProjectSettings::get_global(cx).lsp.clone(),
                 node: None,
@@ -15,6 +15,7 @@ use serde_json::json;
 use std::os;
 use std::task::Poll;
 use task::{ResolvedTask, TaskContext, TaskTemplate, TaskTemplates};
+use terminal::terminal_settings::TerminalSettings;
 use unindent::Unindent as _;
 use util::{assert_set_eq, paths::PathMatcher, test::temp_tree};
 
@@ -89,6 +90,42 @@ async fn test_symlinks(cx: &mut gpui::TestAppContext) {
     });
 }
 
+#[gpui::test]
+async fn test_terminal_activation_script_from_project_settings(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+    cx.update(terminal::init);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/the-root",
+        json!({
+            ".zed": {
+                "settings.json": r#"{ "terminal": { "activation_script": "source .venv/bin/activate" } }"#,
+            },
+            "a.py": "print('a')",
+        }),
+    )
+    .await;
+
+    let project = Project::test(fs.clone(), ["/the-root".as_ref()], cx).await;
+    cx.executor().run_until_parked();
+    cx.update(|cx| {
+        let worktree_id = project.read(cx).worktrees().next().unwrap().read(cx).id();
+        let project_settings = TerminalSettings::get(
+            Some(SettingsLocation {
+                worktree_id: worktree_id.to_usize(),
+                path: Path::new("a.py"),
+            }),
+            cx,
+        );
+        assert_eq!(
+            project_settings.activation_script.as_deref(),
+            Some("source .venv/bin/activate")
+        );
+        assert_eq!(TerminalSettings::get_global(cx).activation_script, None);
+    });
+}
+
 #[gpui::test]
 async fn test_managing_project_specific_settings(cx: &mut gpui::TestAppContext) {
     init_test(cx);
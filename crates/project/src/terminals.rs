@@ -1,10 +1,11 @@
 use crate::Project;
 use anyhow::Context as _;
-use collections::HashMap;
+use collections::{HashMap, VecDeque};
 use gpui::{
     AnyWindowHandle, AppContext, Context, Entity, Model, ModelContext, SharedString, WeakModel,
 };
 use itertools::Itertools;
+use release_channel::AppVersion;
 use settings::{Settings, SettingsLocation};
 use smol::channel::bounded;
 use std::{
@@ -23,8 +24,14 @@ use util::ResultExt;
 // #[cfg(target_os = "macos")]
 // use std::os::unix::ffi::OsStrExt;
 
+/// The most working directories of the project's terminals that are remembered.
+const MAX_RECENT_DIRECTORIES: usize = 20;
+
 pub struct Terminals {
     pub(crate) local_handles: Vec<WeakModel<terminal::Terminal>>,
+    /// The working directories the shells of the project's terminals were in, most
+    /// recently entered first.
+    pub(crate) recent_directories: VecDeque<PathBuf>,
 }
 
 #[derive(Debug, Clone)]
@@ -96,9 +103,12 @@ impl Project {
                 .map_or(true, |work_dir| work_dir.is_local());
         let settings = TerminalSettings::get(settings_location, cx);
         let python_settings = settings.detect_venv.clone();
+        let activation_script = settings.activation_script.clone();
         let (completion_tx, completion_rx) = bounded(1);
 
         let mut env = settings.env.clone();
+        env.entry("TERM_PROGRAM_VERSION".to_string())
+            .or_insert_with(|| AppVersion::global(cx).to_string());
         // Alacritty uses parent project's working directory when no working directory is provided
         // https://github.com/alacritty/alacritty/blob/fd1a3cc79192d1d03839f0fd8c72e1f8d0fce42e/extra/man/alacritty.5.scd?plain=1#L47-L52
 
@@ -162,7 +172,7 @@ impl Project {
                         },
                     )
                 } else {
-                    (None, settings.shell.clone())
+                    (None, settings.shell_with_args())
                 }
             }
         };
@@ -203,6 +213,16 @@ impl Project {
             })
             .detach();
 
+            // The terminal's title changes along with the working directory of its shell.
+            cx.subscribe(&terminal_handle, |project, terminal, event, cx| {
+                if let terminal::Event::TitleChanged = event {
+                    if let Some(cwd) = terminal.read(cx).get_cwd() {
+                        project.record_terminal_directory(cwd);
+                    }
+                }
+            })
+            .detach();
+
             // if the terminal is not a task, activate full Python virtual environment
             if is_terminal {
                 if let Some(python_settings) = &python_settings.as_option() {
@@ -217,6 +237,9 @@ impl Project {
                         );
                     }
                 }
+                if let Some(activation_script) = activation_script {
+                    run_activation_script(&activation_script, &terminal_handle, cx);
+                }
             }
             terminal_handle
         });
@@ -303,6 +326,40 @@ impl Project {
     pub fn local_terminal_handles(&self) -> &Vec<WeakModel<terminal::Terminal>> {
         &self.terminals.local_handles
     }
+
+    /// The working directories the shells of the project's terminals were in, most
+    /// recently entered first.
+    pub fn recent_terminal_directories(&self) -> impl Iterator<Item = &Path> {
+        self.terminals
+            .recent_directories
+            .iter()
+            .map(PathBuf::as_path)
+    }
+
+    fn record_terminal_directory(&mut self, directory: PathBuf) {
+        let recent_directories = &mut self.terminals.recent_directories;
+        if recent_directories.front() == Some(&directory) {
+            return;
+        }
+        recent_directories.retain(|recent_directory| *recent_directory != directory);
+        recent_directories.push_front(directory);
+        recent_directories.truncate(MAX_RECENT_DIRECTORIES);
+    }
+}
+
+/// Types the project's activation script into a new terminal. The shell reads it once
+/// it's done starting up, as the terminal buffers the input until then.
+fn run_activation_script(
+    activation_script: &str,
+    terminal_handle: &Model<Terminal>,
+    cx: &mut ModelContext<Project>,
+) {
+    let activation_script = activation_script.trim_end();
+    if activation_script.is_empty() {
+        return;
+    }
+    let command = format!("{activation_script}\n");
+    terminal_handle.update(cx, |terminal, _| terminal.input(command));
 }
 
 fn prepare_ssh_shell(
@@ -134,6 +134,7 @@ actions!(
         OpenPermanent,
         ToggleFocus,
         NewSearchInDirectory,
+        NewSemanticSearchInDirectory,
         UnfoldDirectory,
         FoldDirectory,
         SelectParent,
@@ -465,6 +466,10 @@ impl ProjectPanel {
                             .when(is_dir, |menu| {
                                 menu.separator()
                                     .action("Find in Folder…", Box::new(NewSearchInDirectory))
+                                    .action(
+                                        "Semantic Search in This Folder…",
+                                        Box::new(NewSemanticSearchInDirectory),
+                                    )
                             })
                             .when(is_unfoldable, |menu| {
                                 menu.action("Unfold Directory", Box::new(UnfoldDirectory))
@@ -1342,6 +1347,26 @@ impl ProjectPanel {
         }
     }
 
+    fn new_semantic_search_in_directory(
+        &mut self,
+        _: &NewSemanticSearchInDirectory,
+        cx: &mut ViewContext<Self>,
+    ) {
+        if let Some((worktree, entry)) = self.selected_entry(cx) {
+            if entry.is_dir() {
+                let include_root = self.project.read(cx).visible_worktrees(cx).count() > 1;
+                let directory = if include_root {
+                    let mut full_path = PathBuf::from(worktree.root_name());
+                    full_path.push(&entry.path);
+                    full_path
+                } else {
+                    entry.path.to_path_buf()
+                };
+                cx.dispatch_action(workspace::SemanticSearchInDirectory { directory }.boxed_clone())
+            }
+        }
+    }
+
     fn open_in_terminal(&mut self, _: &OpenInTerminal, cx: &mut ViewContext<Self>) {
         if let Some((worktree, entry)) = self.selected_entry(cx) {
             let abs_path = worktree.abs_path().join(&entry.path);
@@ -2269,6 +2294,7 @@ impl Render for ProjectPanel {
                 .on_action(cx.listener(Self::copy_path))
                 .on_action(cx.listener(Self::copy_relative_path))
                 .on_action(cx.listener(Self::new_search_in_directory))
+                .on_action(cx.listener(Self::new_semantic_search_in_directory))
                 .on_action(cx.listener(Self::unfold_directory))
                 .on_action(cx.listener(Self::fold_directory))
                 .when(!project.is_read_only(), |el| {
@@ -337,7 +337,10 @@ messages!(
     (OpenNewBuffer, Foreground),
     (RestartLanguageServers, Foreground),
     (LinkedEditingRange, Background),
-    (LinkedEditingRangeResponse, Background)
+    (LinkedEditingRangeResponse, Background),
+    (SearchProjectIndex, Background),
+    (SearchProjectIndexResponse, Background),
+    (RefreshProjectIndex, Foreground)
 );
 
 request_messages!(
@@ -449,7 +452,9 @@ request_messages!(
     (DeleteDevServerProject, Ack),
     (RegenerateDevServerToken, RegenerateDevServerTokenResponse),
     (RenameDevServer, Ack),
-    (RestartLanguageServers, Ack)
+    (RestartLanguageServers, Ack),
+    (SearchProjectIndex, SearchProjectIndexResponse),
+    (RefreshProjectIndex, Ack)
 );
 
 entity_messages!(
@@ -489,6 +494,7 @@ entity_messages!(
     PerformRename,
     PrepareRename,
     RefreshInlayHints,
+    RefreshProjectIndex,
     ReloadBuffers,
     RemoveProjectCollaborator,
     RenameProjectEntry,
@@ -496,6 +502,7 @@ entity_messages!(
     ResolveInlayHint,
     SaveBuffer,
     SearchProject,
+    SearchProjectIndex,
     StartLanguageServer,
     SynchronizeBuffers,
     TaskContextForLocation,
@@ -4,7 +4,9 @@ use gpui::App;
 use http::HttpClientWithUrl;
 use language::language_settings::AllLanguageSettings;
 use project::Project;
-use semantic_index::{OpenAiEmbeddingModel, OpenAiEmbeddingProvider, SemanticIndex};
+use semantic_index::{
+    OpenAiEmbeddingModel, OpenAiEmbeddingProvider, SearchResultSource, SemanticIndex,
+};
 use settings::SettingsStore;
 use std::{
     path::{Path, PathBuf},
@@ -30,6 +32,7 @@ fn main() {
 
         let client = client::Client::new(clock, http.clone(), cx);
         Client::set_global(client.clone(), cx);
+        semantic_index::init(&client, cx);
 
         let args: Vec<String> = std::env::args().collect();
         if args.len() < 2 {
@@ -38,8 +41,6 @@ fn main() {
             return;
         }
 
-        // let embedding_provider = semantic_index::FakeEmbeddingProvider;
-
         let api_key = std::env::var("OPENAI_API_KEY").expect("OPENAI_API_KEY not set");
 
         let embedding_provider = Arc::new(OpenAiEmbeddingProvider::new(
@@ -92,19 +93,21 @@ fn main() {
                 .update(|cx| {
                     let project_index = project_index.read(cx);
                     let query = "converting an anchor to a point";
-                    project_index.search(query.into(), 4, cx)
+                    project_index.search(query.into(), 4, None, 0., cx)
                 })
                 .unwrap()
                 .await
                 .unwrap();
 
             for search_result in results {
-                let path = search_result.path.clone();
+                let SearchResultSource::File { worktree, path } = search_result.source else {
+                    continue;
+                };
 
                 let content = cx
                     .update(|cx| {
-                        let worktree = search_result.worktree.read(cx);
-                        let entry_abs_path = worktree.abs_path().join(search_result.path.clone());
+                        let worktree = worktree.read(cx);
+                        let entry_abs_path = worktree.abs_path().join(&path);
                         let fs = project.read(cx).fs().clone();
                         cx.spawn(|_| async move { fs.load(&entry_abs_path).await.unwrap() })
                     })
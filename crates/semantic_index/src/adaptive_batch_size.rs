@@ -0,0 +1,196 @@
+use crate::{
+    DocumentTemplate, Embedding, EmbeddingModelMetadata, EmbeddingProvider, SimilarityMetric,
+    TextToEmbed,
+};
+use anyhow::Result;
+use futures::{future::BoxFuture, FutureExt};
+use parking_lot::Mutex;
+use std::{
+    ops::RangeInclusive,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Full batches that are embedded faster than this grow the batch size.
+const FAST_BATCH_LATENCY: Duration = Duration::from_secs(5);
+/// Batches that take longer than this to embed shrink the batch size.
+const SLOW_BATCH_LATENCY: Duration = Duration::from_secs(30);
+
+/// The number of texts sent to the embedding provider in a request, which shrinks while
+/// the provider is slow or reports being overloaded, and grows back while it keeps up,
+/// so that indexing makes the most of what the provider can handle at the moment.
+#[derive(Default)]
+pub(crate) struct AdaptiveBatchSize {
+    /// `None` until a batch completed, in which case batches are as large as allowed.
+    batch_size: Mutex<Option<usize>>,
+}
+
+impl AdaptiveBatchSize {
+    pub fn batch_size(&self, bounds: &RangeInclusive<usize>) -> usize {
+        self.batch_size.lock().map_or(*bounds.end(), |batch_size| {
+            batch_size.clamp(*bounds.start(), *bounds.end())
+        })
+    }
+
+    /// Records that a batch of `len` texts was embedded in `latency`.
+    pub fn record_success(&self, len: usize, latency: Duration, bounds: &RangeInclusive<usize>) {
+        let batch_size = self.batch_size(bounds);
+        let new_batch_size = if latency > SLOW_BATCH_LATENCY {
+            batch_size / 2
+        } else if latency < FAST_BATCH_LATENCY && len >= batch_size {
+            batch_size + (batch_size / 2).max(1)
+        } else {
+            batch_size
+        };
+        *self.batch_size.lock() = Some(new_batch_size.clamp(*bounds.start(), *bounds.end()));
+    }
+
+    /// Records that a batch failed to embed, which shrinks the batch size if the
+    /// provider reported being overloaded or rate limited.
+    pub fn record_failure(&self, error: &anyhow::Error, bounds: &RangeInclusive<usize>) {
+        if is_overloaded(error) {
+            let batch_size = self.batch_size(bounds) / 2;
+            *self.batch_size.lock() = Some(batch_size.clamp(*bounds.start(), *bounds.end()));
+        }
+    }
+}
+
+/// Whether the provider rejected a request because it has too much to do, rather than
+/// because of the request itself. Providers only report their status in error messages.
+fn is_overloaded(error: &anyhow::Error) -> bool {
+    let message = format!("{error:#}").to_lowercase();
+    [
+        "429",
+        "503",
+        "too many requests",
+        "service unavailable",
+        "overloaded",
+        "rate limit",
+    ]
+    .iter()
+    .any(|pattern| message.contains(pattern))
+}
+
+/// An [`EmbeddingProvider`] that sends batches of the size that an [`AdaptiveBatchSize`]
+/// settled on, and adjusts it to how each batch fared.
+pub(crate) struct AdaptiveBatchProvider {
+    provider: Arc<dyn EmbeddingProvider>,
+    batch_size: Arc<AdaptiveBatchSize>,
+    bounds: RangeInclusive<usize>,
+}
+
+impl AdaptiveBatchProvider {
+    /// Batches are kept between `min_batch_size` and `max_batch_size`, and never exceed
+    /// what the provider accepts.
+    pub fn new(
+        provider: Arc<dyn EmbeddingProvider>,
+        batch_size: Arc<AdaptiveBatchSize>,
+        min_batch_size: usize,
+        max_batch_size: Option<usize>,
+    ) -> Self {
+        let provider_batch_size = provider.batch_size().max(1);
+        let max_batch_size = max_batch_size
+            .unwrap_or(provider_batch_size)
+            .clamp(1, provider_batch_size);
+        let min_batch_size = min_batch_size.clamp(1, max_batch_size);
+        Self {
+            provider,
+            batch_size,
+            bounds: min_batch_size..=max_batch_size,
+        }
+    }
+}
+
+impl EmbeddingProvider for AdaptiveBatchProvider {
+    fn embed<'a>(&'a self, texts: &'a [TextToEmbed<'a>]) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        async move {
+            let start = Instant::now();
+            let result = self.provider.embed(texts).await;
+            match &result {
+                Ok(_) => self
+                    .batch_size
+                    .record_success(texts.len(), start.elapsed(), &self.bounds),
+                Err(error) => self.batch_size.record_failure(error, &self.bounds),
+            }
+            result
+        }
+        .boxed()
+    }
+
+    fn batch_size(&self) -> usize {
+        self.batch_size.batch_size(&self.bounds)
+    }
+
+    fn max_text_len(&self) -> Option<usize> {
+        self.provider.max_text_len()
+    }
+
+    fn max_request_len(&self) -> Option<usize> {
+        self.provider.max_request_len()
+    }
+
+    fn similarity_metric(&self) -> SimilarityMetric {
+        self.provider.similarity_metric()
+    }
+
+    fn model_metadata(&self) -> Option<EmbeddingModelMetadata> {
+        self.provider.model_metadata()
+    }
+
+    fn document_template(&self) -> DocumentTemplate {
+        self.provider.document_template()
+    }
+
+    fn is_remote(&self) -> bool {
+        self.provider.is_remote()
+    }
+
+    fn verify(&self) -> BoxFuture<'_, Result<()>> {
+        self.provider.verify()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+
+    #[test]
+    fn test_adaptive_batch_size() {
+        let bounds = 8..=64;
+        let batch_size = AdaptiveBatchSize::default();
+        assert_eq!(batch_size.batch_size(&bounds), 64);
+
+        batch_size.record_failure(
+            &anyhow!("error during embedding, status: 503 Service Unavailable"),
+            &bounds,
+        );
+        assert_eq!(batch_size.batch_size(&bounds), 32);
+        batch_size.record_failure(&anyhow!("status: 429 Too Many Requests"), &bounds);
+        assert_eq!(batch_size.batch_size(&bounds), 16);
+        // Errors caused by the request itself don't affect the batch size.
+        batch_size.record_failure(&anyhow!("status: 400 Bad Request"), &bounds);
+        assert_eq!(batch_size.batch_size(&bounds), 16);
+
+        batch_size.record_success(16, Duration::from_secs(45), &bounds);
+        assert_eq!(batch_size.batch_size(&bounds), 8);
+        batch_size.record_success(8, Duration::from_secs(45), &bounds);
+        assert_eq!(batch_size.batch_size(&bounds), 8);
+
+        // Batches that weren't full say nothing about whether larger ones would be fast.
+        batch_size.record_success(3, Duration::from_secs(1), &bounds);
+        assert_eq!(batch_size.batch_size(&bounds), 8);
+        batch_size.record_success(8, Duration::from_secs(1), &bounds);
+        assert_eq!(batch_size.batch_size(&bounds), 12);
+        batch_size.record_success(12, Duration::from_secs(10), &bounds);
+        assert_eq!(batch_size.batch_size(&bounds), 12);
+        for _ in 0..10 {
+            let len = batch_size.batch_size(&bounds);
+            batch_size.record_success(len, Duration::from_secs(1), &bounds);
+        }
+        assert_eq!(batch_size.batch_size(&bounds), 64);
+
+        // Narrower bounds apply to the current batch size right away.
+        assert_eq!(batch_size.batch_size(&(1..=20)), 20);
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{
+    embed_in_batches, embedding_cache::embed_with_cache, redaction, ProjectIndex, SearchResult,
+    SearchResultSource, TextToEmbed, VectorStoreSettings,
+};
+use anyhow::{anyhow, Result};
+use gpui::{AppContext, Task};
+use settings::Settings;
+use std::{ops::Range, path::Path, sync::Arc};
+
+/// How many lines of a result are compared with the query at a time.
+const ANSWER_SPAN_LINES: usize = 3;
+/// The most windows of a result that are embedded, so that a long result doesn't cost an
+/// embedding per line.
+const MAX_ANSWER_SPAN_WINDOWS: usize = 32;
+
+impl ProjectIndex {
+    /// Finds the lines within each of `results` that are most similar to `query`, so that
+    /// they can be highlighted rather than only the start of the result. The lines are
+    /// found by embedding a window of a few lines at each line of the result, or at every
+    /// few lines of a long one, with the windows of all the results embedded together.
+    ///
+    /// Returns one range per result, which is the result's whole range when it's too
+    /// short to be narrowed down or its text can't be loaded, e.g. in a remote project.
+    pub fn answer_spans(
+        &self,
+        query: String,
+        results: &[SearchResult],
+        cx: &AppContext,
+    ) -> Task<Result<Vec<Range<usize>>>> {
+        let texts = results
+            .iter()
+            .map(|result| {
+                let range = result.range.clone();
+                let text = self.result_text(result, cx);
+                let path: Option<Arc<Path>> = match &result.source {
+                    SearchResultSource::File { path, .. } => Some(path.clone()),
+                    SearchResultSource::Document(_) => None,
+                };
+                async move { (path, range, text.await) }
+            })
+            .collect::<Vec<_>>();
+
+        let embedding_provider = self.embedding_provider.clone();
+        let embedding_cache = self.embedding_cache.clone();
+        let redact_secrets =
+            embedding_provider.is_remote() && VectorStoreSettings::get_global(cx).redact_secrets;
+        cx.background_executor().spawn(async move {
+            let similarity_metric = embedding_provider.similarity_metric();
+            let query_embedding = embed_with_cache(
+                embedding_provider.as_ref(),
+                &embedding_cache,
+                &[TextToEmbed::new(&query)],
+            )
+            .await?
+            .pop()
+            .map(|embedding| similarity_metric.prepare(embedding))
+            .ok_or_else(|| anyhow!("no embedding for query"))?;
+
+            let mut answer_spans = Vec::new();
+            let mut window_texts = Vec::new();
+            let mut result_windows = Vec::new();
+            for (path, range, text) in futures::future::join_all(texts).await {
+                let text = match text {
+                    Ok(Some(text)) => text,
+                    Ok(None) => {
+                        answer_spans.push(range);
+                        continue;
+                    }
+                    Err(error) => {
+                        log::warn!("failed to load search result: {error}");
+                        answer_spans.push(range);
+                        continue;
+                    }
+                };
+                let windows = line_windows(
+                    &text,
+                    range.clone(),
+                    ANSWER_SPAN_LINES,
+                    MAX_ANSWER_SPAN_WINDOWS,
+                );
+                answer_spans.push(range);
+                if windows.len() < 2 {
+                    continue;
+                }
+
+                let secrets = path
+                    .as_ref()
+                    .filter(|_| redact_secrets)
+                    .map(|path| redaction::Secrets::find(path, &text));
+                window_texts.extend(windows.iter().map(|window| match &secrets {
+                    Some(secrets) => secrets.redact(&text, window.clone()).into_owned(),
+                    None => text[window.clone()].to_string(),
+                }));
+                result_windows.push((answer_spans.len() - 1, windows));
+            }
+
+            let to_embed = window_texts
+                .iter()
+                .map(|text| TextToEmbed::new(text))
+                .collect::<Vec<_>>();
+            let mut embeddings = embed_in_batches(
+                embedding_provider.as_ref(),
+                &to_embed,
+                Some(query_embedding.len()),
+                similarity_metric,
+            )
+            .await
+            .into_iter();
+            for (ix, windows) in result_windows {
+                let window_count = windows.len();
+                let best_window = windows
+                    .into_iter()
+                    .zip(embeddings.by_ref().take(window_count))
+                    .filter_map(|(window, embedding)| {
+                        Some((
+                            window,
+                            similarity_metric.score(&embedding?, &query_embedding),
+                        ))
+                    })
+                    .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                    .map(|(window, _)| window);
+                if let Some(best_window) = best_window {
+                    answer_spans[ix] = best_window;
+                }
+            }
+            Ok(answer_spans)
+        })
+    }
+}
+
+/// Returns a window of `window_lines` lines starting at every line of `text` within
+/// `range`, up to the window that reaches the end of the range. A range with fewer lines
+/// than a window yields a single window covering all of it.
+///
+/// Beyond `max_windows` windows, they start every few lines instead, and are as long as
+/// the gap between them, with the last one reaching the end of the range.
+fn line_windows(
+    text: &str,
+    range: Range<usize>,
+    window_lines: usize,
+    max_windows: usize,
+) -> Vec<Range<usize>> {
+    let Some(range_text) = text.get(range.clone()) else {
+        return Vec::new();
+    };
+    let mut line_starts = vec![range.start];
+    line_starts.extend(
+        range_text
+            .match_indices('\n')
+            .map(|(ix, _)| range.start + ix + 1)
+            .filter(|&start| start < range.end),
+    );
+
+    let window_count = line_starts.len().saturating_sub(window_lines) + 1;
+    let stride = window_count.div_ceil(max_windows.max(1));
+    let window_lines = window_lines.max(stride);
+    let mut windows = (0..window_count)
+        .step_by(stride)
+        .map(|ix| {
+            let end = line_starts
+                .get(ix + window_lines)
+                .copied()
+                .unwrap_or(range.end);
+            line_starts[ix]..end
+        })
+        .collect::<Vec<_>>();
+    if let Some(last_window) = windows.last_mut() {
+        last_window.end = range.end;
+    }
+    windows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_windows() {
+        let text = "zero\none\ntwo\nthree\nfour\n";
+        assert_eq!(
+            line_windows(text, 5..text.len(), 2, 10),
+            vec![5..13, 9..19, 13..24]
+        );
+        assert_eq!(line_windows(text, 5..12, 3, 10), vec![5..12]);
+        assert_eq!(
+            line_windows(text, 0..100, 3, 10),
+            Vec::<Range<usize>>::new()
+        );
+
+        // Fewer windows start every other line and cover the lines in between.
+        assert_eq!(line_windows(text, 0..text.len(), 2, 2), vec![0..9, 9..24]);
+    }
+}
@@ -0,0 +1,238 @@
+//! A portable, single-file copy of the index of a worktree, so that an index built
+//! elsewhere, e.g. by CI for a large repository, can be downloaded instead of embedding
+//! every file again.
+//!
+//! An archive holds the [`IndexMetadata`] describing the model that produced it, followed
+//! by every [`EmbeddedFile`], with paths relative to the worktree's root. Each record is
+//! prefixed by its length.
+
+use crate::{
+    db_key_for_path, resolve_db_name, EmbeddedFile, IndexMetadata, ProjectIndex, METADATA_DB_NAME,
+};
+use anyhow::{anyhow, Context as _, Result};
+use gpui::{AppContext, Task};
+use heed::{
+    types::{SerdeBincode, Str},
+    BytesDecode, BytesEncode,
+};
+use project::WorktreeId;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+
+const ARCHIVE_MAGIC: &[u8; 8] = b"ZEDINDEX";
+
+/// Bump this whenever the layout of archives changes. The contents of the records are
+/// versioned by [`IndexMetadata`].
+const ARCHIVE_VERSION: u32 = 1;
+
+/// The largest record an archive may contain. Records are read into memory whole, so a
+/// corrupt or malicious length must not be trusted. This is far more than the embeddings
+/// of the largest files that are indexed need.
+const MAX_RECORD_LEN: u64 = 256 * 1024 * 1024;
+
+/// Writes the files of the database named `db_name` to an archive at `archive_path`,
+/// returning how many files were written.
+pub(crate) fn export_archive(
+    db_connection: &heed::Env,
+    db_name: &str,
+    archive_path: &Path,
+) -> Result<usize> {
+    let txn = db_connection
+        .read_txn()
+        .context("failed to create read transaction")?;
+    let metadata = db_connection
+        .open_database::<Str, SerdeBincode<IndexMetadata>>(&txn, Some(METADATA_DB_NAME))?
+        .and_then(|metadata_db| metadata_db.get(&txn, db_name).transpose())
+        .transpose()?
+        .ok_or_else(|| anyhow!("{db_name:?} hasn't been indexed"))?;
+    let db = db_connection
+        .open_database::<Str, SerdeBincode<EmbeddedFile>>(
+            &txn,
+            Some(&resolve_db_name(db_connection, &txn, db_name)?),
+        )?
+        .ok_or_else(|| anyhow!("{db_name:?} hasn't been indexed"))?;
+
+    let mut writer = BufWriter::new(
+        File::create(archive_path)
+            .with_context(|| format!("failed to create archive {archive_path:?}"))?,
+    );
+    writer.write_all(ARCHIVE_MAGIC)?;
+    writer.write_all(&ARCHIVE_VERSION.to_le_bytes())?;
+    write_record(&mut writer, &metadata)?;
+    let mut file_count = 0;
+    for entry in db.iter(&txn)? {
+        let (_, file) = entry?;
+        write_record(&mut writer, &file)?;
+        file_count += 1;
+    }
+    writer.flush()?;
+    Ok(file_count)
+}
+
+/// Replaces the files of the database named `db_name` with the ones in the archive at
+/// `archive_path`, returning how many files were imported. Fails if the archive's
+/// embeddings weren't produced the way `metadata` describes.
+///
+/// Files keep the modification times they had where the archive was built, so they're
+/// chunked again when their worktree is next scanned. Their embeddings are reused for
+/// every chunk whose contents didn't change.
+pub(crate) fn import_archive(
+    db_connection: &heed::Env,
+    txn: &mut heed::RwTxn,
+    db_name: &str,
+    archive_path: &Path,
+    metadata: &IndexMetadata,
+) -> Result<usize> {
+    let mut reader = BufReader::new(
+        File::open(archive_path)
+            .with_context(|| format!("failed to open archive {archive_path:?}"))?,
+    );
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != ARCHIVE_MAGIC {
+        return Err(anyhow!("{archive_path:?} isn't an index archive"));
+    }
+    let mut version = [0; 4];
+    reader.read_exact(&mut version)?;
+    let version = u32::from_le_bytes(version);
+    if version != ARCHIVE_VERSION {
+        return Err(anyhow!(
+            "unsupported index archive version {version}, expected {ARCHIVE_VERSION}"
+        ));
+    }
+    let archive_metadata: IndexMetadata =
+        read_record(&mut reader)?.context("index archive is empty")?;
+    if &archive_metadata != metadata {
+        return Err(anyhow!(
+            "index archive was built with {archive_metadata:?}, but this index uses {metadata:?}"
+        ));
+    }
+
+    let metadata_db: heed::Database<Str, SerdeBincode<IndexMetadata>> =
+        db_connection.create_database(txn, Some(METADATA_DB_NAME))?;
+    let location = resolve_db_name(db_connection, txn, db_name)?;
+    let db: heed::Database<Str, SerdeBincode<EmbeddedFile>> =
+        db_connection.create_database(txn, Some(&location))?;
+    db.clear(txn)?;
+    metadata_db.put(txn, db_name, metadata)?;
+    let mut file_count = 0;
+    while let Some(file) = read_record::<EmbeddedFile>(&mut reader)? {
+        db.put(txn, &db_key_for_path(&file.path), &file)?;
+        file_count += 1;
+    }
+    Ok(file_count)
+}
+
+fn write_record<T: Serialize + 'static>(writer: &mut impl Write, record: &T) -> Result<()> {
+    let bytes = SerdeBincode::<T>::bytes_encode(record).map_err(|error| anyhow!(error))?;
+    if bytes.len() as u64 > MAX_RECORD_LEN {
+        return Err(anyhow!(
+            "index archive record of {} bytes exceeds the maximum of {MAX_RECORD_LEN}",
+            bytes.len()
+        ));
+    }
+    writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    writer.write_all(&bytes)?;
+    Ok(())
+}
+
+/// Reads the next record, or returns `None` at the end of the archive.
+fn read_record<T: DeserializeOwned + 'static>(reader: &mut impl Read) -> Result<Option<T>> {
+    let mut len = [0; 8];
+    match reader.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(error) => return Err(error.into()),
+    }
+    let len = u64::from_le_bytes(len);
+    if len > MAX_RECORD_LEN {
+        return Err(anyhow!(
+            "index archive record of {len} bytes exceeds the maximum of {MAX_RECORD_LEN}"
+        ));
+    }
+    let mut bytes = vec![0; len as usize];
+    reader
+        .read_exact(&mut bytes)
+        .context("index archive is truncated")?;
+    let record = SerdeBincode::<T>::bytes_decode(&bytes).map_err(|error| anyhow!(error))?;
+    Ok(Some(record))
+}
+
+impl ProjectIndex {
+    /// Writes the index of a worktree to a single file, which can be imported into the
+    /// index of the same repository on another machine.
+    pub fn export_worktree(
+        &self,
+        worktree_id: WorktreeId,
+        archive_path: PathBuf,
+        cx: &AppContext,
+    ) -> Task<Result<usize>> {
+        let Some(index) = self.worktree_index(worktree_id, cx) else {
+            return Task::ready(Err(anyhow!("worktree {worktree_id:?} isn't indexed")));
+        };
+        let db_name = self.worktree_db_name(&index.read(cx).worktree.read(cx).abs_path());
+        let db_connection = self.db_connection.clone();
+        cx.background_executor()
+            .spawn(async move { export_archive(&db_connection, &db_name, &archive_path) })
+    }
+
+    /// Replaces the index of a worktree with an archive written by
+    /// [`ProjectIndex::export_worktree`] or the `zed-index` tool, then indexes the files
+    /// that changed since the archive was built.
+    pub fn import_worktree(
+        &self,
+        worktree_id: WorktreeId,
+        archive_path: PathBuf,
+        cx: &AppContext,
+    ) -> Task<Result<usize>> {
+        let Some(index) = self.worktree_index(worktree_id, cx) else {
+            return Task::ready(Err(anyhow!("worktree {worktree_id:?} isn't indexed")));
+        };
+        let db_name = self.worktree_db_name(&index.read(cx).worktree.read(cx).abs_path());
+        let metadata = IndexMetadata::for_provider(self.embedding_provider.as_ref());
+        let import = self.db_writer.write(move |db_connection, txn| {
+            import_archive(db_connection, txn, &db_name, &archive_path, &metadata)
+        });
+        cx.spawn(|cx| async move {
+            let file_count = import.await?;
+            index.read_with(&cx, |index, _| index.refresh())?;
+            Ok(file_count)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_records() {
+        let mut bytes = Vec::new();
+        write_record(&mut bytes, &"first".to_string()).unwrap();
+        write_record(&mut bytes, &vec![1u32, 2, 3]).unwrap();
+
+        let mut reader = bytes.as_slice();
+        assert_eq!(
+            read_record::<String>(&mut reader).unwrap().as_deref(),
+            Some("first")
+        );
+        assert_eq!(
+            read_record::<Vec<u32>>(&mut reader).unwrap(),
+            Some(vec![1, 2, 3])
+        );
+        assert_eq!(read_record::<String>(&mut reader).unwrap(), None);
+
+        let mut truncated = &bytes[..bytes.len() - 1];
+        read_record::<String>(&mut truncated).unwrap();
+        assert!(read_record::<Vec<u32>>(&mut truncated).is_err());
+
+        // A corrupt length fails instead of allocating that much memory.
+        let oversized_len = u64::MAX.to_le_bytes();
+        let mut oversized = oversized_len.as_slice();
+        assert!(read_record::<String>(&mut oversized).is_err());
+    }
+}
@@ -0,0 +1,69 @@
+use crate::{db_key_for_path, Embedding, ProjectIndex};
+use anyhow::{Context as _, Result};
+use gpui::{AppContext, Model, Task};
+use language::{Anchor, Buffer};
+use project::WorktreeId;
+use sha2::{Digest, Sha256};
+use std::ops::Range;
+
+/// The embedding the index has for a span of an open buffer.
+pub struct SpanEmbedding {
+    pub range: Range<Anchor>,
+    /// The span of the outline item this span is the signature of, if any.
+    pub item_range: Option<Range<Anchor>>,
+    pub embedding: Embedding,
+}
+
+impl ProjectIndex {
+    /// Returns the embeddings the index has for the spans of a buffer, so that features
+    /// like finding similar code can build on the index without embedding the buffer
+    /// again. Returns no embeddings when the buffer's file hasn't been indexed, or when
+    /// the buffer's text differs from the text that was indexed.
+    pub fn embeddings_for_buffer(
+        &self,
+        buffer: &Model<Buffer>,
+        cx: &AppContext,
+    ) -> Task<Result<Vec<SpanEmbedding>>> {
+        let buffer = buffer.read(cx);
+        let Some(file) = buffer.file() else {
+            return Task::ready(Ok(Vec::new()));
+        };
+        let worktree_id = WorktreeId::from_usize(file.worktree_id());
+        let Some(index) = self.worktree_index(worktree_id, cx) else {
+            return Task::ready(Ok(Vec::new()));
+        };
+        let index = index.read(cx);
+        let connection = index.db_connection.clone();
+        let db = index.db;
+        let key = db_key_for_path(file.path());
+        let snapshot = buffer.text_snapshot();
+
+        cx.background_executor().spawn(async move {
+            let tx = connection
+                .read_txn()
+                .context("failed to create read transaction")?;
+            let Some(embedded_file) = db.get(&tx, &key)? else {
+                return Ok(Vec::new());
+            };
+            drop(tx);
+
+            let digest: [u8; 32] = Sha256::digest(snapshot.text()).into();
+            if digest != embedded_file.digest {
+                return Ok(Vec::new());
+            }
+            let anchor_range = |range: &Range<usize>| {
+                snapshot.anchor_before(range.start)..snapshot.anchor_after(range.end)
+            };
+            Ok(embedded_file
+                .chunks
+                .into_iter()
+                .filter(|chunk| chunk.chunk.range.end <= snapshot.len())
+                .map(|chunk| SpanEmbedding {
+                    range: anchor_range(&chunk.chunk.range),
+                    item_range: chunk.chunk.item_range.as_ref().map(anchor_range),
+                    embedding: chunk.embedding,
+                })
+                .collect())
+        })
+    }
+}
@@ -1,4 +1,6 @@
-use language::{with_parser, with_query_cursor, Language};
+use crate::vector_store_settings::{ChunkGranularity, ChunkingSettings};
+use collections::HashMap;
+use language::{with_parser, with_query_cursor, EmbeddingConfig, Language};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::{
@@ -7,7 +9,7 @@ use std::{
     path::Path,
     sync::Arc,
 };
-use tree_sitter::QueryCapture;
+use tree_sitter::Tree;
 use util::ResultExt as _;
 
 #[derive(Copy, Clone)]
@@ -16,19 +18,108 @@ struct ChunkSizeRange {
     max: usize,
 }
 
-const CHUNK_SIZE_RANGE: ChunkSizeRange = ChunkSizeRange {
-    min: 1024,
-    max: 8192,
-};
-
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Chunk {
     pub range: Range<usize>,
     pub digest: [u8; 32],
+    /// The outline items enclosing the start of this chunk, e.g. `impl Foo > fn bar`.
+    pub scope_path: Option<String>,
+    /// For a signature chunk, the range of the item whose signature and doc comment it
+    /// contains. The chunks of the item's body lie within this range.
+    pub item_range: Option<Range<usize>>,
+}
+
+/// A symbol in a file, as reported by a language server rather than an outline query.
+pub struct OutlineSymbol {
+    pub range: Range<usize>,
+    pub label: String,
+}
+
+pub struct ChunkedText {
+    pub chunks: Vec<Chunk>,
+    /// Chunks containing only the signature and doc comment of long items, which are
+    /// embedded in addition to [`ChunkedText::chunks`] so that queries describing what an
+    /// item does can match it even when its body is split across several chunks.
+    pub signature_chunks: Vec<Chunk>,
+    /// Describes the first syntax error in the text, if any. The text is still chunked,
+    /// but chunk boundaries may not line up with the items in the file.
+    pub syntax_error: Option<String>,
+}
+
+/// An outline item (type, function, etc.) that spans multiple lines.
+struct SyntacticItem {
+    range: Range<usize>,
+    label: String,
+    /// Where the item's body starts, for items whose grammar gives them one.
+    body_start: Option<usize>,
+}
+
+#[derive(Default)]
+struct SyntacticItems {
+    items: Vec<SyntacticItem>,
+    syntax_error: Option<String>,
+}
+
+pub fn chunk_text(
+    text: &str,
+    language: Option<&Arc<Language>>,
+    path: &Path,
+    settings: &ChunkingSettings,
+) -> ChunkedText {
+    chunk_with_granularity(text, settings, || {
+        syntactic_items(text, language, path).unwrap_or_default()
+    })
+}
+
+/// Whether [`chunk_text`] can split files in the given language along the items in
+/// their outline.
+pub fn has_outline_query(language: Option<&Arc<Language>>) -> bool {
+    language
+        .and_then(|language| language.grammar())
+        .map_or(false, |grammar| grammar.outline_config.is_some())
+}
+
+/// Chunks text along the given symbols, for languages without an outline query.
+pub fn chunk_text_with_symbols(
+    text: &str,
+    symbols: Vec<OutlineSymbol>,
+    settings: &ChunkingSettings,
+) -> ChunkedText {
+    chunk_with_granularity(text, settings, || symbol_items(text, symbols))
 }
 
-pub fn chunk_text(text: &str, language: Option<&Arc<Language>>, path: &Path) -> Vec<Chunk> {
-    chunk_text_with_size_range(text, language, path, CHUNK_SIZE_RANGE)
+/// Chunks text at the granularity given by `settings`, only computing its items if
+/// they're needed.
+fn chunk_with_granularity(
+    text: &str,
+    settings: &ChunkingSettings,
+    items: impl FnOnce() -> SyntacticItems,
+) -> ChunkedText {
+    let size_config = ChunkSizeRange {
+        min: settings.min_chunk_size,
+        max: settings.max_chunk_size,
+    };
+    match settings.granularity {
+        ChunkGranularity::Items => chunk_syntactic_items(text, items(), size_config),
+        ChunkGranularity::File => {
+            chunk_syntactic_items(text, SyntacticItems::default(), size_config)
+        }
+        ChunkGranularity::Both => {
+            let mut chunked = chunk_syntactic_items(text, items(), size_config);
+            // Small files are a single chunk either way, which only needs embedding once.
+            let file_chunks = chunk_text_with_syntactic_ranges(text, &[], size_config)
+                .into_iter()
+                .filter(|file_chunk| {
+                    !chunked
+                        .chunks
+                        .iter()
+                        .any(|chunk| chunk.range == file_chunk.range)
+                })
+                .collect::<Vec<_>>();
+            chunked.chunks.extend(file_chunks);
+            chunked
+        }
+    }
 }
 
 fn chunk_text_with_size_range(
@@ -36,16 +127,106 @@ fn chunk_text_with_size_range(
     language: Option<&Arc<Language>>,
     path: &Path,
     size_config: ChunkSizeRange,
+) -> ChunkedText {
+    let items = syntactic_items(text, language, path).unwrap_or_default();
+    chunk_syntactic_items(text, items, size_config)
+}
+
+fn chunk_syntactic_items(
+    text: &str,
+    SyntacticItems {
+        items,
+        syntax_error,
+    }: SyntacticItems,
+    size_config: ChunkSizeRange,
+) -> ChunkedText {
+    let ranges = items
+        .iter()
+        .map(|item| item.range.clone())
+        .collect::<Vec<_>>();
+    let mut chunks = chunk_text_with_syntactic_ranges(text, &ranges, size_config);
+    for chunk in &mut chunks {
+        chunk.scope_path = scope_path(&items, chunk.range.start);
+    }
+    let signature_chunks = signature_chunks(text, &items, size_config);
+    ChunkedText {
+        chunks,
+        signature_chunks,
+        syntax_error,
+    }
+}
+
+/// Creates a chunk for the signature and doc comment of each item that's too long to be
+/// embedded along with its whole body.
+fn signature_chunks(
+    text: &str,
+    items: &[SyntacticItem],
+    size_config: ChunkSizeRange,
 ) -> Vec<Chunk> {
-    let ranges = syntactic_ranges(text, language, path).unwrap_or_default();
-    chunk_text_with_syntactic_ranges(text, &ranges, size_config)
+    items
+        .iter()
+        .filter_map(|item| {
+            let body_start = item.body_start?;
+            let range = item.range.start..body_start;
+            if item.range.len() <= size_config.min || range.len() > size_config.max {
+                return None;
+            }
+            Some(Chunk {
+                digest: Sha256::digest(&text[range.clone()]).into(),
+                scope_path: scope_path(items, body_start),
+                item_range: Some(item.range.clone()),
+                range,
+            })
+        })
+        .collect()
+}
+
+/// Joins the labels of the items that contain the given offset, from outermost to innermost.
+fn scope_path(items: &[SyntacticItem], offset: usize) -> Option<String> {
+    let mut scope_path = String::new();
+    for item in items {
+        if item.range.start >= offset {
+            break;
+        }
+        if item.range.end > offset && !item.label.is_empty() {
+            if !scope_path.is_empty() {
+                scope_path.push_str(" > ");
+            }
+            scope_path.push_str(&item.label);
+        }
+    }
+    if scope_path.is_empty() {
+        None
+    } else {
+        Some(scope_path)
+    }
 }
 
-fn syntactic_ranges(
+/// Finds the outline item of `text` whose scope path, including the item itself, is
+/// `scope_path`, so that a chunk that started inside of it can be found again after the
+/// text was edited. Of several such items, the one starting closest to `near` is returned.
+pub(crate) fn find_scope(
     text: &str,
     language: Option<&Arc<Language>>,
     path: &Path,
-) -> Option<Vec<Range<usize>>> {
+    scope_path_to_find: &str,
+    near: usize,
+) -> Option<Range<usize>> {
+    let items = syntactic_items(text, language, path)?.items;
+    items
+        .iter()
+        .filter(|item| {
+            scope_path(&items, item.range.start + 1).as_deref() == Some(scope_path_to_find)
+        })
+        .min_by_key(|item| item.range.start.abs_diff(near))
+        .map(|item| item.range.clone())
+}
+
+fn syntactic_items(
+    text: &str,
+    language: Option<&Arc<Language>>,
+    path: &Path,
+) -> Option<SyntacticItems> {
     let language = language?;
     let grammar = language.grammar()?;
     let outline = grammar.outline_config.as_ref()?;
@@ -56,8 +237,17 @@ fn syntactic_ranges(
 
     let Some(tree) = tree else {
         log::error!("failed to parse file {path:?} for chunking");
-        return None;
+        return Some(SyntacticItems {
+            items: Vec::new(),
+            syntax_error: Some("failed to parse file".into()),
+        });
     };
+    let syntax_error = first_syntax_error(&tree);
+    let context_starts = grammar
+        .embedding_config
+        .as_ref()
+        .map(|config| item_context_starts(config, &tree, text))
+        .unwrap_or_default();
 
     struct RowInfo {
         offset: usize,
@@ -82,40 +272,196 @@ fn syntactic_ranges(
         })
         .collect::<Vec<_>>();
 
-    // Retrieve a list of ranges of outline items (types, functions, etc) in the document.
+    // Retrieve a list of outline items (types, functions, etc) in the document.
     // Omit single-line outline items (e.g. struct fields, constant declarations), because
     // we'll already be attempting to split on lines.
-    let mut ranges = with_query_cursor(|cursor| {
+    let mut items = with_query_cursor(|cursor| {
         cursor
             .matches(&outline.query, tree.root_node(), text.as_bytes())
             .filter_map(|mat| {
-                mat.captures
+                let node = mat
+                    .captures
                     .iter()
-                    .find_map(|QueryCapture { node, index }| {
-                        if *index == outline.item_capture_ix {
-                            let mut start_offset = node.start_byte();
-                            let mut start_row = node.start_position().row;
-                            let end_offset = node.end_byte();
-                            let end_row = node.end_position().row;
-
-                            // Expand the range to include any preceding comments.
-                            while start_row > 0 && row_infos[start_row - 1].is_comment {
-                                start_offset = row_infos[start_row - 1].offset;
-                                start_row -= 1;
-                            }
-
-                            if end_row > start_row {
-                                return Some(start_offset..end_offset);
-                            }
+                    .find(|capture| capture.index == outline.item_capture_ix)?
+                    .node;
+                let mut start_offset = node.start_byte();
+                let mut start_row = node.start_position().row;
+                let end_offset = node.end_byte();
+                let end_row = node.end_position().row;
+                let body_start = node
+                    .child_by_field_name("body")
+                    .map(|body| body.start_byte())
+                    .filter(|body_start| *body_start > start_offset);
+
+                // Expand the range to include the item's context, which may have been
+                // captured for an enclosing node such as an export statement.
+                let mut ancestor = Some(node);
+                while let Some(enclosing_node) =
+                    ancestor.filter(|ancestor| ancestor.end_byte() == end_offset)
+                {
+                    if let Some(&(context_start, context_row)) =
+                        context_starts.get(&enclosing_node.start_byte())
+                    {
+                        if context_start < start_offset {
+                            start_offset = context_start;
+                            start_row = context_row;
                         }
-                        None
+                    }
+                    ancestor = enclosing_node.parent();
+                }
+
+                // Expand the range to include any preceding comments.
+                while start_row > 0 && row_infos[start_row - 1].is_comment {
+                    start_offset = row_infos[start_row - 1].offset;
+                    start_row -= 1;
+                }
+
+                if end_row <= start_row {
+                    return None;
+                }
+
+                // Label the item the way it appears in the outline, e.g. `impl Foo`.
+                let mut label_ranges = mat
+                    .captures
+                    .iter()
+                    .filter(|capture| {
+                        capture.index == outline.name_capture_ix
+                            || Some(capture.index) == outline.context_capture_ix
                     })
+                    .map(|capture| capture.node.byte_range())
+                    .collect::<Vec<_>>();
+                label_ranges.sort_unstable_by_key(|range| range.start);
+                let label = label_ranges
+                    .into_iter()
+                    .filter_map(|range| text[range].lines().next())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+
+                Some(SyntacticItem {
+                    range: start_offset..end_offset,
+                    label,
+                    body_start,
+                })
             })
             .collect::<Vec<_>>()
     });
 
-    ranges.sort_unstable_by_key(|range| (range.start, Reverse(range.end)));
-    Some(ranges)
+    items.sort_unstable_by_key(|item| (item.range.start, Reverse(item.range.end)));
+    Some(SyntacticItems {
+        items,
+        syntax_error,
+    })
+}
+
+/// Finds where the doc comments, attributes and decorators that the embedding query
+/// captures as `@context` of an item start, keyed by the start of the item. Context
+/// separated from the item by a blank line, like a license header, isn't included.
+fn item_context_starts(
+    config: &EmbeddingConfig,
+    tree: &Tree,
+    text: &str,
+) -> HashMap<usize, (usize, usize)> {
+    let mut context_starts = HashMap::default();
+    let Some(context_capture_ix) = config.context_capture_ix else {
+        return context_starts;
+    };
+
+    with_query_cursor(|cursor| {
+        for mat in cursor.matches(&config.query, tree.root_node(), text.as_bytes()) {
+            let Some(item) = mat
+                .captures
+                .iter()
+                .find(|capture| capture.index == config.item_capture_ix)
+                .map(|capture| capture.node)
+            else {
+                continue;
+            };
+            let mut contexts = mat
+                .captures
+                .iter()
+                .filter(|capture| {
+                    capture.index == context_capture_ix
+                        && capture.node.end_byte() <= item.start_byte()
+                })
+                .map(|capture| capture.node)
+                .collect::<Vec<_>>();
+            contexts.sort_unstable_by_key(|node| Reverse(node.start_byte()));
+
+            let mut start = (item.start_byte(), item.start_position().row);
+            for context in contexts {
+                let gap = text.get(context.end_byte()..start.0);
+                if gap.map_or(true, |gap| gap.matches('\n').count() > 1) {
+                    break;
+                }
+                start = (context.start_byte(), context.start_position().row);
+            }
+            if start.0 < item.start_byte() {
+                let context_start = context_starts.entry(item.start_byte()).or_insert(start);
+                if start.0 < context_start.0 {
+                    *context_start = start;
+                }
+            }
+        }
+    });
+    context_starts
+}
+
+fn symbol_items(text: &str, symbols: Vec<OutlineSymbol>) -> SyntacticItems {
+    // Like outline items, single-line symbols are omitted because we already split on lines.
+    let mut items = symbols
+        .into_iter()
+        .filter(|symbol| {
+            symbol.range.start < symbol.range.end
+                && symbol.range.end <= text.len()
+                && text
+                    .get(symbol.range.clone())
+                    .map_or(false, |symbol_text| symbol_text.trim_end().contains('\n'))
+        })
+        .map(|symbol| SyntacticItem {
+            range: symbol.range,
+            label: symbol.label,
+            body_start: None,
+        })
+        .collect::<Vec<_>>();
+    items.sort_unstable_by_key(|item| (item.range.start, Reverse(item.range.end)));
+    SyntacticItems {
+        items,
+        syntax_error: None,
+    }
+}
+
+fn first_syntax_error(tree: &Tree) -> Option<String> {
+    if !tree.root_node().has_error() {
+        return None;
+    }
+
+    let mut cursor = tree.walk();
+    loop {
+        let node = cursor.node();
+        if node.is_error() || node.is_missing() {
+            let position = node.start_position();
+            let kind = if node.is_missing() {
+                "missing syntax"
+            } else {
+                "syntax error"
+            };
+            return Some(format!(
+                "{kind} at {}:{}",
+                position.row + 1,
+                position.column + 1
+            ));
+        }
+
+        // Only descend into nodes that contain an error.
+        if node.has_error() && cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                return None;
+            }
+        }
+    }
 }
 
 fn chunk_text_with_syntactic_ranges(
@@ -152,6 +498,8 @@ fn chunk_text_with_syntactic_ranges(
             chunks.push(Chunk {
                 range: range.clone(),
                 digest: Sha256::digest(&text[range.clone()]).into(),
+                scope_path: None,
+                item_range: None,
             });
             range_end_nesting_depth = 0;
             range.start = range.end;
@@ -193,6 +541,8 @@ fn chunk_text_with_syntactic_ranges(
         chunks.push(Chunk {
             range: range.clone(),
             digest: Sha256::digest(&text[range]).into(),
+            scope_path: None,
+            item_range: None,
         });
     }
 
@@ -202,6 +552,7 @@ fn chunk_text_with_syntactic_ranges(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{DocumentTemplate, DocumentVariables, TextToEmbed};
     use language::{tree_sitter_rust, Language, LanguageConfig, LanguageMatcher};
     use unindent::Unindent as _;
 
@@ -246,7 +597,8 @@ mod tests {
                 min: text.find('}').unwrap(),
                 max: text.find("Self {").unwrap(),
             },
-        );
+        )
+        .chunks;
 
         // The entire impl cannot fit in a chunk, so it is split.
         // Within the impl, two methods can fit in a chunk.
@@ -280,7 +632,8 @@ mod tests {
                 min: text.find('{').unwrap(),
                 max: text.find('V').unwrap(),
             },
-        );
+        )
+        .chunks;
 
         // Two single-line structs can fit in a chunk.
         // The last struct cannot fit in a chunk together
@@ -297,6 +650,196 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_chunk_scope_paths() {
+        let language = rust_language();
+
+        let text = "
+            struct Person {
+                first_name: String,
+                age: u32,
+            }
+
+            impl Person {
+                fn new(first_name: String, age: u32) -> Self {
+                    Self { first_name, age }
+                }
+
+                fn first_name(&self) -> &str {
+                    &self.first_name
+                }
+            }
+        "
+        .unindent();
+
+        let chunks = chunk_text_with_size_range(
+            &text,
+            Some(&language),
+            Path::new("lib.rs"),
+            ChunkSizeRange {
+                min: text.find('}').unwrap(),
+                max: text.find("Self {").unwrap(),
+            },
+        )
+        .chunks;
+
+        assert_chunks(
+            &text,
+            &chunks,
+            &["struct Person {", "impl Person {", "    fn first_name"],
+        );
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| chunk.scope_path.as_deref())
+                .collect::<Vec<_>>(),
+            &[None, None, Some("impl Person")]
+        );
+        let document = DocumentTemplate::default().render(&DocumentVariables {
+            path: Path::new("lib.rs"),
+            language: None,
+            scope: chunks[2].scope_path.as_deref(),
+            contents: &text[chunks[2].range.clone()],
+        });
+        assert!(document.starts_with("impl Person\n    fn first_name"));
+        assert_ne!(
+            TextToEmbed::new(&document).digest,
+            <[u8; 32]>::from(Sha256::digest(&text[chunks[2].range.clone()]))
+        );
+    }
+
+    #[test]
+    fn test_signature_chunks() {
+        let language = rust_language();
+
+        let text = "
+            fn full_name(person: &Person) -> String {
+                let mut name = person.first_name.clone();
+                name.push(' ');
+                name.push_str(&person.last_name);
+                name
+            }
+
+            fn age(person: &Person) -> u32 {
+                person.age
+            }
+        "
+        .unindent();
+
+        let chunked = chunk_text_with_size_range(
+            &text,
+            Some(&language),
+            Path::new("lib.rs"),
+            ChunkSizeRange {
+                min: text.find("fn age").unwrap() / 2,
+                max: text.len(),
+            },
+        );
+
+        // Only the item that's longer than the minimum chunk size gets a signature chunk.
+        assert_eq!(chunked.signature_chunks.len(), 1);
+        let signature_chunk = &chunked.signature_chunks[0];
+        assert_eq!(
+            &text[signature_chunk.range.clone()],
+            "fn full_name(person: &Person) -> String "
+        );
+        assert_eq!(signature_chunk.scope_path.as_deref(), Some("fn full_name"));
+        assert_eq!(
+            signature_chunk.item_range,
+            Some(0..text.find("\n}").unwrap() + 2)
+        );
+    }
+
+    #[test]
+    fn test_find_scope() {
+        let language = rust_language();
+        let text = "
+            impl Person {
+                fn new() -> Self {
+                    todo!()
+                }
+            }
+
+            impl Pet {
+                fn new() -> Self {
+                    todo!()
+                }
+            }
+        "
+        .unindent();
+        let path = Path::new("lib.rs");
+
+        let second_new = text.rfind("fn new").unwrap();
+        let range = find_scope(&text, Some(&language), path, "impl Pet > fn new", 0).unwrap();
+        assert_eq!(range.start, second_new);
+        let range = find_scope(&text, Some(&language), path, "impl Person", second_new).unwrap();
+        assert_eq!(range.start, 0);
+        assert_eq!(
+            find_scope(&text, Some(&language), path, "impl Animal", 0),
+            None
+        );
+        assert_eq!(find_scope(&text, None, path, "impl Person", 0), None);
+    }
+
+    #[test]
+    fn test_chunk_text_with_symbols() {
+        let text = "
+            struct Person {
+                first_name: String,
+                age: u32,
+            }
+
+            impl Person {
+                fn new(first_name: String, age: u32) -> Self {
+                    Self { first_name, age }
+                }
+
+                fn first_name(&self) -> &str {
+                    &self.first_name
+                }
+            }
+        "
+        .unindent();
+
+        let symbol = |start: &str, end: &str, label: &str| {
+            let start = text.find(start).unwrap();
+            OutlineSymbol {
+                range: start..start + text[start..].find(end).unwrap() + end.len(),
+                label: label.into(),
+            }
+        };
+        let symbols = vec![
+            symbol("impl Person", "\n}", "impl Person"),
+            symbol("struct Person", "\n}", "Person"),
+            symbol("fn new", "    }", "new"),
+            symbol("fn first_name", "    }", "first_name"),
+            symbol("age: u32,", ",", "age"),
+        ];
+
+        let chunks = chunk_syntactic_items(
+            &text,
+            symbol_items(&text, symbols),
+            ChunkSizeRange {
+                min: text.find('}').unwrap(),
+                max: text.find("Self {").unwrap(),
+            },
+        )
+        .chunks;
+
+        assert_chunks(
+            &text,
+            &chunks,
+            &["struct Person {", "impl Person {", "    fn first_name"],
+        );
+        assert_eq!(
+            chunks
+                .iter()
+                .map(|chunk| chunk.scope_path.as_deref())
+                .collect::<Vec<_>>(),
+            &[None, None, Some("impl Person")]
+        );
+    }
+
     #[test]
     fn test_chunk_with_long_lines() {
         let language = rust_language();
@@ -314,7 +857,8 @@ mod tests {
             Some(&language),
             Path::new("lib.rs"),
             ChunkSizeRange { min: 32, max: 64 },
-        );
+        )
+        .chunks;
 
         // The line is too long to fit in one chunk
         assert_chunks(
@@ -380,13 +924,260 @@ mod tests {
     #[test]
     fn test_chunk_text() {
         let text = "a\n".repeat(1000);
-        let chunks = chunk_text(&text, None, Path::new("lib.rs"));
+        let settings = ChunkingSettings::default();
+        let chunks = chunk_text(&text, None, Path::new("lib.rs"), &settings).chunks;
         assert_eq!(
             chunks.len(),
-            ((2000_f64) / (CHUNK_SIZE_RANGE.max as f64)).ceil() as usize
+            ((2000_f64) / (settings.max_chunk_size as f64)).ceil() as usize
         );
     }
 
+    #[test]
+    fn test_chunk_text_granularity() {
+        let text = "
+            fn first() {
+                one();
+                two();
+            }
+
+            fn second() {
+                three();
+                four();
+            }
+        "
+        .unindent();
+        let language = rust_language();
+        let chunk = |granularity| {
+            let settings = ChunkingSettings {
+                min_chunk_size: 1,
+                max_chunk_size: text.find("    three").unwrap(),
+                granularity,
+            };
+            chunk_text(&text, Some(&language), Path::new("lib.rs"), &settings).chunks
+        };
+
+        let items = chunk(ChunkGranularity::Items);
+        assert_chunks(&text, &items, &["fn first", "fn second"]);
+
+        // Without items, chunks are only split where they exceed the maximum size.
+        let file = chunk(ChunkGranularity::File);
+        assert_chunks(&text, &file, &["fn first", "    three"]);
+        assert!(file.iter().all(|chunk| chunk.scope_path.is_none()));
+
+        let both = chunk(ChunkGranularity::Both);
+        let ranges = |chunks: &[Chunk]| {
+            chunks
+                .iter()
+                .map(|chunk| chunk.range.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ranges(&both), [ranges(&items), ranges(&file)].concat());
+    }
+
+    #[test]
+    fn test_chunk_text_with_syntax_error() {
+        let language = rust_language();
+
+        let text = "
+            struct Person {
+                name: String,
+            }
+
+            fn broken( {
+        "
+        .unindent();
+        let settings = ChunkingSettings::default();
+        let chunked = chunk_text(&text, Some(&language), Path::new("lib.rs"), &settings);
+        assert_eq!(chunked.chunks.len(), 1);
+        assert!(chunked.syntax_error.is_some());
+
+        let chunked = chunk_text(
+            "struct Person {}\n",
+            Some(&language),
+            Path::new("lib.rs"),
+            &settings,
+        );
+        assert_eq!(chunked.syntax_error, None);
+    }
+
+    #[test]
+    fn test_item_context_python() {
+        let language = languages::language("python", tree_sitter_python::language());
+        let text = "
+            # Not about the class.
+
+            @dataclass
+            class Person:
+                name: str
+
+                @property
+                @cache
+                def greeting(self):
+                    return f\"Hello, {self.name}\"
+        "
+        .unindent();
+
+        assert_eq!(
+            item_first_lines(&text, &language, "person.py"),
+            &["@dataclass", "@property"]
+        );
+    }
+
+    #[test]
+    fn test_item_context_typescript() {
+        let language =
+            languages::language("typescript", tree_sitter_typescript::language_typescript());
+        let text = "
+            /**
+             * Greets a person.
+             */
+            export function greet(name: string) {
+                return `Hello, ${name}`;
+            }
+
+            class Greeter {
+                /** Greets a person twice. */
+                greetTwice(name: string) {
+                    return greet(name) + greet(name);
+                }
+            }
+        "
+        .unindent();
+
+        assert_eq!(
+            item_first_lines(&text, &language, "greet.ts"),
+            &["/**", "class Greeter {", "/** Greets a person twice. */"]
+        );
+    }
+
+    #[test]
+    fn test_item_context_rust() {
+        let language = languages::language("rust", tree_sitter_rust::language());
+        let text = "
+            // Not about the struct.
+
+            /// A person.
+            #[derive(Debug)]
+            struct Person {
+                name: String,
+            }
+        "
+        .unindent();
+
+        assert_eq!(
+            item_first_lines(&text, &language, "person.rs"),
+            &["/// A person."]
+        );
+    }
+
+    fn item_first_lines<'a>(text: &'a str, language: &Arc<Language>, path: &str) -> Vec<&'a str> {
+        syntactic_items(text, Some(language), Path::new(path))
+            .unwrap()
+            .items
+            .iter()
+            .map(|item| text[item.range.clone()].lines().next().unwrap())
+            .collect()
+    }
+
+    /// Chunks every file in `test_data/chunking/<language>` and compares its chunks with
+    /// the ones listed in the `.chunks` file next to it, so that changes to chunking or to
+    /// a language's queries that move chunk boundaries are caught. To add a fixture, or
+    /// after an intended change, run with `UPDATE_CHUNKING_FIXTURES=1` and review the
+    /// `.chunks` files that were written.
+    #[test]
+    fn test_chunking_fixtures() {
+        let update = std::env::var_os("UPDATE_CHUNKING_FIXTURES").is_some();
+        let fixtures_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data/chunking");
+        let mut mismatches = Vec::new();
+        for language_dir in sorted_dir_entries(&fixtures_path) {
+            let language_name = language_dir.file_name().unwrap().to_string_lossy();
+            let language = fixture_language(&language_name);
+            for path in sorted_dir_entries(&language_dir) {
+                if path
+                    .extension()
+                    .map_or(false, |extension| extension == "chunks")
+                {
+                    continue;
+                }
+                let text = std::fs::read_to_string(&path).unwrap();
+                let chunked = chunk_text_with_size_range(
+                    &text,
+                    language.as_ref(),
+                    &path,
+                    ChunkSizeRange { min: 64, max: 256 },
+                );
+                check_chunk_invariants(&text, &chunked.chunks);
+                let actual = describe_chunks(&text, &chunked);
+
+                let mut expected_path = path.clone().into_os_string();
+                expected_path.push(".chunks");
+                if update {
+                    std::fs::write(&expected_path, &actual).unwrap();
+                    continue;
+                }
+                let expected = std::fs::read_to_string(&expected_path).unwrap_or_else(|_| {
+                    panic!("{expected_path:?} is missing, run with UPDATE_CHUNKING_FIXTURES=1")
+                });
+                if actual != expected {
+                    mismatches.push(format!(
+                        "{path:?}\nexpected:\n{expected}\nactual:\n{actual}"
+                    ));
+                }
+            }
+        }
+        assert!(
+            mismatches.is_empty(),
+            "chunks differ from the fixtures, run with UPDATE_CHUNKING_FIXTURES=1 to update \
+             them if that's intended:\n\n{}",
+            mismatches.join("\n")
+        );
+    }
+
+    /// The language whose fixtures are in the directory with the given name.
+    fn fixture_language(name: &str) -> Option<Arc<Language>> {
+        match name {
+            "plain_text" => None,
+            "python" => Some(languages::language(
+                "python",
+                tree_sitter_python::language(),
+            )),
+            "rust" => Some(languages::language("rust", tree_sitter_rust::language())),
+            "typescript" => Some(languages::language(
+                "typescript",
+                tree_sitter_typescript::language_typescript(),
+            )),
+            _ => panic!("no grammar for chunking fixtures in {name:?}"),
+        }
+    }
+
+    fn sorted_dir_entries(path: &Path) -> Vec<std::path::PathBuf> {
+        let mut entries = std::fs::read_dir(path)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        entries.sort();
+        entries
+    }
+
+    /// Lists the range and first line of each chunk, one per line, along with its scope.
+    fn describe_chunks(text: &str, chunked: &ChunkedText) -> String {
+        let mut description = String::new();
+        for (kind, chunks) in [
+            ("chunk", &chunked.chunks),
+            ("signature", &chunked.signature_chunks),
+        ] {
+            for chunk in chunks {
+                let first_line = text[chunk.range.clone()].lines().next().unwrap_or_default();
+                description.push_str(&format!("{kind} {:?} {first_line:?}", chunk.range));
+                if let Some(scope_path) = &chunk.scope_path {
+                    description.push_str(&format!(" in {scope_path:?}"));
+                }
+                description.push('\n');
+            }
+        }
+        description
+    }
+
     fn rust_language() -> Arc<Language> {
         Arc::new(
             Language::new(
@@ -402,9 +1193,9 @@ mod tests {
             )
             .with_outline_query(
                 "
-            (function_item name: (_) @name) @item
-            (impl_item type: (_) @name) @item
-            (struct_item name: (_) @name) @item
+            (function_item \"fn\" @context name: (_) @name) @item
+            (impl_item \"impl\" @context type: (_) @name) @item
+            (struct_item \"struct\" @context name: (_) @name) @item
             (field_declaration name: (_) @name) @item
         ",
             )
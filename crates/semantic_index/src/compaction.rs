@@ -0,0 +1,185 @@
+//! Reclaiming the disk space of deleted embeddings. The database file never shrinks, as
+//! the pages of deleted entries are only reused by later writes, so after large deletions,
+//! such as when switching branches or excluding paths, a compacted copy of the database
+//! is written in the background. The copy replaces the database the next time it's
+//! opened, unless the database was written to after the copy was taken.
+
+use crate::VectorStoreSettings;
+use anyhow::{Context as _, Result};
+use gpui::{AsyncAppContext, Task};
+use heed::CompactionOption;
+use settings::Settings;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use util::ResultExt;
+
+/// How often to check how much of the database is unused.
+const COMPACTION_CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+const DB_FILE_NAME: &str = "data.mdb";
+/// The compacted copy, once it's been fully written.
+const COMPACTED_DB_FILE_NAME: &str = "data.mdb.compacted";
+/// The compacted copy while it's being written.
+const PARTIAL_DB_FILE_NAME: &str = "data.mdb.compacting";
+/// The id of the database's last transaction when the compacted copy was taken.
+const COMPACTED_TXN_ID_FILE_NAME: &str = "compacted_txn_id";
+
+/// Compacts the database whenever the space left unused by deletions exceeds the
+/// thresholds in the settings.
+pub(crate) fn compact_periodically(
+    db_connection: heed::Env,
+    db_path: PathBuf,
+    cx: &AsyncAppContext,
+) -> Task<()> {
+    cx.spawn(|cx| async move {
+        loop {
+            cx.background_executor()
+                .timer(COMPACTION_CHECK_INTERVAL)
+                .await;
+            let Ok((min_unused_size, min_unused_ratio)) = cx.update(|cx| {
+                let settings = VectorStoreSettings::get_global(cx);
+                (
+                    settings.compaction_threshold(),
+                    settings.compaction_threshold_ratio,
+                )
+            }) else {
+                break;
+            };
+            let Some(min_unused_size) = min_unused_size else {
+                continue;
+            };
+            let db_connection = db_connection.clone();
+            let db_path = db_path.clone();
+            cx.background_executor()
+                .spawn(async move {
+                    compact_if_unused(&db_connection, &db_path, min_unused_size, min_unused_ratio)
+                })
+                .await
+                .context("failed to compact database")
+                .log_err();
+        }
+    })
+}
+
+/// Writes a compacted copy of the database when at least `min_unused_size` bytes and
+/// `min_unused_ratio` of it are unused. Returns whether it did.
+fn compact_if_unused(
+    db_connection: &heed::Env,
+    db_path: &Path,
+    min_unused_size: u64,
+    min_unused_ratio: f32,
+) -> Result<bool> {
+    let size = db_connection.real_disk_size()?;
+    let unused_size = size.saturating_sub(db_connection.non_free_pages_size()?);
+    if unused_size < min_unused_size || (unused_size as f64) < size as f64 * min_unused_ratio as f64
+    {
+        return Ok(false);
+    }
+
+    log::info!("compacting database at {db_path:?}, {unused_size} of {size} bytes are unused");
+    let partial_path = db_path.join(PARTIAL_DB_FILE_NAME);
+    fs::remove_file(&partial_path).ok();
+    // Reading the transaction id before copying means that any write racing with the copy
+    // makes the copy look outdated, rather than losing that write.
+    let txn_id = db_connection.info().last_txn_id;
+    db_connection
+        .copy_to_file(&partial_path, CompactionOption::Enabled)
+        .context("failed to copy database")?;
+    fs::write(db_path.join(COMPACTED_TXN_ID_FILE_NAME), txn_id.to_string())?;
+    fs::rename(&partial_path, db_path.join(COMPACTED_DB_FILE_NAME))?;
+    Ok(true)
+}
+
+/// Replaces the database that was just opened with its compacted copy, if there's one
+/// that's up to date, and opens the copy with `open` instead. Outdated copies are deleted.
+pub(crate) fn replace_with_compacted_copy(
+    db_connection: heed::Env,
+    db_path: &Path,
+    open: impl FnOnce(&Path) -> Result<heed::Env>,
+) -> Result<heed::Env> {
+    let compacted_path = db_path.join(COMPACTED_DB_FILE_NAME);
+    let txn_id_path = db_path.join(COMPACTED_TXN_ID_FILE_NAME);
+    if !compacted_path.exists() {
+        return Ok(db_connection);
+    }
+
+    let compacted_txn_id = fs::read_to_string(&txn_id_path)
+        .ok()
+        .and_then(|txn_id| txn_id.trim().parse::<usize>().ok());
+    if compacted_txn_id != Some(db_connection.info().last_txn_id) {
+        log::info!("discarding outdated compacted copy of database at {db_path:?}");
+        fs::remove_file(&compacted_path).log_err();
+        fs::remove_file(&txn_id_path).log_err();
+        return Ok(db_connection);
+    }
+
+    db_connection.prepare_for_closing().wait();
+    fs::rename(&compacted_path, db_path.join(DB_FILE_NAME))
+        .context("failed to replace database with its compacted copy")?;
+    fs::remove_file(&txn_id_path).log_err();
+    log::info!("replaced database at {db_path:?} with its compacted copy");
+    open(db_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::open_db;
+    use heed::types::{Bytes, Str};
+
+    #[test]
+    fn test_compaction() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_path = temp_dir.path();
+        let db_connection = open_db(db_path).unwrap();
+        let mut txn = db_connection.write_txn().unwrap();
+        let db: heed::Database<Str, Bytes> = db_connection
+            .create_database(&mut txn, Some("worktree"))
+            .unwrap();
+        for ix in 0..1000 {
+            db.put(&mut txn, &format!("file-{ix}"), &[0; 4096]).unwrap();
+        }
+        db.put(&mut txn, "kept", b"kept").unwrap();
+        txn.commit().unwrap();
+        let mut txn = db_connection.write_txn().unwrap();
+        for ix in 0..1000 {
+            db.delete(&mut txn, &format!("file-{ix}")).unwrap();
+        }
+        txn.commit().unwrap();
+
+        assert!(!compact_if_unused(&db_connection, db_path, u64::MAX, 0.).unwrap());
+        assert!(compact_if_unused(&db_connection, db_path, 0, 0.5).unwrap());
+        let size = fs::metadata(db_path.join(DB_FILE_NAME)).unwrap().len();
+        db_connection.prepare_for_closing().wait();
+
+        let db_connection = open_db(db_path).unwrap();
+        assert!(!db_path.join(COMPACTED_DB_FILE_NAME).exists());
+        assert!(fs::metadata(db_path.join(DB_FILE_NAME)).unwrap().len() < size);
+        let txn = db_connection.read_txn().unwrap();
+        let db: heed::Database<Str, Bytes> = db_connection
+            .open_database(&txn, Some("worktree"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(db.get(&txn, "kept").unwrap(), Some(&b"kept"[..]));
+        assert_eq!(db.len(&txn).unwrap(), 1);
+        drop(txn);
+
+        // A copy taken before the database was written to again is discarded.
+        assert!(compact_if_unused(&db_connection, db_path, 0, 0.).unwrap());
+        let mut txn = db_connection.write_txn().unwrap();
+        db.put(&mut txn, "added", b"added").unwrap();
+        txn.commit().unwrap();
+        db_connection.prepare_for_closing().wait();
+
+        let db_connection = open_db(db_path).unwrap();
+        assert!(!db_path.join(COMPACTED_DB_FILE_NAME).exists());
+        let txn = db_connection.read_txn().unwrap();
+        let db: heed::Database<Str, Bytes> = db_connection
+            .open_database(&txn, Some("worktree"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(db.get(&txn, "added").unwrap(), Some(&b"added"[..]));
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{multi_query::result_key, ProjectIndex, SearchResult, SearchResultStream};
+use anyhow::{anyhow, Result};
+use collections::HashMap;
+use futures::{future::try_join_all, StreamExt};
+use gpui::{AppContext, Model, Task};
+use language::{Anchor, Buffer};
+use project::WorktreeId;
+use std::{cmp::Ordering, collections::hash_map::Entry, hash::Hash, ops::Range};
+
+/// The most spans of a buffer that are searched for at once.
+const MAX_SOURCE_SPANS: usize = 16;
+
+/// A result of searching a project for the spans of a buffer from another project.
+#[derive(Clone)]
+pub struct CrossProjectSearchResult {
+    /// The span of the source buffer that the result is most similar to.
+    pub source_range: Range<Anchor>,
+    /// Where the result was found in the searched project.
+    pub result: SearchResult,
+}
+
+impl ProjectIndex {
+    /// Searches the project of `target` for code similar to spans of a buffer from the
+    /// project of `source`, e.g. to find where a pattern from one repository is used in
+    /// another. Without `ranges`, the chunks the source index has for the buffer are
+    /// searched for.
+    ///
+    /// Each span is searched for on its own with the target's embedding model, so
+    /// projects indexed with different models can be searched against each other. A
+    /// result found for several spans is attributed to the span it matched best, and the
+    /// `limit` best results are returned ordered by score.
+    pub fn search_with_spans(
+        target: &Model<ProjectIndex>,
+        source: &Model<ProjectIndex>,
+        buffer: &Model<Buffer>,
+        ranges: Vec<Range<Anchor>>,
+        limit: usize,
+        cx: &AppContext,
+    ) -> Task<Result<Vec<CrossProjectSearchResult>>> {
+        let source_index = source.read(cx);
+        let is_source_buffer = buffer.read(cx).file().map_or(false, |file| {
+            source_index
+                .worktree_index(WorktreeId::from_usize(file.worktree_id()), cx)
+                .is_some()
+        });
+        if !is_source_buffer {
+            return Task::ready(Err(anyhow!(
+                "buffer doesn't belong to an indexed worktree of the source project"
+            )));
+        }
+
+        let source_ranges = if ranges.is_empty() {
+            let embeddings = source_index.embeddings_for_buffer(buffer, cx);
+            cx.background_executor().spawn(async move {
+                Ok(embeddings
+                    .await?
+                    .into_iter()
+                    .map(|span| span.range)
+                    .collect::<Vec<_>>())
+            })
+        } else {
+            Task::ready(Ok(ranges))
+        };
+        let snapshot = buffer.read(cx).snapshot();
+        let target = target.clone();
+        cx.spawn(|cx| async move {
+            let mut source_ranges = source_ranges.await?;
+            source_ranges.truncate(MAX_SOURCE_SPANS);
+            let searches = target.read_with(&cx, |index, cx| {
+                source_ranges
+                    .into_iter()
+                    .filter_map(|range| {
+                        let text = snapshot.text_for_range(range.clone()).collect::<String>();
+                        if text.trim().is_empty() {
+                            return None;
+                        }
+                        let SearchResultStream {
+                            results: mut result_batches,
+                            task,
+                        } = index.search_stream(text, limit, None, 0., cx);
+                        Some(async move {
+                            let mut results = Vec::new();
+                            while let Some(batch) = result_batches.next().await {
+                                results = batch;
+                            }
+                            task.await?;
+                            anyhow::Ok((range, results))
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            })?;
+            let result_lists = try_join_all(searches).await?;
+            Ok(
+                merge_by_best_score(result_lists, result_key, |result| result.score, limit)
+                    .into_iter()
+                    .map(|(source_range, result)| CrossProjectSearchResult {
+                        source_range,
+                        result,
+                    })
+                    .collect(),
+            )
+        })
+    }
+}
+
+/// Merges the results of several searches, each labeled with what was searched for, into
+/// the `limit` results with the highest scores. Of the results with the same key, the one
+/// with the highest score is kept, along with the label of its search.
+fn merge_by_best_score<S: Clone, T, K: Eq + Hash>(
+    result_lists: Vec<(S, Vec<T>)>,
+    key: impl Fn(&T) -> K,
+    score: impl Fn(&T) -> f32,
+    limit: usize,
+) -> Vec<(S, T)> {
+    let mut merged = HashMap::<K, (S, T)>::default();
+    for (label, results) in result_lists {
+        for result in results {
+            match merged.entry(key(&result)) {
+                Entry::Occupied(mut entry) => {
+                    if score(&result) > score(&entry.get().1) {
+                        entry.insert((label.clone(), result));
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert((label.clone(), result));
+                }
+            }
+        }
+    }
+
+    let mut merged = merged.into_values().collect::<Vec<_>>();
+    merged.sort_by(|(_, a), (_, b)| score(b).partial_cmp(&score(a)).unwrap_or(Ordering::Equal));
+    merged.truncate(limit);
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_by_best_score() {
+        let result_lists = vec![
+            ("first span", vec![("a", 0.9), ("b", 0.5)]),
+            ("second span", vec![("b", 0.7), ("c", 0.6)]),
+            ("third span", vec![("a", 0.8)]),
+        ];
+        assert_eq!(
+            merge_by_best_score(
+                result_lists.clone(),
+                |(key, _)| *key,
+                |(_, score)| *score,
+                10
+            ),
+            [
+                ("first span", ("a", 0.9)),
+                ("second span", ("b", 0.7)),
+                ("second span", ("c", 0.6)),
+            ]
+        );
+        assert_eq!(
+            merge_by_best_score(result_lists, |(key, _)| *key, |(_, score)| *score, 1),
+            [("first span", ("a", 0.9))]
+        );
+    }
+}
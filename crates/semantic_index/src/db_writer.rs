@@ -0,0 +1,169 @@
+use anyhow::{anyhow, Context as _, Result};
+use futures::channel::oneshot;
+use gpui::BackgroundExecutor;
+use smol::channel;
+use std::{future::Future, thread};
+
+/// How many queued writes are committed together in a single transaction.
+const MAX_BATCHED_WRITES: usize = 64;
+
+type Write = Box<dyn FnOnce(&heed::Env, &mut heed::RwTxn) -> Completion + Send>;
+type Completion = Box<dyn FnOnce(Option<&anyhow::Error>) + Send>;
+
+/// Serializes every write to the database on a dedicated thread.
+///
+/// LMDB only allows one write transaction at a time, so indexing tasks that each opened
+/// their own would block executor threads waiting on one another, and committing every
+/// small write separately syncs the database to disk each time. Instead, writes are
+/// queued here and the writes queued at the same time are committed together on a thread
+/// that owns the write side of the database, so no executor thread ever waits on LMDB's
+/// write lock. Readers are never blocked, since they read a snapshot of the memory-mapped
+/// database.
+#[derive(Clone)]
+pub(crate) struct DbWriter {
+    writes_tx: channel::Sender<Write>,
+}
+
+impl DbWriter {
+    pub fn new(db_connection: heed::Env, executor: &BackgroundExecutor) -> Self {
+        if !cfg!(test) {
+            return Self::on_thread(db_connection);
+        }
+
+        // Tests commit writes on the deterministic executor, so that running it until it's
+        // parked also waits for every queued write.
+        let (writes_tx, writes_rx) = channel::unbounded::<Write>();
+        executor
+            .spawn(async move {
+                while let Ok(write) = writes_rx.recv().await {
+                    commit_writes(&db_connection, write, &writes_rx);
+                }
+            })
+            .detach();
+        Self { writes_tx }
+    }
+
+    /// Creates a writer that commits writes on a thread of its own, which exits once every
+    /// handle to the writer has been dropped. Unlike [`DbWriter::new`], this doesn't need an
+    /// executor, so directories can be indexed without the app running.
+    pub fn on_thread(db_connection: heed::Env) -> Self {
+        let (writes_tx, writes_rx) = channel::unbounded::<Write>();
+        thread::Builder::new()
+            .name("semantic index db writer".into())
+            .spawn(move || {
+                while let Ok(write) = writes_rx.recv_blocking() {
+                    commit_writes(&db_connection, write, &writes_rx);
+                }
+            })
+            .expect("failed to spawn database writer thread");
+        Self { writes_tx }
+    }
+
+    /// Runs `write` in a transaction that is committed along with any other writes
+    /// queued at the same time. If `write` returns an error, none of its changes are
+    /// committed, but the other writes in the batch are unaffected.
+    pub fn write<R, F>(&self, write: F) -> impl Future<Output = Result<R>>
+    where
+        R: Send + 'static,
+        F: FnOnce(&heed::Env, &mut heed::RwTxn) -> Result<R> + Send + 'static,
+    {
+        let (result_tx, result_rx) = oneshot::channel();
+        let write: Write = Box::new(move |db_connection, parent_txn| {
+            let result = db_connection
+                .nested_write_txn(parent_txn)
+                .context("failed to create write transaction")
+                .and_then(|mut txn| {
+                    let value = write(db_connection, &mut txn)?;
+                    txn.commit()?;
+                    Ok(value)
+                });
+            Box::new(move |commit_error| {
+                let result = match commit_error {
+                    Some(error) => Err(anyhow!("{error:?}")),
+                    None => result,
+                };
+                result_tx.send(result).ok();
+            })
+        });
+        let queued = self.writes_tx.try_send(write).is_ok();
+        async move {
+            if !queued {
+                return Err(anyhow!("database writer was dropped"));
+            }
+            result_rx
+                .await
+                .map_err(|_| anyhow!("database write was dropped"))?
+        }
+    }
+}
+
+/// Commits `write` together with the writes queued after it, up to [`MAX_BATCHED_WRITES`].
+fn commit_writes(db_connection: &heed::Env, write: Write, writes_rx: &channel::Receiver<Write>) {
+    let mut writes = vec![write];
+    while writes.len() < MAX_BATCHED_WRITES {
+        let Ok(write) = writes_rx.try_recv() else {
+            break;
+        };
+        writes.push(write);
+    }
+
+    let mut txn = match db_connection.write_txn() {
+        Ok(txn) => txn,
+        Err(error) => {
+            log::error!("failed to create write transaction: {error:?}");
+            return;
+        }
+    };
+    let completions = writes
+        .into_iter()
+        .map(|write| write(db_connection, &mut txn))
+        .collect::<Vec<_>>();
+    let commit_error = txn
+        .commit()
+        .context("failed to commit write transaction")
+        .err();
+    log::debug!("committed {} writes", completions.len());
+    for completion in completions {
+        completion(commit_error.as_ref());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gpui::TestAppContext;
+    use heed::types::Str;
+
+    #[gpui::test]
+    async fn test_failed_write_does_not_affect_batch(cx: &mut TestAppContext) {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_connection = unsafe {
+            heed::EnvOpenOptions::new()
+                .max_dbs(1)
+                .open(temp_dir.path())
+                .unwrap()
+        };
+        let db_writer = DbWriter::new(db_connection.clone(), &cx.executor());
+        let db = db_writer
+            .write(|db_connection, txn| {
+                Ok(db_connection.create_database::<Str, Str>(txn, Some("test"))?)
+            })
+            .await
+            .unwrap();
+
+        let first = db_writer.write(move |_, txn| Ok(db.put(txn, "a", "1")?));
+        let failed = db_writer.write(move |_, txn| {
+            db.put(txn, "b", "2")?;
+            Err::<(), _>(anyhow!("write failed"))
+        });
+        let last = db_writer.write(move |_, txn| Ok(db.put(txn, "c", "3")?));
+        first.await.unwrap();
+        assert!(failed.await.is_err());
+        last.await.unwrap();
+
+        let txn = db_connection.read_txn().unwrap();
+        assert_eq!(db.get(&txn, "a").unwrap(), Some("1"));
+        assert_eq!(db.get(&txn, "b").unwrap(), None);
+        assert_eq!(db.get(&txn, "c").unwrap(), Some("3"));
+    }
+}
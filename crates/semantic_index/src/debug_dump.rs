@@ -0,0 +1,137 @@
+use crate::{
+    EmbeddingModelMetadata, IndexingTimings, ProjectIndex, SemanticIndex, Status, WorktreeIndex,
+};
+use anyhow::{Context as _, Result};
+use gpui::{actions, AppContext, Task, ViewContext};
+use serde::Serialize;
+use std::{path::Path, sync::Arc};
+use workspace::Workspace;
+
+actions!(semantic_index, [DebugDump]);
+
+/// A report of everything stored in a project's index.
+#[derive(Debug, Serialize)]
+pub struct IndexDump {
+    pub status: Status,
+    pub embedding_model: Option<EmbeddingModelMetadata>,
+    /// The time spent in each stage of indexing across all worktrees, since the project
+    /// was opened.
+    pub timings: IndexingTimings,
+    pub worktrees: Vec<WorktreeIndexDump>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorktreeIndexDump {
+    pub abs_path: Arc<Path>,
+    pub document_count: usize,
+    pub timings: IndexingTimings,
+    pub files: Vec<FileIndexDump>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileIndexDump {
+    pub path: Arc<Path>,
+    pub document_count: usize,
+    /// The distinct sizes of the file's embeddings. Anything but a single value that
+    /// matches the model's dimensions means the file can't be found by searches.
+    pub dimensions: Vec<usize>,
+    pub syntax_error: Option<String>,
+    /// The file holding the embeddings for this one, which has the same contents.
+    pub duplicate_of: Option<Arc<Path>>,
+}
+
+impl ProjectIndex {
+    /// Collects a report of the files stored in the index, to help diagnose why
+    /// a file doesn't show up in search results.
+    pub fn debug_dump(&self, cx: &AppContext) -> Task<Result<IndexDump>> {
+        let status = self.status();
+        let embedding_model = self.embedding_provider.model_metadata();
+        let worktree_dumps = self
+            .worktree_indices(cx)
+            .into_iter()
+            .map(|index| index.read(cx).debug_dump(cx))
+            .collect::<Vec<_>>();
+        cx.background_executor().spawn(async move {
+            let worktrees = futures::future::try_join_all(worktree_dumps).await?;
+            let mut timings = IndexingTimings::default();
+            for worktree in &worktrees {
+                timings.merge(&worktree.timings);
+            }
+            Ok(IndexDump {
+                status,
+                embedding_model,
+                timings,
+                worktrees,
+            })
+        })
+    }
+}
+
+impl WorktreeIndex {
+    fn debug_dump(&self, cx: &AppContext) -> Task<Result<WorktreeIndexDump>> {
+        let abs_path = self.worktree.read(cx).abs_path();
+        let timings = self.pipeline_stats.timings();
+        let db_connection = self.db_connection.clone();
+        let db = self.db;
+        cx.background_executor().spawn(async move {
+            let txn = db_connection
+                .read_txn()
+                .context("failed to create read transaction")?;
+            let mut files = Vec::new();
+            for db_entry in db.iter(&txn).context("failed to iterate database")? {
+                let (_, file) = db_entry?;
+                let mut dimensions = file
+                    .chunks
+                    .iter()
+                    .map(|chunk| chunk.embedding.len())
+                    .collect::<Vec<_>>();
+                dimensions.sort_unstable();
+                dimensions.dedup();
+                files.push(FileIndexDump {
+                    document_count: file.chunks.len(),
+                    dimensions,
+                    path: file.path,
+                    syntax_error: file.syntax_error,
+                    duplicate_of: file.duplicate_of,
+                });
+            }
+            drop(txn);
+
+            Ok(WorktreeIndexDump {
+                abs_path,
+                document_count: files.iter().map(|file| file.document_count).sum(),
+                timings,
+                files,
+            })
+        })
+    }
+}
+
+pub(crate) fn dump_project_index(
+    workspace: &mut Workspace,
+    _: &DebugDump,
+    cx: &mut ViewContext<Workspace>,
+) {
+    if !cx.has_global::<SemanticIndex>() {
+        return;
+    }
+
+    let project = workspace.project().clone();
+    let fs = project.read(cx).fs().clone();
+    let project_index =
+        cx.update_global(|index: &mut SemanticIndex, cx| index.project_index(project, cx));
+    let dump = project_index.read(cx).debug_dump(cx);
+    cx.spawn(|workspace, mut cx| async move {
+        let report = serde_json::to_string_pretty(&dump.await?)?;
+        let report_path = paths::LOGS_DIR.join("semantic-index-dump.json");
+        fs.create_dir(&paths::LOGS_DIR).await?;
+        fs.atomic_write(report_path.clone(), report).await?;
+        workspace
+            .update(&mut cx, |workspace, cx| {
+                workspace.open_abs_path(report_path, false, cx)
+            })?
+            .await?;
+        anyhow::Ok(())
+    })
+    .detach_and_log_err(cx);
+}
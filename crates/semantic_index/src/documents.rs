@@ -0,0 +1,289 @@
+use crate::{
+    chunking::{chunk_text, ChunkedText},
+    clear_stale_embeddings,
+    db_writer::DbWriter,
+    payload_limits::embed_within_limits,
+    redaction, ChunkOwner, DocumentVariables, EmbeddedChunk, IndexMetadata, ProjectIndex,
+    SemanticIndex, TextToEmbed, VectorStoreSettings,
+};
+use anyhow::{anyhow, Context as _, Result};
+use collections::HashSet;
+use gpui::{AppContext, Model, Task};
+use heed::types::{SerdeBincode, Str};
+use project::Project;
+use serde::{Deserialize, Serialize};
+use settings::Settings;
+use smol::channel;
+use std::{borrow::Cow, path::Path, sync::Arc};
+
+/// The database holding the documents that don't come from a worktree, keyed by
+/// [`DocumentId::db_key`].
+pub(crate) const DOCUMENTS_DB_NAME: &str = "documents";
+
+/// Where a document that isn't a file in a worktree comes from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DocumentSource {
+    Conversation,
+    ProjectNote,
+    ChannelMessage,
+}
+
+impl DocumentSource {
+    fn name(&self) -> &'static str {
+        match self {
+            DocumentSource::Conversation => "conversation",
+            DocumentSource::ProjectNote => "project_note",
+            DocumentSource::ChannelMessage => "channel_message",
+        }
+    }
+}
+
+/// Identifies a document that isn't a file in a worktree.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct DocumentId {
+    pub source: DocumentSource,
+    /// Identifies the document among the others from the same source, e.g. the path
+    /// of a saved conversation. It's also used as the document's path when embedding it.
+    pub key: Arc<str>,
+}
+
+impl DocumentId {
+    pub fn new(source: DocumentSource, key: impl Into<Arc<str>>) -> Self {
+        Self {
+            source,
+            key: key.into(),
+        }
+    }
+
+    fn db_key(&self) -> String {
+        format!("{}\0{}", self.source.name(), self.key)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct EmbeddedDocument {
+    id: DocumentId,
+    text: String,
+    /// The worktrees of the project the document was added for. Only the projects with
+    /// one of them return it in their search results.
+    worktree_roots: Vec<Arc<Path>>,
+    chunks: Vec<EmbeddedChunk>,
+}
+
+type DocumentsDb = heed::Database<Str, SerdeBincode<EmbeddedDocument>>;
+
+impl SemanticIndex {
+    /// Chunks and embeds a document that doesn't live in a worktree, such as an assistant
+    /// conversation, so that it shows up in the search results of `project`, and of the
+    /// other projects that have one of its worktrees open.
+    ///
+    /// Indexing a document again replaces its previous contents and project.
+    pub fn index_document(
+        &self,
+        id: DocumentId,
+        text: String,
+        project: &Model<Project>,
+        cx: &AppContext,
+    ) -> Task<Result<()>> {
+        let worktree_roots = project_worktree_roots(project, cx);
+        let db_connection = self.db_connection.clone();
+        let db_writer = self.db_writer.clone();
+        let embedding_provider = self.embedding_provider.clone();
+        let settings = VectorStoreSettings::get_global(cx);
+        let redact_secrets = embedding_provider.is_remote() && settings.redact_secrets;
+        let chunking = settings.chunking_for_language(None);
+        cx.background_executor().spawn(async move {
+            let metadata = IndexMetadata::for_provider(embedding_provider.as_ref());
+            let db = open_documents_db(&db_writer, metadata).await?;
+            let db_key = id.db_key();
+            {
+                let txn = db_connection
+                    .read_txn()
+                    .context("failed to create read transaction")?;
+                if db.get(&txn, &db_key)?.map_or(false, |document| {
+                    document.text == text && document.worktree_roots == worktree_roots
+                }) {
+                    return Ok(());
+                }
+            }
+
+            let path = Path::new(id.key.as_ref());
+            let ChunkedText { chunks, .. } = chunk_text(&text, None, path, &chunking);
+            let secrets = redact_secrets.then(|| redaction::Secrets::find(path, &text));
+            let document_template = embedding_provider.document_template();
+            let documents = chunks
+                .iter()
+                .map(|chunk| {
+                    let contents = match &secrets {
+                        Some(secrets) => secrets.redact(&text, chunk.range.clone()),
+                        None => Cow::Borrowed(&text[chunk.range.clone()]),
+                    };
+                    document_template.render(&DocumentVariables {
+                        path,
+                        language: None,
+                        scope: chunk.scope_path.as_deref(),
+                        contents: &contents,
+                    })
+                })
+                .collect::<Vec<_>>();
+            let texts_to_embed = documents
+                .iter()
+                .map(|document| TextToEmbed::new(document))
+                .collect::<Vec<_>>();
+
+            let similarity_metric = embedding_provider.similarity_metric();
+            let dimensions = metadata.model.as_ref().map(|model| model.dimensions);
+            let batch_size = embedding_provider.batch_size();
+            let mut embedded_chunks = Vec::with_capacity(chunks.len());
+            for (chunk_batch, text_batch) in chunks
+                .chunks(batch_size)
+                .zip(texts_to_embed.chunks(batch_size))
+            {
+                let embeddings = embed_within_limits(embedding_provider.as_ref(), text_batch).await?;
+                if embeddings.len() != text_batch.len() {
+                    return Err(anyhow!(
+                        "embedding provider returned unexpected embedding count {}, expected {}",
+                        embeddings.len(),
+                        text_batch.len()
+                    ));
+                }
+                for (chunk, embedding) in chunk_batch.iter().zip(embeddings) {
+                    if let Some(dimensions) = dimensions {
+                        if embedding.len() != dimensions {
+                            return Err(anyhow!(
+                                "embedding provider returned an embedding with {} dimensions, expected {}",
+                                embedding.len(),
+                                dimensions
+                            ));
+                        }
+                    }
+                    embedded_chunks.push(EmbeddedChunk {
+                        chunk: chunk.clone(),
+                        embedding: similarity_metric.prepare(embedding),
+                    });
+                }
+            }
+
+            let document = EmbeddedDocument {
+                id,
+                text,
+                worktree_roots,
+                chunks: embedded_chunks,
+            };
+            db_writer
+                .write(move |_, txn| Ok(db.put(txn, &db_key, &document)?))
+                .await
+        })
+    }
+
+    /// Removes a document added with [`SemanticIndex::index_document`].
+    pub fn remove_document(&self, id: DocumentId, cx: &AppContext) -> Task<Result<()>> {
+        let db_writer = self.db_writer.clone();
+        let metadata = IndexMetadata::for_provider(self.embedding_provider.as_ref());
+        cx.background_executor().spawn(async move {
+            let db = open_documents_db(&db_writer, metadata).await?;
+            db_writer
+                .write(move |_, txn| {
+                    db.delete(txn, &id.db_key())?;
+                    Ok(())
+                })
+                .await
+        })
+    }
+}
+
+impl ProjectIndex {
+    /// Returns the text of a document added with [`SemanticIndex::index_document`], which
+    /// the ranges of its search results refer to.
+    pub fn load_document(&self, id: DocumentId, cx: &AppContext) -> Task<Result<Option<String>>> {
+        let db_connection = self.db_connection.clone();
+        cx.background_executor().spawn(async move {
+            let txn = db_connection
+                .read_txn()
+                .context("failed to create read transaction")?;
+            let Some(db) = db_connection.open_database::<Str, SerdeBincode<EmbeddedDocument>>(
+                &txn,
+                Some(DOCUMENTS_DB_NAME),
+            )?
+            else {
+                return Ok(None);
+            };
+            Ok(db.get(&txn, &id.db_key())?.map(|document| document.text))
+        })
+    }
+}
+
+async fn open_documents_db(db_writer: &DbWriter, metadata: IndexMetadata) -> Result<DocumentsDb> {
+    db_writer
+        .write(move |db_connection, txn| {
+            let db: DocumentsDb = db_connection.create_database(txn, Some(DOCUMENTS_DB_NAME))?;
+            clear_stale_embeddings(db_connection, txn, DOCUMENTS_DB_NAME, db, &metadata)?;
+            Ok(db)
+        })
+        .await
+}
+
+/// The root paths of the local worktrees of a project, which documents are added for.
+pub(crate) fn project_worktree_roots(project: &Model<Project>, cx: &AppContext) -> Vec<Arc<Path>> {
+    project
+        .read(cx)
+        .visible_worktrees(cx)
+        .filter(|worktree| worktree.read(cx).is_local())
+        .map(|worktree| worktree.read(cx).abs_path())
+        .collect()
+}
+
+/// Sends the chunks of the stored documents added for a project with one of
+/// `worktree_roots`, or only of the given documents among them, to be scored by a search.
+pub(crate) fn scan_documents(
+    db_connection: heed::Env,
+    documents: Option<HashSet<DocumentId>>,
+    worktree_roots: Vec<Arc<Path>>,
+    chunks_tx: channel::Sender<(ChunkOwner, EmbeddedChunk)>,
+    cx: &AppContext,
+) -> Task<Result<()>> {
+    cx.background_executor().spawn(async move {
+        let txn = db_connection
+            .read_txn()
+            .context("failed to create read transaction")?;
+        let Some(db) = db_connection
+            .open_database::<Str, SerdeBincode<EmbeddedDocument>>(&txn, Some(DOCUMENTS_DB_NAME))?
+        else {
+            return Ok(());
+        };
+        let in_project = |document: &EmbeddedDocument| {
+            document
+                .worktree_roots
+                .iter()
+                .any(|root| worktree_roots.contains(root))
+        };
+        if let Some(documents) = documents {
+            for id in documents {
+                if let Some(document) = db.get(&txn, &id.db_key())? {
+                    if in_project(&document) {
+                        send_document_chunks(document, &chunks_tx).await?;
+                    }
+                }
+            }
+        } else {
+            for db_entry in db.iter(&txn).context("failed to iterate database")? {
+                let (_key, document) = db_entry?;
+                if in_project(&document) {
+                    send_document_chunks(document, &chunks_tx).await?;
+                }
+            }
+        }
+        Ok(())
+    })
+}
+
+async fn send_document_chunks(
+    document: EmbeddedDocument,
+    chunks_tx: &channel::Sender<(ChunkOwner, EmbeddedChunk)>,
+) -> Result<()> {
+    let owner = ChunkOwner::Document(document.id);
+    for chunk in document.chunks {
+        chunks_tx.send((owner.clone(), chunk)).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use crate::db_key_for_path;
+use collections::HashMap;
+use parking_lot::Mutex;
+use std::{
+    ops::{Bound, RangeBounds},
+    path::Path,
+    sync::Arc,
+};
+
+/// Tracks which file of a worktree holds the embeddings for each distinct file contents.
+///
+/// Vendored dependencies, copies and symlinks often make the same contents appear at
+/// several paths. Only the first of them is chunked and embedded, and the others are
+/// stored as duplicates that refer to it, which keeps the index small. Searches score
+/// the duplicates with its embeddings, so every copy is still returned.
+#[derive(Default)]
+pub(crate) struct ContentIndex {
+    state: Mutex<ContentIndexState>,
+}
+
+#[derive(Default)]
+struct ContentIndexState {
+    paths_by_digest: HashMap<[u8; 32], Arc<Path>>,
+    digests_by_path: HashMap<Arc<Path>, [u8; 32]>,
+}
+
+impl ContentIndex {
+    /// Returns the path of the file holding the embeddings for `digest`, after making
+    /// `path` that file if there's none yet.
+    pub fn claim(&self, digest: [u8; 32], path: &Arc<Path>) -> Arc<Path> {
+        self.state
+            .lock()
+            .paths_by_digest
+            .entry(digest)
+            .or_insert_with(|| path.clone())
+            .clone()
+    }
+
+    /// Records that the embeddings of `path` were stored for the contents with `digest`.
+    /// Returns whether it held embeddings for other contents before, which other files
+    /// may still refer to.
+    pub fn update(&self, path: &Arc<Path>, digest: [u8; 32]) -> bool {
+        let mut state = self.state.lock();
+        let previous_digest = state.digests_by_path.insert(path.clone(), digest);
+        state
+            .paths_by_digest
+            .entry(digest)
+            .or_insert_with(|| path.clone());
+        match previous_digest {
+            Some(previous_digest) if previous_digest != digest => {
+                state.release(previous_digest, path);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Forgets the files whose database keys are in `range`. Returns whether any of them
+    /// held embeddings, which other files may still refer to.
+    pub fn remove_range(&self, range: &(Bound<&str>, Bound<&str>)) -> bool {
+        let mut state = self.state.lock();
+        let in_range = |path: &Arc<Path>| range.contains(db_key_for_path(path).as_str());
+        state.paths_by_digest.retain(|_, path| !in_range(path));
+        let path_count = state.digests_by_path.len();
+        state.digests_by_path.retain(|path, _| !in_range(path));
+        state.digests_by_path.len() < path_count
+    }
+}
+
+impl ContentIndexState {
+    fn release(&mut self, digest: [u8; 32], path: &Path) {
+        if self
+            .paths_by_digest
+            .get(&digest)
+            .map_or(false, |canonical_path| canonical_path.as_ref() == path)
+        {
+            self.paths_by_digest.remove(&digest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claim() {
+        let index = ContentIndex::default();
+        let a: Arc<Path> = Path::new("a.rs").into();
+        let b: Arc<Path> = Path::new("vendor/a.rs").into();
+        assert_eq!(index.claim([1; 32], &a), a);
+        assert_eq!(index.claim([1; 32], &b), a);
+        assert_eq!(index.claim([2; 32], &b), b);
+        assert!(!index.update(&a, [1; 32]));
+
+        // Changing the contents of a file releases the ones it had.
+        assert!(index.update(&a, [3; 32]));
+        assert!(!index.update(&a, [3; 32]));
+        assert_eq!(index.claim([1; 32], &b), b);
+
+        // Once the file holding the embeddings is gone, the next copy takes its place.
+        assert!(index.remove_range(&(Bound::Included("a.rs"), Bound::Included("a.rs"))));
+        assert!(!index.remove_range(&(Bound::Included("a.rs"), Bound::Included("a.rs"))));
+        let c: Arc<Path> = Path::new("c.rs").into();
+        assert_eq!(index.claim([3; 32], &c), c);
+    }
+}
@@ -7,10 +7,10 @@ pub use ollama::*;
 pub use open_ai::*;
 use sha2::{Digest, Sha256};
 
-use anyhow::Result;
+use anyhow::{anyhow, Context as _, Result};
 use futures::{future::BoxFuture, FutureExt};
 use serde::{Deserialize, Serialize};
-use std::{fmt, future};
+use std::{borrow::Cow, fmt, future, path::Path};
 
 #[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Embedding(Vec<f32>);
@@ -32,11 +32,50 @@ impl Embedding {
         Self(embedding)
     }
 
-    fn len(&self) -> usize {
+    /// Creates an embedding from the vector returned by a model, without normalizing it.
+    pub fn unnormalized(embedding: Vec<f32>) -> Self {
+        Self(embedding)
+    }
+
+    /// Scales the embedding to unit length.
+    pub(crate) fn normalize(self) -> Self {
+        Self::new(self.0)
+    }
+
+    /// Whether the embedding has unit length, up to rounding errors.
+    pub(crate) fn is_normalized(&self) -> bool {
+        (self.similarity(self) - 1.).abs() < 1e-3
+    }
+
+    /// Averages `embeddings`, weighting each of them by the given weight, without
+    /// normalizing the result.
+    pub(crate) fn weighted_average(
+        embeddings: impl IntoIterator<Item = (Embedding, usize)>,
+    ) -> Self {
+        let mut sum = Vec::new();
+        let mut total_weight = 0;
+        for (embedding, weight) in embeddings {
+            if sum.is_empty() {
+                sum = vec![0f32; embedding.len()];
+            }
+            for (sum, value) in sum.iter_mut().zip(embedding.0) {
+                *sum += value * weight as f32;
+            }
+            total_weight += weight;
+        }
+        if total_weight > 0 {
+            for sum in &mut sum {
+                *sum /= total_weight as f32;
+            }
+        }
+        Self(sum)
+    }
+
+    pub(crate) fn len(&self) -> usize {
         self.0.len()
     }
 
-    pub fn similarity(self, other: &Embedding) -> f32 {
+    pub fn similarity(&self, other: &Embedding) -> f32 {
         debug_assert_eq!(self.0.len(), other.0.len());
         self.0
             .iter()
@@ -45,6 +84,49 @@ impl Embedding {
             .map(|(a, b)| a * b)
             .sum()
     }
+
+    fn distance(&self, other: &Embedding) -> f32 {
+        debug_assert_eq!(self.0.len(), other.0.len());
+        self.0
+            .iter()
+            .copied()
+            .zip(other.0.iter().copied())
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum::<f32>()
+            .sqrt()
+    }
+}
+
+/// How embeddings are compared to each other when searching.
+///
+/// Not every embedding model produces normalized vectors, so the metric is chosen
+/// for each model and recorded alongside the stored embeddings.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SimilarityMetric {
+    /// Embeddings are normalized before they're stored, which makes the cosine
+    /// similarity equal to the dot product.
+    #[default]
+    Cosine,
+    DotProduct,
+    /// Scores are the negated euclidean distance, so that higher is still better.
+    Euclidean,
+}
+
+impl SimilarityMetric {
+    /// Prepares an embedding returned by a provider to be stored or compared.
+    pub fn prepare(self, embedding: Embedding) -> Embedding {
+        match self {
+            SimilarityMetric::Cosine => Embedding::new(embedding.0),
+            SimilarityMetric::DotProduct | SimilarityMetric::Euclidean => embedding,
+        }
+    }
+
+    pub fn score(self, a: &Embedding, b: &Embedding) -> f32 {
+        match self {
+            SimilarityMetric::Cosine | SimilarityMetric::DotProduct => a.similarity(b),
+            SimilarityMetric::Euclidean => -a.distance(b),
+        }
+    }
 }
 
 impl fmt::Display for Embedding {
@@ -68,10 +150,195 @@ impl fmt::Display for Embedding {
     }
 }
 
+/// Describes the vectors produced by an embedding model.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbeddingModelMetadata {
+    pub name: String,
+    pub dimensions: usize,
+    /// Whether the model returns vectors that already have unit length. Providers of
+    /// such models are checked to return them, and embeddings averaged from the parts
+    /// of a long text are normalized to match.
+    pub normalized: bool,
+    /// How the model's embeddings are compared when searching.
+    pub similarity_metric: SimilarityMetric,
+}
+
+/// Embedding models we know about: name, dimensions, whether they're normalized, and
+/// the metric their embeddings are compared with. The dot product of normalized
+/// vectors is their cosine similarity, without normalizing them again.
+const EMBEDDING_MODELS: &[(&str, usize, bool, SimilarityMetric)] = &[
+    (
+        "openai/text-embedding-3-small",
+        1536,
+        true,
+        SimilarityMetric::DotProduct,
+    ),
+    (
+        "openai/text-embedding-3-large",
+        3072,
+        true,
+        SimilarityMetric::DotProduct,
+    ),
+    (
+        "ollama/nomic-embed-text",
+        768,
+        false,
+        SimilarityMetric::Cosine,
+    ),
+    (
+        "ollama/mxbai-embed-large",
+        1024,
+        false,
+        SimilarityMetric::Cosine,
+    ),
+];
+
+impl EmbeddingModelMetadata {
+    pub fn for_model(name: &str) -> Option<Self> {
+        EMBEDDING_MODELS
+            .iter()
+            .find(|(model_name, _, _, _)| *model_name == name)
+            .map(|(name, dimensions, normalized, similarity_metric)| Self {
+                name: name.to_string(),
+                dimensions: *dimensions,
+                normalized: *normalized,
+                similarity_metric: *similarity_metric,
+            })
+    }
+}
+
 /// Trait for embedding providers. Texts in, vectors out.
 pub trait EmbeddingProvider: Sync + Send {
     fn embed<'a>(&'a self, texts: &'a [TextToEmbed<'a>]) -> BoxFuture<'a, Result<Vec<Embedding>>>;
     fn batch_size(&self) -> usize;
+    /// The longest text, in bytes, that the model can embed. Longer texts are split into
+    /// parts whose embeddings are averaged.
+    fn max_text_len(&self) -> Option<usize> {
+        None
+    }
+    /// The most bytes of text the provider accepts in a single request.
+    fn max_request_len(&self) -> Option<usize> {
+        None
+    }
+    /// How the provider's embeddings are compared, which is the metric of its model, or
+    /// the cosine similarity when the model is unknown.
+    fn similarity_metric(&self) -> SimilarityMetric {
+        self.model_metadata()
+            .map_or(SimilarityMetric::Cosine, |model| model.similarity_metric)
+    }
+    /// The model used to compute embeddings, if known. Embeddings whose size doesn't
+    /// match the model's dimensions are rejected.
+    fn model_metadata(&self) -> Option<EmbeddingModelMetadata> {
+        None
+    }
+    /// How chunks of a file are framed before they're embedded.
+    fn document_template(&self) -> DocumentTemplate {
+        DocumentTemplate::default()
+    }
+    /// Whether documents leave the user's machine to be embedded, in which case
+    /// likely secrets are redacted from them first.
+    fn is_remote(&self) -> bool {
+        true
+    }
+    /// Checks that the provider can embed text at all, e.g. that its API key is valid and
+    /// the model exists, by embedding a single short text.
+    fn verify(&self) -> BoxFuture<'_, Result<()>> {
+        async move {
+            let texts = [TextToEmbed::new("verify")];
+            let embeddings = self.embed(&texts).await?;
+            let embedding = embeddings
+                .first()
+                .context("the provider returned no embedding")?;
+            if let Some(metadata) = self.model_metadata() {
+                if embedding.len() != metadata.dimensions {
+                    return Err(anyhow!(
+                        "{} returned an embedding of {} dimensions instead of {}",
+                        metadata.name,
+                        embedding.len(),
+                        metadata.dimensions
+                    ));
+                }
+                // Normalized models' embeddings may be compared without normalizing them.
+                if metadata.normalized && !embedding.is_normalized() {
+                    return Err(anyhow!(
+                        "{} returned an embedding that isn't normalized",
+                        metadata.name
+                    ));
+                }
+            }
+            Ok(())
+        }
+        .boxed()
+    }
+}
+
+/// Describes the text that gets embedded for a chunk of a file.
+///
+/// Templates can reference `{path}`, `{language}`, `{scope}` and `{contents}`. A line
+/// referencing a variable that has no value, such as `{scope}` for a chunk at the
+/// top level of a file, is left out of the document entirely.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocumentTemplate(String);
+
+/// The values substituted into a [`DocumentTemplate`].
+pub struct DocumentVariables<'a> {
+    pub path: &'a Path,
+    pub language: Option<&'a str>,
+    pub scope: Option<&'a str>,
+    pub contents: &'a str,
+}
+
+impl Default for DocumentTemplate {
+    fn default() -> Self {
+        Self::new("{scope}\n{contents}")
+    }
+}
+
+impl DocumentTemplate {
+    pub fn new(template: impl Into<String>) -> Self {
+        Self(template.into())
+    }
+
+    /// A template that also mentions the file's path, which helps general-purpose
+    /// text embedding models relate code to the place it lives in.
+    pub fn with_path() -> Self {
+        Self::new("{path}\n{scope}\n{contents}")
+    }
+
+    pub fn render(&self, variables: &DocumentVariables) -> String {
+        let mut document = String::new();
+        let mut line = String::new();
+        'lines: for template_line in self.0.split('\n') {
+            line.clear();
+            let mut rest = template_line;
+            while let Some(start) = rest.find('{') {
+                let Some(len) = rest[start..].find('}') else {
+                    break;
+                };
+                line.push_str(&rest[..start]);
+                let name = &rest[start + 1..start + len];
+                let value = match name {
+                    "path" => Some(variables.path.to_string_lossy()),
+                    "language" => variables.language.map(Cow::Borrowed),
+                    "scope" => variables.scope.map(Cow::Borrowed),
+                    "contents" => Some(Cow::Borrowed(variables.contents)),
+                    _ => Some(Cow::Borrowed(&rest[start..=start + len])),
+                };
+                let Some(value) = value else {
+                    continue 'lines;
+                };
+                line.push_str(&value);
+                rest = &rest[start + len + 1..];
+            }
+            line.push_str(rest);
+
+            if !document.is_empty() {
+                document.push('\n');
+            }
+            document.push_str(&line);
+        }
+        document
+    }
 }
 
 #[derive(Debug)]
@@ -90,25 +357,127 @@ impl<'a> TextToEmbed<'a> {
     }
 }
 
-pub struct FakeEmbeddingProvider;
+/// An embedding provider for tests that embeds texts by how often each letter occurs in
+/// them, so that texts sharing words score as similar without calling a model.
+#[cfg(any(test, feature = "test-support"))]
+pub struct FakeEmbeddingProvider {
+    batch_size: usize,
+    noise: Option<(u64, f32)>,
+    latency: Option<(gpui::BackgroundExecutor, std::time::Duration)>,
+}
+
+#[cfg(any(test, feature = "test-support"))]
+impl FakeEmbeddingProvider {
+    /// The size of the embeddings: one dimension per letter, plus one that keeps texts
+    /// without letters from having an empty embedding.
+    pub const DIMENSIONS: usize = 27;
+
+    pub fn new() -> Self {
+        Self {
+            batch_size: 16,
+            noise: None,
+            latency: None,
+        }
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Perturbs every dimension of the embeddings by up to `amount`. The noise only
+    /// depends on the `seed` and the embedded text, so runs with the same seed produce
+    /// the same embeddings.
+    pub fn with_noise(mut self, seed: u64, amount: f32) -> Self {
+        self.noise = Some((seed, amount));
+        self
+    }
+
+    /// Delays every batch by `latency` on the given executor's clock, like a remote model.
+    pub fn with_latency(
+        mut self,
+        executor: gpui::BackgroundExecutor,
+        latency: std::time::Duration,
+    ) -> Self {
+        self.latency = Some((executor, latency));
+        self
+    }
+
+    pub fn compute_embedding(&self, text: &TextToEmbed) -> Embedding {
+        let mut embedding = vec![0f32; Self::DIMENSIONS];
+        for char in text.text.chars() {
+            if char.is_ascii_alphabetic() {
+                embedding[(char.to_ascii_lowercase() as u8 - b'a') as usize] += 1.;
+            }
+        }
+        embedding[Self::DIMENSIONS - 1] = 1.;
 
+        if let Some((seed, amount)) = self.noise {
+            let mut state = text
+                .digest
+                .iter()
+                .fold(seed, |state, byte| state.rotate_left(8) ^ *byte as u64);
+            for dimension in &mut embedding {
+                state = splitmix64(state);
+                let unit = (state >> 40) as f32 / (1u64 << 24) as f32;
+                *dimension += (unit * 2. - 1.) * amount;
+            }
+        }
+
+        Embedding::new(embedding)
+    }
+}
+
+#[cfg(any(test, feature = "test-support"))]
+impl Default for FakeEmbeddingProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(any(test, feature = "test-support"))]
+fn splitmix64(state: u64) -> u64 {
+    let mut z = state.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(any(test, feature = "test-support"))]
 impl EmbeddingProvider for FakeEmbeddingProvider {
     fn embed<'a>(&'a self, texts: &'a [TextToEmbed<'a>]) -> BoxFuture<'a, Result<Vec<Embedding>>> {
         let embeddings = texts
             .iter()
-            .map(|_text| {
-                let mut embedding = vec![0f32; 1536];
-                for i in 0..embedding.len() {
-                    embedding[i] = i as f32;
-                }
-                Embedding::new(embedding)
-            })
+            .map(|text| self.compute_embedding(text))
             .collect();
-        future::ready(Ok(embeddings)).boxed()
+        match &self.latency {
+            Some((executor, latency)) => {
+                let timer = executor.timer(*latency);
+                async move {
+                    timer.await;
+                    Ok(embeddings)
+                }
+                .boxed()
+            }
+            None => future::ready(Ok(embeddings)).boxed(),
+        }
     }
 
     fn batch_size(&self) -> usize {
-        16
+        self.batch_size
+    }
+
+    fn model_metadata(&self) -> Option<EmbeddingModelMetadata> {
+        Some(EmbeddingModelMetadata {
+            name: "fake-letter-frequency".into(),
+            dimensions: Self::DIMENSIONS,
+            normalized: true,
+            similarity_metric: SimilarityMetric::Cosine,
+        })
+    }
+
+    fn is_remote(&self) -> bool {
+        false
     }
 }
 
@@ -122,4 +491,90 @@ mod test {
         let value: f32 = 1.0 / 3.0_f32.sqrt();
         assert_eq!(normalized, Embedding(vec![value; 3]));
     }
+
+    #[gpui::test]
+    fn test_fake_embedding_provider() {
+        let provider = FakeEmbeddingProvider::new();
+        let parse = provider.compute_embedding(&TextToEmbed::new("fn parse_file()"));
+        let parse_again = provider.compute_embedding(&TextToEmbed::new("fn parse_files()"));
+        let render = provider.compute_embedding(&TextToEmbed::new("fn render_view()"));
+        assert!(parse.similarity(&parse_again) > parse.similarity(&render));
+
+        let noisy = FakeEmbeddingProvider::new().with_noise(7, 0.5);
+        let noisy_parse = noisy.compute_embedding(&TextToEmbed::new("fn parse_file()"));
+        assert_ne!(noisy_parse, parse);
+        assert_eq!(
+            noisy_parse,
+            FakeEmbeddingProvider::new()
+                .with_noise(7, 0.5)
+                .compute_embedding(&TextToEmbed::new("fn parse_file()"))
+        );
+        assert_ne!(
+            noisy_parse,
+            FakeEmbeddingProvider::new()
+                .with_noise(8, 0.5)
+                .compute_embedding(&TextToEmbed::new("fn parse_file()"))
+        );
+    }
+
+    #[gpui::test]
+    fn test_similarity_metrics() {
+        let a = Embedding::unnormalized(vec![3.0, 4.0]);
+        let b = Embedding::unnormalized(vec![6.0, 8.0]);
+
+        let cosine = SimilarityMetric::Cosine;
+        let score = cosine.score(&cosine.prepare(a.clone()), &cosine.prepare(b.clone()));
+        assert!((score - 1.0).abs() < 1e-6);
+
+        let dot_product = SimilarityMetric::DotProduct;
+        assert_eq!(dot_product.prepare(a.clone()), a);
+        assert_eq!(dot_product.score(&a, &b), 50.0);
+
+        let euclidean = SimilarityMetric::Euclidean;
+        assert_eq!(euclidean.score(&a, &b), -5.0);
+        assert_eq!(euclidean.score(&a, &a), 0.0);
+    }
+
+    #[gpui::test]
+    fn test_render_document_template() {
+        let variables = DocumentVariables {
+            path: Path::new("src/main.rs"),
+            language: Some("Rust"),
+            scope: None,
+            contents: "fn main() {}",
+        };
+        assert_eq!(
+            DocumentTemplate::default().render(&variables),
+            "fn main() {}"
+        );
+        assert_eq!(
+            DocumentTemplate::with_path().render(&variables),
+            "src/main.rs\nfn main() {}"
+        );
+        assert_eq!(
+            DocumentTemplate::new("{language} code from {path} ({scope}):\n{contents}")
+                .render(&variables),
+            "fn main() {}"
+        );
+        assert_eq!(
+            DocumentTemplate::new("{language} {unknown} {scope\n{contents}").render(
+                &DocumentVariables {
+                    scope: Some("impl Foo"),
+                    ..variables
+                }
+            ),
+            "Rust {unknown} {scope\nfn main() {}"
+        );
+    }
+
+    #[gpui::test]
+    fn test_embedding_model_metadata() {
+        let metadata = EmbeddingModelMetadata::for_model("openai/text-embedding-3-small").unwrap();
+        assert_eq!(metadata.dimensions, 1536);
+        assert!(metadata.normalized);
+        assert_eq!(metadata.similarity_metric, SimilarityMetric::DotProduct);
+        let metadata = EmbeddingModelMetadata::for_model("ollama/nomic-embed-text").unwrap();
+        assert_eq!(metadata.similarity_metric, SimilarityMetric::Cosine);
+        assert_eq!(EmbeddingModelMetadata::for_model("unknown-model"), None);
+    }
 }
@@ -1,4 +1,4 @@
-use crate::{Embedding, EmbeddingProvider, TextToEmbed};
+use crate::{DocumentTemplate, Embedding, EmbeddingModelMetadata, EmbeddingProvider, TextToEmbed};
 use anyhow::{anyhow, Context, Result};
 use client::{proto, Client};
 use collections::HashMap;
@@ -8,6 +8,7 @@ use std::sync::Arc;
 pub struct CloudEmbeddingProvider {
     model: String,
     client: Arc<Client>,
+    document_template: DocumentTemplate,
 }
 
 impl CloudEmbeddingProvider {
@@ -15,8 +16,19 @@ impl CloudEmbeddingProvider {
         Self {
             model: "openai/text-embedding-3-small".into(),
             client,
+            document_template: DocumentTemplate::with_path(),
         }
     }
+
+    pub fn with_model(mut self, model: String) -> Self {
+        self.model = model;
+        self
+    }
+
+    pub fn with_document_template(mut self, document_template: DocumentTemplate) -> Self {
+        self.document_template = document_template;
+        self
+    }
 }
 
 impl EmbeddingProvider for CloudEmbeddingProvider {
@@ -80,7 +92,7 @@ impl EmbeddingProvider for CloudEmbeddingProvider {
                         embeddings.get(&to_embed.digest).cloned().with_context(|| {
                             format!("server did not return an embedding for {:?}", to_embed)
                         })?;
-                    Ok(Embedding::new(embedding))
+                    Ok(Embedding::unnormalized(embedding))
                 })
                 .collect()
         }
@@ -90,4 +102,23 @@ impl EmbeddingProvider for CloudEmbeddingProvider {
     fn batch_size(&self) -> usize {
         2048
     }
+
+    fn max_text_len(&self) -> Option<usize> {
+        // The default model, text-embedding-3-small, accepts 8191 tokens per input.
+        // Code averages fewer bytes per token than prose, so this leaves some room.
+        Some(8191 * 3)
+    }
+
+    fn max_request_len(&self) -> Option<usize> {
+        // OpenAI limits requests to 300,000 tokens across all inputs.
+        Some(300_000 * 3)
+    }
+
+    fn model_metadata(&self) -> Option<EmbeddingModelMetadata> {
+        EmbeddingModelMetadata::for_model(&self.model)
+    }
+
+    fn document_template(&self) -> DocumentTemplate {
+        self.document_template.clone()
+    }
 }
@@ -4,7 +4,7 @@ use http::HttpClient;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-use crate::{Embedding, EmbeddingProvider, TextToEmbed};
+use crate::{DocumentTemplate, Embedding, EmbeddingModelMetadata, EmbeddingProvider, TextToEmbed};
 
 pub enum OllamaEmbeddingModel {
     NomicEmbedText,
@@ -14,6 +14,7 @@ pub enum OllamaEmbeddingModel {
 pub struct OllamaEmbeddingProvider {
     client: Arc<dyn HttpClient>,
     model: OllamaEmbeddingModel,
+    document_template: DocumentTemplate,
 }
 
 #[derive(Serialize)]
@@ -29,7 +30,16 @@ struct OllamaEmbeddingResponse {
 
 impl OllamaEmbeddingProvider {
     pub fn new(client: Arc<dyn HttpClient>, model: OllamaEmbeddingModel) -> Self {
-        Self { client, model }
+        Self {
+            client,
+            model,
+            document_template: DocumentTemplate::with_path(),
+        }
+    }
+
+    pub fn with_document_template(mut self, document_template: DocumentTemplate) -> Self {
+        self.document_template = document_template;
+        self
     }
 }
 
@@ -61,7 +71,7 @@ impl EmbeddingProvider for OllamaEmbeddingProvider {
                 let response: OllamaEmbeddingResponse =
                     serde_json::from_str(&body).context("Unable to pull response")?;
 
-                Ok(Embedding::new(response.embedding))
+                Ok(Embedding::unnormalized(response.embedding))
             }
         }))
         .boxed()
@@ -71,4 +81,28 @@ impl EmbeddingProvider for OllamaEmbeddingProvider {
         // TODO: Figure out decent value
         10
     }
+
+    fn max_text_len(&self) -> Option<usize> {
+        // Ollama truncates inputs longer than its context window, which is 2048 tokens
+        // unless configured otherwise, and 512 tokens for mxbai-embed-large.
+        Some(match self.model {
+            OllamaEmbeddingModel::NomicEmbedText => 2048 * 3,
+            OllamaEmbeddingModel::MxbaiEmbedLarge => 512 * 3,
+        })
+    }
+
+    fn model_metadata(&self) -> Option<EmbeddingModelMetadata> {
+        EmbeddingModelMetadata::for_model(match self.model {
+            OllamaEmbeddingModel::NomicEmbedText => "ollama/nomic-embed-text",
+            OllamaEmbeddingModel::MxbaiEmbedLarge => "ollama/mxbai-embed-large",
+        })
+    }
+
+    fn document_template(&self) -> DocumentTemplate {
+        self.document_template.clone()
+    }
+
+    fn is_remote(&self) -> bool {
+        false
+    }
 }
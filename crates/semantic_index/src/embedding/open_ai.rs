@@ -1,4 +1,4 @@
-use crate::{Embedding, EmbeddingProvider, TextToEmbed};
+use crate::{DocumentTemplate, Embedding, EmbeddingModelMetadata, EmbeddingProvider, TextToEmbed};
 use anyhow::Result;
 use futures::{future::BoxFuture, FutureExt};
 use http::HttpClient;
@@ -10,6 +10,7 @@ pub struct OpenAiEmbeddingProvider {
     model: OpenAiEmbeddingModel,
     api_url: String,
     api_key: String,
+    document_template: DocumentTemplate,
 }
 
 impl OpenAiEmbeddingProvider {
@@ -24,8 +25,14 @@ impl OpenAiEmbeddingProvider {
             model,
             api_url,
             api_key,
+            document_template: DocumentTemplate::with_path(),
         }
     }
+
+    pub fn with_document_template(mut self, document_template: DocumentTemplate) -> Self {
+        self.document_template = document_template;
+        self
+    }
 }
 
 impl EmbeddingProvider for OpenAiEmbeddingProvider {
@@ -42,7 +49,7 @@ impl EmbeddingProvider for OpenAiEmbeddingProvider {
             Ok(response
                 .data
                 .into_iter()
-                .map(|data| Embedding::new(data.embedding))
+                .map(|data| Embedding::unnormalized(data.embedding))
                 .collect())
         }
         .boxed()
@@ -52,4 +59,26 @@ impl EmbeddingProvider for OpenAiEmbeddingProvider {
         // From https://platform.openai.com/docs/api-reference/embeddings/create
         2048
     }
+
+    fn max_text_len(&self) -> Option<usize> {
+        // The model accepts 8191 tokens per input. Code averages fewer bytes per token
+        // than prose, so this leaves some room.
+        Some(8191 * 3)
+    }
+
+    fn max_request_len(&self) -> Option<usize> {
+        // Requests are limited to 300,000 tokens across all inputs.
+        Some(300_000 * 3)
+    }
+
+    fn model_metadata(&self) -> Option<EmbeddingModelMetadata> {
+        EmbeddingModelMetadata::for_model(match self.model {
+            OpenAiEmbeddingModel::TextEmbedding3Small => "openai/text-embedding-3-small",
+            OpenAiEmbeddingModel::TextEmbedding3Large => "openai/text-embedding-3-large",
+        })
+    }
+
+    fn document_template(&self) -> DocumentTemplate {
+        self.document_template.clone()
+    }
 }
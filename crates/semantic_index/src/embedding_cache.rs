@@ -0,0 +1,129 @@
+use crate::{payload_limits::embed_within_limits, Embedding, EmbeddingProvider, TextToEmbed};
+use anyhow::{anyhow, Result};
+use collections::{BTreeMap, HashMap};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+/// How many embeddings of search queries and other ephemeral spans are kept in memory.
+const EMBEDDING_CACHE_CAPACITY: usize = 256;
+
+/// The most recently embedded texts that aren't stored in the index, like search queries,
+/// keyed by the digest of their text. Repeating an interactive query then doesn't hit the
+/// embedding provider again.
+///
+/// The cache belongs to a single [`EmbeddingProvider`], since embeddings produced by
+/// different models aren't interchangeable.
+pub(crate) struct EmbeddingCache {
+    capacity: usize,
+    entries: HashMap<[u8; 32], (Embedding, u64)>,
+    /// The digest of every entry, keyed by when the entry was last used.
+    recency: BTreeMap<u64, [u8; 32]>,
+    next_use: u64,
+}
+
+impl EmbeddingCache {
+    pub fn new() -> Self {
+        Self::with_capacity(EMBEDDING_CACHE_CAPACITY)
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::default(),
+            recency: BTreeMap::default(),
+            next_use: 0,
+        }
+    }
+
+    pub fn get(&mut self, digest: &[u8; 32]) -> Option<Embedding> {
+        let use_id = self.next_use;
+        let (embedding, last_use) = self.entries.get_mut(digest)?;
+        self.recency.remove(last_use);
+        self.recency.insert(use_id, *digest);
+        *last_use = use_id;
+        self.next_use += 1;
+        Some(embedding.clone())
+    }
+
+    pub fn insert(&mut self, digest: [u8; 32], embedding: Embedding) {
+        let use_id = self.next_use;
+        self.next_use += 1;
+        if let Some((_, last_use)) = self.entries.insert(digest, (embedding, use_id)) {
+            self.recency.remove(&last_use);
+        }
+        self.recency.insert(use_id, digest);
+
+        while self.entries.len() > self.capacity {
+            let Some((_, evicted)) = self.recency.pop_first() else {
+                break;
+            };
+            self.entries.remove(&evicted);
+        }
+    }
+}
+
+/// Embeds `texts`, only asking the provider for the ones missing from the `cache`, in a
+/// single batch.
+pub(crate) async fn embed_with_cache(
+    embedding_provider: &dyn EmbeddingProvider,
+    cache: &Arc<Mutex<EmbeddingCache>>,
+    texts: &[TextToEmbed<'_>],
+) -> Result<Vec<Embedding>> {
+    let mut embeddings = {
+        let mut cache = cache.lock();
+        texts
+            .iter()
+            .map(|text| cache.get(&text.digest))
+            .collect::<Vec<_>>()
+    };
+    let missing_texts = texts
+        .iter()
+        .zip(&embeddings)
+        .filter(|(_, embedding)| embedding.is_none())
+        .map(|(text, _)| TextToEmbed {
+            text: text.text,
+            digest: text.digest,
+        })
+        .collect::<Vec<_>>();
+
+    if !missing_texts.is_empty() {
+        let new_embeddings = embed_within_limits(embedding_provider, &missing_texts).await?;
+        if new_embeddings.len() != missing_texts.len() {
+            return Err(anyhow!(
+                "embedding provider returned {} embeddings for {} texts",
+                new_embeddings.len(),
+                missing_texts.len()
+            ));
+        }
+
+        let mut cache = cache.lock();
+        let mut new_embeddings = new_embeddings.into_iter();
+        for (text, embedding) in texts.iter().zip(&mut embeddings) {
+            if embedding.is_none() {
+                let new_embedding = new_embeddings.next().unwrap();
+                cache.insert(text.digest, new_embedding.clone());
+                *embedding = Some(new_embedding);
+            }
+        }
+    }
+
+    Ok(embeddings.into_iter().flatten().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedding_cache_evicts_least_recently_used() {
+        let mut cache = EmbeddingCache::with_capacity(2);
+        cache.insert([1; 32], Embedding::new(vec![1., 0.]));
+        cache.insert([2; 32], Embedding::new(vec![0., 1.]));
+        assert!(cache.get(&[1; 32]).is_some());
+
+        cache.insert([3; 32], Embedding::new(vec![1., 1.]));
+        assert!(cache.get(&[1; 32]).is_some());
+        assert!(cache.get(&[2; 32]).is_none());
+        assert!(cache.get(&[3; 32]).is_some());
+    }
+}
@@ -0,0 +1,38 @@
+//! Decides which files are indexed, whether they're found in a worktree or while indexing
+//! a directory without the app running.
+
+/// Files larger than this aren't indexed. They're rarely written by hand, and chunking and
+/// embedding them would hold up the rest of the index.
+const MAX_INDEXED_FILE_SIZE: u64 = 1024 * 1024;
+
+/// How many bytes at the start of a file are checked for a NUL byte, which text files
+/// don't contain.
+const BINARY_CHECK_LEN: usize = 8000;
+
+/// Whether a file of this many bytes may be indexed, for skipping large files without
+/// reading them.
+pub(crate) fn is_indexed_size(size: u64) -> bool {
+    size <= MAX_INDEXED_FILE_SIZE
+}
+
+/// Whether a file with these contents is indexed. Binary files and very large files
+/// aren't.
+pub(crate) fn is_indexed_file(contents: &[u8]) -> bool {
+    is_indexed_size(contents.len() as u64)
+        && !contents[..contents.len().min(BINARY_CHECK_LEN)].contains(&0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_indexed_file() {
+        assert!(is_indexed_file(b"fn main() {}\n"));
+        assert!(is_indexed_file(b""));
+        assert!(!is_indexed_file(b"\x7fELF\x02\x01\x01\0\0\0"));
+        assert!(!is_indexed_file(
+            &b"a".repeat(MAX_INDEXED_FILE_SIZE as usize + 1)
+        ));
+    }
+}
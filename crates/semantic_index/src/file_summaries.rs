@@ -0,0 +1,441 @@
+use crate::{
+    auxiliary_db_name, db_key_for_path, db_writer::DbWriter, redaction, resolve_db_name,
+    EmbeddedFile, ProjectIndex, SemanticIndex, VectorStoreSettings, WorktreeIndex,
+    WorktreeIndexHandle,
+};
+use anyhow::{anyhow, Context as _, Result};
+use client::{proto, Client};
+use fs::Fs;
+use futures::{future::BoxFuture, FutureExt, StreamExt};
+use gpui::{AppContext, Model, ModelContext, Task};
+use heed::types::{DecodeIgnore, SerdeBincode, Str};
+use serde::{Deserialize, Serialize};
+use settings::Settings;
+use sha2::{Digest, Sha256};
+use std::{path::Path, sync::Arc};
+use util::ResultExt;
+use worktree::Worktree;
+
+/// The most bytes at the start of a file that are sent to be summarized.
+const MAX_SUMMARIZED_TEXT_LEN: usize = 32 * 1024;
+
+/// Describes files in a few sentences of natural language. Summaries are generated after
+/// files are indexed and returned along with search results, e.g. to ground the answers
+/// of the assistant in what the files they come from are for.
+pub trait SummaryProvider: Sync + Send {
+    fn summarize<'a>(&'a self, path: &'a Path, text: &'a str) -> BoxFuture<'a, Result<String>>;
+    /// Whether files leave the user's machine to be summarized, in which case likely
+    /// secrets are redacted from them first.
+    fn is_remote(&self) -> bool {
+        true
+    }
+}
+
+/// Summarizes files with a language model hosted by Zed.
+pub struct CloudSummaryProvider {
+    model: String,
+    client: Arc<Client>,
+}
+
+impl CloudSummaryProvider {
+    pub fn new(client: Arc<Client>) -> Self {
+        Self {
+            model: "claude-3-haiku".into(),
+            client,
+        }
+    }
+}
+
+impl SummaryProvider for CloudSummaryProvider {
+    fn summarize<'a>(&'a self, path: &'a Path, text: &'a str) -> BoxFuture<'a, Result<String>> {
+        async move {
+            if !self.client.status().borrow().is_connected() {
+                return Err(anyhow!("sign in required"));
+            }
+
+            let request = proto::CompleteWithLanguageModel {
+                model: self.model.clone(),
+                messages: vec![
+                    proto::LanguageModelRequestMessage {
+                        role: proto::LanguageModelRole::LanguageModelSystem as i32,
+                        content: "Describe what the following file is for in at most three \
+                            sentences, without repeating its path."
+                            .into(),
+                        tool_call_id: None,
+                        tool_calls: Vec::new(),
+                    },
+                    proto::LanguageModelRequestMessage {
+                        role: proto::LanguageModelRole::LanguageModelUser as i32,
+                        content: format!("{}\n\n{text}", path.display()),
+                        tool_call_id: None,
+                        tool_calls: Vec::new(),
+                    },
+                ],
+                stop: Vec::new(),
+                temperature: 0.,
+                tools: Vec::new(),
+                tool_choice: None,
+            };
+            let mut stream = self.client.request_stream(request).await?;
+            let mut summary = String::new();
+            while let Some(response) = stream.next().await {
+                let mut response = response?;
+                if let Some(content) = response
+                    .choices
+                    .pop()
+                    .and_then(|choice| choice.delta?.content)
+                {
+                    summary.push_str(&content);
+                }
+            }
+            Ok(summary.trim().to_string())
+        }
+        .boxed()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FileSummary {
+    /// The SHA-256 digest of the file's contents when it was summarized.
+    digest: [u8; 32],
+    summary: String,
+}
+
+type SummariesDb = heed::Database<Str, SerdeBincode<FileSummary>>;
+
+fn summaries_db_name(db_name: &str) -> String {
+    auxiliary_db_name(db_name, "summaries")
+}
+
+impl SemanticIndex {
+    /// Summarizes the files of every project with `provider` once they're indexed.
+    pub fn set_summary_provider(
+        &mut self,
+        provider: Arc<dyn SummaryProvider>,
+        cx: &mut AppContext,
+    ) {
+        self.summary_provider = Some(provider.clone());
+        for project_index in self.project_indices.values() {
+            project_index.update(cx, |project_index, cx| {
+                project_index.set_summary_provider(provider.clone(), cx)
+            });
+        }
+    }
+}
+
+impl ProjectIndex {
+    pub(crate) fn set_summary_provider(
+        &mut self,
+        provider: Arc<dyn SummaryProvider>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        self.summary_provider = Some(provider.clone());
+        for index in self.worktree_indices.values() {
+            if let WorktreeIndexHandle::Loaded { index } = index {
+                index.update(cx, |index, cx| {
+                    index.set_summary_provider(provider.clone(), cx)
+                });
+            }
+        }
+    }
+
+    /// The summary of a file generated by the [`SummaryProvider`], unless the file changed
+    /// since it was summarized.
+    pub fn file_summary(
+        &self,
+        worktree: &Model<Worktree>,
+        path: &Path,
+        cx: &AppContext,
+    ) -> Task<Result<Option<String>>> {
+        let db_connection = self.db_connection.clone();
+        let db_name = self.worktree_db_name(&worktree.read(cx).abs_path());
+        let key = db_key_for_path(&path.into());
+        cx.background_executor().spawn(async move {
+            let txn = db_connection
+                .read_txn()
+                .context("failed to create read transaction")?;
+            let (Some(db), Some(summaries_db)) = (
+                db_connection.open_database::<Str, SerdeBincode<EmbeddedFile>>(
+                    &txn,
+                    Some(&resolve_db_name(&db_connection, &txn, &db_name)?),
+                )?,
+                db_connection.open_database::<Str, SerdeBincode<FileSummary>>(
+                    &txn,
+                    Some(&summaries_db_name(&db_name)),
+                )?,
+            ) else {
+                return Ok(None);
+            };
+            let (Some(file), Some(summary)) = (db.get(&txn, &key)?, summaries_db.get(&txn, &key)?)
+            else {
+                return Ok(None);
+            };
+            Ok((file.digest == summary.digest).then_some(summary.summary))
+        })
+    }
+}
+
+impl WorktreeIndex {
+    pub(crate) fn set_summary_provider(
+        &mut self,
+        provider: Arc<dyn SummaryProvider>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        self.summary_provider = Some(provider);
+        if self.initial_scan_done {
+            self.summarize_files(cx);
+        }
+    }
+
+    /// Summarizes the indexed files that changed since they were last summarized, and
+    /// forgets the summaries of the files that aren't indexed anymore. Summarizing starts
+    /// over when this is called again before it's done, which skips the files that were
+    /// summarized in the meantime.
+    pub(crate) fn summarize_files(&mut self, cx: &mut ModelContext<Self>) {
+        let Some(provider) = self.summary_provider.clone() else {
+            return;
+        };
+        let settings = VectorStoreSettings::get_global(cx);
+        if !settings.summarize_files {
+            return;
+        }
+        let redact_secrets = provider.is_remote() && settings.redact_secrets;
+        let db_connection = self.db_connection.clone();
+        let db_writer = self.db_writer.clone();
+        let db = self.db;
+        let db_name = self.db_name.clone();
+        let fs = self.fs.clone();
+        let worktree_abs_path = self.worktree.read(cx).abs_path();
+        self._summarize_files = cx.background_executor().spawn(async move {
+            summarize_files(
+                provider.as_ref(),
+                &db_connection,
+                &db_writer,
+                db,
+                &db_name,
+                fs.as_ref(),
+                &worktree_abs_path,
+                redact_secrets,
+            )
+            .await
+            .context("failed to summarize files")
+            .log_err();
+        });
+    }
+}
+
+async fn summarize_files(
+    provider: &dyn SummaryProvider,
+    db_connection: &heed::Env,
+    db_writer: &DbWriter,
+    db: heed::Database<Str, SerdeBincode<EmbeddedFile>>,
+    db_name: &str,
+    fs: &dyn Fs,
+    worktree_abs_path: &Path,
+    redact_secrets: bool,
+) -> Result<()> {
+    let summaries_db: SummariesDb = db_writer
+        .write({
+            let summaries_db_name = summaries_db_name(db_name);
+            move |db_connection, txn| {
+                Ok(db_connection.create_database(txn, Some(&summaries_db_name))?)
+            }
+        })
+        .await?;
+
+    let (unsummarized_files, stale_keys) = {
+        let txn = db_connection
+            .read_txn()
+            .context("failed to create read transaction")?;
+        let mut unsummarized_files = Vec::new();
+        for entry in db.iter(&txn)? {
+            let (key, file) = entry?;
+            if file.generated || file.duplicate_of.is_some() {
+                continue;
+            }
+            let is_summarized = summaries_db
+                .get(&txn, key)?
+                .map_or(false, |summary| summary.digest == file.digest);
+            if !is_summarized {
+                unsummarized_files.push((key.to_string(), file.path, file.digest));
+            }
+        }
+
+        let files_db = db.remap_data_type::<DecodeIgnore>();
+        let mut stale_keys = Vec::new();
+        for entry in summaries_db.remap_data_type::<DecodeIgnore>().iter(&txn)? {
+            let (key, _) = entry?;
+            if files_db.get(&txn, key)?.is_none() {
+                stale_keys.push(key.to_string());
+            }
+        }
+        (unsummarized_files, stale_keys)
+    };
+
+    if !stale_keys.is_empty() {
+        db_writer
+            .write(move |_, txn| {
+                for key in &stale_keys {
+                    summaries_db.delete(txn, key)?;
+                }
+                Ok(())
+            })
+            .await?;
+    }
+
+    for (key, path, digest) in unsummarized_files {
+        let Some(text) = fs.load(&worktree_abs_path.join(&path)).await.log_err() else {
+            continue;
+        };
+        // The file changed since it was indexed, so it's summarized once it's indexed again.
+        if <[u8; 32]>::from(Sha256::digest(&text)) != digest {
+            continue;
+        }
+        let mut end = text.len().min(MAX_SUMMARIZED_TEXT_LEN);
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        let text = if redact_secrets {
+            redaction::Secrets::find(&path, &text).redact(&text, 0..end)
+        } else {
+            text[..end].into()
+        };
+        // A file the provider fails on is retried the next time files are summarized.
+        let Some(summary) = provider
+            .summarize(&path, &text)
+            .await
+            .with_context(|| format!("failed to summarize {path:?}"))
+            .log_err()
+        else {
+            continue;
+        };
+        db_writer
+            .write(move |_, txn| {
+                summaries_db.put(txn, &key, &FileSummary { digest, summary })?;
+                Ok(())
+            })
+            .await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fs::FakeFs;
+    use futures::{future, FutureExt};
+    use gpui::TestAppContext;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+    struct TestSummaryProvider {
+        summarized_count: AtomicUsize,
+    }
+
+    impl SummaryProvider for TestSummaryProvider {
+        fn summarize<'a>(&'a self, path: &'a Path, text: &'a str) -> BoxFuture<'a, Result<String>> {
+            self.summarized_count.fetch_add(1, SeqCst);
+            if path == Path::new("c.rs") {
+                return future::ready(Err(anyhow!("rate limited"))).boxed();
+            }
+            future::ready(Ok(format!(
+                "{} has {} lines",
+                path.display(),
+                text.lines().count()
+            )))
+            .boxed()
+        }
+    }
+
+    #[gpui::test]
+    async fn test_summarize_files(cx: &mut TestAppContext) {
+        let fs = FakeFs::new(cx.executor());
+        fs.insert_tree(
+            "/root",
+            json!({
+                "a.rs": "fn a() {}\n",
+                "b.rs": "fn b() {}\nfn c() {}\n",
+                "c.rs": "fn d() {}\n",
+            }),
+        )
+        .await;
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_connection = unsafe {
+            heed::EnvOpenOptions::new()
+                .max_dbs(2)
+                .open(temp_dir.path())
+                .unwrap()
+        };
+        let db_writer = DbWriter::new(db_connection.clone(), &cx.executor());
+        let db: heed::Database<Str, SerdeBincode<EmbeddedFile>> = db_writer
+            .write(|db_connection, txn| {
+                let db = db_connection.create_database(txn, Some("worktree"))?;
+                for (path, text) in [
+                    ("a.rs", "fn a() {}\n"),
+                    ("b.rs", "fn b() {}\nfn c() {}\n"),
+                    ("c.rs", "fn d() {}\n"),
+                ] {
+                    let path: Arc<Path> = Path::new(path).into();
+                    let file = EmbeddedFile {
+                        keywords: Vec::new(),
+                        path: path.clone(),
+                        mtime: None,
+                        digest: Sha256::digest(text).into(),
+                        generated: false,
+                        chunks: Vec::new(),
+                        syntax_error: None,
+                        duplicate_of: None,
+                    };
+                    db.put(txn, &db_key_for_path(&path), &file)?;
+                }
+                Ok(db)
+            })
+            .await
+            .unwrap();
+        let provider = TestSummaryProvider {
+            summarized_count: AtomicUsize::new(0),
+        };
+        let summaries = || {
+            let txn = db_connection.read_txn().unwrap();
+            let summaries_db: SummariesDb = db_connection
+                .open_database(&txn, Some(&summaries_db_name("worktree")))
+                .unwrap()
+                .unwrap();
+            summaries_db
+                .iter(&txn)
+                .unwrap()
+                .map(|entry| entry.unwrap().1.summary)
+                .collect::<Vec<_>>()
+        };
+
+        let summarize = || {
+            summarize_files(
+                &provider,
+                &db_connection,
+                &db_writer,
+                db,
+                "worktree",
+                fs.as_ref(),
+                Path::new("/root"),
+                false,
+            )
+        };
+        // Files the provider fails on don't prevent the others from being summarized.
+        summarize().await.unwrap();
+        assert_eq!(summaries(), ["a.rs has 1 lines", "b.rs has 2 lines"]);
+        assert_eq!(provider.summarized_count.load(SeqCst), 3);
+
+        // Files that didn't change aren't summarized again, unlike the ones that failed,
+        // and the summaries of the files that aren't indexed anymore are deleted.
+        db_writer
+            .write(move |_, txn| {
+                db.delete(txn, &db_key_for_path(&Path::new("a.rs").into()))?;
+                Ok(())
+            })
+            .await
+            .unwrap();
+        summarize().await.unwrap();
+        assert_eq!(summaries(), ["b.rs has 2 lines"]);
+        assert_eq!(provider.summarized_count.load(SeqCst), 4);
+    }
+}
@@ -0,0 +1,100 @@
+use crate::{EmbeddedFile, GeneratedFiles, VectorStoreSettings};
+use std::{path::Path, sync::Arc};
+use util::{paths::PathMatcher, ResultExt};
+
+/// How many bytes at the start of a file are searched for a marker saying it was
+/// generated.
+const GENERATED_MARKER_SEARCH_LEN: usize = 1024;
+
+/// Comments that code generators put at the top of their output.
+const GENERATED_MARKERS: &[&str] = &[
+    "@generated",
+    "code generated",
+    "do not edit",
+    "auto-generated",
+    "autogenerated",
+];
+
+/// The average line length above which a file is considered minified.
+const MINIFIED_LINE_LEN: usize = 500;
+
+/// Whether the contents of a file look machine-generated: it starts with a marker like
+/// `// Code generated ... DO NOT EDIT.`, or it's minified onto a few very long lines.
+pub(crate) fn is_generated_text(text: &str) -> bool {
+    let mut head_len = text.len().min(GENERATED_MARKER_SEARCH_LEN);
+    while !text.is_char_boundary(head_len) {
+        head_len -= 1;
+    }
+    let head = text[..head_len].to_lowercase();
+    if GENERATED_MARKERS.iter().any(|marker| head.contains(marker)) {
+        return true;
+    }
+
+    let line_count = text.lines().count();
+    line_count > 0 && text.len() / line_count > MINIFIED_LINE_LEN
+}
+
+/// Decides which indexed files are generated, and what searches do with them.
+#[derive(Clone)]
+pub(crate) struct GeneratedFileFilter {
+    pub mode: GeneratedFiles,
+    patterns: Arc<[PathMatcher]>,
+}
+
+impl GeneratedFileFilter {
+    pub fn new(settings: &VectorStoreSettings) -> Self {
+        let patterns = settings
+            .generated_file_patterns
+            .iter()
+            .filter_map(|pattern| PathMatcher::new(pattern).log_err())
+            .collect();
+        Self {
+            mode: settings.generated_files,
+            patterns,
+        }
+    }
+
+    /// Whether the file is generated, either because of its path or its contents. Always
+    /// false when generated files are treated like any other file.
+    pub fn is_generated(&self, file: &EmbeddedFile) -> bool {
+        self.mode != GeneratedFiles::Include
+            && (file.generated || self.is_generated_path(&file.path))
+    }
+
+    /// Whether a file with these contents at `path` is left out of searches, in which
+    /// case it isn't embedded either.
+    pub fn excludes(&self, path: &Path, generated_text: bool) -> bool {
+        self.mode == GeneratedFiles::Exclude && (generated_text || self.is_generated_path(path))
+    }
+
+    /// Whether a file was stored without embeddings because generated files were
+    /// excluded, and has to be embedded now that they aren't.
+    pub fn was_excluded(&self, file: &EmbeddedFile) -> bool {
+        self.mode != GeneratedFiles::Exclude
+            && file.chunks.is_empty()
+            && file.duplicate_of.is_none()
+            && (file.generated || self.is_generated_path(&file.path))
+    }
+
+    fn is_generated_path(&self, path: &Path) -> bool {
+        self.patterns.iter().any(|pattern| pattern.is_match(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_generated_text() {
+        assert!(is_generated_text(
+            "// Code generated by protoc-gen-go. DO NOT EDIT.\npackage foo\n"
+        ));
+        assert!(is_generated_text("/* @generated */\nexport const a = 1;\n"));
+        assert!(is_generated_text(&format!("var a={};", "1+".repeat(600))));
+        assert!(!is_generated_text(
+            "fn main() {\n    println!(\"hi\");\n}\n"
+        ));
+        assert!(!is_generated_text(""));
+    }
+}
@@ -0,0 +1,395 @@
+//! Builds and searches the index of a directory without the app running, e.g. from the
+//! `zed-index` command-line tool.
+//!
+//! Directories are indexed into the same database format as the worktrees of a project,
+//! so an index built here is picked up by the app when the directory is opened, and vice
+//! versa. Without the app's language registry, files are chunked by lines rather than
+//! along their syntax, so only the `vector_store.chunking` settings apply to them, and not
+//! the per-language ones.
+
+use crate::{
+    archive::{export_archive, import_archive},
+    chunking::{chunk_text, ChunkedText},
+    clear_stale_embeddings, db_key_for_path,
+    db_writer::DbWriter,
+    embed_in_batches,
+    file_filter::{is_indexed_file, is_indexed_size},
+    generated_files::{is_generated_text, GeneratedFileFilter},
+    keyword_prefilter::keywords_in_text,
+    open_db,
+    payload_limits::embed_within_limits,
+    redaction, DocumentVariables, EmbeddedChunk, EmbeddedFile, EmbeddingProvider, IndexMetadata,
+    IndexingError, IndexingErrorKind, TextToEmbed, VectorStoreSettings,
+};
+use anyhow::{Context as _, Result};
+use collections::HashSet;
+use heed::types::{SerdeBincode, Str};
+use sha2::{Digest, Sha256};
+use std::{borrow::Cow, ops::Range, path::Path, sync::Arc};
+
+pub struct HeadlessIndex {
+    db_connection: heed::Env,
+    db_writer: DbWriter,
+    embedding_provider: Arc<dyn EmbeddingProvider>,
+    settings: VectorStoreSettings,
+}
+
+/// Reads the `vector_store` settings the app would use from its default settings and
+/// the contents of the user's settings file, if there is one.
+pub fn load_settings(user_settings: Option<&str>) -> Result<VectorStoreSettings> {
+    let default_settings =
+        settings::parse_json_with_comments::<serde_json::Value>(&settings::default_settings())?;
+    let mut vector_store = default_settings
+        .get("vector_store")
+        .cloned()
+        .context("no default vector_store settings")?;
+    if let Some(user_settings) = user_settings {
+        let user_settings = settings::parse_json_with_comments::<serde_json::Value>(user_settings)
+            .context("failed to parse user settings")?;
+        if let Some(user_vector_store) = user_settings.get("vector_store") {
+            util::merge_non_null_json_value_into(user_vector_store.clone(), &mut vector_store);
+        }
+    }
+    serde_json::from_value(vector_store).context("invalid vector_store settings")
+}
+
+/// What changed in the index of a directory when it was indexed.
+#[derive(Debug, Default)]
+pub struct IndexSummary {
+    pub indexed_count: usize,
+    pub unchanged_count: usize,
+    pub deleted_count: usize,
+    pub errors: Vec<(Arc<Path>, IndexingError)>,
+}
+
+#[derive(Debug)]
+pub struct HeadlessSearchResult {
+    /// The path of the matching file, relative to the indexed directory.
+    pub path: Arc<Path>,
+    pub range: Range<usize>,
+    pub score: f32,
+}
+
+impl HeadlessIndex {
+    pub fn open(
+        db_path: &Path,
+        embedding_provider: Arc<dyn EmbeddingProvider>,
+        settings: VectorStoreSettings,
+    ) -> Result<Self> {
+        let db_connection = open_db(db_path)?;
+        Ok(Self {
+            db_writer: DbWriter::on_thread(db_connection.clone()),
+            db_connection,
+            embedding_provider,
+            settings,
+        })
+    }
+
+    /// Indexes the files in `root` that changed since it was last indexed, and removes
+    /// the files that no longer exist. Like in the app, files excluded by `.gitignore`
+    /// files, binary files, and very large files aren't indexed, and generated files are
+    /// only embedded if the `vector_store.generated_files` setting allows it.
+    pub async fn index_directory(&self, root: &Path) -> Result<IndexSummary> {
+        let db_name = root.to_string_lossy().into_owned();
+        let metadata = IndexMetadata::for_provider(self.embedding_provider.as_ref());
+        let db = self
+            .db_writer
+            .write({
+                let db_name = db_name.clone();
+                move |db_connection, txn| {
+                    let db: heed::Database<Str, SerdeBincode<EmbeddedFile>> =
+                        db_connection.create_database(txn, Some(&db_name))?;
+                    clear_stale_embeddings(db_connection, txn, &db_name, db, &metadata)?;
+                    Ok(db)
+                }
+            })
+            .await?;
+        let generated_file_filter = GeneratedFileFilter::new(&self.settings);
+
+        let mut summary = IndexSummary::default();
+        let mut indexed_keys = HashSet::default();
+        // Files are written as they're embedded, and the writes queued meanwhile are
+        // committed together.
+        let mut writes = Vec::new();
+        for entry in ignore::WalkBuilder::new(root).build() {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(error) => {
+                    log::warn!("failed to walk {root:?}: {error}");
+                    continue;
+                }
+            };
+            if !entry
+                .file_type()
+                .map_or(false, |file_type| file_type.is_file())
+            {
+                continue;
+            }
+            let Ok(path) = entry.path().strip_prefix(root) else {
+                continue;
+            };
+            let file_metadata = entry.metadata().ok();
+            if file_metadata
+                .as_ref()
+                .map_or(false, |metadata| !is_indexed_size(metadata.len()))
+            {
+                continue;
+            }
+            let path: Arc<Path> = path.into();
+            let key = db_key_for_path(&path);
+            let mtime = file_metadata.and_then(|metadata| metadata.modified().ok());
+
+            let previous_file = {
+                let txn = self.db_connection.read_txn()?;
+                db.get(&txn, &key)?
+            };
+            if let Some(previous_file) = &previous_file {
+                if mtime.is_some()
+                    && previous_file.mtime == mtime
+                    && !generated_file_filter.was_excluded(previous_file)
+                {
+                    indexed_keys.insert(key);
+                    summary.unchanged_count += 1;
+                    continue;
+                }
+            }
+
+            match self
+                .embed_file(
+                    entry.path(),
+                    path.clone(),
+                    mtime,
+                    previous_file,
+                    &generated_file_filter,
+                )
+                .await
+            {
+                Ok(Some(file)) => {
+                    indexed_keys.insert(key.clone());
+                    writes.push(
+                        self.db_writer
+                            .write(move |_, txn| Ok(db.put(txn, &key, &file)?)),
+                    );
+                    summary.indexed_count += 1;
+                }
+                // Binary files are left out of the index, like files that no longer exist.
+                Ok(None) => {}
+                Err(error) => {
+                    log::warn!(
+                        "failed to index {path:?} ({:?}): {}",
+                        error.kind,
+                        error.message
+                    );
+                    indexed_keys.insert(key);
+                    summary.errors.push((path, error));
+                }
+            }
+        }
+        futures::future::try_join_all(writes).await?;
+
+        summary.deleted_count = self
+            .db_writer
+            .write(move |_, txn| {
+                let deleted_keys = db
+                    .iter(txn)?
+                    .filter_map(|entry| Some(entry.ok()?.0.to_string()))
+                    .filter(|key| !indexed_keys.contains(key))
+                    .collect::<Vec<_>>();
+                for key in &deleted_keys {
+                    db.delete(txn, key)?;
+                }
+                Ok(deleted_keys.len())
+            })
+            .await?;
+
+        Ok(summary)
+    }
+
+    /// Writes the index of `root` to a single file that can be imported elsewhere, returning
+    /// how many files it contains.
+    pub fn export(&self, root: &Path, archive_path: &Path) -> Result<usize> {
+        export_archive(&self.db_connection, &root.to_string_lossy(), archive_path)
+    }
+
+    /// Replaces the index of `root` with an archive written by [`HeadlessIndex::export`],
+    /// returning how many files it contained. The archive must have been built with the
+    /// same embedding model.
+    pub fn import(&self, root: &Path, archive_path: &Path) -> Result<usize> {
+        let metadata = IndexMetadata::for_provider(self.embedding_provider.as_ref());
+        let mut txn = self.db_connection.write_txn()?;
+        let file_count = import_archive(
+            &self.db_connection,
+            &mut txn,
+            &root.to_string_lossy(),
+            archive_path,
+            &metadata,
+        )?;
+        txn.commit()?;
+        Ok(file_count)
+    }
+
+    async fn embed_file(
+        &self,
+        abs_path: &Path,
+        path: Arc<Path>,
+        mtime: Option<std::time::SystemTime>,
+        previous_file: Option<EmbeddedFile>,
+        generated_file_filter: &GeneratedFileFilter,
+    ) -> Result<Option<EmbeddedFile>, IndexingError> {
+        let read_error = |error: &dyn std::fmt::Display| IndexingError {
+            kind: IndexingErrorKind::Read,
+            message: error.to_string(),
+        };
+        let contents = std::fs::read(abs_path).map_err(|error| read_error(&error))?;
+        if !is_indexed_file(&contents) {
+            return Ok(None);
+        }
+        let text = String::from_utf8(contents).map_err(|error| read_error(&error))?;
+        let digest: [u8; 32] = Sha256::digest(&text).into();
+
+        // Generated files are only embedded if searches may return them.
+        let generated = is_generated_text(&text);
+        if generated_file_filter.excludes(&path, generated) {
+            return Ok(Some(EmbeddedFile {
+                keywords: keywords_in_text(&text),
+                path,
+                mtime,
+                digest,
+                generated,
+                chunks: Vec::new(),
+                syntax_error: None,
+                duplicate_of: None,
+            }));
+        }
+        if let Some(previous_file) = previous_file {
+            if previous_file.digest == digest
+                && previous_file.duplicate_of.is_none()
+                && !generated_file_filter.was_excluded(&previous_file)
+            {
+                return Ok(Some(EmbeddedFile {
+                    mtime,
+                    ..previous_file
+                }));
+            }
+        }
+
+        let ChunkedText {
+            mut chunks,
+            signature_chunks,
+            syntax_error,
+        } = chunk_text(
+            &text,
+            None,
+            &path,
+            &self.settings.chunking_for_language(None),
+        );
+        chunks.extend(signature_chunks);
+
+        let redact_secrets = self.embedding_provider.is_remote() && self.settings.redact_secrets;
+        let secrets = redact_secrets.then(|| redaction::Secrets::find(&path, &text));
+        let document_template = self.embedding_provider.document_template();
+        let documents = chunks
+            .iter()
+            .map(|chunk| {
+                let contents = match &secrets {
+                    Some(secrets) => secrets.redact(&text, chunk.range.clone()),
+                    None => Cow::Borrowed(&text[chunk.range.clone()]),
+                };
+                document_template.render(&DocumentVariables {
+                    path: &path,
+                    language: None,
+                    scope: chunk.scope_path.as_deref(),
+                    contents: &contents,
+                })
+            })
+            .collect::<Vec<_>>();
+        let texts = documents
+            .iter()
+            .map(|document| TextToEmbed::new(document))
+            .collect::<Vec<_>>();
+        let embeddings = embed_in_batches(
+            self.embedding_provider.as_ref(),
+            &texts,
+            self.embedding_provider
+                .model_metadata()
+                .map(|model| model.dimensions),
+            self.embedding_provider.similarity_metric(),
+        )
+        .await;
+
+        let chunks = chunks
+            .into_iter()
+            .zip(embeddings)
+            .map(|(chunk, embedding)| {
+                Some(EmbeddedChunk {
+                    chunk,
+                    embedding: embedding?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| IndexingError {
+                kind: IndexingErrorKind::Embed,
+                message: "failed to embed some of the file's chunks".into(),
+            })?;
+
+        Ok(Some(EmbeddedFile {
+            keywords: keywords_in_text(&text),
+            path,
+            mtime,
+            digest,
+            generated,
+            chunks,
+            syntax_error,
+            duplicate_of: None,
+        }))
+    }
+
+    /// Returns the `limit` chunks of `root`'s index that best match `query`, best first.
+    pub async fn search(
+        &self,
+        root: &Path,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<HeadlessSearchResult>> {
+        let similarity_metric = self.embedding_provider.similarity_metric();
+        let query_embedding =
+            embed_within_limits(self.embedding_provider.as_ref(), &[TextToEmbed::new(query)])
+                .await?
+                .pop()
+                .context("no embedding for query")?;
+        let query_embedding = similarity_metric.prepare(query_embedding);
+
+        let db_name = root.to_string_lossy();
+        let txn = self.db_connection.read_txn()?;
+        let Some(db) = self
+            .db_connection
+            .open_database::<Str, SerdeBincode<EmbeddedFile>>(&txn, Some(&db_name))?
+        else {
+            return Ok(Vec::new());
+        };
+
+        let mut results = Vec::<HeadlessSearchResult>::new();
+        for entry in db.iter(&txn)? {
+            let (_, file) = entry?;
+            for embedded_chunk in file.chunks {
+                if embedded_chunk.embedding.len() != query_embedding.len() {
+                    continue;
+                }
+                let score = similarity_metric.score(&query_embedding, &embedded_chunk.embedding);
+                let ix = results.partition_point(|result| result.score >= score);
+                if ix < limit {
+                    results.insert(
+                        ix,
+                        HeadlessSearchResult {
+                            path: file.path.clone(),
+                            range: embedded_chunk.chunk.range,
+                            score,
+                        },
+                    );
+                    results.truncate(limit);
+                }
+            }
+        }
+        Ok(results)
+    }
+}
@@ -0,0 +1,136 @@
+use crate::SearchResult;
+use anyhow::{anyhow, Result};
+use collections::HashMap;
+use futures::{
+    future::{Shared, WeakShared},
+    FutureExt,
+};
+use gpui::{AppContext, Task};
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+type SearchTask = Task<Result<Vec<SearchResult>, Arc<anyhow::Error>>>;
+
+/// Everything that affects the results of a search.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) struct SearchKey {
+    query: String,
+    limit: usize,
+    min_similarity: Option<u32>,
+    diversity: u32,
+}
+
+impl SearchKey {
+    pub fn new(query: &str, limit: usize, min_similarity: Option<f32>, diversity: f32) -> Self {
+        Self {
+            query: query.to_string(),
+            limit,
+            min_similarity: min_similarity.map(f32::to_bits),
+            diversity: diversity.to_bits(),
+        }
+    }
+}
+
+/// The searches that are running, so that a search issued again before it completes,
+/// e.g. when typing a query is debounced unevenly, waits for the same results instead
+/// of embedding the query and scanning the database a second time.
+#[derive(Default)]
+pub(crate) struct InFlightSearches {
+    searches: Mutex<HashMap<SearchKey, WeakShared<SearchTask>>>,
+}
+
+impl InFlightSearches {
+    /// Joins the running search with the given key, or runs `search` if there's none.
+    /// A search is cancelled once every task waiting for it has been dropped.
+    pub fn join_or_start(
+        self: &Arc<Self>,
+        key: SearchKey,
+        search: impl FnOnce() -> Task<Result<Vec<SearchResult>>>,
+        cx: &AppContext,
+    ) -> Task<Result<Vec<SearchResult>>> {
+        let mut searches = self.searches.lock();
+        // Searches that were cancelled never got to remove themselves.
+        searches.retain(|_, search| search.upgrade().is_some());
+        let shared_search = match searches.get(&key).and_then(WeakShared::upgrade) {
+            Some(shared_search) => shared_search,
+            None => {
+                let search = search();
+                let this = self.clone();
+                let shared_search: Shared<SearchTask> = cx
+                    .spawn({
+                        let key = key.clone();
+                        |_| async move {
+                            let results = search.await.map_err(Arc::new);
+                            this.searches.lock().remove(&key);
+                            results
+                        }
+                    })
+                    .shared();
+                if let Some(weak_search) = shared_search.downgrade() {
+                    searches.insert(key, weak_search);
+                }
+                shared_search
+            }
+        };
+        drop(searches);
+
+        cx.spawn(|_| async move { shared_search.await.map_err(|error| anyhow!(error)) })
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.searches.lock().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gpui::TestAppContext;
+    use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+    #[gpui::test]
+    async fn test_coalesce_identical_searches(cx: &mut TestAppContext) {
+        let searches = Arc::new(InFlightSearches::default());
+        let started = AtomicUsize::new(0);
+        let executor = cx.executor();
+        let search = |query: &str, cx: &mut TestAppContext| {
+            cx.update(|cx| {
+                searches.join_or_start(
+                    SearchKey::new(query, 10, None, 0.),
+                    || {
+                        started.fetch_add(1, SeqCst);
+                        executor.spawn(async { Ok(Vec::new()) })
+                    },
+                    cx,
+                )
+            })
+        };
+
+        let first = search("parse config", cx);
+        let second = search("parse config", cx);
+        let other = search("render terminal", cx);
+        assert_eq!(started.load(SeqCst), 2);
+        first.await.unwrap();
+        second.await.unwrap();
+        other.await.unwrap();
+        cx.run_until_parked();
+        assert_eq!(searches.len(), 0);
+
+        // Once a search has completed, issuing it again searches anew.
+        search("parse config", cx).await.unwrap();
+        assert_eq!(started.load(SeqCst), 3);
+
+        // A search that nobody waits for anymore isn't joined.
+        drop(search("parse config", cx));
+        search("parse config", cx).await.unwrap();
+        assert_eq!(started.load(SeqCst), 5);
+
+        // Cancelled searches are forgotten when the next search starts.
+        drop(search("render terminal", cx));
+        search("parse config", cx).await.unwrap();
+        cx.run_until_parked();
+        assert_eq!(started.load(SeqCst), 7);
+        assert_eq!(searches.len(), 0);
+    }
+}
@@ -0,0 +1,98 @@
+use crate::{
+    documents::DOCUMENTS_DB_NAME, resolve_db_name, ModelHint, SemanticIndex, WorktreeIndex,
+};
+use anyhow::{Context as _, Result};
+use gpui::{AppContext, Model};
+use heed::types::{DecodeIgnore, SerdeBincode, Str};
+use project::Project;
+use std::{future::Future, time::SystemTime};
+
+/// The database holding when the index of each worktree was last brought up to date,
+/// keyed by the name of the worktree's database.
+const UPDATED_AT_DB_NAME: &str = "updated_at";
+
+type UpdatedAtDb = heed::Database<Str, SerdeBincode<SystemTime>>;
+
+/// What's stored in the index for a project, e.g. for deciding whether to offer indexing
+/// the project or searching it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IndexSummary {
+    /// How many of the project's files are indexed.
+    pub file_count: u64,
+    /// How many documents are indexed. Documents are stored together for all projects,
+    /// so this counts the documents added for every project, not only this one's.
+    pub document_count: u64,
+    /// When the index of one of the project's worktrees was last brought up to date, if
+    /// it ever was, including in earlier sessions.
+    pub updated_at: Option<SystemTime>,
+}
+
+impl IndexSummary {
+    /// Whether any of the project's files are indexed.
+    pub fn has_indexed_files(&self) -> bool {
+        self.file_count > 0
+    }
+}
+
+impl SemanticIndex {
+    /// Whether any of the project's files were indexed, in this session or an earlier
+    /// one. Unlike [`SemanticIndex::project_index`], this doesn't start indexing the
+    /// project.
+    pub fn has_index_for_project(&self, project: &Model<Project>, cx: &AppContext) -> bool {
+        self.index_summary(project, cx)
+            .map_or(false, |summary| summary.has_indexed_files())
+    }
+
+    /// Summarizes what's stored in the index for the project. This only reads the sizes
+    /// of the project's databases, so it's cheap enough to call when rendering, and
+    /// doesn't wait for indexing in progress.
+    pub fn index_summary(&self, project: &Model<Project>, cx: &AppContext) -> Result<IndexSummary> {
+        let txn = self
+            .db_connection
+            .read_txn()
+            .context("failed to create read transaction")?;
+        let updated_at_db: Option<UpdatedAtDb> = self
+            .db_connection
+            .open_database(&txn, Some(UPDATED_AT_DB_NAME))?;
+
+        let mut summary = IndexSummary::default();
+        for worktree in project.read(cx).worktrees() {
+            let db_name = ModelHint::Accurate.worktree_db_name(&worktree.read(cx).abs_path());
+            let Some(db) = self.db_connection.open_database::<Str, DecodeIgnore>(
+                &txn,
+                Some(&resolve_db_name(&self.db_connection, &txn, &db_name)?),
+            )?
+            else {
+                continue;
+            };
+            summary.file_count += db.len(&txn)?;
+            if let Some(updated_at) = updated_at_db
+                .map(|updated_at_db| updated_at_db.get(&txn, &db_name))
+                .transpose()?
+                .flatten()
+            {
+                summary.updated_at = summary.updated_at.max(Some(updated_at));
+            }
+        }
+        if let Some(documents_db) = self
+            .db_connection
+            .open_database::<Str, DecodeIgnore>(&txn, Some(DOCUMENTS_DB_NAME))?
+        {
+            summary.document_count = documents_db.len(&txn)?;
+        }
+        Ok(summary)
+    }
+}
+
+impl WorktreeIndex {
+    /// Records that the worktree's index was just brought up to date.
+    pub(crate) fn record_updated_at(&self) -> impl Future<Output = Result<()>> {
+        let db_name = self.db_name.clone();
+        let updated_at = SystemTime::now();
+        self.db_writer.write(move |db_connection, txn| {
+            let db: UpdatedAtDb = db_connection.create_database(txn, Some(UPDATED_AT_DB_NAME))?;
+            db.put(txn, &db_name, &updated_at)?;
+            Ok(())
+        })
+    }
+}
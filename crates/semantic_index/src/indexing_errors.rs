@@ -0,0 +1,130 @@
+use collections::BTreeMap;
+use parking_lot::Mutex;
+use std::{path::Path, sync::Arc};
+
+/// The stage of indexing at which a file failed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IndexingErrorKind {
+    /// The file couldn't be read, so it wasn't indexed.
+    Read,
+    /// The file couldn't be parsed cleanly. It was still indexed, but its chunks may not
+    /// line up with its syntax.
+    Parse,
+    /// Some of the file's chunks couldn't be embedded, so it wasn't indexed.
+    Embed,
+}
+
+/// How many times in a row a file may fail to embed while the provider works, before it's
+/// parked rather than retried.
+const MAX_EMBED_ATTEMPTS: u32 = 3;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexingError {
+    pub kind: IndexingErrorKind,
+    pub message: String,
+}
+
+/// The files of a worktree that couldn't be fully indexed, which are remembered until
+/// they're indexed again.
+///
+/// Files that keep failing to embed although the provider works are parked: they're no
+/// longer retried when the worktree is rescanned, so that indexing can finish, until
+/// they change.
+#[derive(Default)]
+pub(crate) struct IndexingErrors {
+    errors: Mutex<BTreeMap<Arc<Path>, IndexingError>>,
+    embed_failures: Mutex<BTreeMap<Arc<Path>, u32>>,
+}
+
+impl IndexingErrors {
+    pub fn record(&self, path: Arc<Path>, kind: IndexingErrorKind, message: impl Into<String>) {
+        let message = message.into();
+        log::warn!("failed to index {path:?} ({kind:?}): {message}");
+        self.errors
+            .lock()
+            .insert(path, IndexingError { kind, message });
+    }
+
+    pub fn clear(&self, path: &Path) {
+        self.errors.lock().remove(path);
+    }
+
+    /// Records that a file failed to embed. Unless `provider_works`, the failure is
+    /// blamed on the provider rather than the file, and doesn't count towards parking it.
+    pub fn record_embed_failure(&self, path: Arc<Path>, provider_works: bool) {
+        let mut embed_failures = self.embed_failures.lock();
+        let failure_count = embed_failures.entry(path.clone()).or_default();
+        if provider_works {
+            *failure_count += 1;
+        }
+        let message = if *failure_count >= MAX_EMBED_ATTEMPTS {
+            format!(
+                "failed to compute embeddings {MAX_EMBED_ATTEMPTS} times, \
+                 so it won't be retried until it changes"
+            )
+        } else {
+            "failed to compute embeddings".into()
+        };
+        drop(embed_failures);
+        self.record(path, IndexingErrorKind::Embed, message);
+    }
+
+    /// Forgets how many times a file failed to embed, e.g. because it changed, which
+    /// unparks it.
+    pub fn reset_embed_failures(&self, path: &Path) {
+        self.embed_failures.lock().remove(path);
+    }
+
+    pub fn is_parked(&self, path: &Path) -> bool {
+        self.embed_failures
+            .lock()
+            .get(path)
+            .map_or(false, |failure_count| *failure_count >= MAX_EMBED_ATTEMPTS)
+    }
+
+    /// The files that failed to embed too many times to be retried.
+    pub fn parked(&self) -> Vec<Arc<Path>> {
+        self.embed_failures
+            .lock()
+            .iter()
+            .filter(|(_, failure_count)| **failure_count >= MAX_EMBED_ATTEMPTS)
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+
+    pub fn errors(&self) -> Vec<(Arc<Path>, IndexingError)> {
+        self.errors
+            .lock()
+            .iter()
+            .map(|(path, error)| (path.clone(), error.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parking_files_that_fail_to_embed() {
+        let errors = IndexingErrors::default();
+        let path: Arc<Path> = Path::new("a.rs").into();
+
+        // Failures while the provider doesn't work aren't the file's fault.
+        for _ in 0..MAX_EMBED_ATTEMPTS {
+            errors.record_embed_failure(path.clone(), false);
+        }
+        assert!(!errors.is_parked(&path));
+
+        for _ in 0..MAX_EMBED_ATTEMPTS {
+            errors.record_embed_failure(path.clone(), true);
+        }
+        assert!(errors.is_parked(&path));
+        assert_eq!(errors.parked(), vec![path.clone()]);
+        assert_eq!(errors.errors()[0].1.kind, IndexingErrorKind::Embed);
+
+        errors.reset_embed_failures(&path);
+        assert!(!errors.is_parked(&path));
+        assert!(errors.parked().is_empty());
+    }
+}
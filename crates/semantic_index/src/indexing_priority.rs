@@ -0,0 +1,199 @@
+use crate::{IndexingEntryHandle, WorktreeIndex};
+use anyhow::Result;
+use collections::HashSet;
+use futures::{
+    future::{BoxFuture, Fuse, FusedFuture},
+    FutureExt,
+};
+use gpui::{AppContext, BackgroundExecutor, Task};
+use project::{Entry, WorktreeId};
+use smol::channel;
+use std::{cmp::Reverse, collections::BinaryHeap, path::Path, sync::Arc, time::SystemTime};
+
+/// How soon a file is indexed relative to the others that changed: open files first, so
+/// that searches cover what the user is working on seconds after opening a project, then
+/// files by how recently they were modified.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct IndexingPriority {
+    is_open: bool,
+    mtime: Option<SystemTime>,
+}
+
+impl IndexingPriority {
+    fn for_entry(entry: &Entry, open_paths: &HashSet<Arc<Path>>) -> Self {
+        Self {
+            is_open: open_paths.contains(&entry.path),
+            mtime: entry.mtime,
+        }
+    }
+}
+
+pub(crate) struct PrioritizeEntries {
+    pub updated_entries: channel::Receiver<(Entry, IndexingEntryHandle)>,
+    pub task: Task<Result<()>>,
+}
+
+impl WorktreeIndex {
+    /// Orders the entries to index by their [`IndexingPriority`].
+    pub(crate) fn prioritize_entries(
+        &self,
+        updated_entries: channel::Receiver<(Entry, IndexingEntryHandle)>,
+        cx: &AppContext,
+    ) -> PrioritizeEntries {
+        let worktree_id = self.worktree.read(cx).id();
+        let open_paths = self
+            .project
+            .upgrade()
+            .map(|project| {
+                project
+                    .read(cx)
+                    .opened_buffers()
+                    .into_iter()
+                    .filter_map(|buffer| {
+                        let file = buffer.read(cx).file()?;
+                        (WorktreeId::from_usize(file.worktree_id()) == worktree_id)
+                            .then(|| file.path().clone())
+                    })
+                    .collect::<HashSet<_>>()
+            })
+            .unwrap_or_default();
+        let (updated_entries, task) = prioritize(
+            updated_entries,
+            move |(entry, _)| IndexingPriority::for_entry(entry, &open_paths),
+            cx.background_executor(),
+        );
+        PrioritizeEntries {
+            updated_entries,
+            task,
+        }
+    }
+}
+
+struct Prioritized<T> {
+    priority: IndexingPriority,
+    /// Among items of the same priority, the ones received first come first.
+    order: Reverse<usize>,
+    item: T,
+}
+
+impl<T> PartialEq for Prioritized<T> {
+    fn eq(&self, other: &Self) -> bool {
+        (self.priority, self.order) == (other.priority, other.order)
+    }
+}
+
+impl<T> Eq for Prioritized<T> {}
+
+impl<T> PartialOrd for Prioritized<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Prioritized<T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.priority, self.order).cmp(&(other.priority, other.order))
+    }
+}
+
+/// Passes on the items received from `items` highest priority first. Items are only
+/// passed on once the next stage is ready for them, so while it's slower than `items`
+/// arrive, every item received so far competes for the next slot.
+pub(crate) fn prioritize<T: Send + 'static>(
+    items: channel::Receiver<T>,
+    priority: impl Fn(&T) -> IndexingPriority + Send + 'static,
+    executor: &BackgroundExecutor,
+) -> (channel::Receiver<T>, Task<Result<()>>) {
+    let (items_tx, items_rx) = channel::bounded(1);
+    let task = executor.spawn(async move {
+        let mut pending_items = BinaryHeap::new();
+        let mut next_order = 0;
+        let mut receiving: Fuse<BoxFuture<_>> = items.recv().boxed().fuse();
+        let mut sending: Fuse<BoxFuture<_>> = Fuse::terminated();
+        loop {
+            if sending.is_terminated() {
+                // Let the items that already arrived compete for the next slot too.
+                while let Ok(item) = items.try_recv() {
+                    pending_items.push(Prioritized {
+                        priority: priority(&item),
+                        order: Reverse(next_order),
+                        item,
+                    });
+                    next_order += 1;
+                }
+                if let Some(Prioritized { item, .. }) = pending_items.pop() {
+                    sending = items_tx.send(item).boxed().fuse();
+                } else if receiving.is_terminated() {
+                    break;
+                }
+            }
+
+            futures::select_biased! {
+                item = receiving => {
+                    let Ok(item) = item else {
+                        continue;
+                    };
+                    receiving = items.recv().boxed().fuse();
+                    pending_items.push(Prioritized {
+                        priority: priority(&item),
+                        order: Reverse(next_order),
+                        item,
+                    });
+                    next_order += 1;
+                }
+                sent = sending => {
+                    if sent.is_err() {
+                        break;
+                    }
+                }
+                complete => break,
+            }
+        }
+        Ok(())
+    });
+    (items_rx, task)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gpui::TestAppContext;
+    use std::time::Duration;
+
+    #[gpui::test]
+    async fn test_prioritize(cx: &mut TestAppContext) {
+        let now = SystemTime::now();
+        let files = [
+            ("old.rs", false, Some(now - Duration::from_secs(3600))),
+            ("unknown.rs", false, None),
+            ("open.rs", true, Some(now - Duration::from_secs(7200))),
+            ("new.rs", false, Some(now)),
+            ("also_old.rs", false, Some(now - Duration::from_secs(3600))),
+        ];
+        let (files_tx, files_rx) = channel::unbounded();
+        for file in files {
+            files_tx.send(file).await.unwrap();
+        }
+        drop(files_tx);
+
+        let (prioritized_files, task) = prioritize(
+            files_rx,
+            |(_, is_open, mtime)| IndexingPriority {
+                is_open: *is_open,
+                mtime: *mtime,
+            },
+            &cx.executor(),
+        );
+        cx.run_until_parked();
+
+        let mut paths = Vec::new();
+        while let Ok((path, _, _)) = prioritized_files.recv().await {
+            paths.push(path);
+        }
+        task.await.unwrap();
+        assert_eq!(
+            paths,
+            ["open.rs", "new.rs", "old.rs", "also_old.rs", "unknown.rs"]
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use serde::Deserialize;
+
+/// Identifiers shorter than this are too common to narrow down a search.
+const MIN_KEYWORD_LEN: usize = 4;
+
+/// The leading field of a stored [`EmbeddedFile`](crate::EmbeddedFile). Reading a file's
+/// entry as this type only decodes its keywords, which is much cheaper than decoding its
+/// embeddings, so a search can skip the files that can't contain what it's looking for.
+#[derive(Deserialize)]
+pub(crate) struct FileKeywords {
+    pub keywords: Vec<u32>,
+}
+
+/// The sorted hashes of the identifiers in a file's text.
+pub(crate) fn keywords_in_text(text: &str) -> Vec<u32> {
+    let mut keywords = identifiers(text)
+        .filter(|identifier| identifier.len() >= MIN_KEYWORD_LEN)
+        .map(keyword_hash)
+        .collect::<Vec<_>>();
+    keywords.sort_unstable();
+    keywords.dedup();
+    keywords
+}
+
+/// The hashes of the identifiers in a query that look like code rather than prose, such
+/// as `snake_case`, `camelCase` or `utf8` words. Queries without any are conceptual,
+/// and every file is scored for them.
+pub(crate) fn query_keywords(query: &str) -> Vec<u32> {
+    let mut keywords = identifiers(query)
+        .filter(|identifier| identifier.len() >= MIN_KEYWORD_LEN && looks_like_code(identifier))
+        .map(keyword_hash)
+        .collect::<Vec<_>>();
+    keywords.sort_unstable();
+    keywords.dedup();
+    keywords
+}
+
+/// Whether a file with the given keywords contains any of the query's keywords.
+pub(crate) fn contains_any_keyword(file_keywords: &[u32], query_keywords: &[u32]) -> bool {
+    query_keywords
+        .iter()
+        .any(|keyword| file_keywords.binary_search(keyword).is_ok())
+}
+
+fn identifiers(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|identifier| !identifier.is_empty())
+}
+
+fn looks_like_code(identifier: &str) -> bool {
+    let mut prev: Option<char> = None;
+    for c in identifier.chars() {
+        if c == '_' || c.is_ascii_digit() {
+            return true;
+        }
+        if prev.map_or(false, char::is_lowercase) && c.is_uppercase() {
+            return true;
+        }
+        prev = Some(c);
+    }
+    false
+}
+
+/// FNV-1a of the lowercased identifier. It's stored in the index, so it must not change
+/// between releases without bumping the index's format version.
+fn keyword_hash(identifier: &str) -> u32 {
+    let mut hash = 0x811c9dc5u32;
+    for c in identifier.chars().flat_map(char::to_lowercase) {
+        let mut buf = [0; 4];
+        for byte in c.encode_utf8(&mut buf).bytes() {
+            hash ^= byte as u32;
+            hash = hash.wrapping_mul(0x01000193);
+        }
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_keywords() {
+        assert!(query_keywords("how are settings loaded at startup?").is_empty());
+        assert_eq!(
+            query_keywords("where is parse_config called"),
+            [keyword_hash("parse_config")]
+        );
+        assert_eq!(
+            query_keywords("WorktreeIndex"),
+            [keyword_hash("worktreeindex")]
+        );
+        assert_eq!(query_keywords("utf8 decoding"), [keyword_hash("utf8")]);
+        // Too short to be selective.
+        assert!(query_keywords("a_b").is_empty());
+    }
+
+    #[test]
+    fn test_contains_any_keyword() {
+        let file_keywords = keywords_in_text("fn parse_config(path: &Path) -> WorktreeIndex {}");
+        assert!(contains_any_keyword(
+            &file_keywords,
+            &query_keywords("worktreeIndex")
+        ));
+        assert!(contains_any_keyword(
+            &file_keywords,
+            &query_keywords("load_settings or parse_config")
+        ));
+        assert!(!contains_any_keyword(
+            &file_keywords,
+            &query_keywords("load_settings")
+        ));
+    }
+}
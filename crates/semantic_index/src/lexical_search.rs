@@ -0,0 +1,143 @@
+use crate::{EmbeddedFile, PositionHints, ProjectIndex, SearchResult, SearchResultSource};
+use anyhow::{Context as _, Result};
+use fs::Fs;
+use gpui::{AppContext, Model};
+use heed::types::{SerdeBincode, Str};
+use project::Worktree;
+use std::{cmp::Ordering, path::Path, sync::Arc};
+
+/// Searches the indexed files for the chunks containing the most words of a query, for
+/// when the query can't be embedded because the embedding provider is unreachable.
+pub(crate) struct LexicalSearch {
+    fs: Arc<dyn Fs>,
+    worktrees: Vec<LexicalSearchWorktree>,
+}
+
+struct LexicalSearchWorktree {
+    worktree: Model<Worktree>,
+    abs_path: Arc<Path>,
+    db_connection: heed::Env,
+    db: heed::Database<Str, SerdeBincode<EmbeddedFile>>,
+}
+
+impl LexicalSearch {
+    pub fn new(project_index: &ProjectIndex, cx: &AppContext) -> Self {
+        let worktrees = project_index
+            .worktree_indices(cx)
+            .into_iter()
+            .map(|index| {
+                let index = index.read(cx);
+                LexicalSearchWorktree {
+                    worktree: index.worktree.clone(),
+                    abs_path: index.worktree.read(cx).abs_path(),
+                    db_connection: index.db_connection.clone(),
+                    db: index.db,
+                }
+            })
+            .collect();
+        Self {
+            fs: project_index.fs.clone(),
+            worktrees,
+        }
+    }
+
+    pub async fn run(self, query: String, limit: usize) -> Result<Vec<SearchResult>> {
+        let terms = query_terms(&query);
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut results = Vec::new();
+        for worktree in self.worktrees {
+            let files = {
+                let txn = worktree
+                    .db_connection
+                    .read_txn()
+                    .context("failed to create read transaction")?;
+                worktree
+                    .db
+                    .iter(&txn)?
+                    .map(|entry| {
+                        let file = entry?.1;
+                        // Signature chunks repeat the start of their item's chunk.
+                        let chunks = file
+                            .chunks
+                            .into_iter()
+                            .filter(|chunk| chunk.chunk.item_range.is_none())
+                            .map(|chunk| chunk.chunk)
+                            .collect::<Vec<_>>();
+                        Ok((file.path, chunks))
+                    })
+                    .collect::<Result<Vec<_>>>()?
+            };
+
+            for (path, chunks) in files {
+                let Ok(text) = self.fs.load(&worktree.abs_path.join(&path)).await else {
+                    continue;
+                };
+                for chunk in chunks {
+                    let Some(chunk_text) = text.get(chunk.range.clone()) else {
+                        continue;
+                    };
+                    let score = lexical_score(&terms, chunk_text);
+                    if score > 0. {
+                        results.push(SearchResult {
+                            source: SearchResultSource::File {
+                                worktree: worktree.worktree.clone(),
+                                path: path.clone(),
+                            },
+                            range: chunk.range,
+                            score,
+                            relevance: None,
+                            position_hints: PositionHints {
+                                digest: Some(chunk.digest),
+                                scope_path: chunk.scope_path,
+                            },
+                            unsaved_text: None,
+                        });
+                    }
+                }
+            }
+        }
+
+        results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+        results.truncate(limit);
+        Ok(results)
+    }
+}
+
+/// The distinct lowercase words of a query.
+fn query_terms(query: &str) -> Vec<String> {
+    let mut terms = query
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|term| !term.is_empty())
+        .map(|term| term.to_lowercase())
+        .collect::<Vec<_>>();
+    terms.sort_unstable();
+    terms.dedup();
+    terms
+}
+
+/// The fraction of `terms` that occur in `text`, ignoring case.
+fn lexical_score(terms: &[String], text: &str) -> f32 {
+    let text = text.to_lowercase();
+    let matched_count = terms
+        .iter()
+        .filter(|term| text.contains(term.as_str()))
+        .count();
+    matched_count as f32 / terms.len() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lexical_score() {
+        let terms = query_terms("Parse config, parse the CONFIG");
+        assert_eq!(terms, ["config", "parse", "the"]);
+        assert_eq!(lexical_score(&terms, "// Parse the config."), 1.);
+        assert_eq!(lexical_score(&terms, "fn parse_config() {}"), 2. / 3.);
+        assert_eq!(lexical_score(&terms, "unrelated"), 0.);
+    }
+}
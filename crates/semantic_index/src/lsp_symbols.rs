@@ -0,0 +1,185 @@
+use crate::chunking::OutlineSymbol;
+use anyhow::{anyhow, Result};
+use collections::HashMap;
+use futures::FutureExt as _;
+use gpui::BackgroundExecutor;
+use language::{Language, LanguageRegistry, LanguageServerName};
+use lsp::LanguageServer;
+use std::{iter, path::Path, sync::Arc, time::Duration};
+use util::ResultExt;
+
+/// How long a language server gets to list the symbols of a file before the file is
+/// chunked without them.
+const DOCUMENT_SYMBOLS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Whether a file is open in a buffer of the project, which has then already told the
+/// language servers about it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum OpenState {
+    Closed,
+    Saved,
+    /// The servers know the buffer's text rather than the file's, so their symbols
+    /// wouldn't line up with the text being chunked.
+    Unsaved,
+}
+
+/// Asks the language servers for the given language for the symbols in a file, so that
+/// files in languages without an outline query can still be chunked along their items.
+/// Files that aren't open in the project are opened on the servers for the request and
+/// closed again afterwards.
+pub(crate) async fn symbols_from_language_servers(
+    language_registry: &LanguageRegistry,
+    language_servers: &HashMap<LanguageServerName, Arc<LanguageServer>>,
+    language: &Arc<Language>,
+    abs_path: &Path,
+    text: &str,
+    open_state: OpenState,
+    executor: &BackgroundExecutor,
+) -> Option<Vec<OutlineSymbol>> {
+    if open_state == OpenState::Unsaved {
+        return None;
+    }
+    let uri = lsp::Url::from_file_path(abs_path)
+        .map_err(|_| anyhow!("invalid file path {abs_path:?}"))
+        .log_err()?;
+    for adapter in language_registry.lsp_adapters(language) {
+        let Some(server) = language_servers.get(&adapter.name) else {
+            continue;
+        };
+        let supports_document_symbols = match &server.capabilities().document_symbol_provider {
+            Some(lsp::OneOf::Left(supported)) => *supported,
+            Some(lsp::OneOf::Right(_)) => true,
+            None => false,
+        };
+        if !supports_document_symbols {
+            continue;
+        }
+
+        if open_state == OpenState::Closed {
+            server
+                .notify::<lsp::notification::DidOpenTextDocument>(lsp::DidOpenTextDocumentParams {
+                    text_document: lsp::TextDocumentItem::new(
+                        uri.clone(),
+                        adapter.language_id(language),
+                        0,
+                        text.to_string(),
+                    ),
+                })
+                .log_err();
+        }
+        let symbols = futures::select_biased! {
+            symbols = document_symbols(server, uri.clone(), text).fuse() => symbols,
+            _ = executor.timer(DOCUMENT_SYMBOLS_TIMEOUT).fuse() => Err(anyhow!(
+                "{} took too long to list the symbols of {abs_path:?}",
+                adapter.name.0
+            )),
+        };
+        if open_state == OpenState::Closed {
+            server
+                .notify::<lsp::notification::DidCloseTextDocument>(
+                    lsp::DidCloseTextDocumentParams {
+                        text_document: lsp::TextDocumentIdentifier::new(uri.clone()),
+                    },
+                )
+                .log_err();
+        }
+
+        if let Some(symbols) = symbols.log_err() {
+            if !symbols.is_empty() {
+                return Some(symbols);
+            }
+        }
+    }
+    None
+}
+
+async fn document_symbols(
+    server: &LanguageServer,
+    uri: lsp::Url,
+    text: &str,
+) -> Result<Vec<OutlineSymbol>> {
+    let response = server
+        .request::<lsp::request::DocumentSymbolRequest>(lsp::DocumentSymbolParams {
+            text_document: lsp::TextDocumentIdentifier::new(uri),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        })
+        .await?;
+
+    let line_offsets = iter::once(0)
+        .chain(text.match_indices('\n').map(|(ix, _)| ix + 1))
+        .collect::<Vec<_>>();
+    let mut symbols = Vec::new();
+    match response {
+        Some(lsp::DocumentSymbolResponse::Flat(flat_symbols)) => {
+            for symbol in flat_symbols {
+                symbols.push(OutlineSymbol {
+                    range: offset_for_position(text, &line_offsets, symbol.location.range.start)
+                        ..offset_for_position(text, &line_offsets, symbol.location.range.end),
+                    label: symbol.name,
+                });
+            }
+        }
+        Some(lsp::DocumentSymbolResponse::Nested(nested_symbols)) => {
+            push_nested_symbols(&mut symbols, nested_symbols, text, &line_offsets);
+        }
+        None => {}
+    }
+    Ok(symbols)
+}
+
+fn push_nested_symbols(
+    symbols: &mut Vec<OutlineSymbol>,
+    nested_symbols: Vec<lsp::DocumentSymbol>,
+    text: &str,
+    line_offsets: &[usize],
+) {
+    for symbol in nested_symbols {
+        symbols.push(OutlineSymbol {
+            range: offset_for_position(text, line_offsets, symbol.range.start)
+                ..offset_for_position(text, line_offsets, symbol.range.end),
+            label: symbol.name,
+        });
+        if let Some(children) = symbol.children {
+            push_nested_symbols(symbols, children, text, line_offsets);
+        }
+    }
+}
+
+/// Converts an LSP position, whose column counts UTF-16 code units, to a byte offset.
+fn offset_for_position(text: &str, line_offsets: &[usize], position: lsp::Position) -> usize {
+    let Some(&line_start) = line_offsets.get(position.line as usize) else {
+        return text.len();
+    };
+    let line = text[line_start..].split('\n').next().unwrap_or_default();
+    let mut column = 0;
+    for (ix, ch) in line.char_indices() {
+        if column >= position.character as usize {
+            return line_start + ix;
+        }
+        column += ch.len_utf16();
+    }
+    line_start + line.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offset_for_position() {
+        let text = "fn a() {}\nlet é = \"🦀\";\n";
+        let line_offsets = [0, 10, text.len()];
+        let offset = |line, character| {
+            offset_for_position(text, &line_offsets, lsp::Position::new(line, character))
+        };
+        assert_eq!(offset(0, 3), 3);
+        assert_eq!(offset(1, 5), text.find(" =").unwrap());
+        assert_eq!(offset(1, 11), text.find("\";").unwrap());
+        assert_eq!(
+            offset(1, 100),
+            text.find('\n').unwrap() + "let é = \"🦀\";".len() + 1
+        );
+        assert_eq!(offset(5, 0), text.len());
+    }
+}
@@ -0,0 +1,111 @@
+use crate::{
+    auxiliary_db_name, embedding_cache::EmbeddingCache, EmbeddingProvider, ProjectIndex,
+    SemanticIndex,
+};
+use collections::HashMap;
+use gpui::{AppContext, Model, WeakModel};
+use parking_lot::Mutex;
+use project::Project;
+use std::{path::Path, sync::Arc};
+
+/// Which embedding model a search should use, trading accuracy for speed.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ModelHint {
+    /// The main model of the [`SemanticIndex`], for explicit searches and retrieval.
+    #[default]
+    Accurate,
+    /// The model set with [`SemanticIndex::set_fast_embedding_provider`], usually a small
+    /// local one, for interactive searches that run as the user types. Falls back to the
+    /// main model if there's no fast model.
+    Fast,
+}
+
+/// A cheaper model whose embeddings are stored side by side with the main model's, in
+/// databases of their own.
+pub(crate) struct FastModel {
+    pub embedding_provider: Arc<dyn EmbeddingProvider>,
+    pub embedding_cache: Arc<Mutex<EmbeddingCache>>,
+    pub project_indices: HashMap<WeakModel<Project>, Model<ProjectIndex>>,
+}
+
+impl ModelHint {
+    /// The name of the database holding the embeddings of a worktree for this model.
+    pub(crate) fn worktree_db_name(self, worktree_abs_path: &Path) -> String {
+        let db_name = worktree_abs_path.to_string_lossy();
+        match self {
+            ModelHint::Accurate => db_name.into_owned(),
+            ModelHint::Fast => auxiliary_db_name(&db_name, "fast"),
+        }
+    }
+}
+
+impl SemanticIndex {
+    /// Sets the model used by searches with [`ModelHint::Fast`]. Projects are indexed with
+    /// it alongside the main model the first time such a search is made.
+    pub fn set_fast_embedding_provider(&mut self, embedding_provider: Arc<dyn EmbeddingProvider>) {
+        self.fast_model = Some(FastModel {
+            embedding_provider,
+            embedding_cache: Arc::new(Mutex::new(EmbeddingCache::new())),
+            project_indices: HashMap::default(),
+        });
+    }
+
+    /// Returns the index of the project for the model that best matches `hint`.
+    pub fn project_index_for_hint(
+        &mut self,
+        project: Model<Project>,
+        hint: ModelHint,
+        cx: &mut AppContext,
+    ) -> Model<ProjectIndex> {
+        let Some(fast_model) = self.fast_model.as_mut().filter(|_| hint == ModelHint::Fast) else {
+            return self.project_index(project, cx);
+        };
+
+        if let Some(project_index) = fast_model.project_indices.get(&project.downgrade()) {
+            return project_index.clone();
+        }
+
+        let project_weak = project.downgrade();
+        project.update(cx, move |_, cx| {
+            cx.on_release(move |_, cx| {
+                if cx.has_global::<SemanticIndex>() {
+                    cx.update_global::<SemanticIndex, _>(|this, _| {
+                        if let Some(fast_model) = this.fast_model.as_mut() {
+                            fast_model.project_indices.remove(&project_weak);
+                        }
+                    })
+                }
+            })
+            .detach();
+        });
+
+        let project_index = cx.new_model(|cx| {
+            ProjectIndex::new(
+                project.clone(),
+                ModelHint::Fast,
+                false,
+                self.db_connection.clone(),
+                self.db_writer.clone(),
+                fast_model.embedding_provider.clone(),
+                fast_model.embedding_cache.clone(),
+                cx,
+            )
+        });
+        fast_model
+            .project_indices
+            .insert(project.downgrade(), project_index.clone());
+        project_index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_worktree_db_name() {
+        let path = Path::new("/code/zed");
+        assert_eq!(ModelHint::Accurate.worktree_db_name(path), "/code/zed");
+        assert_eq!(ModelHint::Fast.worktree_db_name(path), "/code/zed#fast");
+    }
+}
@@ -0,0 +1,248 @@
+//! Switching the main embedding model without a gap in search results. Projects are
+//! indexed again with the new model in databases of their own, while searches keep using
+//! the embeddings of the previous model. Once a project is fully indexed, its new
+//! embeddings replace the old ones in a single transaction, by swapping the databases
+//! that the names of its worktree databases refer to.
+
+use crate::{
+    embedding_cache::EmbeddingCache, resolve_db_name, EmbeddingProvider, IndexMetadata, ModelHint,
+    ProjectIndex, SemanticIndex, Status, DB_LOCATIONS_DB_NAME, METADATA_DB_NAME,
+};
+use anyhow::Result;
+use collections::HashMap;
+use gpui::{
+    AppContext, BorrowAppContext, Context, Model, ModelContext, Subscription, Task, WeakModel,
+};
+use heed::types::{DecodeIgnore, SerdeBincode, Str};
+use parking_lot::Mutex;
+use project::Project;
+use std::sync::Arc;
+use util::ResultExt;
+
+/// A switch to a new main embedding model that is in progress.
+pub(crate) struct ModelMigration {
+    embedding_provider: Arc<dyn EmbeddingProvider>,
+    embedding_cache: Arc<Mutex<EmbeddingCache>>,
+    /// The projects that are still being indexed with the new model.
+    staged_indices: HashMap<WeakModel<Project>, (Model<ProjectIndex>, Subscription)>,
+}
+
+impl ModelMigration {
+    pub fn forget_project(&mut self, project: &WeakModel<Project>) {
+        self.staged_indices.remove(project);
+    }
+}
+
+impl SemanticIndex {
+    /// Switches the main embedding model, e.g. when the user picks another one. Searches
+    /// keep using the embeddings of the previous model until each project has been
+    /// indexed with the new one in the background.
+    pub fn set_embedding_provider(
+        &mut self,
+        embedding_provider: Arc<dyn EmbeddingProvider>,
+        cx: &mut AppContext,
+    ) {
+        let embedding_cache = Arc::new(Mutex::new(EmbeddingCache::new()));
+        let metadata = IndexMetadata::for_provider(embedding_provider.as_ref());
+        if metadata == IndexMetadata::for_provider(self.embedding_provider.as_ref()) {
+            // The stored embeddings are compatible, so there's nothing to embed again.
+            self.migration = None;
+            self.embedding_provider = embedding_provider.clone();
+            self.embedding_cache = embedding_cache.clone();
+            for project_index in self.project_indices.values() {
+                project_index.update(cx, |project_index, cx| {
+                    project_index.switch_embedding_provider(
+                        embedding_provider.clone(),
+                        embedding_cache.clone(),
+                        Task::ready(Ok(())),
+                        cx,
+                    )
+                });
+            }
+            return;
+        }
+
+        self.migration = Some(ModelMigration {
+            embedding_provider,
+            embedding_cache,
+            staged_indices: HashMap::default(),
+        });
+        let projects = self
+            .project_indices
+            .keys()
+            .filter_map(|project| project.upgrade())
+            .collect::<Vec<_>>();
+        for project in projects {
+            self.migrate_project(project, cx);
+        }
+        self.finish_migration_if_done();
+    }
+
+    /// Starts indexing a project with the model being migrated to.
+    pub(crate) fn migrate_project(&mut self, project: Model<Project>, cx: &mut AppContext) {
+        let Some(migration) = self.migration.as_mut() else {
+            return;
+        };
+        let project_weak = project.downgrade();
+        let has_local_worktrees = project
+            .read(cx)
+            .visible_worktrees(cx)
+            .any(|worktree| worktree.read(cx).is_local());
+        if project.read(cx).is_remote() || !has_local_worktrees {
+            // Nothing is embedded locally for this project, so it can switch right away.
+            if let Some(project_index) = self.project_indices.get(&project_weak) {
+                project_index.update(cx, |project_index, cx| {
+                    project_index.switch_embedding_provider(
+                        migration.embedding_provider.clone(),
+                        migration.embedding_cache.clone(),
+                        Task::ready(Ok(())),
+                        cx,
+                    )
+                });
+            }
+            return;
+        }
+
+        let staged_index = cx.new_model(|cx| {
+            ProjectIndex::new(
+                project,
+                ModelHint::Accurate,
+                true,
+                self.db_connection.clone(),
+                self.db_writer.clone(),
+                migration.embedding_provider.clone(),
+                migration.embedding_cache.clone(),
+                cx,
+            )
+        });
+        let subscription = cx.subscribe(&staged_index, {
+            let project = project_weak.clone();
+            move |staged_index, status: &Status, cx| {
+                if *status == Status::Idle
+                    && staged_index.read(cx).embedding_provider_error().is_none()
+                    && cx.has_global::<SemanticIndex>()
+                {
+                    cx.update_global::<SemanticIndex, _>(|this, cx| {
+                        this.finish_project_migration(&project, cx)
+                    });
+                }
+            }
+        });
+        migration
+            .staged_indices
+            .insert(project_weak, (staged_index, subscription));
+    }
+
+    /// Replaces the embeddings of a project that was fully indexed with the new model.
+    fn finish_project_migration(&mut self, project: &WeakModel<Project>, cx: &mut AppContext) {
+        let Some(migration) = self.migration.as_mut() else {
+            return;
+        };
+        let Some((staged_index, _)) = migration.staged_indices.remove(project) else {
+            return;
+        };
+
+        let staged_index = staged_index.read(cx);
+        let db_names = staged_index
+            .worktree_indices(cx)
+            .into_iter()
+            .map(|index| {
+                let abs_path = index.read(cx).worktree.read(cx).abs_path();
+                (
+                    staged_index.worktree_db_name(&abs_path),
+                    ModelHint::Accurate.worktree_db_name(&abs_path),
+                )
+            })
+            .collect::<Vec<_>>();
+        let metadata = IndexMetadata::for_provider(migration.embedding_provider.as_ref());
+        let promote = self.db_writer.write(move |db_connection, txn| {
+            for (staged_db_name, db_name) in &db_names {
+                promote_staged_embeddings(db_connection, txn, staged_db_name, db_name, &metadata)?;
+            }
+            Ok(())
+        });
+        let promote = cx.background_executor().spawn(promote);
+
+        if let Some(project_index) = self.project_indices.get(project) {
+            project_index.update(cx, |project_index, cx| {
+                project_index.switch_embedding_provider(
+                    migration.embedding_provider.clone(),
+                    migration.embedding_cache.clone(),
+                    promote,
+                    cx,
+                )
+            });
+        } else {
+            promote.detach_and_log_err(cx);
+        }
+        self.finish_migration_if_done();
+    }
+
+    /// Makes the new model the main one once every project has switched to it.
+    pub(crate) fn finish_migration_if_done(&mut self) {
+        if self
+            .migration
+            .as_ref()
+            .map_or(false, |migration| migration.staged_indices.is_empty())
+        {
+            if let Some(migration) = self.migration.take() {
+                self.embedding_provider = migration.embedding_provider;
+                self.embedding_cache = migration.embedding_cache;
+            }
+        }
+    }
+}
+
+impl ProjectIndex {
+    /// Switches to another embedding model once `promote` has stored the embeddings of
+    /// that model in this index's databases. Until then, this index has no worktrees to
+    /// search, so that no embeddings of the previous model are written after them.
+    fn switch_embedding_provider(
+        &mut self,
+        embedding_provider: Arc<dyn EmbeddingProvider>,
+        embedding_cache: Arc<Mutex<EmbeddingCache>>,
+        promote: Task<Result<()>>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        self.embedding_provider = embedding_provider;
+        self.embedding_cache = embedding_cache;
+        self.provider_health = Default::default();
+        self.worktree_indices.clear();
+        self.verify_embedding_provider(cx);
+        self.update_status(cx);
+        cx.spawn(|this, mut cx| async move {
+            promote.await.log_err();
+            this.update(&mut cx, |this, cx| this.update_worktree_indices(cx))
+                .ok();
+        })
+        .detach();
+    }
+}
+
+/// Replaces the embeddings in the database named `db_name` with the ones staged in
+/// `staged_db_name`, which were produced the way `metadata` describes. Rather than
+/// copying them, the two names swap the databases they refer to, and the previous
+/// model's embeddings are cleared to be reused by the next migration.
+fn promote_staged_embeddings(
+    db_connection: &heed::Env,
+    txn: &mut heed::RwTxn,
+    staged_db_name: &str,
+    db_name: &str,
+    metadata: &IndexMetadata,
+) -> Result<()> {
+    let metadata_db: heed::Database<Str, SerdeBincode<IndexMetadata>> =
+        db_connection.create_database(txn, Some(METADATA_DB_NAME))?;
+    let locations_db: heed::Database<Str, Str> =
+        db_connection.create_database(txn, Some(DB_LOCATIONS_DB_NAME))?;
+    let location = resolve_db_name(db_connection, txn, db_name)?;
+    let staged_location = resolve_db_name(db_connection, txn, staged_db_name)?;
+
+    let previous_db: heed::Database<Str, DecodeIgnore> =
+        db_connection.create_database(txn, Some(&location))?;
+    previous_db.clear(txn)?;
+    locations_db.put(txn, db_name, &staged_location)?;
+    locations_db.put(txn, staged_db_name, &location)?;
+    metadata_db.put(txn, db_name, metadata)?;
+    metadata_db.delete(txn, staged_db_name)?;
+    Ok(())
+}
@@ -0,0 +1,171 @@
+use crate::{DocumentId, ProjectIndex, SearchResult, SearchResultSource, SearchResultStream};
+use anyhow::Result;
+use collections::HashMap;
+use futures::{future::try_join_all, StreamExt};
+use gpui::{AppContext, EntityId, Task};
+use std::{
+    cmp::Ordering, collections::hash_map::Entry, hash::Hash, ops::Range, path::Path, sync::Arc,
+};
+
+/// Sentences with fewer words than this carry too little meaning to be searched alone.
+const MIN_SUB_QUERY_WORDS: usize = 3;
+/// The most sentences of a prompt that are searched on their own.
+const MAX_SUB_QUERIES: usize = 6;
+/// Dampens the advantage of the top ranks in reciprocal rank fusion. 60 is the value
+/// from the paper that introduced it, and works well without tuning.
+const RRF_K: f32 = 60.;
+
+impl ProjectIndex {
+    /// Retrieves context for a long prompt, such as a question to the assistant that
+    /// touches several parts of the project. The prompt is searched as a whole and
+    /// sentence by sentence, so that each part of it can retrieve its own regions, and
+    /// the results are merged with reciprocal rank fusion. The merged results keep the
+    /// score they had in the search that ranked them highest. See [`ProjectIndex::search`]
+    /// for the meaning of `min_similarity` and `diversity`.
+    pub fn retrieve_context_multi(
+        &self,
+        prompt: String,
+        limit: usize,
+        min_similarity: Option<f32>,
+        diversity: f32,
+        cx: &AppContext,
+    ) -> Task<Result<Vec<SearchResult>>> {
+        let sub_queries = sub_queries(&prompt);
+        if sub_queries.len() < 2 {
+            return self.search(prompt, limit, min_similarity, diversity, cx);
+        }
+
+        let searches = Some(prompt)
+            .into_iter()
+            .chain(sub_queries)
+            .map(|query| {
+                let SearchResultStream {
+                    results: mut result_batches,
+                    task,
+                } = self.search_stream(query, limit, min_similarity, diversity, cx);
+                async move {
+                    let mut results = Vec::new();
+                    while let Some(batch) = result_batches.next().await {
+                        results = batch;
+                    }
+                    task.await?;
+                    anyhow::Ok(results)
+                }
+            })
+            .collect::<Vec<_>>();
+        cx.background_executor().spawn(async move {
+            let result_lists = try_join_all(searches).await?;
+            Ok(reciprocal_rank_fusion(result_lists, result_key, limit))
+        })
+    }
+}
+
+#[derive(PartialEq, Eq, Hash)]
+pub(crate) enum ResultKey {
+    File(EntityId, Arc<Path>, Range<usize>),
+    Document(DocumentId, Range<usize>),
+}
+
+pub(crate) fn result_key(result: &SearchResult) -> ResultKey {
+    match &result.source {
+        SearchResultSource::File { worktree, path } => {
+            ResultKey::File(worktree.entity_id(), path.clone(), result.range.clone())
+        }
+        SearchResultSource::Document(id) => ResultKey::Document(id.clone(), result.range.clone()),
+    }
+}
+
+/// The sentences of a prompt that are long enough to be searched on their own.
+fn sub_queries(prompt: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = prompt.char_indices().peekable();
+    while let Some((ix, c)) = chars.next() {
+        let ends_sentence = c == '\n'
+            || (matches!(c, '.' | '?' | '!')
+                && chars.peek().map_or(true, |(_, next)| next.is_whitespace()));
+        if ends_sentence {
+            let end = ix + c.len_utf8();
+            sentences.push(&prompt[start..end]);
+            start = end;
+        }
+    }
+    sentences.push(&prompt[start..]);
+
+    sentences
+        .into_iter()
+        .map(str::trim)
+        .filter(|sentence| sentence.split_whitespace().count() >= MIN_SUB_QUERY_WORDS)
+        .take(MAX_SUB_QUERIES)
+        .map(ToString::to_string)
+        .collect()
+}
+
+/// Merges ranked lists into one, ordered by the sum of the reciprocal ranks each item
+/// has in the lists it appears in, so that items ranked well by several lists come
+/// first. Of the items with the same key, the one ranked highest is kept.
+fn reciprocal_rank_fusion<T, K: Eq + Hash>(
+    lists: Vec<Vec<T>>,
+    key: impl Fn(&T) -> K,
+    limit: usize,
+) -> Vec<T> {
+    let mut fused = HashMap::<K, (f32, usize, T)>::default();
+    for list in lists {
+        for (rank, item) in list.into_iter().enumerate() {
+            let rrf_score = 1. / (RRF_K + rank as f32 + 1.);
+            match fused.entry(key(&item)) {
+                Entry::Occupied(mut entry) => {
+                    let (score, best_rank, best_item) = entry.get_mut();
+                    *score += rrf_score;
+                    if rank < *best_rank {
+                        *best_rank = rank;
+                        *best_item = item;
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert((rrf_score, rank, item));
+                }
+            }
+        }
+    }
+
+    let mut fused = fused.into_values().collect::<Vec<_>>();
+    fused.sort_by(|(a_score, a_rank, _), (b_score, b_rank, _)| {
+        b_score
+            .partial_cmp(a_score)
+            .unwrap_or(Ordering::Equal)
+            .then(a_rank.cmp(b_rank))
+    });
+    fused.truncate(limit);
+    fused.into_iter().map(|(_, _, item)| item).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sub_queries() {
+        assert_eq!(
+            sub_queries(
+                "How are settings loaded? Where is the config file watched.\nOk.\nAlso, what does v1.2 change!"
+            ),
+            [
+                "How are settings loaded?",
+                "Where is the config file watched.",
+                "Also, what does v1.2 change!",
+            ]
+        );
+        assert_eq!(sub_queries("parse_config"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_reciprocal_rank_fusion() {
+        let lists = vec![vec!["a", "b", "c"], vec!["c", "d"], vec!["c", "b"]];
+        assert_eq!(
+            reciprocal_rank_fusion(lists.clone(), |item| *item, 10),
+            ["c", "b", "a", "d"]
+        );
+        assert_eq!(reciprocal_rank_fusion(lists, |item| *item, 2), ["c", "b"]);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::{ProjectIndex, SearchResult};
+use anyhow::Result;
+use gpui::{AppContext, Task};
+use std::sync::Arc;
+
+/// How many pages of results a paginated search ranks up front. Later pages are served
+/// from these results, without scanning the index again, and results past them are
+/// only reported by [`SearchPage::truncated`].
+pub const MAX_SEARCH_PAGES: usize = 10;
+
+/// A page of the results of [`ProjectIndex::search_paginated`].
+pub struct SearchPage {
+    pub results: Vec<SearchResult>,
+    /// Where the next page starts, if there are more results.
+    pub cursor: Option<SearchCursor>,
+    /// Whether this is the last page but the index has more results, which didn't fit
+    /// in the [`MAX_SEARCH_PAGES`] pages of the search.
+    pub truncated: bool,
+}
+
+/// The position of a page in the results of a paginated search.
+#[derive(Clone)]
+pub struct SearchCursor {
+    results: Arc<[SearchResult]>,
+    offset: usize,
+    page_size: usize,
+    truncated: bool,
+}
+
+impl SearchCursor {
+    /// The page of results that starts at this cursor.
+    pub fn next_page(&self) -> SearchPage {
+        let end = (self.offset + self.page_size).min(self.results.len());
+        let is_last_page = end == self.results.len();
+        SearchPage {
+            results: self.results[self.offset..end].to_vec(),
+            cursor: (!is_last_page).then(|| SearchCursor {
+                results: self.results.clone(),
+                offset: end,
+                page_size: self.page_size,
+                truncated: self.truncated,
+            }),
+            truncated: is_last_page && self.truncated,
+        }
+    }
+
+    /// How many results precede this cursor.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl ProjectIndex {
+    /// Searches the index like [`ProjectIndex::search`], returning the first `page_size`
+    /// results along with a cursor to load the next ones, e.g. when the user asks for
+    /// more results.
+    pub fn search_paginated(
+        &self,
+        query: String,
+        page_size: usize,
+        min_similarity: Option<f32>,
+        diversity: f32,
+        cx: &AppContext,
+    ) -> Task<Result<SearchPage>> {
+        let page_size = page_size.max(1);
+        let max_results = page_size.saturating_mul(MAX_SEARCH_PAGES);
+        // One more result than fits in the pages tells whether any were left out.
+        let search = self.search(
+            query,
+            max_results.saturating_add(1),
+            min_similarity,
+            diversity,
+            cx,
+        );
+        cx.background_executor().spawn(async move {
+            let mut results = search.await?;
+            let truncated = results.len() > max_results;
+            results.truncate(max_results);
+            let cursor = SearchCursor {
+                results: results.into(),
+                offset: 0,
+                page_size,
+                truncated,
+            };
+            Ok(cursor.next_page())
+        })
+    }
+}
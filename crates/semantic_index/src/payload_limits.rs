@@ -0,0 +1,244 @@
+use crate::{Embedding, EmbeddingProvider, TextToEmbed};
+use anyhow::{anyhow, Result};
+use std::ops::Range;
+
+/// Embeds `texts` with as many requests as it takes to stay within the provider's limits
+/// on the number of texts in a request, the length of each text, and the length of a
+/// request, rather than having the provider reject a whole batch because of one chunk.
+///
+/// A text longer than the provider accepts is split at line boundaries into parts that
+/// fit, which are embedded separately. The text's embedding is the average of the
+/// embeddings of its parts, weighted by their length, so that every byte of the text
+/// counts the same as it would in an embedding of the whole text. The average is
+/// normalized if the provider's model returns normalized embeddings.
+pub(crate) async fn embed_within_limits(
+    embedding_provider: &dyn EmbeddingProvider,
+    texts: &[TextToEmbed<'_>],
+) -> Result<Vec<Embedding>> {
+    let max_text_len = embedding_provider.max_text_len();
+    let normalized = embedding_provider
+        .model_metadata()
+        .map_or(false, |model| model.normalized);
+    let parts_per_text = texts
+        .iter()
+        .map(|text| match max_text_len {
+            Some(max_text_len) if text.text.len() > max_text_len => {
+                split_text(text.text, max_text_len)
+            }
+            _ => vec![text.text],
+        })
+        .collect::<Vec<_>>();
+
+    // Texts that fit keep their digest, which providers may use to cache embeddings.
+    let parts = texts
+        .iter()
+        .zip(&parts_per_text)
+        .flat_map(|(text, parts)| {
+            let is_split = parts.len() > 1;
+            parts.iter().map(move |part| {
+                if is_split {
+                    TextToEmbed::new(part)
+                } else {
+                    TextToEmbed {
+                        text: text.text,
+                        digest: text.digest,
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut part_embeddings = Vec::with_capacity(parts.len());
+    for request in requests(
+        &parts,
+        embedding_provider.batch_size(),
+        embedding_provider.max_request_len(),
+    ) {
+        let embeddings = embedding_provider.embed(&parts[request.clone()]).await?;
+        if embeddings.len() != request.len() {
+            return Err(anyhow!(
+                "embedding provider returned {} embeddings for {} texts",
+                embeddings.len(),
+                request.len()
+            ));
+        }
+        part_embeddings.extend(embeddings);
+    }
+
+    let mut part_embeddings = part_embeddings.into_iter();
+    Ok(parts_per_text
+        .iter()
+        .map(|parts| {
+            if parts.len() == 1 {
+                part_embeddings.next().unwrap()
+            } else {
+                let average = Embedding::weighted_average(
+                    parts
+                        .iter()
+                        .map(|part| (part_embeddings.next().unwrap(), part.len())),
+                );
+                if normalized {
+                    average.normalize()
+                } else {
+                    average
+                }
+            }
+        })
+        .collect())
+}
+
+/// Splits `text` into parts of at most `max_len` bytes, ending at a line boundary unless
+/// a single line is longer than that.
+fn split_text(text: &str, max_len: usize) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut rest = text;
+    while rest.len() > max_len {
+        let mut end = max_len;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        if let Some(newline_ix) = rest[..end].rfind('\n') {
+            end = newline_ix + 1;
+        }
+        if end == 0 {
+            end = rest.chars().next().map_or(rest.len(), char::len_utf8);
+        }
+        parts.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    if !rest.is_empty() || parts.is_empty() {
+        parts.push(rest);
+    }
+    parts
+}
+
+/// Groups consecutive texts into requests of at most `batch_size` texts and, if given,
+/// `max_request_len` bytes. A text longer than that is sent in a request of its own.
+fn requests(
+    texts: &[TextToEmbed],
+    batch_size: usize,
+    max_request_len: Option<usize>,
+) -> Vec<Range<usize>> {
+    let mut requests = Vec::new();
+    let mut start = 0;
+    let mut request_len = 0;
+    for (ix, text) in texts.iter().enumerate() {
+        let exceeds_len = max_request_len.map_or(false, |max_request_len| {
+            request_len + text.text.len() > max_request_len
+        });
+        if ix > start && (ix - start >= batch_size || exceeds_len) {
+            requests.push(start..ix);
+            start = ix;
+            request_len = 0;
+        }
+        request_len += text.text.len();
+    }
+    if start < texts.len() {
+        requests.push(start..texts.len());
+    }
+    requests
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EmbeddingModelMetadata, SimilarityMetric};
+    use futures::{future::BoxFuture, FutureExt};
+    use parking_lot::Mutex;
+    use std::future;
+
+    struct LimitedEmbeddingProvider {
+        requests: Mutex<Vec<Vec<String>>>,
+        normalized: bool,
+    }
+
+    impl EmbeddingProvider for LimitedEmbeddingProvider {
+        fn embed<'a>(
+            &'a self,
+            texts: &'a [TextToEmbed<'a>],
+        ) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+            self.requests
+                .lock()
+                .push(texts.iter().map(|text| text.text.to_string()).collect());
+            let embeddings = texts
+                .iter()
+                .map(|text| {
+                    if text.text.starts_with('a') {
+                        Embedding::unnormalized(vec![1., 0.])
+                    } else {
+                        Embedding::unnormalized(vec![0., 1.])
+                    }
+                })
+                .collect();
+            future::ready(Ok(embeddings)).boxed()
+        }
+
+        fn batch_size(&self) -> usize {
+            3
+        }
+
+        fn max_text_len(&self) -> Option<usize> {
+            Some(8)
+        }
+
+        fn max_request_len(&self) -> Option<usize> {
+            Some(12)
+        }
+
+        fn model_metadata(&self) -> Option<EmbeddingModelMetadata> {
+            Some(EmbeddingModelMetadata {
+                name: "test/limited".into(),
+                dimensions: 2,
+                normalized: self.normalized,
+                similarity_metric: SimilarityMetric::DotProduct,
+            })
+        }
+    }
+
+    #[test]
+    fn test_split_text() {
+        assert_eq!(split_text("aaa\nbb\ncccc\n", 8), ["aaa\nbb\n", "cccc\n"]);
+        assert_eq!(split_text("aaaaaaaaaa", 4), ["aaaa", "aaaa", "aa"]);
+        assert_eq!(split_text("ééé", 3), ["é", "é", "é"]);
+        assert_eq!(split_text("short", 8), ["short"]);
+        assert_eq!(split_text("", 8), [""]);
+    }
+
+    #[gpui::test]
+    async fn test_embed_within_limits() {
+        let provider = LimitedEmbeddingProvider {
+            requests: Mutex::new(Vec::new()),
+            normalized: false,
+        };
+        let texts = ["aaa", "bbb", "aaaaaa\nbb", "aa", "bb"];
+        let texts = texts
+            .iter()
+            .map(|text| TextToEmbed::new(text))
+            .collect::<Vec<_>>();
+        let embeddings = embed_within_limits(&provider, &texts).await.unwrap();
+
+        assert_eq!(
+            *provider.requests.lock(),
+            [vec!["aaa", "bbb"], vec!["aaaaaa\n", "bb", "aa"], vec!["bb"],]
+        );
+        assert_eq!(
+            embeddings,
+            [
+                Embedding::unnormalized(vec![1., 0.]),
+                Embedding::unnormalized(vec![0., 1.]),
+                Embedding::unnormalized(vec![7. / 9., 2. / 9.]),
+                Embedding::unnormalized(vec![1., 0.]),
+                Embedding::unnormalized(vec![0., 1.]),
+            ]
+        );
+
+        // The averages of a model whose embeddings are normalized are normalized too.
+        let provider = LimitedEmbeddingProvider {
+            requests: Mutex::new(Vec::new()),
+            normalized: true,
+        };
+        let embeddings = embed_within_limits(&provider, &texts).await.unwrap();
+        assert_eq!(embeddings[2], Embedding::new(vec![7. / 9., 2. / 9.]));
+        assert!(embeddings.iter().all(Embedding::is_normalized));
+    }
+}
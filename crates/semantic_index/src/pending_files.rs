@@ -0,0 +1,254 @@
+use crate::{
+    chunking::Chunk, db_writer::DbWriter, ChunkFiles, ChunkedFile, EmbeddedChunk,
+    IndexingEntryHandle,
+};
+use anyhow::{anyhow, Context as _, Result};
+use futures::{
+    future::{BoxFuture, Fuse, FusedFuture},
+    FutureExt,
+};
+use gpui::BackgroundExecutor;
+use heed::types::{SerdeBincode, Str};
+use serde::{Deserialize, Serialize};
+use smol::channel;
+use std::{collections::VecDeque, mem, path::Path, sync::Arc, time::SystemTime};
+
+pub(crate) type PendingFilesDb = heed::Database<Str, SerdeBincode<SpilledFile>>;
+
+/// A chunked file set aside in the database while it waits to be embedded. Its
+/// [`IndexingEntryHandle`] stays in memory, so that the file still counts as being
+/// indexed.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SpilledFile {
+    path: Arc<Path>,
+    language: Option<Arc<str>>,
+    mtime: Option<SystemTime>,
+    text: String,
+    digest: [u8; 32],
+    generated: bool,
+    chunks: Vec<Chunk>,
+    previous_chunks: Vec<EmbeddedChunk>,
+    syntax_error: Option<String>,
+    duplicate_of: Option<Arc<Path>>,
+}
+
+enum PendingFile {
+    InMemory(ChunkedFile),
+    Spilled {
+        key: String,
+        handle: IndexingEntryHandle,
+    },
+}
+
+impl ChunkedFile {
+    /// Roughly how many bytes the file takes up in memory.
+    fn estimated_size(&self) -> usize {
+        let previous_embeddings = self
+            .previous_chunks
+            .iter()
+            .map(|chunk| chunk.embedding.len() * mem::size_of::<f32>())
+            .sum::<usize>();
+        mem::size_of::<Self>()
+            + self.text.len()
+            + self.chunks.len() * mem::size_of::<Chunk>()
+            + self.previous_chunks.len() * mem::size_of::<EmbeddedChunk>()
+            + previous_embeddings
+    }
+
+    fn spill(self) -> (SpilledFile, IndexingEntryHandle) {
+        let spilled_file = SpilledFile {
+            path: self.path,
+            language: self.language,
+            mtime: self.mtime,
+            text: self.text,
+            digest: self.digest,
+            generated: self.generated,
+            chunks: self.chunks,
+            previous_chunks: self.previous_chunks,
+            syntax_error: self.syntax_error,
+            duplicate_of: self.duplicate_of,
+        };
+        (spilled_file, self.handle)
+    }
+}
+
+impl SpilledFile {
+    fn restore(self, handle: IndexingEntryHandle) -> ChunkedFile {
+        ChunkedFile {
+            path: self.path,
+            language: self.language,
+            mtime: self.mtime,
+            handle,
+            text: self.text,
+            digest: self.digest,
+            generated: self.generated,
+            chunks: self.chunks,
+            previous_chunks: self.previous_chunks,
+            syntax_error: self.syntax_error,
+            duplicate_of: self.duplicate_of,
+        }
+    }
+}
+
+/// Passes chunked files on to be embedded in the order they were chunked, holding up to
+/// `memory_limit` bytes of them in memory while the embedding provider is slower than
+/// chunking. Files beyond that are written to `db` and read back when their turn comes,
+/// so that indexing a large worktree doesn't keep all of its files in memory.
+pub(crate) fn buffer_chunked_files(
+    chunked_files: channel::Receiver<ChunkedFile>,
+    memory_limit: usize,
+    db_writer: DbWriter,
+    db: PendingFilesDb,
+    executor: &BackgroundExecutor,
+) -> ChunkFiles {
+    let (files_tx, files_rx) = channel::bounded(1);
+    let task = executor.spawn(async move {
+        let mut pending_files = VecDeque::new();
+        let mut memory_used = 0;
+        let mut next_key = 0usize;
+        let mut receiving: Fuse<BoxFuture<_>> = chunked_files.recv().boxed().fuse();
+        let mut sending: Fuse<BoxFuture<_>> = Fuse::terminated();
+        loop {
+            if sending.is_terminated() {
+                if let Some(pending_file) = pending_files.pop_front() {
+                    let file = match pending_file {
+                        PendingFile::InMemory(file) => {
+                            memory_used -= file.estimated_size();
+                            file
+                        }
+                        PendingFile::Spilled { key, handle } => {
+                            let spilled_file = db_writer
+                                .write(move |_, txn| {
+                                    let spilled_file = db
+                                        .get(txn, &key)?
+                                        .ok_or_else(|| anyhow!("spilled file not found"))?;
+                                    db.delete(txn, &key)?;
+                                    Ok(spilled_file)
+                                })
+                                .await
+                                .context("failed to read spilled file")?;
+                            spilled_file.restore(handle)
+                        }
+                    };
+                    sending = files_tx.send(file).boxed().fuse();
+                } else if receiving.is_terminated() {
+                    break;
+                }
+            }
+
+            futures::select_biased! {
+                sent = sending => {
+                    if sent.is_err() {
+                        break;
+                    }
+                }
+                file = receiving => {
+                    let Ok(file) = file else {
+                        continue;
+                    };
+                    receiving = chunked_files.recv().boxed().fuse();
+
+                    let size = file.estimated_size();
+                    if memory_used + size <= memory_limit || pending_files.is_empty() {
+                        memory_used += size;
+                        pending_files.push_back(PendingFile::InMemory(file));
+                    } else {
+                        let key = format!("{next_key:020}");
+                        next_key += 1;
+                        let (spilled_file, handle) = file.spill();
+                        db_writer
+                            .write({
+                                let key = key.clone();
+                                move |_, txn| Ok(db.put(txn, &key, &spilled_file)?)
+                            })
+                            .await
+                            .context("failed to spill chunked file")?;
+                        pending_files.push_back(PendingFile::Spilled { key, handle });
+                    }
+                }
+                complete => break,
+            }
+        }
+
+        // Files left over because embedding stopped early are of no use anymore.
+        if next_key > 0 {
+            db_writer.write(move |_, txn| Ok(db.clear(txn)?)).await?;
+        }
+        Ok(())
+    });
+
+    ChunkFiles {
+        files: files_rx,
+        task,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::IndexingEntrySet;
+    use gpui::TestAppContext;
+    use project::ProjectEntryId;
+
+    #[gpui::test]
+    async fn test_spill_files_beyond_memory_limit(cx: &mut TestAppContext) {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let db_connection = unsafe {
+            heed::EnvOpenOptions::new()
+                .max_dbs(1)
+                .open(temp_dir.path())
+                .unwrap()
+        };
+        let db_writer = DbWriter::new(db_connection.clone(), &cx.executor());
+        let db: PendingFilesDb = db_writer
+            .write(|db_connection, txn| Ok(db_connection.create_database(txn, Some("pending"))?))
+            .await
+            .unwrap();
+
+        let (status_tx, _status_rx) = channel::unbounded();
+        let entries_being_indexed = Arc::new(IndexingEntrySet::new(status_tx));
+        let (chunked_files_tx, chunked_files_rx) = channel::unbounded();
+        for id in 0..6 {
+            chunked_files_tx
+                .send(ChunkedFile {
+                    path: Path::new(&format!("file{id}.txt")).into(),
+                    language: None,
+                    mtime: None,
+                    handle: entries_being_indexed.insert(ProjectEntryId::from_proto(id)),
+                    text: "a".repeat(1024),
+                    digest: [0; 32],
+                    generated: false,
+                    chunks: Vec::new(),
+                    previous_chunks: Vec::new(),
+                    syntax_error: None,
+                    duplicate_of: None,
+                })
+                .await
+                .unwrap();
+        }
+        drop(chunked_files_tx);
+
+        let buffer = buffer_chunked_files(chunked_files_rx, 2048, db_writer, db, &cx.executor());
+        cx.run_until_parked();
+        {
+            let txn = db_connection.read_txn().unwrap();
+            assert!(db.len(&txn).unwrap() > 0);
+        }
+        // Spilled files still count as being indexed.
+        assert_eq!(entries_being_indexed.len(), 6);
+
+        let mut paths = Vec::new();
+        while let Ok(file) = buffer.files.recv().await {
+            paths.push(file.path.to_string_lossy().into_owned());
+            assert_eq!(file.text.len(), 1024);
+        }
+        buffer.task.await.unwrap();
+        assert_eq!(
+            paths,
+            (0..6).map(|id| format!("file{id}.txt")).collect::<Vec<_>>()
+        );
+
+        let txn = db_connection.read_txn().unwrap();
+        assert_eq!(db.len(&txn).unwrap(), 0);
+    }
+}
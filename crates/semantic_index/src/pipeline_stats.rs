@@ -0,0 +1,167 @@
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// The stages files go through while they're indexed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum IndexingStage {
+    /// Comparing the files in the worktree with the ones in the database.
+    Scan,
+    /// Reading and parsing files, and splitting them into chunks.
+    Chunk,
+    /// Computing the embeddings of chunks with the embedding provider.
+    Embed,
+    /// Storing embedded files in the database.
+    Write,
+}
+
+impl IndexingStage {
+    fn name(self) -> &'static str {
+        match self {
+            IndexingStage::Scan => "scan",
+            IndexingStage::Chunk => "chunk",
+            IndexingStage::Embed => "embed",
+            IndexingStage::Write => "write",
+        }
+    }
+}
+
+/// How much time a worktree's indexing spent in each stage, to tell which one makes
+/// indexing slow.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct IndexingTimings {
+    pub scan: StageTimings,
+    pub chunk: StageTimings,
+    pub embed: StageTimings,
+    pub write: StageTimings,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct StageTimings {
+    /// How many times the stage ran, e.g. once per file for `chunk` and once per batch
+    /// of chunks for `embed`.
+    pub runs: usize,
+    /// How many files or chunks the stage processed.
+    pub items: usize,
+    pub total_ms: u64,
+    pub max_ms: u64,
+}
+
+impl IndexingTimings {
+    fn stage_mut(&mut self, stage: IndexingStage) -> &mut StageTimings {
+        match stage {
+            IndexingStage::Scan => &mut self.scan,
+            IndexingStage::Chunk => &mut self.chunk,
+            IndexingStage::Embed => &mut self.embed,
+            IndexingStage::Write => &mut self.write,
+        }
+    }
+
+    /// Adds the timings of another worktree to these.
+    pub fn merge(&mut self, other: &IndexingTimings) {
+        self.scan.merge(&other.scan);
+        self.chunk.merge(&other.chunk);
+        self.embed.merge(&other.embed);
+        self.write.merge(&other.write);
+    }
+}
+
+impl StageTimings {
+    fn merge(&mut self, other: &StageTimings) {
+        self.runs += other.runs;
+        self.items += other.items;
+        self.total_ms += other.total_ms;
+        self.max_ms = self.max_ms.max(other.max_ms);
+    }
+}
+
+/// Collects the timings of a worktree's indexing pipeline.
+#[derive(Default)]
+pub(crate) struct PipelineStats {
+    timings: Mutex<IndexingTimings>,
+}
+
+impl PipelineStats {
+    /// Starts timing a run of `stage`, which is recorded when the returned span is
+    /// dropped.
+    pub fn span(&self, stage: IndexingStage) -> StageSpan<'_> {
+        StageSpan {
+            stats: self,
+            stage,
+            items: 0,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn record(&self, stage: IndexingStage, items: usize, elapsed: Duration) {
+        let elapsed_ms = elapsed.as_millis() as u64;
+        let mut timings = self.timings.lock();
+        let timings = timings.stage_mut(stage);
+        timings.runs += 1;
+        timings.items += items;
+        timings.total_ms += elapsed_ms;
+        timings.max_ms = timings.max_ms.max(elapsed_ms);
+    }
+
+    pub fn timings(&self) -> IndexingTimings {
+        self.timings.lock().clone()
+    }
+}
+
+/// A run of an indexing stage, which is timed until it's dropped.
+pub(crate) struct StageSpan<'a> {
+    stats: &'a PipelineStats,
+    stage: IndexingStage,
+    items: usize,
+    start: Instant,
+}
+
+impl StageSpan<'_> {
+    /// Counts files or chunks processed during this run.
+    pub fn add_items(&mut self, count: usize) {
+        self.items += count;
+    }
+}
+
+impl Drop for StageSpan<'_> {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        log::trace!(
+            "indexing stage {} processed {} items in {:?}",
+            self.stage.name(),
+            self.items,
+            elapsed
+        );
+        self.stats.record(self.stage, self.items, elapsed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_and_merge() {
+        let stats = PipelineStats::default();
+        stats.record(IndexingStage::Embed, 10, Duration::from_millis(30));
+        stats.record(IndexingStage::Embed, 5, Duration::from_millis(50));
+        {
+            let mut span = stats.span(IndexingStage::Chunk);
+            span.add_items(1);
+        }
+
+        let mut timings = stats.timings();
+        assert_eq!(timings.embed.runs, 2);
+        assert_eq!(timings.embed.items, 15);
+        assert_eq!(timings.embed.total_ms, 80);
+        assert_eq!(timings.embed.max_ms, 50);
+        assert_eq!(timings.chunk.runs, 1);
+        assert_eq!(timings.chunk.items, 1);
+        assert_eq!(timings.write.runs, 0);
+
+        timings.merge(&stats.timings());
+        assert_eq!(timings.embed.runs, 4);
+        assert_eq!(timings.embed.total_ms, 160);
+        assert_eq!(timings.embed.max_ms, 50);
+    }
+}
@@ -0,0 +1,179 @@
+use crate::ProjectIndex;
+use anyhow::Result;
+use gpui::ModelContext;
+use parking_lot::Mutex;
+use std::time::Duration;
+
+/// How long to wait before retrying to embed files after the first failure.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(10);
+/// The longest wait between retries, however many times the provider failed in a row.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10 * 60);
+
+/// Tracks whether the embedding provider is reachable, so that indexing can back off
+/// and retry while it isn't, and searches can fall back to matching the words of the
+/// query instead of failing.
+#[derive(Default)]
+pub(crate) struct ProviderHealth {
+    state: Mutex<ProviderHealthState>,
+}
+
+#[derive(Default)]
+struct ProviderHealthState {
+    consecutive_failures: u32,
+    last_error: Option<String>,
+    verification: Verification,
+}
+
+/// Whether the embedding provider passed its last verification. Verification happens in
+/// the background, so no files are sent to the provider until it's known to work.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) enum Verification {
+    #[default]
+    Unverified,
+    Passed,
+    /// The provider failed, for the given reason.
+    Failed(String),
+}
+
+impl ProviderHealth {
+    pub fn record_success(&self) {
+        let mut state = self.state.lock();
+        state.consecutive_failures = 0;
+        state.last_error = None;
+    }
+
+    pub fn record_failure(&self, error: impl Into<String>) {
+        let mut state = self.state.lock();
+        state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+        state.last_error = Some(error.into());
+    }
+
+    pub fn record_verification(&self, result: &Result<()>) {
+        match result {
+            Ok(()) => {
+                self.record_success();
+                self.state.lock().verification = Verification::Passed;
+            }
+            Err(error) => {
+                let error = format!("{error:#}");
+                self.record_failure(error.clone());
+                self.state.lock().verification = Verification::Failed(error);
+            }
+        }
+    }
+
+    /// Whether the provider passed its last verification. Until it has, no files are
+    /// sent to it.
+    pub fn verification(&self) -> Verification {
+        self.state.lock().verification.clone()
+    }
+
+    /// The error of the provider's last request, if it failed.
+    pub fn last_error(&self) -> Option<String> {
+        self.state.lock().last_error.clone()
+    }
+
+    /// How long to wait before retrying after the provider's last request failed, which
+    /// doubles with every failure in a row.
+    pub fn retry_delay(&self) -> Option<Duration> {
+        let failures = self.state.lock().consecutive_failures;
+        let exponent = failures.checked_sub(1)?.min(16);
+        Some((INITIAL_RETRY_DELAY * 2u32.pow(exponent)).min(MAX_RETRY_DELAY))
+    }
+}
+
+impl ProjectIndex {
+    /// Checks that the embedding provider works before any files are sent to it, so that
+    /// a missing API key or an unknown model shows up as an error right away, rather than
+    /// after every file of the project failed to embed. While it doesn't, the check is
+    /// retried with a growing delay or as soon as the settings change. The worktrees are
+    /// rescanned once it passes, and after the first check so that they stop loading.
+    pub(crate) fn verify_embedding_provider(&mut self, cx: &mut ModelContext<Self>) {
+        let provider = self.embedding_provider.clone();
+        let provider_health = self.provider_health.clone();
+        self._verify_embedding_provider = cx.spawn(|this, mut cx| async move {
+            loop {
+                let result = provider.verify().await;
+                let previous_verification = provider_health.verification();
+                provider_health.record_verification(&result);
+                match result {
+                    Ok(()) => {
+                        if previous_verification != Verification::Passed {
+                            this.update(&mut cx, |this, cx| this.refresh(cx)).ok();
+                        }
+                        break;
+                    }
+                    Err(error) => {
+                        log::error!("embedding provider failed verification: {error:#}");
+                        if previous_verification == Verification::Unverified {
+                            this.update(&mut cx, |this, cx| this.refresh(cx)).ok();
+                        }
+                        let Some(delay) = provider_health.retry_delay() else {
+                            break;
+                        };
+                        cx.background_executor().timer(delay).await;
+                    }
+                }
+            }
+        });
+    }
+
+    pub(crate) fn handle_settings_changed(&mut self, cx: &mut ModelContext<Self>) {
+        // The provider may have been fixed, e.g. by entering its API key.
+        if matches!(self.provider_health.verification(), Verification::Failed(_)) {
+            self.verify_embedding_provider(cx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay() {
+        let health = ProviderHealth::default();
+        assert_eq!(health.retry_delay(), None);
+
+        health.record_failure("offline");
+        assert_eq!(health.retry_delay(), Some(INITIAL_RETRY_DELAY));
+        assert_eq!(health.last_error().as_deref(), Some("offline"));
+        health.record_failure("offline");
+        assert_eq!(health.retry_delay(), Some(INITIAL_RETRY_DELAY * 2));
+        for _ in 0..100 {
+            health.record_failure("offline");
+        }
+        assert_eq!(health.retry_delay(), Some(MAX_RETRY_DELAY));
+
+        health.record_success();
+        assert_eq!(health.retry_delay(), None);
+        assert_eq!(health.last_error(), None);
+    }
+
+    #[test]
+    fn test_verification() {
+        let health = ProviderHealth::default();
+        assert_eq!(health.verification(), Verification::Unverified);
+        health.record_verification(&Ok(()));
+        assert_eq!(health.verification(), Verification::Passed);
+
+        health.record_verification(&Err(anyhow::anyhow!("invalid API key")));
+        assert_eq!(
+            health.verification(),
+            Verification::Failed("invalid API key".into())
+        );
+        assert_eq!(health.last_error().as_deref(), Some("invalid API key"));
+        assert_eq!(health.retry_delay(), Some(INITIAL_RETRY_DELAY));
+
+        // Only verification decides whether the provider is verified, not whether other
+        // requests succeeded or failed.
+        health.record_success();
+        assert_eq!(
+            health.verification(),
+            Verification::Failed("invalid API key".into())
+        );
+        health.record_verification(&Ok(()));
+        health.record_failure("offline");
+        assert_eq!(health.verification(), Verification::Passed);
+    }
+}
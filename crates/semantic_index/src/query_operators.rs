@@ -0,0 +1,172 @@
+use crate::ChunkOwner;
+use language::LanguageRegistry;
+use std::{path::Path, sync::Arc};
+use util::{
+    paths::{PathExt, PathMatcher},
+    ResultExt,
+};
+
+/// Restricts a search to some of the project's files, according to operators in its
+/// query like `lang:rust`, `path:src/` and `-path:tests`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SearchFilter {
+    /// Names or extensions of the languages whose files are searched.
+    pub languages: Vec<String>,
+    /// Paths or globs one of which the searched files must be in.
+    pub include_paths: Vec<String>,
+    /// Paths or globs none of which the searched files may be in.
+    pub exclude_paths: Vec<String>,
+}
+
+/// A search query, split into the text that's embedded and the filter given by its
+/// operators.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParsedQuery {
+    pub text: String,
+    pub filter: SearchFilter,
+}
+
+/// Extracts the operators from a search query. Words that aren't operators are kept as
+/// the query's text, and a query without operators is left as is.
+pub fn parse_query(query: &str) -> ParsedQuery {
+    let mut filter = SearchFilter::default();
+    let mut words = Vec::new();
+    for word in query.split_whitespace() {
+        match word.split_once(':').filter(|(_, value)| !value.is_empty()) {
+            Some(("lang", value)) => filter.languages.push(value.to_string()),
+            Some(("path", value)) => filter.include_paths.push(value.to_string()),
+            Some(("-path", value)) => filter.exclude_paths.push(value.to_string()),
+            _ => words.push(word),
+        }
+    }
+
+    let text = if filter.is_empty() {
+        query.to_string()
+    } else {
+        words.join(" ")
+    };
+    ParsedQuery { text, filter }
+}
+
+impl SearchFilter {
+    pub fn is_empty(&self) -> bool {
+        self.languages.is_empty() && self.include_paths.is_empty() && self.exclude_paths.is_empty()
+    }
+
+    /// Looks up the file extensions of the filter's languages. A name that isn't a
+    /// known language is taken to be an extension.
+    pub(crate) async fn resolve(&self, language_registry: &Arc<LanguageRegistry>) -> FileFilter {
+        let path_suffixes = if self.languages.is_empty() {
+            None
+        } else {
+            let mut path_suffixes = Vec::new();
+            for name in &self.languages {
+                match language_registry.language_for_name_or_extension(name).await {
+                    Ok(language) => path_suffixes.extend_from_slice(language.path_suffixes()),
+                    Err(_) => path_suffixes.push(name.clone()),
+                }
+            }
+            Some(path_suffixes)
+        };
+        let matchers = |paths: &[String]| {
+            paths
+                .iter()
+                .filter_map(|path| PathMatcher::new(path).log_err())
+                .collect()
+        };
+        FileFilter {
+            path_suffixes,
+            include_paths: matchers(&self.include_paths),
+            exclude_paths: matchers(&self.exclude_paths),
+        }
+    }
+}
+
+/// A [`SearchFilter`] that's ready to be matched against the chunks scanned by a search.
+#[derive(Default)]
+pub(crate) struct FileFilter {
+    /// The extensions or file names of the files in the filter's languages, if it has any.
+    path_suffixes: Option<Vec<String>>,
+    include_paths: Vec<PathMatcher>,
+    exclude_paths: Vec<PathMatcher>,
+}
+
+impl FileFilter {
+    /// Whether a search with this filter scores the chunks of `owner`. Documents aren't
+    /// files, so they're only searched when the filter doesn't require a language or path.
+    pub fn matches(&self, owner: &ChunkOwner) -> bool {
+        match owner {
+            ChunkOwner::File { path, .. } => self.matches_path(path),
+            ChunkOwner::Document(_) => {
+                self.path_suffixes.is_none() && self.include_paths.is_empty()
+            }
+        }
+    }
+
+    fn matches_path(&self, path: &Path) -> bool {
+        // A path operator names a directory anywhere in the project, so it's matched
+        // against every directory containing the file.
+        let is_in = |matcher: &PathMatcher| path.ancestors().any(|dir| matcher.is_match(dir));
+
+        if let Some(path_suffixes) = &self.path_suffixes {
+            let extension = path.extension_or_hidden_file_name();
+            let file_name = path.file_name().and_then(|name| name.to_str());
+            if !path_suffixes.iter().any(|suffix| {
+                Some(suffix.as_str()) == extension || Some(suffix.as_str()) == file_name
+            }) {
+                return false;
+            }
+        }
+        (self.include_paths.is_empty() || self.include_paths.iter().any(is_in))
+            && !self.exclude_paths.iter().any(is_in)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_query() {
+        assert_eq!(
+            parse_query("parse  the config\nfile"),
+            ParsedQuery {
+                text: "parse  the config\nfile".into(),
+                filter: SearchFilter::default(),
+            }
+        );
+        assert_eq!(
+            parse_query("lang:rust parse config path:src/ -path:tests note: a:b"),
+            ParsedQuery {
+                text: "parse config note: a:b".into(),
+                filter: SearchFilter {
+                    languages: vec!["rust".into()],
+                    include_paths: vec!["src/".into()],
+                    exclude_paths: vec!["tests".into()],
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_file_filter() {
+        let filter = FileFilter {
+            path_suffixes: Some(vec!["rs".into()]),
+            include_paths: vec![PathMatcher::new("src/").unwrap()],
+            exclude_paths: vec![PathMatcher::new("tests").unwrap()],
+        };
+        assert!(filter.matches_path(Path::new("src/main.rs")));
+        assert!(filter.matches_path(Path::new("crates/a/src/lib.rs")));
+        assert!(!filter.matches_path(Path::new("src/main.py")));
+        assert!(!filter.matches_path(Path::new("benches/main.rs")));
+        assert!(!filter.matches_path(Path::new("src/tests/main.rs")));
+        assert!(filter.matches_path(Path::new("src/latest.rs")));
+
+        let filter = FileFilter {
+            exclude_paths: vec![PathMatcher::new("**/*_test.go").unwrap()],
+            ..Default::default()
+        };
+        assert!(filter.matches_path(Path::new("pkg/server.go")));
+        assert!(!filter.matches_path(Path::new("pkg/server_test.go")));
+    }
+}
@@ -0,0 +1,90 @@
+use crate::ChunkOwner;
+use collections::HashSet;
+use gpui::AppContext;
+use project::{Project, WorktreeId};
+use std::{
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+/// How long after a file was last modified its recency boost halves.
+const RECENCY_HALF_LIFE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Raises the scores of files the user is working on, so that among similarly relevant
+/// results, the ones from open or recently modified files come first.
+pub(crate) struct RecencyBoost {
+    max_boost: f32,
+    now: SystemTime,
+    open_files: HashSet<(WorktreeId, Arc<Path>)>,
+}
+
+impl RecencyBoost {
+    pub fn new(max_boost: f32, project: &Project, cx: &AppContext) -> Self {
+        let open_files = if max_boost > 0. {
+            project
+                .opened_buffers()
+                .into_iter()
+                .filter_map(|buffer| {
+                    let file = buffer.read(cx).file()?;
+                    Some((
+                        WorktreeId::from_usize(file.worktree_id()),
+                        file.path().clone(),
+                    ))
+                })
+                .collect()
+        } else {
+            HashSet::default()
+        };
+        Self {
+            max_boost,
+            now: SystemTime::now(),
+            open_files,
+        }
+    }
+
+    /// How much to add to the score of a chunk from the given owner. Open files get the
+    /// full boost, other files a share of it that decays with the time since they were
+    /// last modified.
+    pub fn boost(&self, owner: &ChunkOwner) -> f32 {
+        if self.max_boost <= 0. {
+            return 0.;
+        }
+        match owner {
+            ChunkOwner::File {
+                worktree_id,
+                path,
+                mtime,
+                ..
+            } => {
+                if self.open_files.contains(&(*worktree_id, path.clone())) {
+                    self.max_boost
+                } else if let Some(mtime) = mtime {
+                    let age = self.now.duration_since(*mtime).unwrap_or_default();
+                    self.max_boost * recency_factor(age)
+                } else {
+                    0.
+                }
+            }
+            ChunkOwner::Document(_) => 0.,
+        }
+    }
+}
+
+/// A factor from 1, for a file modified just now, decaying towards 0 as the file ages.
+fn recency_factor(age: Duration) -> f32 {
+    0.5f32.powf(age.as_secs_f32() / RECENCY_HALF_LIFE.as_secs_f32())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recency_factor() {
+        assert_eq!(recency_factor(Duration::ZERO), 1.);
+        assert!((recency_factor(RECENCY_HALF_LIFE) - 0.5).abs() < 1e-6);
+        assert!((recency_factor(RECENCY_HALF_LIFE * 2) - 0.25).abs() < 1e-6);
+        assert!(recency_factor(RECENCY_HALF_LIFE * 30) < 1e-6);
+    }
+}
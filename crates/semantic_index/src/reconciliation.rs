@@ -0,0 +1,96 @@
+use crate::{IndexRequest, ProjectIndex, VectorStoreSettings};
+use gpui::{AppContext, AsyncAppContext, Task};
+use settings::Settings;
+use smol::channel;
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, SystemTime},
+};
+
+/// How often to check whether reconciliation was enabled while it's disabled.
+const RECONCILIATION_SETTINGS_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The outcome of the last pass that verified the contents of every indexed file, which
+/// repairs the index when file system events were missed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ReconciliationReport {
+    /// How many files were checked.
+    pub checked_count: usize,
+    /// How many files had to be indexed again or removed from the index.
+    pub repaired_count: usize,
+    pub finished_at: SystemTime,
+}
+
+/// Counts the files checked by a reconciliation pass while it's running.
+#[derive(Default)]
+pub(crate) struct ReconciliationCounts {
+    checked_count: AtomicUsize,
+    repaired_count: AtomicUsize,
+}
+
+impl ReconciliationCounts {
+    pub fn record_checked(&self) {
+        self.checked_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_repaired(&self, count: usize) {
+        self.repaired_count.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn report(&self) -> ReconciliationReport {
+        ReconciliationReport {
+            checked_count: self.checked_count.load(Ordering::Relaxed),
+            repaired_count: self.repaired_count.load(Ordering::Relaxed),
+            finished_at: SystemTime::now(),
+        }
+    }
+}
+
+/// Requests a reconciliation pass every `reconcile_interval_minutes`, for as long as the
+/// worktree index receiving the requests exists.
+pub(crate) fn reconcile_periodically(
+    index_requests_tx: channel::Sender<IndexRequest>,
+    cx: &AppContext,
+) -> Task<()> {
+    cx.spawn(|cx: AsyncAppContext| async move {
+        loop {
+            let Ok(interval) =
+                cx.update(|cx| VectorStoreSettings::get_global(cx).reconcile_interval())
+            else {
+                break;
+            };
+            match interval {
+                Some(interval) => {
+                    cx.background_executor().timer(interval).await;
+                    if index_requests_tx
+                        .send(IndexRequest::Reconcile)
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+                None => {
+                    cx.background_executor()
+                        .timer(RECONCILIATION_SETTINGS_POLL_INTERVAL)
+                        .await;
+                }
+            }
+        }
+    })
+}
+
+impl ProjectIndex {
+    /// Sums up the last reconciliation pass of every worktree, or returns `None` if none of
+    /// them has completed one yet.
+    pub fn last_reconciliation(&self, cx: &AppContext) -> Option<ReconciliationReport> {
+        self.worktree_indices(cx)
+            .into_iter()
+            .filter_map(|index| index.read(cx).last_reconciliation)
+            .reduce(|a, b| ReconciliationReport {
+                checked_count: a.checked_count + b.checked_count,
+                repaired_count: a.repaired_count + b.repaired_count,
+                finished_at: a.finished_at.max(b.finished_at),
+            })
+    }
+}
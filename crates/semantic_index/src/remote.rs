@@ -0,0 +1,212 @@
+//! Semantic search in shared and remote projects.
+//!
+//! Guests have no access to the files of a remote project's worktrees, so they can't
+//! index them. Instead, searches and refreshes are forwarded to the host, which answers
+//! them from the index of its own project. Guests can't make the host index a project it
+//! didn't choose to index, since that's paid for by the host.
+
+use crate::{
+    search_within::SearchScope, PositionHints, ProjectIndex, SearchResult, SearchResultSource,
+    SearchResultStream, SemanticIndex,
+};
+use anyhow::{anyhow, Result};
+use client::{proto, Client, TypedEnvelope};
+use futures::StreamExt;
+use gpui::{AppContext, AsyncAppContext, Model};
+use project::{Project, WorktreeId};
+use smol::channel;
+use std::{path::Path, sync::Arc};
+
+/// How many more results a scoped remote search requests than it returns, to make up for
+/// the results outside of its scope.
+const SCOPED_SEARCH_LIMIT_FACTOR: usize = 8;
+
+/// The most results a host returns for a guest's search, and so the most a guest asks for.
+const MAX_REMOTE_SEARCH_LIMIT: usize = 256;
+
+pub(crate) fn init(client: &Arc<Client>) {
+    client.add_model_request_handler(handle_search_project_index);
+    client.add_model_request_handler(handle_refresh_project_index);
+}
+
+/// Searches the host's index for a guest. Unlike the host's own searches, the query isn't
+/// recorded in the project's search history.
+async fn handle_search_project_index(
+    project: Model<Project>,
+    envelope: TypedEnvelope<proto::SearchProjectIndex>,
+    _: Arc<Client>,
+    mut cx: AsyncAppContext,
+) -> Result<proto::SearchProjectIndexResponse> {
+    let project_index = host_project_index(project, &mut cx)?;
+    let payload = envelope.payload;
+    let limit = usize::try_from(payload.limit)
+        .unwrap_or(usize::MAX)
+        .min(MAX_REMOTE_SEARCH_LIMIT);
+    let SearchResultStream {
+        results: mut result_batches,
+        task,
+    } = project_index.read_with(&cx, |project_index, cx| {
+        project_index.search_stream(
+            payload.query,
+            limit,
+            payload.min_similarity,
+            payload.diversity,
+            cx,
+        )
+    })?;
+    let mut results = Vec::new();
+    while let Some(batch) = result_batches.next().await {
+        results = batch;
+    }
+    task.await?;
+
+    cx.update(|cx| proto::SearchProjectIndexResponse {
+        results: results
+            .into_iter()
+            .filter_map(|result| {
+                // Documents belong to the host, so they aren't shared with guests.
+                let SearchResultSource::File { worktree, path } = result.source else {
+                    return None;
+                };
+                Some(proto::ProjectIndexSearchResult {
+                    worktree_id: worktree.read(cx).id().to_proto(),
+                    path: path.to_string_lossy().into_owned(),
+                    start: result.range.start as u64,
+                    end: result.range.end as u64,
+                    score: result.score,
+                    relevance: result.relevance,
+                })
+            })
+            .collect(),
+    })
+}
+
+async fn handle_refresh_project_index(
+    project: Model<Project>,
+    _: TypedEnvelope<proto::RefreshProjectIndex>,
+    _: Arc<Client>,
+    mut cx: AsyncAppContext,
+) -> Result<proto::Ack> {
+    let project_index = host_project_index(project, &mut cx)?;
+    project_index.read_with(&cx, |project_index, cx| project_index.refresh(cx))?;
+    Ok(proto::Ack {})
+}
+
+/// The index of a shared project, if the host indexed it.
+fn host_project_index(
+    project: Model<Project>,
+    cx: &mut AsyncAppContext,
+) -> Result<Model<ProjectIndex>> {
+    cx.update(|cx| {
+        cx.try_global::<SemanticIndex>()
+            .and_then(|semantic_index| semantic_index.existing_project_index(&project))
+            .ok_or_else(|| anyhow!("the host hasn't indexed this project"))
+    })?
+}
+
+impl ProjectIndex {
+    /// Whether this index belongs to a remote project, whose searches are answered by
+    /// the host.
+    pub fn is_remote(&self, cx: &AppContext) -> bool {
+        self.project
+            .upgrade()
+            .map_or(false, |project| project.read(cx).is_remote())
+    }
+
+    /// Asks the host of a remote project to rescan its worktrees.
+    pub(crate) fn refresh_remote(&self, cx: &AppContext) -> Result<()> {
+        let (client, project_id) = self.remote_client(cx)?;
+        cx.background_executor()
+            .spawn(async move {
+                client
+                    .request(proto::RefreshProjectIndex { project_id })
+                    .await
+            })
+            .detach_and_log_err(cx);
+        Ok(())
+    }
+
+    /// Forwards a search to the host of a remote project. The host only reports its
+    /// final results, so the stream yields a single batch.
+    ///
+    /// The host doesn't know about the `scope` of a search, so scoped searches fetch
+    /// more results and drop the ones outside of the scope.
+    pub(crate) fn search_remote(
+        &self,
+        query: String,
+        limit: usize,
+        min_similarity: Option<f32>,
+        diversity: f32,
+        scope: Option<Arc<SearchScope>>,
+        cx: &AppContext,
+    ) -> SearchResultStream {
+        let (results_tx, results_rx) = channel::bounded(1);
+        let remote_client = self.remote_client(cx);
+        let project = self.project.clone();
+        let task = cx.spawn(|cx| async move {
+            let (client, project_id) = remote_client?;
+            let request_limit = if scope.is_some() {
+                limit.saturating_mul(SCOPED_SEARCH_LIMIT_FACTOR)
+            } else {
+                limit
+            }
+            .min(MAX_REMOTE_SEARCH_LIMIT);
+            let response = client
+                .request(proto::SearchProjectIndex {
+                    project_id,
+                    query,
+                    limit: request_limit as u64,
+                    diversity,
+                    min_similarity,
+                })
+                .await?;
+
+            let results = project.read_with(&cx, |project, cx| {
+                response
+                    .results
+                    .into_iter()
+                    .filter_map(|result| {
+                        let worktree_id = WorktreeId::from_proto(result.worktree_id);
+                        let path: Arc<Path> = Path::new(&result.path).into();
+                        if let Some(scope) = &scope {
+                            if !scope.contains_file(worktree_id, &path) {
+                                return None;
+                            }
+                        }
+                        Some(SearchResult {
+                            source: SearchResultSource::File {
+                                worktree: project.worktree_for_id(worktree_id, cx)?,
+                                path,
+                            },
+                            range: result.start as usize..result.end as usize,
+                            score: result.score,
+                            relevance: result.relevance,
+                            position_hints: PositionHints::default(),
+                            unsaved_text: None,
+                        })
+                    })
+                    .take(limit)
+                    .collect::<Vec<_>>()
+            })?;
+            results_tx.send(results).await.ok();
+            Ok(())
+        });
+
+        SearchResultStream {
+            results: results_rx,
+            task,
+        }
+    }
+
+    fn remote_client(&self, cx: &AppContext) -> Result<(Arc<Client>, u64)> {
+        let project = self
+            .project
+            .upgrade()
+            .ok_or_else(|| anyhow!("project was dropped"))?;
+        let project = project.read(cx);
+        let project_id = project
+            .remote_id()
+            .ok_or_else(|| anyhow!("project is not shared"))?;
+        Ok((project.client(), project_id))
+    }
+}
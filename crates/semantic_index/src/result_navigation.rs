@@ -0,0 +1,165 @@
+use crate::{chunking::find_scope, ProjectIndex, SearchResult, SearchResultSource};
+use anyhow::{anyhow, Result};
+use gpui::{AppContext, Model, Task};
+use language::{Anchor, Buffer, Language};
+use project::ProjectPath;
+use sha2::{Digest, Sha256};
+use std::{ops::Range, path::Path, sync::Arc};
+
+/// How many of the lines closest to a result's original position are checked for its
+/// text when the file was edited above it.
+const MAX_RELOCATION_CANDIDATES: usize = 2000;
+
+/// What's known about a [`SearchResult`] besides its range, so that it can be found again
+/// after its file was edited. See [`SearchResult::resolve_range`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PositionHints {
+    /// The SHA-256 digest of the result's text when its file was indexed.
+    pub digest: Option<[u8; 32]>,
+    /// The outline items enclosing the start of the result, e.g. `impl Foo > fn bar`.
+    pub scope_path: Option<String>,
+}
+
+impl SearchResult {
+    /// Finds the result in the current `text` of its file, which may have been edited
+    /// since the file was indexed. The result's range is kept if its text didn't change,
+    /// and otherwise its text is looked for at the start of the lines around it. If the
+    /// text itself was edited, the result lands on the outline item it was in, and only
+    /// if that's gone too on its original range, clipped to the text.
+    pub fn resolve_range(
+        &self,
+        text: &str,
+        language: Option<&Arc<Language>>,
+        path: &Path,
+    ) -> Range<usize> {
+        resolve_range(text, &self.range, &self.position_hints, |scope_path| {
+            find_scope(text, language, path, scope_path, self.range.start)
+        })
+    }
+}
+
+impl ProjectIndex {
+    /// Opens the file of a search result, e.g. when it's clicked, and returns where the
+    /// result is in it now. See [`SearchResult::resolve_range`].
+    pub fn open_result(
+        &self,
+        result: &SearchResult,
+        cx: &mut AppContext,
+    ) -> Task<Result<(Model<Buffer>, Range<Anchor>)>> {
+        let SearchResultSource::File { worktree, path } = &result.source else {
+            return Task::ready(Err(anyhow!("only results from files can be opened")));
+        };
+        let Some(project) = self.project.upgrade() else {
+            return Task::ready(Err(anyhow!("project was dropped")));
+        };
+        let project_path = ProjectPath {
+            worktree_id: worktree.read(cx).id(),
+            path: path.clone(),
+        };
+        let open_buffer = project.update(cx, |project, cx| project.open_buffer(project_path, cx));
+        let result = result.clone();
+        let path = path.clone();
+        cx.spawn(|cx| async move {
+            let buffer = open_buffer.await?;
+            let snapshot = buffer.read_with(&cx, |buffer, _| buffer.snapshot())?;
+            let range = cx
+                .background_executor()
+                .spawn(async move {
+                    let range = result.resolve_range(&snapshot.text(), snapshot.language(), &path);
+                    snapshot.anchor_before(range.start)..snapshot.anchor_after(range.end)
+                })
+                .await;
+            Ok((buffer, range))
+        })
+    }
+}
+
+fn resolve_range(
+    text: &str,
+    range: &Range<usize>,
+    hints: &PositionHints,
+    find_scope: impl FnOnce(&str) -> Option<Range<usize>>,
+) -> Range<usize> {
+    if let Some(digest) = &hints.digest {
+        let has_text = |range: &Range<usize>| {
+            text.get(range.clone()).map_or(false, |range_text| {
+                <[u8; 32]>::from(Sha256::digest(range_text)) == *digest
+            })
+        };
+        if has_text(range) {
+            return range.clone();
+        }
+
+        let mut line_starts = Some(0)
+            .into_iter()
+            .chain(text.match_indices('\n').map(|(ix, _)| ix + 1))
+            .collect::<Vec<_>>();
+        line_starts.sort_by_key(|start| start.abs_diff(range.start));
+        if let Some(relocated) = line_starts
+            .into_iter()
+            .take(MAX_RELOCATION_CANDIDATES)
+            .map(|start| start..start + range.len())
+            .find(has_text)
+        {
+            return relocated;
+        }
+    }
+
+    if let Some(item_range) = hints.scope_path.as_deref().and_then(find_scope) {
+        let end = (item_range.start + range.len()).min(item_range.end);
+        return item_range.start..end;
+    }
+
+    let clip = |mut offset: usize| {
+        offset = offset.min(text.len());
+        while !text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        offset
+    };
+    clip(range.start)..clip(range.end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_range() {
+        let indexed_text = "fn a() {}\nfn b() {}\n";
+        let range = 10..19;
+        let hints = PositionHints {
+            digest: Some(Sha256::digest(&indexed_text[range.clone()]).into()),
+            scope_path: Some("fn b".into()),
+        };
+        let no_scope = |_: &str| None;
+
+        // The file didn't change.
+        assert_eq!(
+            resolve_range(indexed_text, &range, &hints, no_scope),
+            10..19
+        );
+
+        // Lines were added above the result.
+        let text = "use c;\n\nfn a() {}\nfn b() {}\n";
+        assert_eq!(
+            &text[resolve_range(text, &range, &hints, no_scope)],
+            "fn b() {}"
+        );
+
+        // The result itself was edited, so it's found by its scope.
+        let text = "fn a() {}\n\nfn b() { c() }\n";
+        let b_start = text.find("fn b").unwrap();
+        assert_eq!(
+            resolve_range(text, &range, &hints, |scope_path| {
+                assert_eq!(scope_path, "fn b");
+                Some(b_start..text.len() - 1)
+            }),
+            b_start..b_start + range.len()
+        );
+
+        // Without any hint that still applies, the range is clipped to the text.
+        let text = "fn a() {}\nfn b()😀";
+        assert_eq!(resolve_range(text, &range, &hints, no_scope), 10..16);
+    }
+}
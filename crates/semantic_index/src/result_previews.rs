@@ -0,0 +1,181 @@
+use crate::{ProjectIndex, SearchResult, SearchResultSource};
+use anyhow::Result;
+use gpui::{AppContext, Task};
+use language::{HighlightId, Language, Rope};
+use std::{ops::Range, path::Path, sync::Arc};
+
+/// How many lines before and after a result are included in its preview.
+const PREVIEW_CONTEXT_LINES: usize = 2;
+
+/// Everything needed to render a syntax-highlighted preview of a [`SearchResult`],
+/// without reading its file again.
+pub struct ResultPreview {
+    pub language: Option<Arc<Language>>,
+    /// The lines of the result, along with a few lines around them.
+    pub text: String,
+    /// The zero-based row of the first line of `text` in the result's file.
+    pub start_row: u32,
+    /// The range of the result within `text`.
+    pub match_range: Range<usize>,
+    /// The syntax highlights of `text`, with ranges within it. Their styles are found in
+    /// the theme's syntax theme.
+    pub highlights: Vec<(Range<usize>, HighlightId)>,
+}
+
+impl ProjectIndex {
+    /// Loads the text that the range of `result` refers to: the text of the unsaved
+    /// buffer it was found in, or else the contents of its file or document. Returns
+    /// `None` when the text can't be loaded, e.g. in a remote project.
+    pub fn result_text(
+        &self,
+        result: &SearchResult,
+        cx: &AppContext,
+    ) -> Task<Result<Option<String>>> {
+        if let Some(text) = &result.unsaved_text {
+            return Task::ready(Ok(Some(text.to_string())));
+        }
+        match &result.source {
+            _ if self.is_remote(cx) => Task::ready(Ok(None)),
+            SearchResultSource::File { worktree, path } => {
+                let abs_path = worktree.read(cx).abs_path().join(path);
+                let fs = self.fs.clone();
+                cx.background_executor()
+                    .spawn(async move { Ok(Some(fs.load(&abs_path).await?)) })
+            }
+            SearchResultSource::Document(id) => self.load_document(id.clone(), cx),
+        }
+    }
+
+    /// Loads a preview of each of `results`, in the same order. A result has no preview
+    /// when its text can't be loaded, e.g. in a remote project.
+    pub fn result_previews(
+        &self,
+        results: &[SearchResult],
+        cx: &AppContext,
+    ) -> Task<Result<Vec<Option<ResultPreview>>>> {
+        let previews = results
+            .iter()
+            .map(|result| {
+                let range = result.range.clone();
+                let text = self.result_text(result, cx);
+                let path: Option<Arc<Path>> = match &result.source {
+                    SearchResultSource::File { path, .. } => Some(path.clone()),
+                    SearchResultSource::Document(_) => None,
+                };
+                let language_registry = self.language_registry.clone();
+                async move {
+                    let text = match text.await {
+                        Ok(Some(text)) => text,
+                        Ok(None) => return None,
+                        Err(error) => {
+                            log::warn!("failed to load search result: {error}");
+                            return None;
+                        }
+                    };
+                    let language = match &path {
+                        Some(path) => language_registry.language_for_file_path(path).await.ok(),
+                        None => None,
+                    };
+                    preview(text, range, language)
+                }
+            })
+            .collect::<Vec<_>>();
+
+        cx.background_executor()
+            .spawn(async move { Ok(futures::future::join_all(previews).await) })
+    }
+}
+
+fn preview(
+    text: String,
+    range: Range<usize>,
+    language: Option<Arc<Language>>,
+) -> Option<ResultPreview> {
+    let (preview_range, start_row) = preview_range(&text, range.clone(), PREVIEW_CONTEXT_LINES)?;
+    // The whole file is parsed, so that the preview is highlighted like in the editor
+    // even when it starts within a string or a comment.
+    let highlights = match &language {
+        Some(language) => {
+            language.highlight_text(&Rope::from(text.as_str()), preview_range.clone())
+        }
+        None => Vec::new(),
+    };
+    Some(ResultPreview {
+        language,
+        text: text[preview_range.clone()].to_string(),
+        start_row,
+        match_range: range.start - preview_range.start..range.end - preview_range.start,
+        highlights,
+    })
+}
+
+/// Extends `range` to whole lines, plus `context_lines` lines before and after it.
+/// Returns the extended range and the row of its first line, or `None` if `range` isn't
+/// within `text`.
+fn preview_range(
+    text: &str,
+    range: Range<usize>,
+    context_lines: usize,
+) -> Option<(Range<usize>, u32)> {
+    text.get(range.clone())?;
+
+    let mut start = text[..range.start].rfind('\n').map_or(0, |ix| ix + 1);
+    for _ in 0..context_lines {
+        if start == 0 {
+            break;
+        }
+        start = text[..start - 1].rfind('\n').map_or(0, |ix| ix + 1);
+    }
+
+    let mut end = range.end;
+    // A range ending at the start of a line doesn't include that line.
+    if end > range.start && text[..end].ends_with('\n') {
+        end -= 1;
+    }
+    end = text[end..].find('\n').map_or(text.len(), |ix| end + ix);
+    for _ in 0..context_lines {
+        if end == text.len() {
+            break;
+        }
+        end = text[end + 1..]
+            .find('\n')
+            .map_or(text.len(), |ix| end + 1 + ix);
+    }
+
+    let start_row = text[..start].matches('\n').count() as u32;
+    Some((start..end, start_row))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preview_range() {
+        let text = "zero\none\ntwo\nthree\nfour\nfive\nsix";
+        let range_of = |needle: &str| {
+            let start = text.find(needle).unwrap();
+            start..start + needle.len()
+        };
+
+        let (range, start_row) = preview_range(text, range_of("three"), 1).unwrap();
+        assert_eq!(&text[range], "two\nthree\nfour");
+        assert_eq!(start_row, 2);
+
+        let (range, start_row) = preview_range(text, range_of("ne\ntw"), 0).unwrap();
+        assert_eq!(&text[range], "one\ntwo");
+        assert_eq!(start_row, 1);
+
+        let (range, start_row) = preview_range(text, range_of("zero"), 2).unwrap();
+        assert_eq!(&text[range], "zero\none\ntwo");
+        assert_eq!(start_row, 0);
+
+        let (range, _) = preview_range(text, range_of("six"), 2).unwrap();
+        assert_eq!(&text[range], "four\nfive\nsix");
+
+        let (range, _) = preview_range(text, range_of("one\n"), 1).unwrap();
+        assert_eq!(&text[range], "zero\none\ntwo");
+
+        assert_eq!(preview_range(text, 0..100, 2), None);
+    }
+}
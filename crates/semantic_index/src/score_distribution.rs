@@ -0,0 +1,115 @@
+use crate::{db_writer::DbWriter, EmbeddingProvider};
+use anyhow::{Context as _, Result};
+use heed::types::{SerdeBincode, Str};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+
+/// The database holding a [`ScoreDistribution`] for each embedding model, keyed by
+/// [`score_distribution_key`].
+const SCORE_DISTRIBUTIONS_DB_NAME: &str = "score_distributions";
+
+/// How many searches must be recorded for a model before its scores are normalized.
+const MIN_RECORDED_SEARCHES: u32 = 5;
+
+/// Caps the weight given to past searches, so that a distribution keeps adapting to
+/// how the index changes over time.
+const MAX_RECORDED_SEARCHES: u32 = 1000;
+
+/// The scores an embedding model has produced in past searches, which put the raw
+/// scores of new searches into perspective. Raw scores aren't comparable across models:
+/// one model's best matches may score 0.4 while another's unrelated chunks score 0.7.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct ScoreDistribution {
+    search_count: u32,
+    /// The mean score of all the chunks scored by a search, averaged over searches.
+    mean_score: f32,
+    /// The score of the best result of a search, averaged over searches.
+    mean_top_score: f32,
+}
+
+impl ScoreDistribution {
+    pub fn record(&mut self, mean_score: f32, top_score: f32) {
+        self.search_count = (self.search_count + 1).min(MAX_RECORDED_SEARCHES);
+        let weight = 1. / self.search_count as f32;
+        self.mean_score += (mean_score - self.mean_score) * weight;
+        self.mean_top_score += (top_score - self.mean_top_score) * weight;
+    }
+
+    /// Maps a raw score to a relevance between 0 and 100, where 0 is the score of a
+    /// typical chunk and 100 is the score of a typical best result. Returns `None` until
+    /// enough searches have been recorded.
+    pub fn relevance(&self, score: f32) -> Option<f32> {
+        if self.search_count < MIN_RECORDED_SEARCHES {
+            return None;
+        }
+        let spread = self.mean_top_score - self.mean_score;
+        if spread <= f32::EPSILON {
+            return None;
+        }
+        Some((100. * (score - self.mean_score) / spread).clamp(0., 100.))
+    }
+}
+
+/// Identifies the scores produced by a provider's model and metric, or `None` if the
+/// model is unknown, in which case scores aren't normalized.
+pub(crate) fn score_distribution_key(embedding_provider: &dyn EmbeddingProvider) -> Option<String> {
+    let model = embedding_provider.model_metadata()?;
+    Some(format!(
+        "{}\0{:?}",
+        model.name,
+        embedding_provider.similarity_metric()
+    ))
+}
+
+pub(crate) fn load_score_distribution(
+    db_connection: &heed::Env,
+    key: &str,
+) -> Result<Option<ScoreDistribution>> {
+    let txn = db_connection
+        .read_txn()
+        .context("failed to create read transaction")?;
+    let Some(db) = db_connection.open_database::<Str, SerdeBincode<ScoreDistribution>>(
+        &txn,
+        Some(SCORE_DISTRIBUTIONS_DB_NAME),
+    )?
+    else {
+        return Ok(None);
+    };
+    Ok(db.get(&txn, key)?)
+}
+
+/// Adds the scores of a search to the distribution stored under `key`.
+pub(crate) fn record_score_distribution(
+    db_writer: &DbWriter,
+    key: String,
+    mean_score: f32,
+    top_score: f32,
+) -> impl Future<Output = Result<()>> {
+    db_writer.write(move |db_connection, txn| {
+        let db: heed::Database<Str, SerdeBincode<ScoreDistribution>> =
+            db_connection.create_database(txn, Some(SCORE_DISTRIBUTIONS_DB_NAME))?;
+        let mut distribution = db.get(txn, &key)?.unwrap_or_default();
+        distribution.record(mean_score, top_score);
+        db.put(txn, &key, &distribution)?;
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_distribution_relevance() {
+        let mut distribution = ScoreDistribution::default();
+        for _ in 0..MIN_RECORDED_SEARCHES - 1 {
+            distribution.record(0.25, 0.75);
+        }
+        assert_eq!(distribution.relevance(0.75), None);
+
+        distribution.record(0.25, 0.75);
+        assert_eq!(distribution.relevance(0.), Some(0.));
+        assert_eq!(distribution.relevance(0.5), Some(50.));
+        assert_eq!(distribution.relevance(1.), Some(100.));
+    }
+}
@@ -0,0 +1,164 @@
+use crate::{ProjectIndex, SearchResult, SearchResultSource, WorktreeIndex};
+use anyhow::{Context as _, Result};
+use collections::HashSet;
+use gpui::{AppContext, AsyncAppContext, Model, Task};
+use project::{ProjectPath, WorktreeId};
+use serde::{Deserialize, Serialize};
+use std::{path::Path, sync::Arc, time::SystemTime};
+
+/// How many queries are remembered for each worktree.
+const MAX_SEARCH_HISTORY_LEN: usize = 100;
+
+/// How many result paths are remembered for each query, so that it can be suggested
+/// for those files later on.
+const MAX_RECORDED_RESULT_PATHS: usize = 32;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct SearchHistoryEntry {
+    query: String,
+    searched_at: SystemTime,
+    result_paths: Vec<Arc<Path>>,
+}
+
+impl ProjectIndex {
+    /// Returns the most recent distinct queries starting with `prefix`, ignoring case,
+    /// for offering completions as the user types a query.
+    pub fn search_history(
+        &self,
+        prefix: &str,
+        limit: usize,
+        cx: &AppContext,
+    ) -> Task<Result<Vec<String>>> {
+        let prefix = prefix.to_lowercase();
+        self.recent_searches(limit, cx, move |_, entry| {
+            entry.query.to_lowercase().starts_with(&prefix)
+        })
+    }
+
+    /// Returns the most recent distinct queries whose results included the given file.
+    pub fn related_searches(
+        &self,
+        path: ProjectPath,
+        limit: usize,
+        cx: &AppContext,
+    ) -> Task<Result<Vec<String>>> {
+        self.recent_searches(limit, cx, move |worktree_id, entry| {
+            worktree_id == path.worktree_id && entry.result_paths.contains(&path.path)
+        })
+    }
+
+    fn recent_searches(
+        &self,
+        limit: usize,
+        cx: &AppContext,
+        predicate: impl 'static + Send + Fn(WorktreeId, &SearchHistoryEntry) -> bool,
+    ) -> Task<Result<Vec<String>>> {
+        let histories = self
+            .worktree_indices(cx)
+            .into_iter()
+            .map(|index| {
+                let index = index.read(cx);
+                let worktree_id = index.worktree.read(cx).id();
+                let history = index.search_history(cx);
+                async move { anyhow::Ok((worktree_id, history.await?)) }
+            })
+            .collect::<Vec<_>>();
+        cx.background_executor().spawn(async move {
+            let mut entries = Vec::new();
+            for (worktree_id, history) in futures::future::try_join_all(histories).await? {
+                entries.extend(
+                    history
+                        .into_iter()
+                        .filter(|entry| predicate(worktree_id, entry)),
+                );
+            }
+            entries.sort_by(|a, b| b.searched_at.cmp(&a.searched_at));
+
+            let mut seen_queries = HashSet::default();
+            Ok(entries
+                .into_iter()
+                .filter(|entry| seen_queries.insert(entry.query.clone()))
+                .map(|entry| entry.query)
+                .take(limit)
+                .collect())
+        })
+    }
+
+    pub(crate) async fn record_search(
+        worktree_indices: Vec<Model<WorktreeIndex>>,
+        query: String,
+        results: &[SearchResult],
+        cx: &AsyncAppContext,
+    ) -> Result<()> {
+        for index in worktree_indices {
+            let record = index.read_with(cx, |index, cx| {
+                let result_paths = results
+                    .iter()
+                    .filter_map(|result| match &result.source {
+                        SearchResultSource::File { worktree, path }
+                            if *worktree == index.worktree =>
+                        {
+                            Some(path.clone())
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                index.record_search(query.clone(), result_paths, cx)
+            })?;
+            record.await?;
+        }
+        Ok(())
+    }
+}
+
+impl WorktreeIndex {
+    fn search_history(&self, cx: &AppContext) -> Task<Result<Vec<SearchHistoryEntry>>> {
+        let db_name = self
+            .worktree
+            .read(cx)
+            .abs_path()
+            .to_string_lossy()
+            .into_owned();
+        let db_connection = self.db_connection.clone();
+        let db = self.search_history_db;
+        cx.background_executor().spawn(async move {
+            let txn = db_connection
+                .read_txn()
+                .context("failed to create read transaction")?;
+            Ok(db.get(&txn, &db_name)?.unwrap_or_default())
+        })
+    }
+
+    fn record_search(
+        &self,
+        query: String,
+        mut result_paths: Vec<Arc<Path>>,
+        cx: &AppContext,
+    ) -> Task<Result<()>> {
+        let db_name = self
+            .worktree
+            .read(cx)
+            .abs_path()
+            .to_string_lossy()
+            .into_owned();
+        let db = self.search_history_db;
+        let write = self.db_writer.write(move |_, txn| {
+            let mut history = db.get(txn, &db_name)?.unwrap_or_default();
+            history.retain(|entry| entry.query != query);
+            let mut seen_paths = HashSet::default();
+            result_paths.retain(|path| seen_paths.insert(path.clone()));
+            result_paths.truncate(MAX_RECORDED_RESULT_PATHS);
+            history.push(SearchHistoryEntry {
+                query,
+                searched_at: SystemTime::now(),
+                result_paths,
+            });
+            if history.len() > MAX_SEARCH_HISTORY_LEN {
+                history.drain(..history.len() - MAX_SEARCH_HISTORY_LEN);
+            }
+            db.put(txn, &db_name, &history)?;
+            Ok(())
+        });
+        cx.background_executor().spawn(write)
+    }
+}
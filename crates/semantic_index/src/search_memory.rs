@@ -0,0 +1,157 @@
+use futures::channel::oneshot;
+use parking_lot::Mutex;
+use std::{collections::VecDeque, sync::Arc};
+
+/// Bounds the memory a search uses for the files it has decoded from the index but not
+/// yet sent to be scored.
+///
+/// Files are read from the memory-mapped database without being copied, and each one is
+/// only decoded once its size fits within the limit alongside the other files being
+/// decoded. Reservations are granted in the order they were requested, so that a large
+/// file isn't starved by smaller ones.
+pub(crate) struct SearchMemory {
+    limit: usize,
+    state: Mutex<SearchMemoryState>,
+}
+
+struct SearchMemoryState {
+    reserved: usize,
+    waiting: VecDeque<(usize, oneshot::Sender<()>)>,
+}
+
+impl SearchMemoryState {
+    /// A reservation larger than the limit is granted once nothing else is reserved, so
+    /// that a single large file can still be searched.
+    fn fits(&self, bytes: usize, limit: usize) -> bool {
+        self.reserved == 0 || self.reserved.saturating_add(bytes) <= limit
+    }
+}
+
+impl SearchMemory {
+    pub fn new(limit: usize) -> Arc<Self> {
+        Arc::new(Self {
+            limit,
+            state: Mutex::new(SearchMemoryState {
+                reserved: 0,
+                waiting: VecDeque::new(),
+            }),
+        })
+    }
+
+    /// Waits until `bytes` fit within the limit, and reserves them until the returned
+    /// reservation is dropped.
+    pub async fn reserve(self: &Arc<Self>, bytes: usize) -> MemoryReservation {
+        let granted_rx = {
+            let mut state = self.state.lock();
+            if state.waiting.is_empty() && state.fits(bytes, self.limit) {
+                state.reserved += bytes;
+                None
+            } else {
+                let (granted_tx, granted_rx) = oneshot::channel();
+                state.waiting.push_back((bytes, granted_tx));
+                Some(granted_rx)
+            }
+        };
+        if let Some(granted_rx) = granted_rx {
+            // The sender is only dropped along with this, which `self` keeps alive.
+            granted_rx.await.ok();
+        }
+        MemoryReservation {
+            memory: self.clone(),
+            bytes,
+        }
+    }
+
+    /// How many bytes are currently reserved.
+    pub fn reserved(&self) -> usize {
+        self.state.lock().reserved
+    }
+
+    fn release(&self, bytes: usize) {
+        let mut state = self.state.lock();
+        state.reserved -= bytes;
+        while let Some((bytes, _)) = state.waiting.front() {
+            if !state.fits(*bytes, self.limit) {
+                break;
+            }
+            let (bytes, granted_tx) = state.waiting.pop_front().unwrap();
+            // Reservations that stopped waiting are skipped.
+            if granted_tx.send(()).is_ok() {
+                state.reserved += bytes;
+            }
+        }
+    }
+}
+
+/// Memory reserved for a search until this is dropped.
+pub(crate) struct MemoryReservation {
+    memory: Arc<SearchMemory>,
+    bytes: usize,
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        self.memory.release(self.bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gpui::TestAppContext;
+    use std::sync::atomic::{self, AtomicUsize};
+
+    #[gpui::test(iterations = 10)]
+    async fn test_reservations_stay_within_limit(cx: &mut TestAppContext) {
+        let memory = SearchMemory::new(100);
+        let peak = Arc::new(AtomicUsize::new(0));
+        let granted = Arc::new(AtomicUsize::new(0));
+        let reservations = [30, 50, 20, 70, 10, 40, 60, 30]
+            .into_iter()
+            .map(|bytes| {
+                let memory = memory.clone();
+                let peak = peak.clone();
+                let granted = granted.clone();
+                let executor = cx.executor();
+                cx.executor().spawn(async move {
+                    let _reservation = memory.reserve(bytes).await;
+                    peak.fetch_max(memory.reserved(), atomic::Ordering::SeqCst);
+                    granted.fetch_add(1, atomic::Ordering::SeqCst);
+                    executor.simulate_random_delay().await;
+                })
+            })
+            .collect::<Vec<_>>();
+        futures::future::join_all(reservations).await;
+
+        assert_eq!(granted.load(atomic::Ordering::SeqCst), 8);
+        assert!(peak.load(atomic::Ordering::SeqCst) <= 100);
+        assert_eq!(memory.reserved(), 0);
+    }
+
+    #[gpui::test]
+    async fn test_reservation_larger_than_limit(cx: &mut TestAppContext) {
+        let memory = SearchMemory::new(100);
+        let small = memory.reserve(10).await;
+        let large = cx.executor().spawn({
+            let memory = memory.clone();
+            async move { memory.reserve(150).await }
+        });
+        cx.run_until_parked();
+        assert_eq!(memory.reserved(), 10);
+
+        // The large reservation is only granted once nothing else is reserved, and
+        // reservations made meanwhile wait behind it.
+        let later = cx.executor().spawn({
+            let memory = memory.clone();
+            async move { memory.reserve(10).await }
+        });
+        drop(small);
+        let large = large.await;
+        cx.run_until_parked();
+        assert_eq!(memory.reserved(), 150);
+
+        drop(large);
+        let _later = later.await;
+        assert_eq!(memory.reserved(), 10);
+    }
+}
@@ -0,0 +1,310 @@
+use crate::{
+    search_memory::SearchMemory, ChunkOwner, DocumentId, EmbeddedChunk, PositionHints,
+    ProjectIndex, SearchResult, SearchResultSource, SearchResultStream, VectorStoreSettings,
+};
+use anyhow::Result;
+use collections::{HashMap, HashSet};
+use futures::StreamExt;
+use gpui::{AppContext, Task};
+use project::{ProjectPath, WorktreeId};
+use settings::Settings;
+use smol::channel;
+use std::{ops::Range, path::Path, sync::Arc};
+use util::ResultExt;
+
+/// How [`ProjectIndex::search_within`] matches the query against the chunks of the
+/// previous results' files.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum RefinementMode {
+    /// Rank chunks by their similarity to the query, like [`ProjectIndex::search`].
+    Semantic,
+    /// Only keep the chunks that contain the query, ignoring case. A result's score is
+    /// the number of times its chunk contains the query.
+    Keyword,
+}
+
+/// The files and documents a search is restricted to.
+#[derive(Default)]
+pub(crate) struct SearchScope {
+    pub files: HashMap<WorktreeId, HashSet<Arc<Path>>>,
+    /// Directories all of whose files are in scope, which are scanned without looking up
+    /// their files one by one.
+    pub directories: HashMap<WorktreeId, Arc<Path>>,
+    pub documents: HashSet<DocumentId>,
+}
+
+impl SearchScope {
+    fn for_directory(directory: ProjectPath) -> Self {
+        let mut scope = Self::default();
+        scope
+            .directories
+            .insert(directory.worktree_id, directory.path);
+        scope
+    }
+
+    pub fn contains_file(&self, worktree_id: WorktreeId, path: &Path) -> bool {
+        self.directories
+            .get(&worktree_id)
+            .map_or(false, |directory| path.starts_with(directory))
+            || self
+                .files
+                .get(&worktree_id)
+                .map_or(false, |paths| paths.contains(path))
+    }
+
+    fn for_results(results: &[SearchResult], cx: &AppContext) -> Self {
+        let mut scope = Self::default();
+        for result in results {
+            match &result.source {
+                SearchResultSource::File { worktree, path } => {
+                    scope
+                        .files
+                        .entry(worktree.read(cx).id())
+                        .or_default()
+                        .insert(path.clone());
+                }
+                SearchResultSource::Document(id) => {
+                    scope.documents.insert(id.clone());
+                }
+            }
+        }
+        scope
+    }
+}
+
+impl ProjectIndex {
+    /// Searches the files and documents of previous search `results` again, returning
+    /// the `limit` best matches for `query` among all of their chunks. This narrows down
+    /// a search, like searching within the results of a text search.
+    ///
+    /// Unlike [`ProjectIndex::search`], the query isn't recorded in the search history.
+    pub fn search_within(
+        &self,
+        results: &[SearchResult],
+        query: String,
+        mode: RefinementMode,
+        limit: usize,
+        cx: &AppContext,
+    ) -> Task<Result<Vec<SearchResult>>> {
+        let scope = Arc::new(SearchScope::for_results(results, cx));
+        match mode {
+            RefinementMode::Semantic => {
+                let SearchResultStream {
+                    results: mut result_batches,
+                    task,
+                } = self.search_stream_in_scope(query, limit, None, 0., Some(scope), cx);
+                cx.spawn(|_| async move {
+                    let mut results = Vec::new();
+                    while let Some(batch) = result_batches.next().await {
+                        results = batch;
+                    }
+                    task.await?;
+                    Ok(results)
+                })
+            }
+            RefinementMode::Keyword => self.keyword_search_in_scope(scope, query, limit, cx),
+        }
+    }
+
+    /// Searches the files in a directory of the project and its subdirectories, like
+    /// [`ProjectIndex::search`] does for the whole project.
+    ///
+    /// Unlike [`ProjectIndex::search`], the query isn't recorded in the search history.
+    pub fn search_in_directory(
+        &self,
+        directory: ProjectPath,
+        query: String,
+        limit: usize,
+        min_similarity: Option<f32>,
+        diversity: f32,
+        cx: &AppContext,
+    ) -> Task<Result<Vec<SearchResult>>> {
+        let scope = Arc::new(SearchScope::for_directory(directory));
+        let SearchResultStream {
+            results: mut result_batches,
+            task,
+        } = self.search_stream_in_scope(query, limit, min_similarity, diversity, Some(scope), cx);
+        cx.spawn(|_| async move {
+            let mut results = Vec::new();
+            while let Some(batch) = result_batches.next().await {
+                results = batch;
+            }
+            task.await?;
+            Ok(results)
+        })
+    }
+
+    fn keyword_search_in_scope(
+        &self,
+        scope: Arc<SearchScope>,
+        query: String,
+        limit: usize,
+        cx: &AppContext,
+    ) -> Task<Result<Vec<SearchResult>>> {
+        let query = query.to_lowercase();
+        if query.is_empty() {
+            return Task::ready(Ok(Vec::new()));
+        }
+
+        let worktree_abs_paths = self
+            .worktree_indices(cx)
+            .into_iter()
+            .map(|index| {
+                let worktree = index.read(cx).worktree.read(cx);
+                (worktree.id(), worktree.abs_path())
+            })
+            .collect::<HashMap<_, _>>();
+        let document_texts = scope
+            .documents
+            .iter()
+            .map(|id| {
+                let id = id.clone();
+                let text = self.load_document(id.clone(), cx);
+                async move { anyhow::Ok((id, text.await?)) }
+            })
+            .collect::<Vec<_>>();
+        // Open buffers with unsaved changes are matched in place of their files' stored
+        // chunks, so that the results' ranges refer to the text the user sees.
+        let unsaved_buffers = self.unsaved_buffers(Some(&scope), cx);
+        let unsaved_paths = unsaved_buffers
+            .iter()
+            .map(|buffer| (buffer.worktree_id, buffer.path.clone()))
+            .collect::<HashSet<_>>();
+        let chunking_settings = VectorStoreSettings::get_global(cx).clone();
+        let (chunks_tx, chunks_rx) = channel::unbounded();
+        // Every chunk in scope is collected before any is matched, so their memory
+        // can't be bounded like a search's is.
+        let memory = SearchMemory::new(usize::MAX);
+        let scan_tasks = self.scan_chunks(
+            Some(scope),
+            Vec::new(),
+            unsaved_paths,
+            chunks_tx,
+            memory,
+            cx,
+        );
+        let fs = self.fs.clone();
+        let project = self.project.clone();
+        cx.spawn(|cx| async move {
+            for scan_task in futures::future::join_all(scan_tasks).await {
+                scan_task.log_err();
+            }
+            let mut document_texts = futures::future::try_join_all(document_texts)
+                .await?
+                .into_iter()
+                .filter_map(|(id, text)| Some((id, text?)))
+                .collect::<HashMap<_, _>>();
+
+            let matches = cx
+                .background_executor()
+                .spawn(async move {
+                    let mut chunks_by_owner = HashMap::<ChunkOwner, Vec<EmbeddedChunk>>::default();
+                    while let Ok((owner, chunk)) = chunks_rx.try_recv() {
+                        chunks_by_owner.entry(owner).or_default().push(chunk);
+                    }
+
+                    let mut matches = Vec::new();
+                    for buffer in unsaved_buffers {
+                        let Some(ranges) = buffer.chunk_ranges(&chunking_settings) else {
+                            continue;
+                        };
+                        let owner = buffer.owner();
+                        matches.extend(
+                            keyword_matches(&buffer.text, ranges, &query)
+                                .into_iter()
+                                .map(|(range, count)| (owner.clone(), range, count)),
+                        );
+                    }
+                    for (owner, chunks) in chunks_by_owner {
+                        let text = match &owner {
+                            ChunkOwner::File {
+                                worktree_id, path, ..
+                            } => {
+                                let Some(worktree_abs_path) = worktree_abs_paths.get(worktree_id)
+                                else {
+                                    continue;
+                                };
+                                let Some(text) =
+                                    fs.load(&worktree_abs_path.join(path)).await.log_err()
+                                else {
+                                    continue;
+                                };
+                                text
+                            }
+                            ChunkOwner::Document(id) => {
+                                let Some(text) = document_texts.remove(id) else {
+                                    continue;
+                                };
+                                text
+                            }
+                        };
+                        // Signature chunks overlap the chunks of their item's body.
+                        let ranges = chunks
+                            .into_iter()
+                            .filter(|chunk| chunk.chunk.item_range.is_none())
+                            .map(|chunk| chunk.chunk.range);
+                        matches.extend(
+                            keyword_matches(&text, ranges, &query)
+                                .into_iter()
+                                .map(|(range, count)| (owner.clone(), range, count)),
+                        );
+                    }
+                    matches.sort_by(|(_, _, a), (_, _, b)| b.cmp(a));
+                    matches.truncate(limit);
+                    matches
+                })
+                .await;
+
+            project.read_with(&cx, |project, cx| {
+                matches
+                    .into_iter()
+                    .filter_map(|(owner, range, count)| {
+                        Some(SearchResult {
+                            source: owner.search_result_source(project, cx)?,
+                            range,
+                            score: count as f32,
+                            relevance: None,
+                            position_hints: PositionHints::default(),
+                            unsaved_text: owner.unsaved_text(),
+                        })
+                    })
+                    .collect()
+            })
+        })
+    }
+}
+
+/// Returns the `ranges` of `text` that contain `query`, which must be lowercase, along
+/// with how many times each of them contains it.
+fn keyword_matches(
+    text: &str,
+    ranges: impl IntoIterator<Item = Range<usize>>,
+    query: &str,
+) -> Vec<(Range<usize>, usize)> {
+    ranges
+        .into_iter()
+        .filter_map(|range| {
+            let count = text
+                .get(range.clone())?
+                .to_lowercase()
+                .matches(query)
+                .count();
+            (count > 0).then_some((range, count))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyword_matches() {
+        let text = "fn parse() {}\nfn Parse_all() { parse(); }\nfn render() {}\n";
+        let ranges = [0..14, 14..42, 42..57, 50..100];
+        assert_eq!(
+            keyword_matches(text, ranges, "parse"),
+            vec![(0..14, 1), (14..42, 2)]
+        );
+    }
+}
@@ -1,43 +1,187 @@
+mod adaptive_batch_size;
+mod answer_spans;
+mod archive;
+mod buffer_embeddings;
 mod chunking;
+mod compaction;
+mod cross_project_search;
+mod db_writer;
+mod debug_dump;
+mod documents;
+mod duplicate_files;
 mod embedding;
+mod embedding_cache;
+mod file_filter;
+mod file_summaries;
+mod generated_files;
+#[cfg(feature = "headless")]
+pub mod headless;
+mod in_flight_searches;
+mod index_summary;
+mod indexing_errors;
+mod indexing_priority;
+mod keyword_prefilter;
+mod lexical_search;
+mod lsp_symbols;
+mod model_hint;
+mod model_migration;
+mod multi_query;
+mod pagination;
+mod payload_limits;
+mod pending_files;
+mod pipeline_stats;
 mod project_index_debug_view;
-
+mod provider_health;
+mod query_operators;
+mod recency;
+mod reconciliation;
+mod redaction;
+mod remote;
+mod result_navigation;
+mod result_previews;
+mod score_distribution;
+mod search_history;
+mod search_memory;
+mod search_within;
+mod unsaved_buffers;
+mod vector_store_settings;
+
+use adaptive_batch_size::{AdaptiveBatchProvider, AdaptiveBatchSize};
 use anyhow::{anyhow, Context as _, Result};
-use chunking::{chunk_text, Chunk};
-use collections::{Bound, HashMap, HashSet};
+pub use buffer_embeddings::SpanEmbedding;
+use chunking::{chunk_text, chunk_text_with_symbols, has_outline_query, Chunk, ChunkedText};
+use client::Client;
+use collections::{BTreeMap, Bound, HashMap, HashSet};
+pub use cross_project_search::CrossProjectSearchResult;
+use db_writer::DbWriter;
 pub use embedding::*;
+use embedding_cache::{embed_with_cache, EmbeddingCache};
+use file_filter::is_indexed_file;
+pub use file_summaries::{CloudSummaryProvider, SummaryProvider};
 use fs::Fs;
 use futures::{future::Shared, stream::StreamExt, FutureExt};
 use futures_batch::ChunksTimeoutStreamExt;
+use generated_files::{is_generated_text, GeneratedFileFilter};
 use gpui::{
     AppContext, AsyncAppContext, BorrowAppContext, Context, Entity, EntityId, EventEmitter, Global,
     Model, ModelContext, Subscription, Task, WeakModel,
 };
-use heed::types::{SerdeBincode, Str};
-use language::LanguageRegistry;
+use heed::{
+    types::{Bytes, SerdeBincode, Str},
+    BytesDecode,
+};
+use in_flight_searches::{InFlightSearches, SearchKey};
+pub use index_summary::IndexSummary;
+use indexing_errors::IndexingErrors;
+pub use indexing_errors::{IndexingError, IndexingErrorKind};
+use keyword_prefilter::{contains_any_keyword, keywords_in_text, query_keywords, FileKeywords};
+use language::{LanguageRegistry, LanguageServerName};
+use lexical_search::LexicalSearch;
+use lsp::LanguageServer;
+use lsp_symbols::OpenState;
 use parking_lot::Mutex;
-use project::{Entry, Project, ProjectEntryId, UpdatedEntriesSet, Worktree, WorktreeId};
+use project::{
+    Entry, PathChange, Project, ProjectEntryId, ProjectPath, UpdatedEntriesSet, Worktree,
+    WorktreeId,
+};
 use serde::{Deserialize, Serialize};
+use settings::{Settings, SettingsStore};
+use sha2::{Digest, Sha256};
 use smol::channel;
 use std::{
+    borrow::Cow,
     cmp::Ordering,
     future::Future,
-    iter,
+    iter, mem,
     num::NonZeroUsize,
     ops::Range,
     path::{Path, PathBuf},
     sync::{Arc, Weak},
-    time::{Duration, SystemTime},
+    time::{Duration, Instant, SystemTime},
 };
 use util::ResultExt;
+use workspace::Workspace;
 use worktree::Snapshot;
 
+use compaction::{compact_periodically, replace_with_compacted_copy};
+pub use debug_dump::{DebugDump, FileIndexDump, IndexDump, WorktreeIndexDump};
+pub use documents::{DocumentId, DocumentSource};
+use duplicate_files::ContentIndex;
+use model_hint::FastModel;
+pub use model_hint::ModelHint;
+use model_migration::ModelMigration;
+pub use pagination::{SearchCursor, SearchPage, MAX_SEARCH_PAGES};
+use payload_limits::embed_within_limits;
+use pending_files::{buffer_chunked_files, PendingFilesDb};
+use pipeline_stats::{IndexingStage, PipelineStats};
+pub use pipeline_stats::{IndexingTimings, StageTimings};
 pub use project_index_debug_view::ProjectIndexDebugView;
+use provider_health::{ProviderHealth, Verification};
+pub use query_operators::{parse_query, ParsedQuery, SearchFilter};
+use recency::RecencyBoost;
+pub use reconciliation::ReconciliationReport;
+use reconciliation::{reconcile_periodically, ReconciliationCounts};
+pub use result_navigation::PositionHints;
+pub use result_previews::ResultPreview;
+use score_distribution::{
+    load_score_distribution, record_score_distribution, score_distribution_key, ScoreDistribution,
+};
+use search_history::SearchHistoryEntry;
+use search_memory::{MemoryReservation, SearchMemory};
+pub use search_within::RefinementMode;
+use search_within::SearchScope;
+pub use vector_store_settings::{
+    ChunkGranularity, ChunkingSettings, ChunkingSettingsContent, GeneratedFiles,
+    VectorStoreSettings, VectorStoreSettingsContent,
+};
+
+/// The database holding an [`IndexMetadata`] for each worktree database, keyed by its name.
+/// Worktree databases are named after absolute paths, so this can't collide with them.
+const METADATA_DB_NAME: &str = "metadata";
+
+/// The database holding the recent queries searched in each worktree, keyed like
+/// [`METADATA_DB_NAME`].
+const SEARCH_HISTORY_DB_NAME: &str = "search_history";
+
+/// The database mapping the name of a worktree database to the database that actually
+/// holds its embeddings, for the worktrees whose embeddings were swapped in by a model
+/// migration. Names that aren't in it hold their own embeddings.
+const DB_LOCATIONS_DB_NAME: &str = "db_locations";
+
+/// Bump this whenever the way files are stored or embedded changes, so that
+/// existing indices get rebuilt.
+const INDEX_FORMAT_VERSION: u32 = 9;
+
+/// How many chunks a search worker scores before reporting its results.
+const SEARCH_RESULTS_REPORT_INTERVAL: usize = 4096;
+
+/// How many candidates are considered for each result when re-ranking search results
+/// for diversity.
+const DIVERSITY_CANDIDATES_PER_RESULT: usize = 4;
+
+/// The dimensions assumed when budgeting search memory for a model we know nothing about.
+const DEFAULT_EMBEDDING_DIMENSIONS: usize = 1536;
+
+pub fn init(client: &Arc<Client>, cx: &mut AppContext) {
+    VectorStoreSettings::register(cx);
+    remote::init(client);
+
+    cx.observe_new_views(|workspace: &mut Workspace, cx| {
+        workspace.register_action(debug_dump::dump_project_index);
+    })
+    .detach();
+}
 
 pub struct SemanticIndex {
     embedding_provider: Arc<dyn EmbeddingProvider>,
+    embedding_cache: Arc<Mutex<EmbeddingCache>>,
     db_connection: heed::Env,
+    db_writer: DbWriter,
     project_indices: HashMap<WeakModel<Project>, Model<ProjectIndex>>,
+    fast_model: Option<FastModel>,
+    migration: Option<ModelMigration>,
+    summary_provider: Option<Arc<dyn SummaryProvider>>,
+    _compact_db: Task<()>,
 }
 
 impl Global for SemanticIndex {}
@@ -50,22 +194,24 @@ impl SemanticIndex {
     ) -> Result<Self> {
         let db_connection = cx
             .background_executor()
-            .spawn(async move {
-                std::fs::create_dir_all(&db_path)?;
-                unsafe {
-                    heed::EnvOpenOptions::new()
-                        .map_size(1024 * 1024 * 1024)
-                        .max_dbs(3000)
-                        .open(db_path)
-                }
+            .spawn({
+                let db_path = db_path.clone();
+                async move { open_db(&db_path) }
             })
-            .await
-            .context("opening database connection")?;
+            .await?;
 
+        let db_writer = DbWriter::new(db_connection.clone(), cx.background_executor());
+        let compact_db = compact_periodically(db_connection.clone(), db_path, cx);
         Ok(SemanticIndex {
             db_connection,
+            db_writer,
             embedding_provider,
+            embedding_cache: Arc::new(Mutex::new(EmbeddingCache::new())),
             project_indices: HashMap::default(),
+            fast_model: None,
+            migration: None,
+            summary_provider: None,
+            _compact_db: compact_db,
         })
     }
 
@@ -80,39 +226,74 @@ impl SemanticIndex {
                 if cx.has_global::<SemanticIndex>() {
                     cx.update_global::<SemanticIndex, _>(|this, _| {
                         this.project_indices.remove(&project_weak);
+                        if let Some(migration) = this.migration.as_mut() {
+                            migration.forget_project(&project_weak);
+                        }
+                        this.finish_migration_if_done();
                     })
                 }
             })
             .detach();
         });
 
+        if let Some(project_index) = self.project_indices.get(&project.downgrade()) {
+            return project_index.clone();
+        }
+        let project_index = cx.new_model(|cx| {
+            ProjectIndex::new(
+                project.clone(),
+                ModelHint::Accurate,
+                false,
+                self.db_connection.clone(),
+                self.db_writer.clone(),
+                self.embedding_provider.clone(),
+                self.embedding_cache.clone(),
+                cx,
+            )
+        });
+        if let Some(summary_provider) = self.summary_provider.clone() {
+            project_index.update(cx, |project_index, cx| {
+                project_index.set_summary_provider(summary_provider, cx)
+            });
+        }
         self.project_indices
-            .entry(project.downgrade())
-            .or_insert_with(|| {
-                cx.new_model(|cx| {
-                    ProjectIndex::new(
-                        project,
-                        self.db_connection.clone(),
-                        self.embedding_provider.clone(),
-                        cx,
-                    )
-                })
-            })
-            .clone()
+            .insert(project.downgrade(), project_index.clone());
+        // A project opened while switching models is indexed with the new model too.
+        if self.migration.is_some() {
+            self.migrate_project(project, cx);
+        }
+        project_index
+    }
+
+    /// Returns the index for the given project, if one has already been created.
+    pub fn existing_project_index(&self, project: &Model<Project>) -> Option<Model<ProjectIndex>> {
+        self.project_indices.get(&project.downgrade()).cloned()
     }
 }
 
 pub struct ProjectIndex {
+    model_hint: ModelHint,
+    /// Whether this index is being built with a new model by a [`ModelMigration`], in
+    /// databases of its own, and isn't searched yet.
+    staged: bool,
     db_connection: heed::Env,
+    db_writer: DbWriter,
     project: WeakModel<Project>,
     worktree_indices: HashMap<EntityId, WorktreeIndexHandle>,
     language_registry: Arc<LanguageRegistry>,
     fs: Arc<dyn Fs>,
     last_status: Status,
+    last_indexed_at: Option<SystemTime>,
     status_tx: channel::Sender<()>,
     embedding_provider: Arc<dyn EmbeddingProvider>,
+    embedding_cache: Arc<Mutex<EmbeddingCache>>,
+    provider_health: Arc<ProviderHealth>,
+    in_flight_searches: Arc<InFlightSearches>,
+    summary_provider: Option<Arc<dyn SummaryProvider>>,
     _maintain_status: Task<()>,
+    _verify_embedding_provider: Task<()>,
     _subscription: Subscription,
+    _observe_settings: Subscription,
 }
 
 #[derive(Clone)]
@@ -126,25 +307,40 @@ enum WorktreeIndexHandle {
 }
 
 impl ProjectIndex {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         project: Model<Project>,
+        model_hint: ModelHint,
+        staged: bool,
         db_connection: heed::Env,
+        db_writer: DbWriter,
         embedding_provider: Arc<dyn EmbeddingProvider>,
+        embedding_cache: Arc<Mutex<EmbeddingCache>>,
         cx: &mut ModelContext<Self>,
     ) -> Self {
         let language_registry = project.read(cx).languages().clone();
         let fs = project.read(cx).fs().clone();
         let (status_tx, mut status_rx) = channel::unbounded();
         let mut this = ProjectIndex {
+            model_hint,
+            staged,
             db_connection,
+            db_writer,
             project: project.downgrade(),
             worktree_indices: HashMap::default(),
             language_registry,
             fs,
             status_tx,
             last_status: Status::Idle,
+            last_indexed_at: None,
             embedding_provider,
+            embedding_cache,
+            provider_health: Default::default(),
+            in_flight_searches: Default::default(),
+            summary_provider: None,
             _subscription: cx.subscribe(&project, Self::handle_project_event),
+            _observe_settings: cx.observe_global::<SettingsStore>(Self::handle_settings_changed),
+            _verify_embedding_provider: Task::ready(()),
             _maintain_status: cx.spawn(|this, mut cx| async move {
                 while status_rx.next().await.is_some() {
                     if this
@@ -156,6 +352,7 @@ impl ProjectIndex {
                 }
             }),
         };
+        this.verify_embedding_provider(cx);
         this.update_worktree_indices(cx);
         this
     }
@@ -164,6 +361,59 @@ impl ProjectIndex {
         self.last_status
     }
 
+    /// When the index last finished catching up with the files on disk.
+    pub fn last_indexed_at(&self) -> Option<SystemTime> {
+        self.last_indexed_at
+    }
+
+    /// Rescans every worktree for files that changed since they were last indexed. In a
+    /// remote project, the host rescans its worktrees instead.
+    pub fn refresh(&self, cx: &AppContext) {
+        if self.is_remote(cx) {
+            self.refresh_remote(cx).log_err();
+            return;
+        }
+        for index in self.worktree_indices(cx) {
+            index.read(cx).refresh();
+        }
+    }
+
+    /// The files that couldn't be fully indexed, such as files that failed to load, parse
+    /// or embed.
+    pub fn indexing_errors(&self, cx: &AppContext) -> Vec<(ProjectPath, IndexingError)> {
+        let mut result = Vec::new();
+        for index in self.worktree_indices(cx) {
+            let worktree = index.read(cx).worktree.read(cx);
+            let worktree_id = worktree.id();
+            for (path, error) in index.read(cx).indexing_errors.errors() {
+                if worktree.entry_for_path(&path).is_some() {
+                    result.push((ProjectPath { worktree_id, path }, error));
+                }
+            }
+        }
+        result
+    }
+
+    /// The files that failed to embed so many times, although the embedding provider
+    /// worked, that they won't be retried until they change.
+    pub fn parked_files(&self, cx: &AppContext) -> Vec<ProjectPath> {
+        let mut result = Vec::new();
+        for index in self.worktree_indices(cx) {
+            let worktree_id = index.read(cx).worktree.read(cx).id();
+            for path in index.read(cx).indexing_errors.parked() {
+                result.push(ProjectPath { worktree_id, path });
+            }
+        }
+        result
+    }
+
+    /// Why the embedding provider's last request failed, if it did. While it's failing,
+    /// files are retried with a growing delay and searches only match the words of
+    /// their query.
+    pub fn embedding_provider_error(&self) -> Option<String> {
+        self.provider_health.last_error()
+    }
+
     pub fn project(&self) -> WeakModel<Project> {
         self.project.clone()
     }
@@ -206,27 +456,37 @@ impl ProjectIndex {
         self.worktree_indices
             .retain(|worktree_id, _| worktrees.contains_key(worktree_id));
         for (worktree_id, worktree) in worktrees {
+            let db_name = self.worktree_db_name(&worktree.read(cx).abs_path());
             self.worktree_indices.entry(worktree_id).or_insert_with(|| {
                 let worktree_index = WorktreeIndex::load(
                     worktree.clone(),
+                    self.project.clone(),
+                    db_name,
                     self.db_connection.clone(),
+                    self.db_writer.clone(),
                     self.language_registry.clone(),
                     self.fs.clone(),
                     self.status_tx.clone(),
                     self.embedding_provider.clone(),
+                    self.provider_health.clone(),
                     cx,
                 );
 
                 let load_worktree = cx.spawn(|this, mut cx| async move {
                     let result = match worktree_index.await {
                         Ok(worktree_index) => {
-                            this.update(&mut cx, |this, _| {
+                            this.update(&mut cx, |this, cx| {
                                 this.worktree_indices.insert(
                                     worktree_id,
                                     WorktreeIndexHandle::Loaded {
                                         index: worktree_index.clone(),
                                     },
                                 );
+                                if let Some(summary_provider) = this.summary_provider.clone() {
+                                    worktree_index.update(cx, |index, cx| {
+                                        index.set_summary_provider(summary_provider, cx)
+                                    });
+                                }
                             })?;
                             Ok(worktree_index)
                         }
@@ -252,6 +512,16 @@ impl ProjectIndex {
         self.update_status(cx);
     }
 
+    /// The name of the database holding the embeddings of a worktree.
+    fn worktree_db_name(&self, worktree_abs_path: &Path) -> String {
+        let db_name = self.model_hint.worktree_db_name(worktree_abs_path);
+        if self.staged {
+            auxiliary_db_name(&db_name, "staged")
+        } else {
+            db_name
+        }
+    }
+
     fn update_status(&mut self, cx: &mut ModelContext<Self>) {
         let mut indexing_count = 0;
         let mut any_loading = false;
@@ -262,6 +532,12 @@ impl ProjectIndex {
                     any_loading = true;
                     break;
                 }
+                // Until a worktree has been compared with its database, the files that
+                // need indexing aren't known yet.
+                WorktreeIndexHandle::Loaded { index, .. } if !index.read(cx).initial_scan_done => {
+                    any_loading = true;
+                    break;
+                }
                 WorktreeIndexHandle::Loaded { index, .. } => {
                     indexing_count += index.read(cx).entry_ids_being_indexed.len();
                 }
@@ -277,23 +553,407 @@ impl ProjectIndex {
         };
 
         if status != self.last_status {
+            if status == Status::Idle {
+                self.last_indexed_at = Some(SystemTime::now());
+            }
             self.last_status = status;
             cx.emit(status);
         }
     }
 
+    /// Searches the index and records the query in the project's search history.
+    ///
+    /// At most `limit` results are returned, and results scoring below `min_similarity`
+    /// are omitted, so that no low-confidence results are shown when nothing in the
+    /// project matches the query. Scores come from the similarity metric of the
+    /// embedding model, which is the cosine similarity for most models. Results are
+    /// also omitted below the `vector_store.min_relevance` setting, which is normalized
+    /// to behave the same across models.
+    ///
+    /// `diversity`, between 0 and 1, trades relevance for variety in the results: at 0
+    /// they're ordered by score alone, and higher values increasingly penalize results
+    /// that are similar to the ones ranked above them.
+    ///
+    /// Issuing a search again while it's still running returns the results of the
+    /// running search, rather than embedding the query and scanning the index twice.
     pub fn search(
         &self,
         query: String,
         limit: usize,
+        min_similarity: Option<f32>,
+        diversity: f32,
         cx: &AppContext,
     ) -> Task<Result<Vec<SearchResult>>> {
-        let (chunks_tx, chunks_rx) = channel::bounded(1024);
-        let mut worktree_scan_tasks = Vec::new();
+        let key = SearchKey::new(&query, limit, min_similarity, diversity);
+        self.in_flight_searches.join_or_start(
+            key,
+            || {
+                let worktree_indices = self.worktree_indices(cx);
+                let SearchResultStream {
+                    results: mut result_batches,
+                    task,
+                } = self.search_stream(query.clone(), limit, min_similarity, diversity, cx);
+                cx.spawn(|cx| async move {
+                    let mut results = Vec::new();
+                    while let Some(batch) = result_batches.next().await {
+                        results = batch;
+                    }
+                    task.await?;
+                    Self::record_search(worktree_indices, query, &results, &cx)
+                        .await
+                        .log_err();
+                    Ok(results)
+                })
+            },
+            cx,
+        )
+    }
+
+    /// Searches the index, reporting the best results found so far while the
+    /// database is still being scanned.
+    ///
+    /// Every batch sent on [`SearchResultStream::results`] contains the top `limit`
+    /// results observed up to that point, so it supersedes any earlier batch. See
+    /// [`ProjectIndex::search`] for the meaning of `min_similarity` and `diversity`.
+    pub fn search_stream(
+        &self,
+        query: String,
+        limit: usize,
+        min_similarity: Option<f32>,
+        diversity: f32,
+        cx: &AppContext,
+    ) -> SearchResultStream {
+        self.search_stream_in_scope(query, limit, min_similarity, diversity, None, cx)
+    }
+
+    /// Like [`ProjectIndex::search_stream`], but only scores the chunks of the files and
+    /// documents in `scope`, if given.
+    pub(crate) fn search_stream_in_scope(
+        &self,
+        query: String,
+        limit: usize,
+        min_similarity: Option<f32>,
+        diversity: f32,
+        scope: Option<Arc<SearchScope>>,
+        cx: &AppContext,
+    ) -> SearchResultStream {
+        let settings = VectorStoreSettings::get_global(cx);
+        if self.is_remote(cx) {
+            return self.search_remote(query, limit, min_similarity, diversity, scope, cx);
+        }
+        // Operators like `lang:rust` restrict which files are searched, and are left out
+        // of the text that's embedded.
+        let ParsedQuery {
+            text: query,
+            filter,
+        } = parse_query(&query);
+
+        let diversity = diversity.clamp(0., 1.);
+        let candidate_limit = if diversity > 0. {
+            limit * DIVERSITY_CANDIDATES_PER_RESULT
+        } else {
+            limit
+        };
+        let dimensions = self
+            .embedding_provider
+            .model_metadata()
+            .map_or(DEFAULT_EMBEDDING_DIMENSIONS, |model| model.dimensions);
+        let memory_limit = settings.search_memory_limit();
+        let min_relevance = settings.min_relevance;
+        let generated_files = settings.generated_files;
+        let recency_boost = settings.recency_boost;
+        // Half of the memory is for the files being decoded, and half for the chunks
+        // waiting to be scored.
+        let memory = SearchMemory::new(memory_limit / 2);
+        let (chunks_tx, chunks_rx) =
+            channel::bounded(search_chunks_capacity(memory_limit / 2, dimensions));
+        // Scoped searches only score chunks that already matched an earlier search, which
+        // would skew the distribution of scores.
+        let record_scores = scope.is_none();
+        // Scoped searches refine earlier results, which a lexical search can't do.
+        let lexical_search = scope.is_none().then(|| LexicalSearch::new(self, cx));
+        let keywords = query_keywords(&query);
+        let unsaved_buffers = self.unsaved_buffers(scope.as_deref(), cx);
+        let unsaved_paths = unsaved_buffers
+            .iter()
+            .map(|buffer| (buffer.worktree_id, buffer.path.clone()))
+            .collect::<HashSet<_>>();
+        let unsaved_chunks_tx = chunks_tx.clone();
+        let scan_tasks = self.scan_chunks(scope, keywords, unsaved_paths, chunks_tx, memory, cx);
+        let chunking_settings = settings.clone();
+        let generated_file_filter = GeneratedFileFilter::new(settings);
+
+        let (results_tx, results_rx) = channel::unbounded();
+        let project = self.project.clone();
+        let language_registry = self.language_registry.clone();
+        let embedding_provider = self.embedding_provider.clone();
+        let embedding_cache = self.embedding_cache.clone();
+        let provider_health = self.provider_health.clone();
+        let db_connection = self.db_connection.clone();
+        let db_writer = self.db_writer.clone();
+        let score_distribution_key = score_distribution_key(embedding_provider.as_ref());
+        let task = cx.spawn(|cx| async move {
+            #[cfg(debug_assertions)]
+            let embedding_query_start = std::time::Instant::now();
+            log::info!("Searching for {query}");
+
+            // The chunks of unsaved buffers are embedded along with the query, and scored
+            // in place of the chunks stored for their files.
+            let unsaved_buffers = cx
+                .background_executor()
+                .spawn({
+                    let document_template = embedding_provider.document_template();
+                    let redact_secrets =
+                        embedding_provider.is_remote() && chunking_settings.redact_secrets;
+                    async move {
+                        unsaved_buffers
+                            .into_iter()
+                            .filter_map(|buffer| {
+                                buffer.chunk(&chunking_settings, &document_template, redact_secrets)
+                            })
+                            .collect::<Vec<_>>()
+                    }
+                })
+                .await;
+            let texts = iter::once(TextToEmbed::new(&query))
+                .chain(
+                    unsaved_buffers
+                        .iter()
+                        .flat_map(|buffer| &buffer.documents)
+                        .map(|document| TextToEmbed::new(document)),
+                )
+                .collect::<Vec<_>>();
+            let embeddings =
+                match embed_with_cache(embedding_provider.as_ref(), &embedding_cache, &texts).await
+                {
+                    Ok(embeddings) => {
+                        provider_health.record_success();
+                        embeddings
+                    }
+                    Err(error) => {
+                        provider_health.record_failure(error.to_string());
+                        let Some(lexical_search) = lexical_search else {
+                            return Err(error);
+                        };
+                        log::warn!("failed to embed query, matching its words instead: {error:?}");
+                        let results = cx
+                            .background_executor()
+                            .spawn(lexical_search.run(query, limit))
+                            .await?;
+                        results_tx.send(results).await.ok();
+                        return Ok(());
+                    }
+                };
+            let similarity_metric = embedding_provider.similarity_metric();
+            let mut embeddings = embeddings
+                .into_iter()
+                .map(|embedding| similarity_metric.prepare(embedding));
+            let query_embedding = embeddings
+                .next()
+                .ok_or_else(|| anyhow!("no embedding for query"))?;
+            let file_filter = filter.resolve(&language_registry).await;
+            let unsaved_files = unsaved_buffers
+                .into_iter()
+                .map(|buffer| {
+                    let worktree_id = buffer.worktree_id;
+                    let text = buffer.text.clone();
+                    let chunk_count = buffer.documents.len();
+                    (
+                        worktree_id,
+                        text,
+                        buffer.embedded(embeddings.by_ref().take(chunk_count)),
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            let results_by_worker = (0..cx.background_executor().num_cpus())
+                .map(|_| Mutex::new(Vec::<Arc<ScoredChunk>>::new()))
+                .collect::<Vec<_>>();
+            let score_totals = Mutex::new((0f64, 0usize));
+            let score_distribution = score_distribution_key.as_deref().and_then(|key| {
+                load_score_distribution(&db_connection, key)
+                    .log_err()
+                    .flatten()
+            });
+
+            #[cfg(debug_assertions)]
+            let search_start = std::time::Instant::now();
+
+            // Workers notify this channel whenever they have scored another batch of
+            // chunks. A pending notification is enough to pick up every update that
+            // precedes it, so notifications are dropped when the channel is full.
+            let (results_updated_tx, mut results_updated_rx) = channel::bounded::<()>(1);
+            let scan = cx.background_executor().scoped({
+                let chunks_rx = &chunks_rx;
+                let query_embedding = &query_embedding;
+                let results_by_worker = &results_by_worker;
+                let score_totals = &score_totals;
+                let file_filter = &file_filter;
+                move |cx| {
+                    cx.spawn(async move {
+                        for (worktree_id, text, file) in unsaved_files {
+                            send_file_chunks(
+                                worktree_id,
+                                file,
+                                Some(text),
+                                &generated_file_filter,
+                                &HashSet::default(),
+                                &unsaved_chunks_tx,
+                            )
+                            .await
+                            .log_err();
+                        }
+                    });
+                    for results in results_by_worker {
+                        let results_updated_tx = results_updated_tx.clone();
+                        cx.spawn(async move {
+                            let mut unreported_count = 0;
+                            let mut score_sum = 0f64;
+                            let mut score_count = 0;
+                            while let Ok((owner, chunk)) = chunks_rx.recv().await {
+                                if chunk.embedding.len() != query_embedding.len()
+                                    || !file_filter.matches(&owner)
+                                {
+                                    continue;
+                                }
+                                let score =
+                                    similarity_metric.score(&chunk.embedding, query_embedding);
+                                score_sum += score as f64;
+                                score_count += 1;
+                                if min_similarity.map_or(false, |min| score < min) {
+                                    continue;
+                                }
+                                {
+                                    let mut results = results.lock();
+                                    let ix = match results.binary_search_by(|probe| {
+                                        score.partial_cmp(&probe.score).unwrap_or(Ordering::Equal)
+                                    }) {
+                                        Ok(ix) | Err(ix) => ix,
+                                    };
+                                    results.insert(
+                                        ix,
+                                        Arc::new(ScoredChunk {
+                                            owner,
+                                            range: chunk.chunk.range,
+                                            item_range: chunk.chunk.item_range,
+                                            position_hints: PositionHints {
+                                                digest: Some(chunk.chunk.digest),
+                                                scope_path: chunk.chunk.scope_path,
+                                            },
+                                            embedding: chunk.embedding,
+                                            score,
+                                        }),
+                                    );
+                                    results.truncate(candidate_limit);
+                                }
+
+                                unreported_count += 1;
+                                if unreported_count == SEARCH_RESULTS_REPORT_INTERVAL {
+                                    unreported_count = 0;
+                                    results_updated_tx.try_send(()).ok();
+                                }
+                            }
+                            {
+                                let mut score_totals = score_totals.lock();
+                                score_totals.0 += score_sum;
+                                score_totals.1 += score_count;
+                            }
+                            results_updated_tx.try_send(()).ok();
+                        });
+                    }
+                }
+            });
+
+            let report = async {
+                let recency_boost = project.read_with(&cx, |project, cx| {
+                    RecencyBoost::new(recency_boost, project, cx)
+                })?;
+                while results_updated_rx.next().await.is_some() {
+                    let search_results = project.read_with(&cx, |project, cx| {
+                        Self::collect_search_results(
+                            project,
+                            &results_by_worker,
+                            limit,
+                            diversity,
+                            similarity_metric,
+                            score_distribution.as_ref(),
+                            min_relevance,
+                            generated_files,
+                            &recency_boost,
+                            cx,
+                        )
+                    })?;
+                    if results_tx.send(search_results).await.is_err() {
+                        break;
+                    }
+                }
+                anyhow::Ok(())
+            };
+
+            let ((), report_result) = futures::join!(scan, report);
+            report_result?;
+
+            for scan_task in futures::future::join_all(scan_tasks).await {
+                scan_task.log_err();
+            }
+
+            if let Some(key) = score_distribution_key.filter(|_| record_scores) {
+                let (score_sum, score_count) = *score_totals.lock();
+                let top_score = results_by_worker
+                    .iter()
+                    .filter_map(|results| Some(results.lock().first()?.score))
+                    .max_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+                if let Some(top_score) = top_score {
+                    let mean_score = (score_sum / score_count as f64) as f32;
+                    record_score_distribution(&db_writer, key, mean_score, top_score)
+                        .await
+                        .log_err();
+                }
+            }
+
+            #[cfg(debug_assertions)]
+            {
+                let search_elapsed = search_start.elapsed();
+                log::debug!("searched index in {:?}", search_elapsed);
+                let embedding_query_elapsed = embedding_query_start.elapsed();
+                log::debug!("embedding query took {:?}", embedding_query_elapsed);
+            }
+
+            Ok(())
+        });
+
+        SearchResultStream {
+            results: results_rx,
+            task,
+        }
+    }
+
+    /// Sends the chunks of every file and document in `scope`, or in the whole project if
+    /// there's no scope, to be scored by a search. Files are decoded as `memory` allows.
+    pub(crate) fn scan_chunks(
+        &self,
+        scope: Option<Arc<SearchScope>>,
+        keywords: Vec<u32>,
+        skipped_paths: HashSet<(WorktreeId, Arc<Path>)>,
+        chunks_tx: channel::Sender<(ChunkOwner, EmbeddedChunk)>,
+        memory: Arc<SearchMemory>,
+        cx: &AppContext,
+    ) -> Vec<Task<Result<()>>> {
+        let settings = VectorStoreSettings::get_global(cx);
+        let skipped_paths = Arc::new(skipped_paths);
+        let generated_file_filter = GeneratedFileFilter::new(settings);
+        let keyword_prefilter_min_files = settings.keyword_prefilter_min_files;
+        let keywords: Arc<[u32]> = keywords.into();
+        let mut scan_tasks = Vec::new();
         for worktree_index in self.worktree_indices.values() {
             let worktree_index = worktree_index.clone();
             let chunks_tx = chunks_tx.clone();
-            worktree_scan_tasks.push(cx.spawn(|cx| async move {
+            let scope = scope.clone();
+            let generated_file_filter = generated_file_filter.clone();
+            let keywords = keywords.clone();
+            let skipped_paths = skipped_paths.clone();
+            let memory = memory.clone();
+            scan_tasks.push(cx.spawn(|cx| async move {
                 let index = match worktree_index {
                     WorktreeIndexHandle::Loading { index } => {
                         index.clone().await.map_err(|error| anyhow!(error))?
@@ -304,19 +964,111 @@ impl ProjectIndex {
                 index
                     .read_with(&cx, |index, cx| {
                         let worktree_id = index.worktree.read(cx).id();
+                        let scanned_files = match &scope {
+                            Some(scope) => {
+                                if let Some(directory) = scope.directories.get(&worktree_id) {
+                                    ScannedFiles::Directory(directory.clone())
+                                } else if let Some(paths) = scope.files.get(&worktree_id) {
+                                    ScannedFiles::Paths(paths.clone())
+                                } else {
+                                    return Task::ready(Ok(()));
+                                }
+                            }
+                            None => ScannedFiles::All,
+                        };
                         let db_connection = index.db_connection.clone();
                         let db = index.db;
+                        let generated_file_filter = generated_file_filter.clone();
                         cx.background_executor().spawn(async move {
                             let txn = db_connection
                                 .read_txn()
                                 .context("failed to create read transaction")?;
-                            let db_entries = db.iter(&txn).context("failed to iterate database")?;
-                            for db_entry in db_entries {
-                                let (_key, db_embedded_file) = db_entry?;
-                                for chunk in db_embedded_file.chunks {
-                                    chunks_tx
-                                        .send((worktree_id, db_embedded_file.path.clone(), chunk))
+                            // Files are read straight from the memory map, and only decoded
+                            // once there's memory for them.
+                            let encoded_db = db.remap_data_type::<Bytes>();
+                            if let ScannedFiles::Paths(paths) = scanned_files {
+                                // Scoped searches look up their files rather than scanning
+                                // the whole database.
+                                for path in paths {
+                                    let db_key = db_key_for_path(&path);
+                                    if let Some(encoded_file) = encoded_db.get(&txn, &db_key)? {
+                                        let (file, _reservation) =
+                                            decode_scanned_file(db, &txn, encoded_file, &memory)
+                                                .await?;
+                                        send_file_chunks(
+                                            worktree_id,
+                                            file,
+                                            None,
+                                            &generated_file_filter,
+                                            &skipped_paths,
+                                            &chunks_tx,
+                                        )
                                         .await?;
+                                    }
+                                }
+                            } else if let ScannedFiles::Directory(directory) = scanned_files {
+                                // The keys of the files in a directory all start with the
+                                // directory's key, so only their range of the database is read.
+                                let db_key_prefix = if directory.as_os_str().is_empty() {
+                                    String::new()
+                                } else {
+                                    db_key_for_path(&directory) + "\0"
+                                };
+                                let db_entries = encoded_db
+                                    .prefix_iter(&txn, &db_key_prefix)
+                                    .context("failed to iterate database")?;
+                                for db_entry in db_entries {
+                                    let (_key, encoded_file) = db_entry?;
+                                    let (file, _reservation) =
+                                        decode_scanned_file(db, &txn, encoded_file, &memory)
+                                            .await?;
+                                    send_file_chunks(
+                                        worktree_id,
+                                        file,
+                                        None,
+                                        &generated_file_filter,
+                                        &skipped_paths,
+                                        &chunks_tx,
+                                    )
+                                    .await?;
+                                }
+                            } else if let Some(db_keys) =
+                                prefilter_files(db, &txn, &keywords, keyword_prefilter_min_files)?
+                            {
+                                for db_key in db_keys {
+                                    if let Some(encoded_file) = encoded_db.get(&txn, &db_key)? {
+                                        let (file, _reservation) =
+                                            decode_scanned_file(db, &txn, encoded_file, &memory)
+                                                .await?;
+                                        send_file_chunks(
+                                            worktree_id,
+                                            file,
+                                            None,
+                                            &generated_file_filter,
+                                            &skipped_paths,
+                                            &chunks_tx,
+                                        )
+                                        .await?;
+                                    }
+                                }
+                            } else {
+                                let db_entries = encoded_db
+                                    .iter(&txn)
+                                    .context("failed to iterate database")?;
+                                for db_entry in db_entries {
+                                    let (_key, encoded_file) = db_entry?;
+                                    let (file, _reservation) =
+                                        decode_scanned_file(db, &txn, encoded_file, &memory)
+                                            .await?;
+                                    send_file_chunks(
+                                        worktree_id,
+                                        file,
+                                        None,
+                                        &generated_file_filter,
+                                        &skipped_paths,
+                                        &chunks_tx,
+                                    )
+                                    .await?;
                                 }
                             }
                             anyhow::Ok(())
@@ -325,94 +1077,87 @@ impl ProjectIndex {
                     .await
             }));
         }
-        drop(chunks_tx);
-
-        let project = self.project.clone();
-        let embedding_provider = self.embedding_provider.clone();
-        cx.spawn(|cx| async move {
-            #[cfg(debug_assertions)]
-            let embedding_query_start = std::time::Instant::now();
-            log::info!("Searching for {query}");
-
-            let query_embeddings = embedding_provider
-                .embed(&[TextToEmbed::new(&query)])
-                .await?;
-            let query_embedding = query_embeddings
-                .into_iter()
-                .next()
-                .ok_or_else(|| anyhow!("no embedding for query"))?;
-
-            let mut results_by_worker = Vec::new();
-            for _ in 0..cx.background_executor().num_cpus() {
-                results_by_worker.push(Vec::<WorktreeSearchResult>::new());
+        // Documents are only embedded with the main model.
+        if self.model_hint == ModelHint::Accurate {
+            if let Some(project) = self.project.upgrade() {
+                scan_tasks.push(documents::scan_documents(
+                    self.db_connection.clone(),
+                    scope.map(|scope| scope.documents.clone()),
+                    documents::project_worktree_roots(&project, cx),
+                    chunks_tx,
+                    cx,
+                ));
             }
+        }
+        scan_tasks
+    }
 
-            #[cfg(debug_assertions)]
-            let search_start = std::time::Instant::now();
-
-            cx.background_executor()
-                .scoped(|cx| {
-                    for results in results_by_worker.iter_mut() {
-                        cx.spawn(async {
-                            while let Ok((worktree_id, path, chunk)) = chunks_rx.recv().await {
-                                let score = chunk.embedding.similarity(&query_embedding);
-                                let ix = match results.binary_search_by(|probe| {
-                                    score.partial_cmp(&probe.score).unwrap_or(Ordering::Equal)
-                                }) {
-                                    Ok(ix) | Err(ix) => ix,
-                                };
-                                results.insert(
-                                    ix,
-                                    WorktreeSearchResult {
-                                        worktree_id,
-                                        path: path.clone(),
-                                        range: chunk.chunk.range.clone(),
-                                        score,
-                                    },
-                                );
-                                results.truncate(limit);
-                            }
-                        });
-                    }
-                })
-                .await;
-
-            for scan_task in futures::future::join_all(worktree_scan_tasks).await {
-                scan_task.log_err();
+    #[allow(clippy::too_many_arguments)]
+    fn collect_search_results(
+        project: &Project,
+        results_by_worker: &[Mutex<Vec<Arc<ScoredChunk>>>],
+        limit: usize,
+        diversity: f32,
+        similarity_metric: SimilarityMetric,
+        score_distribution: Option<&ScoreDistribution>,
+        min_relevance: f32,
+        generated_files: GeneratedFiles,
+        recency_boost: &RecencyBoost,
+        cx: &AppContext,
+    ) -> Vec<SearchResult> {
+        let relevance = |score| score_distribution?.relevance(score);
+        // The results are copied out of the workers' locks, which aren't held while the
+        // results are ranked, so that the workers can keep scoring chunks meanwhile.
+        let results = results_by_worker
+            .iter()
+            .flat_map(|results| results.lock().clone())
+            .collect::<Vec<_>>();
+        let mut candidates = merge_signature_matches(results.iter().map(Arc::as_ref).collect());
+        // Until scores can be normalized, there's no telling which ones are too low.
+        candidates.retain(|candidate| {
+            relevance(candidate.score).map_or(true, |relevance| relevance >= min_relevance)
+        });
+        for candidate in &mut candidates {
+            candidate.score += recency_boost.boost(&candidate.chunk.owner);
+        }
+        candidates.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+        let rank = |mut candidates: Vec<_>, limit: usize| {
+            if diversity > 0. {
+                rerank_for_diversity(candidates, limit, diversity, similarity_metric)
+            } else {
+                candidates.truncate(limit);
+                candidates
             }
+        };
+        let ranked = if generated_files == GeneratedFiles::DownRank {
+            let (candidates, generated_candidates): (Vec<_>, Vec<_>) = candidates
+                .into_iter()
+                .partition(|candidate| !candidate.chunk.owner.is_generated());
+            let mut ranked = rank(candidates, limit);
+            ranked.extend(rank(generated_candidates, limit - ranked.len()));
+            ranked
+        } else {
+            rank(candidates, limit)
+        };
 
-            project.read_with(&cx, |project, cx| {
-                let mut search_results = Vec::with_capacity(results_by_worker.len() * limit);
-                for worker_results in results_by_worker {
-                    search_results.extend(worker_results.into_iter().filter_map(|result| {
-                        Some(SearchResult {
-                            worktree: project.worktree_for_id(result.worktree_id, cx)?,
-                            path: result.path,
-                            range: result.range,
-                            score: result.score,
-                        })
-                    }));
-                }
-                search_results.sort_unstable_by(|a, b| {
-                    b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal)
-                });
-                search_results.truncate(limit);
-
-                #[cfg(debug_assertions)]
-                {
-                    let search_elapsed = search_start.elapsed();
-                    log::debug!(
-                        "searched {} entries in {:?}",
-                        search_results.len(),
-                        search_elapsed
-                    );
-                    let embedding_query_elapsed = embedding_query_start.elapsed();
-                    log::debug!("embedding query took {:?}", embedding_query_elapsed);
-                }
-
-                search_results
-            })
-        })
+        ranked
+            .into_iter()
+            .filter_map(
+                |Candidate {
+                     chunk: result,
+                     score,
+                 }| {
+                    Some(SearchResult {
+                        source: result.owner.search_result_source(project, cx)?,
+                        range: result.range.clone(),
+                        score,
+                        relevance: relevance(score),
+                        position_hints: result.position_hints.clone(),
+                        unsaved_text: result.owner.unsaved_text(),
+                    })
+                },
+            )
+            .collect()
     }
 
     #[cfg(test)]
@@ -458,75 +1203,291 @@ impl ProjectIndex {
     }
 }
 
+#[derive(Clone)]
 pub struct SearchResult {
-    pub worktree: Model<Worktree>,
-    pub path: Arc<Path>,
+    pub source: SearchResultSource,
     pub range: Range<usize>,
     pub score: f32,
+    /// The score on a scale from 0 to 100 that's comparable across embedding models,
+    /// once enough searches have been made with the current model to normalize it.
+    pub relevance: Option<f32>,
+    /// Where the result was in its file when the file was indexed, besides its range.
+    pub position_hints: PositionHints,
+    /// The text of the unsaved buffer the result was found in, which its range refers to
+    /// rather than the file's saved contents. See [`ProjectIndex::result_text`].
+    pub unsaved_text: Option<Arc<str>>,
 }
 
-pub struct WorktreeSearchResult {
-    pub worktree_id: WorktreeId,
-    pub path: Arc<Path>,
-    pub range: Range<usize>,
-    pub score: f32,
+/// Where the text of a [`SearchResult`] can be found.
+#[derive(Clone)]
+pub enum SearchResultSource {
+    File {
+        worktree: Model<Worktree>,
+        path: Arc<Path>,
+    },
+    /// A document added with [`SemanticIndex::index_document`], whose text can be
+    /// retrieved with [`ProjectIndex::load_document`].
+    Document(DocumentId),
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
-pub enum Status {
-    Idle,
-    Loading,
-    Scanning { remaining_count: NonZeroUsize },
+pub struct SearchResultStream {
+    /// Batches of the best results found so far. Each batch supersedes the previous one.
+    pub results: channel::Receiver<Vec<SearchResult>>,
+    pub task: Task<Result<()>>,
 }
 
-impl EventEmitter<Status> for ProjectIndex {}
+/// Which files of a worktree a search scans.
+enum ScannedFiles {
+    All,
+    Paths(HashSet<Arc<Path>>),
+    Directory(Arc<Path>),
+}
 
-struct WorktreeIndex {
-    worktree: Model<Worktree>,
-    db_connection: heed::Env,
-    db: heed::Database<Str, SerdeBincode<EmbeddedFile>>,
-    language_registry: Arc<LanguageRegistry>,
-    fs: Arc<dyn Fs>,
-    embedding_provider: Arc<dyn EmbeddingProvider>,
-    entry_ids_being_indexed: Arc<IndexingEntrySet>,
-    _index_entries: Task<Result<()>>,
-    _subscription: Subscription,
+/// What an embedded chunk scanned during a search belongs to.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum ChunkOwner {
+    File {
+        worktree_id: WorktreeId,
+        path: Arc<Path>,
+        mtime: Option<SystemTime>,
+        generated: bool,
+        /// The text of the unsaved buffer the chunk was found in, which its range refers
+        /// to rather than the file's saved contents.
+        unsaved_text: Option<Arc<str>>,
+    },
+    Document(DocumentId),
 }
 
-impl WorktreeIndex {
-    pub fn load(
-        worktree: Model<Worktree>,
-        db_connection: heed::Env,
-        language_registry: Arc<LanguageRegistry>,
-        fs: Arc<dyn Fs>,
-        status_tx: channel::Sender<()>,
-        embedding_provider: Arc<dyn EmbeddingProvider>,
-        cx: &mut AppContext,
-    ) -> Task<Result<Model<Self>>> {
-        let worktree_abs_path = worktree.read(cx).abs_path();
-        cx.spawn(|mut cx| async move {
-            let db = cx
-                .background_executor()
-                .spawn({
-                    let db_connection = db_connection.clone();
-                    async move {
-                        let mut txn = db_connection.write_txn()?;
-                        let db_name = worktree_abs_path.to_string_lossy();
-                        let db = db_connection.create_database(&mut txn, Some(&db_name))?;
-                        txn.commit()?;
-                        anyhow::Ok(db)
+impl ChunkOwner {
+    fn is_generated(&self) -> bool {
+        matches!(
+            self,
+            ChunkOwner::File {
+                generated: true,
+                ..
+            }
+        )
+    }
+
+    fn search_result_source(
+        &self,
+        project: &Project,
+        cx: &AppContext,
+    ) -> Option<SearchResultSource> {
+        match self {
+            ChunkOwner::File {
+                worktree_id, path, ..
+            } => Some(SearchResultSource::File {
+                worktree: project.worktree_for_id(*worktree_id, cx)?,
+                path: path.clone(),
+            }),
+            ChunkOwner::Document(id) => Some(SearchResultSource::Document(id.clone())),
+        }
+    }
+
+    fn unsaved_text(&self) -> Option<Arc<str>> {
+        match self {
+            ChunkOwner::File { unsaved_text, .. } => unsaved_text.clone(),
+            ChunkOwner::Document(_) => None,
+        }
+    }
+}
+
+struct ScoredChunk {
+    owner: ChunkOwner,
+    range: Range<usize>,
+    /// Set when this chunk is the signature of the item spanning this range.
+    item_range: Option<Range<usize>>,
+    position_hints: PositionHints,
+    embedding: Embedding,
+    score: f32,
+}
+
+/// A search result being ranked.
+struct Candidate<'a> {
+    chunk: &'a ScoredChunk,
+    /// The chunk's score, or the score of its item's signature if that's higher.
+    score: f32,
+}
+
+/// Folds each match on an item's signature into the best match within the item's body,
+/// which keeps the body's precise range and the better of the two scores. Signatures
+/// whose body didn't match are kept as results of their own.
+///
+/// Returns the candidates sorted by descending score.
+fn merge_signature_matches(chunks: Vec<&ScoredChunk>) -> Vec<Candidate> {
+    let mut candidates = chunks
+        .into_iter()
+        .map(|chunk| Candidate {
+            chunk,
+            score: chunk.score,
+        })
+        .collect::<Vec<_>>();
+    candidates.sort_unstable_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+
+    let mut ix = 0;
+    while ix < candidates.len() {
+        let signature = candidates[ix].chunk;
+        let body_ix = signature.item_range.as_ref().and_then(|item_range| {
+            candidates.iter().position(|candidate| {
+                candidate.chunk.item_range.is_none()
+                    && candidate.chunk.owner == signature.owner
+                    && candidate.chunk.range.start < item_range.end
+                    && candidate.chunk.range.end > item_range.start
+            })
+        });
+        if let Some(body_ix) = body_ix {
+            candidates[body_ix].score = candidates[body_ix].score.max(signature.score);
+            candidates.remove(ix);
+        } else {
+            ix += 1;
+        }
+    }
+
+    candidates.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+    candidates
+}
+
+/// Picks `limit` of the `candidates`, which are sorted by descending score, using maximal
+/// marginal relevance: each pick maximizes its score minus its similarity to the results
+/// already picked, weighted by `diversity`.
+fn rerank_for_diversity(
+    mut candidates: Vec<Candidate>,
+    limit: usize,
+    diversity: f32,
+    similarity_metric: SimilarityMetric,
+) -> Vec<Candidate> {
+    let mut ranked = Vec::with_capacity(limit.min(candidates.len()));
+    let mut max_similarities = vec![0f32; candidates.len()];
+    while ranked.len() < limit && !candidates.is_empty() {
+        let Some((ix, _)) = candidates
+            .iter()
+            .zip(&max_similarities)
+            .map(|(candidate, max_similarity)| {
+                (1. - diversity) * candidate.score - diversity * max_similarity
+            })
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+        else {
+            break;
+        };
+
+        let picked = candidates.remove(ix);
+        max_similarities.remove(ix);
+        for (candidate, max_similarity) in candidates.iter().zip(&mut max_similarities) {
+            let similarity =
+                similarity_metric.score(&candidate.chunk.embedding, &picked.chunk.embedding);
+            if ranked.is_empty() {
+                *max_similarity = similarity;
+            } else {
+                *max_similarity = max_similarity.max(similarity);
+            }
+        }
+        ranked.push(picked);
+    }
+    ranked
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Status {
+    Idle,
+    Loading,
+    Scanning { remaining_count: NonZeroUsize },
+}
+
+impl EventEmitter<Status> for ProjectIndex {}
+
+enum IndexRequest {
+    UpdatedEntries(UpdatedEntriesSet),
+    Rescan,
+    /// A rescan that also verifies the contents of files whose mtime didn't change.
+    Reconcile,
+}
+
+struct WorktreeIndex {
+    worktree: Model<Worktree>,
+    project: WeakModel<Project>,
+    db_name: String,
+    db_connection: heed::Env,
+    db_writer: DbWriter,
+    db: heed::Database<Str, SerdeBincode<EmbeddedFile>>,
+    search_history_db: heed::Database<Str, SerdeBincode<Vec<SearchHistoryEntry>>>,
+    /// Chunked files waiting to be embedded that didn't fit in the indexing memory limit.
+    pending_files_db: PendingFilesDb,
+    language_registry: Arc<LanguageRegistry>,
+    fs: Arc<dyn Fs>,
+    embedding_provider: Arc<dyn EmbeddingProvider>,
+    /// How many texts to send the embedding provider at once while indexing.
+    embedding_batch_size: Arc<AdaptiveBatchSize>,
+    entry_ids_being_indexed: Arc<IndexingEntrySet>,
+    indexing_errors: Arc<IndexingErrors>,
+    provider_health: Arc<ProviderHealth>,
+    pipeline_stats: Arc<PipelineStats>,
+    content_index: Arc<ContentIndex>,
+    index_requests_tx: channel::Sender<IndexRequest>,
+    status_tx: channel::Sender<()>,
+    last_reconciliation: Option<ReconciliationReport>,
+    /// Generates the summaries of the files once they're indexed, if set.
+    summary_provider: Option<Arc<dyn SummaryProvider>>,
+    /// Whether the worktree has been compared with its database once, and the files
+    /// that changed since they were last indexed were indexed.
+    initial_scan_done: bool,
+    _index_entries: Task<Result<()>>,
+    _reconcile: Task<()>,
+    _summarize_files: Task<()>,
+    _subscription: Subscription,
+}
+
+impl WorktreeIndex {
+    #[allow(clippy::too_many_arguments)]
+    pub fn load(
+        worktree: Model<Worktree>,
+        project: WeakModel<Project>,
+        db_name: String,
+        db_connection: heed::Env,
+        db_writer: DbWriter,
+        language_registry: Arc<LanguageRegistry>,
+        fs: Arc<dyn Fs>,
+        status_tx: channel::Sender<()>,
+        embedding_provider: Arc<dyn EmbeddingProvider>,
+        provider_health: Arc<ProviderHealth>,
+        cx: &mut AppContext,
+    ) -> Task<Result<Model<Self>>> {
+        let metadata = IndexMetadata::for_provider(embedding_provider.as_ref());
+        cx.spawn(|mut cx| async move {
+            let (db, search_history_db, pending_files_db) = db_writer
+                .write({
+                    let db_name = db_name.clone();
+                    move |db_connection, txn| {
+                        let location = resolve_db_name(db_connection, txn, &db_name)?;
+                        let db = db_connection.create_database(txn, Some(&location))?;
+                        let search_history_db =
+                            db_connection.create_database(txn, Some(SEARCH_HISTORY_DB_NAME))?;
+                        // Files left pending when Zed last quit are chunked again by the
+                        // initial scan.
+                        let pending_files_db: PendingFilesDb = db_connection
+                            .create_database(txn, Some(&auxiliary_db_name(&db_name, "pending")))?;
+                        pending_files_db.clear(txn)?;
+                        clear_stale_embeddings(db_connection, txn, &db_name, db, &metadata)?;
+                        Ok((db, search_history_db, pending_files_db))
                     }
                 })
                 .await?;
             cx.new_model(|cx| {
                 Self::new(
                     worktree,
+                    project,
+                    db_name,
                     db_connection,
+                    db_writer,
                     db,
+                    search_history_db,
+                    pending_files_db,
                     status_tx,
                     language_registry,
                     fs,
                     embedding_provider,
+                    provider_health,
                     cx,
                 )
             })
@@ -536,61 +1497,293 @@ impl WorktreeIndex {
     #[allow(clippy::too_many_arguments)]
     fn new(
         worktree: Model<Worktree>,
+        project: WeakModel<Project>,
+        db_name: String,
         db_connection: heed::Env,
+        db_writer: DbWriter,
         db: heed::Database<Str, SerdeBincode<EmbeddedFile>>,
+        search_history_db: heed::Database<Str, SerdeBincode<Vec<SearchHistoryEntry>>>,
+        pending_files_db: PendingFilesDb,
         status: channel::Sender<()>,
         language_registry: Arc<LanguageRegistry>,
         fs: Arc<dyn Fs>,
         embedding_provider: Arc<dyn EmbeddingProvider>,
+        provider_health: Arc<ProviderHealth>,
         cx: &mut ModelContext<Self>,
     ) -> Self {
-        let (updated_entries_tx, updated_entries_rx) = channel::unbounded();
-        let _subscription = cx.subscribe(&worktree, move |_this, _worktree, event, _cx| {
-            if let worktree::Event::UpdatedEntries(update) = event {
-                _ = updated_entries_tx.try_send(update.clone());
+        let (index_requests_tx, index_requests_rx) = channel::unbounded();
+        let _subscription = cx.subscribe(&worktree, {
+            let index_requests_tx = index_requests_tx.clone();
+            move |_this, _worktree, event, _cx| {
+                if let worktree::Event::UpdatedEntries(update) = event {
+                    _ = index_requests_tx.try_send(IndexRequest::UpdatedEntries(update.clone()));
+                }
             }
         });
 
         Self {
+            db_name,
             db_connection,
+            db_writer,
             db,
+            search_history_db,
+            pending_files_db,
             worktree,
+            project,
             language_registry,
             fs,
             embedding_provider,
-            entry_ids_being_indexed: Arc::new(IndexingEntrySet::new(status)),
-            _index_entries: cx.spawn(|this, cx| Self::index_entries(this, updated_entries_rx, cx)),
+            embedding_batch_size: Default::default(),
+            entry_ids_being_indexed: Arc::new(IndexingEntrySet::new(status.clone())),
+            indexing_errors: Default::default(),
+            provider_health,
+            pipeline_stats: Default::default(),
+            content_index: Default::default(),
+            _reconcile: reconcile_periodically(index_requests_tx.clone(), cx),
+            index_requests_tx,
+            status_tx: status,
+            last_reconciliation: None,
+            summary_provider: None,
+            initial_scan_done: false,
+            _index_entries: cx.spawn(|this, cx| Self::index_entries(this, index_requests_rx, cx)),
+            _summarize_files: Task::ready(()),
             _subscription,
         }
     }
 
     async fn index_entries(
         this: WeakModel<Self>,
-        updated_entries: channel::Receiver<UpdatedEntriesSet>,
+        index_requests: channel::Receiver<IndexRequest>,
         mut cx: AsyncAppContext,
     ) -> Result<()> {
-        let index = this.update(&mut cx, |this, cx| this.index_entries_changed_on_disk(cx))?;
-        index.await.log_err();
-
-        while let Ok(updated_entries) = updated_entries.recv().await {
+        // Wait for the worktree to be scanned before comparing it with the database, so
+        // that files which haven't been discovered yet aren't mistaken for deleted ones.
+        let scan_complete = this.update(&mut cx, |this, cx| {
+            this.worktree
+                .read(cx)
+                .as_local()
+                .map(|worktree| worktree.scan_complete())
+        })?;
+        if let Some(scan_complete) = scan_complete {
+            scan_complete.await;
+        }
+        // Until the provider passed verification, files aren't indexed, and the worktree
+        // is rescanned once it has. While it hasn't been checked yet, the worktree is
+        // still loading.
+        let verification = this.update(&mut cx, |this, _| this.provider_health.verification())?;
+        if verification == Verification::Passed {
             let index = this.update(&mut cx, |this, cx| {
-                this.index_updated_entries(updated_entries, cx)
+                this.index_entries_changed_on_disk(None, cx)
+            })?;
+            if index.await.log_err().is_some() {
+                this.update(&mut cx, |this, _| this.record_updated_at())?
+                    .await
+                    .log_err();
+            }
+        }
+        if verification != Verification::Unverified {
+            this.update(&mut cx, |this, cx| {
+                this.finish_initial_scan();
+                this.summarize_files(cx);
+                log::info!(
+                    "indexed {:?}: {:?}",
+                    this.worktree.read(cx).abs_path(),
+                    this.pipeline_stats.timings()
+                );
+            })?;
+        }
+
+        // When files failed to embed because the provider is unreachable, they're retried
+        // by rescanning the worktree, with a growing delay between attempts.
+        let mut retry_at = None;
+        loop {
+            if retry_at.is_none() {
+                retry_at = this
+                    .update(&mut cx, |this, _| this.embed_retry_delay())?
+                    .map(|delay| Instant::now() + delay);
+            }
+            let request = if let Some(retry_at) = retry_at {
+                let mut timer = cx
+                    .background_executor()
+                    .timer(retry_at.saturating_duration_since(Instant::now()))
+                    .fuse();
+                futures::select_biased! {
+                    request = index_requests.recv().fuse() => request,
+                    _ = timer => Ok(IndexRequest::Rescan),
+                }
+            } else {
+                index_requests.recv().await
+            };
+            let Ok(request) = request else {
+                break;
+            };
+            if matches!(request, IndexRequest::Rescan | IndexRequest::Reconcile) {
+                retry_at = None;
+            }
+
+            let request = match request {
+                IndexRequest::UpdatedEntries(updated_entries) => {
+                    let (debounce, max_wait) = cx.update(|cx| {
+                        let settings = VectorStoreSettings::get_global(cx);
+                        (settings.reindex_debounce(), settings.reindex_max_wait())
+                    })?;
+                    Self::debounce_updated_entries(
+                        updated_entries,
+                        &index_requests,
+                        debounce,
+                        max_wait,
+                        &cx,
+                    )
+                    .await
+                }
+                request => request,
+            };
+            let verification =
+                this.update(&mut cx, |this, _| this.provider_health.verification())?;
+            if verification != Verification::Passed {
+                if verification != Verification::Unverified {
+                    this.update(&mut cx, |this, _| this.finish_initial_scan())?;
+                }
+                continue;
+            }
+            let reconciliation = matches!(request, IndexRequest::Reconcile)
+                .then(|| Arc::new(ReconciliationCounts::default()));
+            let index = this.update(&mut cx, |this, cx| match request {
+                IndexRequest::UpdatedEntries(updated_entries) => this
+                    .index_updated_entries(updated_entries, cx)
+                    .boxed_local(),
+                IndexRequest::Rescan | IndexRequest::Reconcile => this
+                    .index_entries_changed_on_disk(reconciliation.clone(), cx)
+                    .boxed_local(),
             })?;
-            index.await.log_err();
+            if index.await.log_err().is_some() {
+                this.update(&mut cx, |this, _| this.record_updated_at())?
+                    .await
+                    .log_err();
+            }
+            this.update(&mut cx, |this, cx| {
+                this.finish_initial_scan();
+                this.summarize_files(cx);
+            })?;
+
+            if let Some(reconciliation) = reconciliation {
+                let report = reconciliation.report();
+                log::info!(
+                    "reconciled index: checked {} files, repaired {}",
+                    report.checked_count,
+                    report.repaired_count
+                );
+                this.update(&mut cx, |this, _| {
+                    this.last_reconciliation = Some(report);
+                    this.status_tx.try_send(()).ok();
+                })?;
+            }
         }
 
         Ok(())
     }
 
-    fn index_entries_changed_on_disk(&self, cx: &AppContext) -> impl Future<Output = Result<()>> {
+    /// Waits until entries stop changing for `debounce`, or for `max_wait` at most,
+    /// merging the changes that arrive in the meantime so that a file saved several times
+    /// in a row is only indexed once.
+    async fn debounce_updated_entries(
+        updated_entries: UpdatedEntriesSet,
+        index_requests: &channel::Receiver<IndexRequest>,
+        debounce: Duration,
+        max_wait: Duration,
+        cx: &AsyncAppContext,
+    ) -> IndexRequest {
+        if debounce.is_zero() {
+            return IndexRequest::UpdatedEntries(updated_entries);
+        }
+
+        let mut changes = BTreeMap::new();
+        merge_entry_changes(&mut changes, &updated_entries);
+        // Entries that never stop changing are still indexed every `max_wait`.
+        let mut max_wait_timer = cx.background_executor().timer(max_wait).fuse();
+        loop {
+            let mut timer = cx.background_executor().timer(debounce).fuse();
+            futures::select_biased! {
+                request = index_requests.recv().fuse() => match request {
+                    Ok(IndexRequest::UpdatedEntries(updated_entries)) => {
+                        merge_entry_changes(&mut changes, &updated_entries);
+                    }
+                    // A rescan picks up every pending change anyway.
+                    Ok(request @ (IndexRequest::Rescan | IndexRequest::Reconcile)) => {
+                        return request
+                    }
+                    Err(_) => break,
+                },
+                _ = timer => break,
+                _ = max_wait_timer => break,
+            }
+        }
+
+        IndexRequest::UpdatedEntries(
+            changes
+                .into_iter()
+                .map(|(path, (entry_id, change))| (path, entry_id, change))
+                .collect(),
+        )
+    }
+
+    fn refresh(&self) {
+        _ = self.index_requests_tx.try_send(IndexRequest::Rescan);
+    }
+
+    /// Records that the worktree has been compared with its database, or that it won't
+    /// be until the embedding provider passes verification.
+    fn finish_initial_scan(&mut self) {
+        if !self.initial_scan_done {
+            self.initial_scan_done = true;
+            self.status_tx.try_send(()).ok();
+        }
+    }
+
+    /// How long to wait before retrying the files that failed to embed, if any did while
+    /// the provider was failing.
+    fn embed_retry_delay(&self) -> Option<Duration> {
+        let has_embed_errors = self.indexing_errors.errors().iter().any(|(path, error)| {
+            error.kind == IndexingErrorKind::Embed && !self.indexing_errors.is_parked(path)
+        });
+        if has_embed_errors {
+            self.provider_health.retry_delay()
+        } else {
+            None
+        }
+    }
+
+    /// Indexes the files whose mtime changed since they were indexed. With
+    /// `reconciliation`, the contents of the other files are checked too.
+    fn index_entries_changed_on_disk(
+        &self,
+        reconciliation: Option<Arc<ReconciliationCounts>>,
+        cx: &AppContext,
+    ) -> impl Future<Output = Result<()>> {
         let worktree = self.worktree.read(cx).snapshot();
         let worktree_abs_path = worktree.abs_path().clone();
-        let scan = self.scan_entries(worktree, cx);
-        let chunk = self.chunk_files(worktree_abs_path, scan.updated_entries, cx);
-        let embed = Self::embed_files(self.embedding_provider.clone(), chunk.files, cx);
+        let scan = self.scan_entries(worktree, reconciliation, cx);
+        let prioritize = self.prioritize_entries(scan.updated_entries, cx);
+        let chunk = self.chunk_files(worktree_abs_path, prioritize.updated_entries, cx);
+        let buffer = self.buffer_chunked_files(chunk.files, cx);
+        let embed = Self::embed_files(
+            self.indexing_embedding_provider(cx),
+            self.indexing_errors.clone(),
+            self.provider_health.clone(),
+            self.pipeline_stats.clone(),
+            buffer.files,
+            cx,
+        );
         let persist = self.persist_embeddings(scan.deleted_entry_ranges, embed.files, cx);
         async move {
-            futures::try_join!(scan.task, chunk.task, embed.task, persist)?;
+            futures::try_join!(
+                scan.task,
+                prioritize.task,
+                chunk.task,
+                buffer.task,
+                embed.task,
+                persist
+            )?;
             Ok(())
         }
     }
@@ -603,22 +1796,50 @@ impl WorktreeIndex {
         let worktree = self.worktree.read(cx).snapshot();
         let worktree_abs_path = worktree.abs_path().clone();
         let scan = self.scan_updated_entries(worktree, updated_entries.clone(), cx);
-        let chunk = self.chunk_files(worktree_abs_path, scan.updated_entries, cx);
-        let embed = Self::embed_files(self.embedding_provider.clone(), chunk.files, cx);
+        let prioritize = self.prioritize_entries(scan.updated_entries, cx);
+        let chunk = self.chunk_files(worktree_abs_path, prioritize.updated_entries, cx);
+        let buffer = self.buffer_chunked_files(chunk.files, cx);
+        let embed = Self::embed_files(
+            self.indexing_embedding_provider(cx),
+            self.indexing_errors.clone(),
+            self.provider_health.clone(),
+            self.pipeline_stats.clone(),
+            buffer.files,
+            cx,
+        );
         let persist = self.persist_embeddings(scan.deleted_entry_ranges, embed.files, cx);
         async move {
-            futures::try_join!(scan.task, chunk.task, embed.task, persist)?;
+            futures::try_join!(
+                scan.task,
+                prioritize.task,
+                chunk.task,
+                buffer.task,
+                embed.task,
+                persist
+            )?;
             Ok(())
         }
     }
 
-    fn scan_entries(&self, worktree: Snapshot, cx: &AppContext) -> ScanEntries {
+    fn scan_entries(
+        &self,
+        worktree: Snapshot,
+        reconciliation: Option<Arc<ReconciliationCounts>>,
+        cx: &AppContext,
+    ) -> ScanEntries {
         let (updated_entries_tx, updated_entries_rx) = channel::bounded(512);
         let (deleted_entry_ranges_tx, deleted_entry_ranges_rx) = channel::bounded(128);
         let db_connection = self.db_connection.clone();
         let db = self.db;
         let entries_being_indexed = self.entry_ids_being_indexed.clone();
+        let indexing_errors = self.indexing_errors.clone();
+        let fs = self.fs.clone();
+        let pipeline_stats = self.pipeline_stats.clone();
+        let content_index = self.content_index.clone();
+        let generated_file_filter = GeneratedFileFilter::new(VectorStoreSettings::get_global(cx));
         let task = cx.background_executor().spawn(async move {
+            // This includes the time spent waiting for the next stages to accept files.
+            let mut span = pipeline_stats.span(IndexingStage::Scan);
             let txn = db_connection
                 .read_txn()
                 .context("failed to create read transaction")?;
@@ -630,13 +1851,18 @@ impl WorktreeIndex {
 
             let mut deletion_range: Option<(Bound<&str>, Bound<&str>)> = None;
             for entry in worktree.files(false, 0) {
+                span.add_items(1);
                 let entry_db_key = db_key_for_path(&entry.path);
 
-                let mut saved_mtime = None;
+                let mut saved_file = None;
+                let mut was_excluded = false;
                 while let Some(db_entry) = db_entries.peek() {
                     match db_entry {
                         Ok((db_path, db_embedded_file)) => match (*db_path).cmp(&entry_db_key) {
                             Ordering::Less => {
+                                if let Some(reconciliation) = &reconciliation {
+                                    reconciliation.record_repaired(1);
+                                }
                                 if let Some(deletion_range) = deletion_range.as_mut() {
                                     deletion_range.1 = Bound::Included(db_path);
                                 } else {
@@ -655,7 +1881,16 @@ impl WorktreeIndex {
                                         ))
                                         .await?;
                                 }
-                                saved_mtime = db_embedded_file.mtime;
+                                if db_embedded_file.duplicate_of.is_none() {
+                                    content_index
+                                        .update(&db_embedded_file.path, db_embedded_file.digest);
+                                }
+                                was_excluded = generated_file_filter.was_excluded(db_embedded_file);
+                                saved_file = Some((
+                                    db_embedded_file.mtime,
+                                    db_embedded_file.digest,
+                                    db_embedded_file.duplicate_of.clone(),
+                                ));
                                 db_entries.next();
                                 break;
                             }
@@ -667,7 +1902,37 @@ impl WorktreeIndex {
                     }
                 }
 
-                if entry.mtime != saved_mtime {
+                let changed = match (&reconciliation, &saved_file) {
+                    _ if was_excluded => true,
+                    (_, Some((saved_mtime, _, _))) if entry.mtime != *saved_mtime => true,
+                    (_, None) => true,
+                    // A duplicate whose contents are no longer embedded for another file
+                    // has to be embedded again.
+                    (_, Some((_, saved_digest, Some(canonical_path))))
+                        if !holds_embeddings_for(db, &txn, canonical_path, saved_digest)? =>
+                    {
+                        true
+                    }
+                    (None, Some(_)) => false,
+                    // The mtime may not change when a file is replaced, or the change may
+                    // have happened while its event was missed.
+                    (Some(_), Some((_, saved_digest, _))) => {
+                        match fs.load(&worktree.abs_path().join(&entry.path)).await {
+                            Ok(text) => {
+                                let digest: [u8; 32] = Sha256::digest(&text).into();
+                                digest != *saved_digest
+                            }
+                            Err(_) => true,
+                        }
+                    }
+                };
+                if let Some(reconciliation) = &reconciliation {
+                    reconciliation.record_checked();
+                    if changed {
+                        reconciliation.record_repaired(1);
+                    }
+                }
+                if changed && !indexing_errors.is_parked(&entry.path) {
                     let handle = entries_being_indexed.insert(entry.id);
                     updated_entries_tx.send((entry.clone(), handle)).await?;
                 }
@@ -675,6 +1940,9 @@ impl WorktreeIndex {
 
             if let Some(db_entry) = db_entries.next() {
                 let (db_path, _) = db_entry?;
+                if let Some(reconciliation) = &reconciliation {
+                    reconciliation.record_repaired(1 + db_entries.count());
+                }
                 deleted_entry_ranges_tx
                     .send((Bound::Included(db_path.to_string()), Bound::Unbounded))
                     .await?;
@@ -699,8 +1967,16 @@ impl WorktreeIndex {
         let (updated_entries_tx, updated_entries_rx) = channel::bounded(512);
         let (deleted_entry_ranges_tx, deleted_entry_ranges_rx) = channel::bounded(128);
         let entries_being_indexed = self.entry_ids_being_indexed.clone();
+        let indexing_errors = self.indexing_errors.clone();
+        let pipeline_stats = self.pipeline_stats.clone();
         let task = cx.background_executor().spawn(async move {
+            let mut span = pipeline_stats.span(IndexingStage::Scan);
+            span.add_items(updated_entries.len());
             for (path, entry_id, status) in updated_entries.iter() {
+                // A parked file gets another chance once it changes.
+                if *status != project::PathChange::Loaded {
+                    indexing_errors.reset_embed_failures(path);
+                }
                 match status {
                     project::PathChange::Added
                     | project::PathChange::Updated
@@ -741,7 +2017,17 @@ impl WorktreeIndex {
         cx: &AppContext,
     ) -> ChunkFiles {
         let language_registry = self.language_registry.clone();
+        let language_servers = self.language_servers(cx);
+        let open_files = self.open_files(cx);
+        let executor = cx.background_executor().clone();
+        let indexing_errors = self.indexing_errors.clone();
+        let db_connection = self.db_connection.clone();
+        let db = self.db;
         let fs = self.fs.clone();
+        let settings = VectorStoreSettings::get_global(cx).clone();
+        let generated_file_filter = GeneratedFileFilter::new(&settings);
+        let pipeline_stats = self.pipeline_stats.clone();
+        let content_index = self.content_index.clone();
         let (chunked_files_tx, chunked_files_rx) = channel::bounded(2048);
         let task = cx.spawn(|cx| async move {
             cx.background_executor()
@@ -749,29 +2035,143 @@ impl WorktreeIndex {
                     for _ in 0..cx.num_cpus() {
                         cx.spawn(async {
                             while let Ok((entry, handle)) = entries.recv().await {
+                                let mut span = pipeline_stats.span(IndexingStage::Chunk);
+                                span.add_items(1);
                                 let entry_abs_path = worktree_abs_path.join(&entry.path);
-                                let Some(text) = fs
-                                    .load(&entry_abs_path)
-                                    .await
-                                    .with_context(|| {
-                                        format!("failed to read path {entry_abs_path:?}")
-                                    })
-                                    .log_err()
-                                else {
+                                let text = match fs.load(&entry_abs_path).await {
+                                    Ok(text) => text,
+                                    Err(error) => {
+                                        indexing_errors.record(
+                                            entry.path.clone(),
+                                            IndexingErrorKind::Read,
+                                            error.to_string(),
+                                        );
+                                        continue;
+                                    }
+                                };
+                                if !is_indexed_file(text.as_bytes()) {
                                     continue;
+                                }
+                                let digest: [u8; 32] = Sha256::digest(&text).into();
+                                let previous_file = db_connection
+                                    .read_txn()
+                                    .context("failed to create read transaction")
+                                    .and_then(
+                                        |txn| Ok(db.get(&txn, &db_key_for_path(&entry.path))?),
+                                    )
+                                    .log_err()
+                                    .flatten();
+                                let generated = is_generated_text(&text);
+                                // Excluded generated files aren't embedded, so they can't
+                                // hold the embeddings of their duplicates.
+                                let excluded =
+                                    generated_file_filter.excludes(&entry.path, generated);
+                                let duplicate_of = if excluded {
+                                    None
+                                } else {
+                                    let canonical_path = content_index.claim(digest, &entry.path);
+                                    (canonical_path != entry.path).then_some(canonical_path)
                                 };
                                 let language = language_registry
                                     .language_for_file_path(&entry.path)
                                     .await
                                     .ok();
+
+                                // A file whose mtime changed but whose contents didn't, e.g.
+                                // because it was checked out again, keeps its chunks.
+                                let (chunks, syntax_error) = match &previous_file {
+                                    // Files with the same contents as another are only
+                                    // embedded once.
+                                    _ if duplicate_of.is_some() => (Vec::new(), None),
+                                    // Generated files are only embedded if searches may
+                                    // return them.
+                                    _ if excluded => (Vec::new(), None),
+                                    Some(previous_file)
+                                        if previous_file.digest == digest
+                                            && previous_file.duplicate_of.is_none() =>
+                                    {
+                                        (
+                                            previous_file
+                                                .chunks
+                                                .iter()
+                                                .map(|previous| previous.chunk.clone())
+                                                .collect(),
+                                            previous_file.syntax_error.clone(),
+                                        )
+                                    }
+                                    _ => {
+                                        let chunking = settings.chunking_for_language(
+                                            language
+                                                .as_ref()
+                                                .map(|language| language.name())
+                                                .as_deref(),
+                                        );
+                                        let symbols = match &language {
+                                            Some(language)
+                                                if chunking.granularity
+                                                    != ChunkGranularity::File
+                                                    && !has_outline_query(Some(language)) =>
+                                            {
+                                                lsp_symbols::symbols_from_language_servers(
+                                                    &language_registry,
+                                                    &language_servers,
+                                                    language,
+                                                    &entry_abs_path,
+                                                    &text,
+                                                    open_files
+                                                        .get(&entry.path)
+                                                        .copied()
+                                                        .unwrap_or(OpenState::Closed),
+                                                    &executor,
+                                                )
+                                                .await
+                                            }
+                                            _ => None,
+                                        };
+                                        let ChunkedText {
+                                            mut chunks,
+                                            signature_chunks,
+                                            syntax_error,
+                                        } = match symbols {
+                                            Some(symbols) => {
+                                                chunk_text_with_symbols(&text, symbols, &chunking)
+                                            }
+                                            None => chunk_text(
+                                                &text,
+                                                language.as_ref(),
+                                                &entry.path,
+                                                &chunking,
+                                            ),
+                                        };
+                                        chunks.extend(signature_chunks);
+                                        (chunks, syntax_error)
+                                    }
+                                };
+                                match &syntax_error {
+                                    Some(error) => indexing_errors.record(
+                                        entry.path.clone(),
+                                        IndexingErrorKind::Parse,
+                                        error.clone(),
+                                    ),
+                                    None => indexing_errors.clear(&entry.path),
+                                }
+                                let previous_chunks =
+                                    previous_file.map_or(Vec::new(), |file| file.chunks);
                                 let chunked_file = ChunkedFile {
-                                    chunks: chunk_text(&text, language.as_ref(), &entry.path),
+                                    chunks,
+                                    digest,
+                                    generated,
+                                    previous_chunks,
+                                    syntax_error,
                                     handle,
                                     path: entry.path,
+                                    language: language.map(|language| language.name()),
                                     mtime: entry.mtime,
                                     text,
+                                    duplicate_of,
                                 };
 
+                                drop(span);
                                 if chunked_files_tx.send(chunked_file).await.is_err() {
                                     return;
                                 }
@@ -789,62 +2189,206 @@ impl WorktreeIndex {
         }
     }
 
+    /// The language servers running for this worktree, which provide the symbols of
+    /// files whose language has no outline query.
+    fn language_servers(
+        &self,
+        cx: &AppContext,
+    ) -> HashMap<LanguageServerName, Arc<LanguageServer>> {
+        let Some(project) = self.project.upgrade() else {
+            return HashMap::default();
+        };
+        let worktree_id = self.worktree.read(cx).id();
+        let project = project.read(cx);
+        project
+            .language_servers()
+            .filter(|(_, _, server_worktree_id)| *server_worktree_id == worktree_id)
+            .filter_map(|(server_id, server_name, _)| {
+                Some((server_name, project.language_server_for_id(server_id)?))
+            })
+            .collect()
+    }
+
+    /// The files of this worktree that are open in buffers, which the language servers
+    /// already know about.
+    fn open_files(&self, cx: &AppContext) -> HashMap<Arc<Path>, OpenState> {
+        let Some(project) = self.project.upgrade() else {
+            return HashMap::default();
+        };
+        let worktree_id = self.worktree.read(cx).id();
+        project
+            .read(cx)
+            .opened_buffers()
+            .into_iter()
+            .filter_map(|buffer| {
+                let buffer = buffer.read(cx);
+                let file = buffer.file()?;
+                if WorktreeId::from_usize(file.worktree_id()) != worktree_id {
+                    return None;
+                }
+                let state = if buffer.is_dirty() {
+                    OpenState::Unsaved
+                } else {
+                    OpenState::Saved
+                };
+                Some((file.path().clone(), state))
+            })
+            .collect()
+    }
+
+    fn buffer_chunked_files(
+        &self,
+        chunked_files: channel::Receiver<ChunkedFile>,
+        cx: &AppContext,
+    ) -> ChunkFiles {
+        buffer_chunked_files(
+            chunked_files,
+            VectorStoreSettings::get_global(cx).indexing_memory_limit(),
+            self.db_writer.clone(),
+            self.pending_files_db,
+            cx.background_executor(),
+        )
+    }
+
+    /// The embedding provider, sending it batches whose size adapts to how fast it
+    /// responds and whether it's overloaded.
+    fn indexing_embedding_provider(&self, cx: &AppContext) -> Arc<dyn EmbeddingProvider> {
+        let settings = VectorStoreSettings::get_global(cx);
+        Arc::new(AdaptiveBatchProvider::new(
+            self.embedding_provider.clone(),
+            self.embedding_batch_size.clone(),
+            settings.min_embedding_batch_size,
+            settings.max_embedding_batch_size,
+        ))
+    }
+
     fn embed_files(
         embedding_provider: Arc<dyn EmbeddingProvider>,
+        indexing_errors: Arc<IndexingErrors>,
+        provider_health: Arc<ProviderHealth>,
+        pipeline_stats: Arc<PipelineStats>,
         chunked_files: channel::Receiver<ChunkedFile>,
         cx: &AppContext,
     ) -> EmbedFiles {
         let embedding_provider = embedding_provider.clone();
+        let similarity_metric = embedding_provider.similarity_metric();
+        let document_template = embedding_provider.document_template();
+        let dimensions = embedding_provider
+            .model_metadata()
+            .map(|model| model.dimensions);
+        let redact_secrets =
+            embedding_provider.is_remote() && VectorStoreSettings::get_global(cx).redact_secrets;
         let (embedded_files_tx, embedded_files_rx) = channel::bounded(512);
         let task = cx.background_executor().spawn(async move {
             let mut chunked_file_batches =
                 chunked_files.chunks_timeout(512, Duration::from_secs(2));
             while let Some(chunked_files) = chunked_file_batches.next().await {
+                let mut span = pipeline_stats.span(IndexingStage::Embed);
                 // View the batch of files as a vec of chunks
                 // Flatten out to a vec of chunks that we can subdivide into batch sized pieces
                 // Once those are done, reassemble them back into the files in which they belong
                 // If any embeddings fail for a file, the entire file is discarded
 
-                let chunks: Vec<TextToEmbed> = chunked_files
+                // Chunks with the same scope and contents as when the file was last indexed
+                // keep their embeddings, so that only the edited parts of a file are embedded.
+                let reused_embeddings = chunked_files
                     .iter()
-                    .flat_map(|file| {
-                        file.chunks.iter().map(|chunk| TextToEmbed {
-                            text: &file.text[chunk.range.clone()],
-                            digest: chunk.digest,
-                        })
+                    .map(|file| {
+                        let previous_embeddings = file
+                            .previous_chunks
+                            .iter()
+                            .map(|previous| {
+                                (
+                                    (previous.chunk.scope_path.as_deref(), previous.chunk.digest),
+                                    &previous.embedding,
+                                )
+                            })
+                            .collect::<HashMap<_, _>>();
+                        file.chunks
+                            .iter()
+                            .map(|chunk| {
+                                previous_embeddings
+                                    .get(&(chunk.scope_path.as_deref(), chunk.digest))
+                                    .map(|embedding| (*embedding).clone())
+                            })
+                            .collect::<Vec<_>>()
                     })
                     .collect::<Vec<_>>();
 
-                let mut embeddings: Vec<Option<Embedding>> = Vec::new();
-                for embedding_batch in chunks.chunks(embedding_provider.batch_size()) {
-                    if let Some(batch_embeddings) =
-                        embedding_provider.embed(embedding_batch).await.log_err()
-                    {
-                        if batch_embeddings.len() == embedding_batch.len() {
-                            embeddings.extend(batch_embeddings.into_iter().map(Some));
-                            continue;
-                        }
-                        log::error!(
-                            "embedding provider returned unexpected embedding count {}, expected {}",
-                            batch_embeddings.len(), embedding_batch.len()
-                        );
-                    }
+                let documents = chunked_files
+                    .iter()
+                    .zip(&reused_embeddings)
+                    .flat_map(|(file, reused_embeddings)| {
+                        let secrets = redact_secrets
+                            .then(|| redaction::Secrets::find(&file.path, &file.text));
+                        file.chunks
+                            .iter()
+                            .zip(reused_embeddings)
+                            .filter(|(_, reused_embedding)| reused_embedding.is_none())
+                            .map(|(chunk, _)| {
+                                let contents = match &secrets {
+                                    Some(secrets) => {
+                                        secrets.redact(&file.text, chunk.range.clone())
+                                    }
+                                    None => Cow::Borrowed(&file.text[chunk.range.clone()]),
+                                };
+                                document_template.render(&DocumentVariables {
+                                    path: &file.path,
+                                    language: file.language.as_deref(),
+                                    scope: chunk.scope_path.as_deref(),
+                                    contents: &contents,
+                                })
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .collect::<Vec<_>>();
+                let chunks: Vec<TextToEmbed> = documents
+                    .iter()
+                    .map(|document| TextToEmbed::new(document))
+                    .collect::<Vec<_>>();
+                span.add_items(chunks.len());
 
-                    embeddings.extend(iter::repeat(None).take(embedding_batch.len()));
+                let embeddings = embed_in_batches(
+                    embedding_provider.as_ref(),
+                    &chunks,
+                    dimensions,
+                    similarity_metric,
+                )
+                .await;
+                if !embeddings.is_empty() {
+                    if embeddings.iter().all(Option::is_some) {
+                        provider_health.record_success();
+                    } else {
+                        provider_health.record_failure("failed to compute embeddings");
+                    }
                 }
+                drop(span);
+
+                // When the provider works for other texts, or passes verification, the
+                // files that failed to embed are to blame rather than the provider.
+                let provider_works = embeddings.iter().any(Option::is_some)
+                    || (!embeddings.is_empty() && embedding_provider.verify().await.is_ok());
 
                 let mut embeddings = embeddings.into_iter();
-                for chunked_file in chunked_files {
+                for (chunked_file, reused_embeddings) in
+                    chunked_files.into_iter().zip(reused_embeddings)
+                {
                     let mut embedded_file = EmbeddedFile {
+                        keywords: keywords_in_text(&chunked_file.text),
                         path: chunked_file.path,
                         mtime: chunked_file.mtime,
+                        digest: chunked_file.digest,
+                        generated: chunked_file.generated,
                         chunks: Vec::new(),
+                        syntax_error: chunked_file.syntax_error,
+                        duplicate_of: chunked_file.duplicate_of,
                     };
 
                     let mut embedded_all_chunks = true;
-                    for (chunk, embedding) in
-                        chunked_file.chunks.into_iter().zip(embeddings.by_ref())
+                    for (chunk, reused_embedding) in
+                        chunked_file.chunks.into_iter().zip(reused_embeddings)
                     {
+                        let embedding = reused_embedding.or_else(|| embeddings.next().flatten());
                         if let Some(embedding) = embedding {
                             embedded_file
                                 .chunks
@@ -855,9 +2399,12 @@ impl WorktreeIndex {
                     }
 
                     if embedded_all_chunks {
+                        indexing_errors.reset_embed_failures(&embedded_file.path);
                         embedded_files_tx
                             .send((embedded_file, chunked_file.handle))
                             .await?;
+                    } else {
+                        indexing_errors.record_embed_failure(embedded_file.path, provider_works);
                     }
                 }
             }
@@ -876,32 +2423,72 @@ impl WorktreeIndex {
         embedded_files: channel::Receiver<(EmbeddedFile, IndexingEntryHandle)>,
         cx: &AppContext,
     ) -> Task<Result<()>> {
-        let db_connection = self.db_connection.clone();
+        let db_writer = self.db_writer.clone();
         let db = self.db;
+        let pipeline_stats = self.pipeline_stats.clone();
+        let content_index = self.content_index.clone();
+        let index_requests_tx = self.index_requests_tx.clone();
         cx.background_executor().spawn(async move {
+            // Files that refer to the embeddings of a file that was deleted or changed are
+            // embedded again by rescanning the worktree.
+            let mut released_embeddings = false;
+            let mut deletion_ranges = Vec::new();
             while let Some(deletion_range) = deleted_entry_ranges.next().await {
-                let mut txn = db_connection.write_txn()?;
-                let start = deletion_range.0.as_ref().map(|start| start.as_str());
-                let end = deletion_range.1.as_ref().map(|end| end.as_str());
-                log::debug!("deleting embeddings in range {:?}", &(start, end));
-                db.delete_range(&mut txn, &(start, end))?;
-                txn.commit()?;
+                released_embeddings |= content_index.remove_range(&(
+                    deletion_range.0.as_ref().map(|start| start.as_str()),
+                    deletion_range.1.as_ref().map(|end| end.as_str()),
+                ));
+                deletion_ranges.push(deletion_range);
+            }
+            if !deletion_ranges.is_empty() {
+                db_writer
+                    .write(move |_, txn| {
+                        for deletion_range in deletion_ranges {
+                            let start = deletion_range.0.as_ref().map(|start| start.as_str());
+                            let end = deletion_range.1.as_ref().map(|end| end.as_str());
+                            log::debug!("deleting embeddings in range {:?}", &(start, end));
+                            db.delete_range(txn, &(start, end))?;
+                        }
+                        Ok(())
+                    })
+                    .await?;
             }
 
             let mut embedded_files = embedded_files.chunks_timeout(4096, Duration::from_secs(2));
             while let Some(embedded_files) = embedded_files.next().await {
-                let mut txn = db_connection.write_txn()?;
-                for (file, _) in &embedded_files {
-                    log::debug!("saving embedding for file {:?}", file.path);
-                    let key = db_key_for_path(&file.path);
-                    db.put(&mut txn, &key, file)?;
+                let (files, handles): (Vec<_>, Vec<_>) = embedded_files.into_iter().unzip();
+                for file in &files {
+                    released_embeddings |= if file.duplicate_of.is_some() {
+                        let key = db_key_for_path(&file.path);
+                        content_index.remove_range(&(
+                            Bound::Included(key.as_str()),
+                            Bound::Included(key.as_str()),
+                        ))
+                    } else {
+                        content_index.update(&file.path, file.digest)
+                    };
                 }
-                txn.commit()?;
+                let mut span = pipeline_stats.span(IndexingStage::Write);
+                span.add_items(files.len());
+                db_writer
+                    .write(move |_, txn| {
+                        for file in &files {
+                            log::debug!("saving embedding for file {:?}", file.path);
+                            let key = db_key_for_path(&file.path);
+                            db.put(txn, &key, file)?;
+                        }
+                        Ok(())
+                    })
+                    .await?;
+                drop(span);
 
-                drop(embedded_files);
+                drop(handles);
                 log::debug!("committed");
             }
 
+            if released_embeddings {
+                index_requests_tx.try_send(IndexRequest::Rescan).ok();
+            }
             Ok(())
         })
     }
@@ -964,10 +2551,17 @@ struct ChunkFiles {
 
 struct ChunkedFile {
     pub path: Arc<Path>,
+    pub language: Option<Arc<str>>,
     pub mtime: Option<SystemTime>,
     pub handle: IndexingEntryHandle,
     pub text: String,
+    pub digest: [u8; 32],
+    pub generated: bool,
     pub chunks: Vec<Chunk>,
+    /// The chunks stored when the file was last indexed, whose embeddings can be reused.
+    pub previous_chunks: Vec<EmbeddedChunk>,
+    pub syntax_error: Option<String>,
+    pub duplicate_of: Option<Arc<Path>>,
 }
 
 struct EmbedFiles {
@@ -977,9 +2571,23 @@ struct EmbedFiles {
 
 #[derive(Debug, Serialize, Deserialize)]
 struct EmbeddedFile {
+    /// The hashes of the identifiers in the file. This must stay the first field, so that
+    /// searches can read it alone as [`FileKeywords`].
+    keywords: Vec<u32>,
     path: Arc<Path>,
     mtime: Option<SystemTime>,
+    /// The SHA-256 digest of the file's contents, which tells whether a file whose mtime
+    /// changed needs to be chunked again.
+    digest: [u8; 32],
+    /// Whether the file's contents look machine-generated. Files can also be generated
+    /// according to their path, see [`GeneratedFileFilter`].
+    generated: bool,
     chunks: Vec<EmbeddedChunk>,
+    syntax_error: Option<String>,
+    /// The path of a file with the same contents, which holds the embeddings for both.
+    /// Duplicates have no chunks of their own, and are searched with the other file's,
+    /// see [`decode_scanned_file`].
+    duplicate_of: Option<Arc<Path>>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -988,6 +2596,196 @@ struct EmbeddedChunk {
     embedding: Embedding,
 }
 
+/// Decodes a file read from the memory-mapped database once the memory it takes up fits
+/// within the search's limit, which stays reserved until the reservation is dropped.
+///
+/// A duplicate is given the chunks of the file holding the embeddings for its contents,
+/// so that searches return every path with those contents, including the duplicates that
+/// are in scope when the file holding the embeddings isn't.
+async fn decode_scanned_file(
+    db: heed::Database<Str, SerdeBincode<EmbeddedFile>>,
+    txn: &heed::RoTxn,
+    encoded_file: &[u8],
+    memory: &Arc<SearchMemory>,
+) -> Result<(EmbeddedFile, MemoryReservation)> {
+    // Embeddings are encoded as plain floats, so a file takes up about as much memory
+    // decoded as it does in the database.
+    let mut reservation = memory.reserve(encoded_file.len()).await;
+    let mut file = decode_embedded_file(encoded_file)?;
+    if let Some(canonical_path) = &file.duplicate_of {
+        let encoded_db = db.remap_data_type::<Bytes>();
+        if let Some(encoded_canonical_file) =
+            encoded_db.get(txn, &db_key_for_path(canonical_path))?
+        {
+            // Duplicates have no chunks of their own, so it's the file holding their
+            // embeddings that takes up memory. The duplicate's memory is released first,
+            // as waiting for more while holding it could wait forever.
+            drop(reservation);
+            reservation = memory.reserve(encoded_canonical_file.len()).await;
+            let canonical_file = decode_embedded_file(encoded_canonical_file)?;
+            if canonical_file.duplicate_of.is_none() && canonical_file.digest == file.digest {
+                file.chunks = canonical_file.chunks;
+            }
+        }
+    }
+    Ok((file, reservation))
+}
+
+fn decode_embedded_file(encoded_file: &[u8]) -> Result<EmbeddedFile> {
+    SerdeBincode::<EmbeddedFile>::bytes_decode(encoded_file).map_err(|error| anyhow!(error))
+}
+
+async fn send_file_chunks(
+    worktree_id: WorktreeId,
+    file: EmbeddedFile,
+    unsaved_text: Option<Arc<str>>,
+    generated_file_filter: &GeneratedFileFilter,
+    skipped_paths: &HashSet<(WorktreeId, Arc<Path>)>,
+    chunks_tx: &channel::Sender<(ChunkOwner, EmbeddedChunk)>,
+) -> Result<()> {
+    if skipped_paths.contains(&(worktree_id, file.path.clone())) {
+        return Ok(());
+    }
+    let generated = generated_file_filter.is_generated(&file);
+    if generated && generated_file_filter.mode == GeneratedFiles::Exclude {
+        return Ok(());
+    }
+    let owner = ChunkOwner::File {
+        worktree_id,
+        path: file.path,
+        mtime: file.mtime,
+        generated,
+        unsaved_text,
+    };
+    for chunk in file.chunks {
+        chunks_tx.send((owner.clone(), chunk)).await?;
+    }
+    Ok(())
+}
+
+/// The keys of the files containing any of the query's `keywords`, when the database
+/// has at least `min_file_count` files. Returns `None` when every file should be scored
+/// instead: when the query has no keywords, the database is small, or no file contains
+/// them, which may just mean that the query misspelled them.
+fn prefilter_files(
+    db: heed::Database<Str, SerdeBincode<EmbeddedFile>>,
+    txn: &heed::RoTxn,
+    keywords: &[u32],
+    min_file_count: u64,
+) -> Result<Option<Vec<String>>> {
+    if keywords.is_empty() || min_file_count == 0 || db.len(txn)? < min_file_count {
+        return Ok(None);
+    }
+
+    let mut db_keys = Vec::new();
+    let keywords_db = db.remap_data_type::<SerdeBincode<FileKeywords>>();
+    for db_entry in keywords_db
+        .iter(txn)
+        .context("failed to iterate database")?
+    {
+        let (db_key, file) = db_entry?;
+        if contains_any_keyword(&file.keywords, keywords) {
+            db_keys.push(db_key.to_string());
+        }
+    }
+    Ok((!db_keys.is_empty()).then_some(db_keys))
+}
+
+/// How many chunks sent by the scan tasks can wait to be scored before they take up
+/// more than `memory_limit` bytes. The files they're sent from are bounded separately,
+/// see [`SearchMemory`].
+fn search_chunks_capacity(memory_limit: usize, dimensions: usize) -> usize {
+    let chunk_size =
+        mem::size_of::<(ChunkOwner, EmbeddedChunk)>() + dimensions * mem::size_of::<f32>();
+    (memory_limit / chunk_size).max(1)
+}
+
+/// Opens the database at `db_path`, creating it if it doesn't exist yet. A compacted copy
+/// of the database that's up to date replaces it first.
+fn open_db(db_path: &Path) -> Result<heed::Env> {
+    std::fs::create_dir_all(db_path).context("creating database directory")?;
+    let db_connection = open_env(db_path)?;
+    replace_with_compacted_copy(db_connection, db_path, open_env)
+}
+
+fn open_env(db_path: &Path) -> Result<heed::Env> {
+    let db_connection = unsafe {
+        heed::EnvOpenOptions::new()
+            .map_size(1024 * 1024 * 1024)
+            .max_dbs(3000)
+            .open(db_path)
+    }
+    .context("opening database connection")?;
+    Ok(db_connection)
+}
+
+/// Describes how the embeddings of a worktree's database were produced.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct IndexMetadata {
+    format_version: u32,
+    similarity_metric: SimilarityMetric,
+    model: Option<EmbeddingModelMetadata>,
+    document_template: DocumentTemplate,
+}
+
+impl IndexMetadata {
+    fn for_provider(embedding_provider: &dyn EmbeddingProvider) -> Self {
+        Self {
+            format_version: INDEX_FORMAT_VERSION,
+            similarity_metric: embedding_provider.similarity_metric(),
+            model: embedding_provider.model_metadata(),
+            document_template: embedding_provider.document_template(),
+        }
+    }
+}
+
+/// The name of the database holding `kind` data for the worktree whose embeddings are in
+/// `db_name`. LMDB rejects names containing NUL, so unlike keys these use a printable
+/// separator.
+pub(crate) fn auxiliary_db_name(db_name: &str, kind: &str) -> String {
+    format!("{db_name}#{kind}")
+}
+
+/// The name of the database holding the embeddings stored under `db_name`. See
+/// [`DB_LOCATIONS_DB_NAME`].
+pub(crate) fn resolve_db_name(
+    db_connection: &heed::Env,
+    txn: &heed::RoTxn,
+    db_name: &str,
+) -> Result<String> {
+    let Some(locations_db) =
+        db_connection.open_database::<Str, Str>(txn, Some(DB_LOCATIONS_DB_NAME))?
+    else {
+        return Ok(db_name.to_string());
+    };
+    Ok(locations_db
+        .get(txn, db_name)?
+        .map_or_else(|| db_name.to_string(), |location| location.to_string()))
+}
+
+/// Clears the database named `db_name` if its embeddings weren't produced the way
+/// `metadata` describes. Embeddings stored for a different model or metric can't be
+/// compared with new ones, so everything in it has to be embedded from scratch.
+fn clear_stale_embeddings<T: 'static>(
+    db_connection: &heed::Env,
+    txn: &mut heed::RwTxn,
+    db_name: &str,
+    db: heed::Database<Str, T>,
+    metadata: &IndexMetadata,
+) -> Result<()> {
+    let metadata_db: heed::Database<Str, SerdeBincode<IndexMetadata>> =
+        db_connection.create_database(txn, Some(METADATA_DB_NAME))?;
+    let stored_metadata = metadata_db.get(txn, db_name).log_err().flatten();
+    if stored_metadata.as_ref() != Some(metadata) {
+        log::info!(
+            "index metadata changed for {db_name:?} ({stored_metadata:?} -> {metadata:?}), clearing embeddings"
+        );
+        db.clear(txn)?;
+        metadata_db.put(txn, db_name, metadata)?;
+    }
+    Ok(())
+}
+
 /// The set of entries that are currently being indexed.
 struct IndexingEntrySet {
     entry_ids: Mutex<HashSet<ProjectEntryId>>,
@@ -1032,19 +2830,111 @@ impl Drop for IndexingEntryHandle {
     }
 }
 
-fn db_key_for_path(path: &Arc<Path>) -> String {
+fn merge_entry_changes(
+    changes: &mut BTreeMap<Arc<Path>, (ProjectEntryId, PathChange)>,
+    updated_entries: &UpdatedEntriesSet,
+) {
+    for (path, entry_id, change) in updated_entries.iter() {
+        match changes.entry(path.clone()) {
+            collections::btree_map::Entry::Vacant(entry) => {
+                entry.insert((*entry_id, *change));
+            }
+            // Only the latest change to a path matters, but an entry being loaded
+            // doesn't undo an earlier change.
+            collections::btree_map::Entry::Occupied(mut entry) => {
+                if *change != PathChange::Loaded {
+                    entry.insert((*entry_id, *change));
+                }
+            }
+        }
+    }
+}
+
+/// Embeds `texts` in batches of the provider's batch size. A batch that fails to embed, or
+/// whose embeddings don't have the expected `dimensions`, yields `None` for each of its
+/// texts.
+async fn embed_in_batches(
+    embedding_provider: &dyn EmbeddingProvider,
+    texts: &[TextToEmbed<'_>],
+    dimensions: Option<usize>,
+    similarity_metric: SimilarityMetric,
+) -> Vec<Option<Embedding>> {
+    let mut embeddings: Vec<Option<Embedding>> = Vec::new();
+    for embedding_batch in texts.chunks(embedding_provider.batch_size()) {
+        if let Some(batch_embeddings) = embed_within_limits(embedding_provider, embedding_batch)
+            .await
+            .log_err()
+        {
+            if let Some(dimensions) = dimensions {
+                if let Some(embedding) = batch_embeddings
+                    .iter()
+                    .find(|embedding| embedding.len() != dimensions)
+                {
+                    log::error!(
+                        "embedding provider returned an embedding with {} dimensions, expected {}",
+                        embedding.len(),
+                        dimensions
+                    );
+                    embeddings.extend(iter::repeat(None).take(embedding_batch.len()));
+                    continue;
+                }
+            }
+
+            if batch_embeddings.len() == embedding_batch.len() {
+                embeddings.extend(
+                    batch_embeddings
+                        .into_iter()
+                        .map(|embedding| Some(similarity_metric.prepare(embedding))),
+                );
+                continue;
+            }
+            log::error!(
+                "embedding provider returned unexpected embedding count {}, expected {}",
+                batch_embeddings.len(),
+                embedding_batch.len()
+            );
+        }
+
+        embeddings.extend(iter::repeat(None).take(embedding_batch.len()));
+    }
+    embeddings
+}
+
+/// Whether the file at `path` is stored with its own embeddings for the contents with
+/// `digest`, so that duplicates of it can refer to them.
+fn holds_embeddings_for(
+    db: heed::Database<Str, SerdeBincode<EmbeddedFile>>,
+    txn: &heed::RoTxn,
+    path: &Arc<Path>,
+    digest: &[u8; 32],
+) -> Result<bool> {
+    Ok(db.get(txn, &db_key_for_path(path))?.map_or(false, |file| {
+        file.duplicate_of.is_none() && file.digest == *digest
+    }))
+}
+
+fn db_key_for_path(path: &Arc<Path>) -> String {
     path.to_string_lossy().replace('/', "\0")
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use fs::FakeFs;
     use futures::{future::BoxFuture, FutureExt};
     use gpui::TestAppContext;
-    use language::language_settings::AllLanguageSettings;
-    use project::Project;
+    use language::{language_settings::AllLanguageSettings, ToOffset};
+    use project::{Project, ProjectPath};
+    use serde_json::json;
     use settings::SettingsStore;
-    use std::{future, path::Path, sync::Arc};
+    use std::{
+        future,
+        path::Path,
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering::SeqCst},
+            Arc,
+        },
+    };
 
     fn init_test(cx: &mut TestAppContext) {
         _ = cx.update(|cx| {
@@ -1052,6 +2942,7 @@ mod tests {
             cx.set_global(store);
             language::init(cx);
             Project::init_settings(cx);
+            VectorStoreSettings::register(cx);
             SettingsStore::update(cx, |store, cx| {
                 store.update_user_settings::<AllLanguageSettings>(cx, |_| {});
             });
@@ -1061,6 +2952,7 @@ mod tests {
     pub struct TestEmbeddingProvider {
         batch_size: usize,
         compute_embedding: Box<dyn Fn(&str) -> Result<Embedding> + Send + Sync>,
+        model_metadata: Option<EmbeddingModelMetadata>,
     }
 
     impl TestEmbeddingProvider {
@@ -1071,8 +2963,14 @@ mod tests {
             return Self {
                 batch_size,
                 compute_embedding: Box::new(compute_embedding),
+                model_metadata: None,
             };
         }
+
+        pub fn with_model_metadata(mut self, model_metadata: EmbeddingModelMetadata) -> Self {
+            self.model_metadata = Some(model_metadata);
+            self
+        }
     }
 
     impl EmbeddingProvider for TestEmbeddingProvider {
@@ -1090,6 +2988,10 @@ mod tests {
         fn batch_size(&self) -> usize {
             self.batch_size
         }
+
+        fn model_metadata(&self) -> Option<EmbeddingModelMetadata> {
+            self.model_metadata.clone()
+        }
     }
 
     #[gpui::test]
@@ -1150,7 +3052,7 @@ mod tests {
             .update(|cx| {
                 let project_index = project_index.read(cx);
                 let query = "garbage in, garbage out";
-                project_index.search(query.into(), 4, cx)
+                project_index.search(query.into(), 4, None, 0., cx)
             })
             .await
             .unwrap();
@@ -1158,19 +3060,28 @@ mod tests {
         assert!(results.len() > 1, "should have found some results");
 
         for result in &results {
-            println!("result: {:?}", result.path);
+            if let SearchResultSource::File { path, .. } = &result.source {
+                println!("result: {:?}", path);
+            }
             println!("score: {:?}", result.score);
         }
 
         // Find result that is greater than 0.5
         let search_result = results.iter().find(|result| result.score > 0.9).unwrap();
+        let SearchResultSource::File {
+            worktree: result_worktree,
+            path: result_path,
+        } = &search_result.source
+        else {
+            panic!("expected a file result");
+        };
 
-        assert_eq!(search_result.path.to_string_lossy(), "needle.md");
+        assert_eq!(result_path.to_string_lossy(), "needle.md");
 
         let content = cx
             .update(|cx| {
-                let worktree = search_result.worktree.read(cx);
-                let entry_abs_path = worktree.abs_path().join(&search_result.path);
+                let worktree = result_worktree.read(cx);
+                let entry_abs_path = worktree.abs_path().join(result_path);
                 let fs = project.read(cx).fs().clone();
                 cx.background_executor()
                     .spawn(async move { fs.load(&entry_abs_path).await.unwrap() })
@@ -1183,9 +3094,1123 @@ mod tests {
         assert!(content.contains("garbage in, garbage out"));
     }
 
+    /// A project with one worktree at `/root` in a [`FakeFs`], and its index.
+    struct TestProject {
+        semantic_index: SemanticIndex,
+        fs: Arc<FakeFs>,
+        project: Model<Project>,
+        project_index: Model<ProjectIndex>,
+        _db_dir: tempfile::TempDir,
+    }
+
+    impl TestProject {
+        /// Starts indexing a project containing `tree`, whose files are embedded with
+        /// `compute_embedding`.
+        async fn new(
+            tree: serde_json::Value,
+            compute_embedding: impl 'static + Fn(&str) -> Result<Embedding> + Send + Sync,
+            cx: &mut TestAppContext,
+        ) -> Self {
+            let db_dir = tempfile::tempdir().unwrap();
+            let mut semantic_index = SemanticIndex::new(
+                db_dir.path().into(),
+                Arc::new(TestEmbeddingProvider::new(16, compute_embedding)),
+                &mut cx.to_async(),
+            )
+            .await
+            .unwrap();
+
+            let fs = FakeFs::new(cx.executor());
+            fs.insert_tree("/root", tree).await;
+            let project = Project::test(fs.clone(), ["/root".as_ref()], cx).await;
+            let project_index = cx.update(|cx| semantic_index.project_index(project.clone(), cx));
+            Self {
+                semantic_index,
+                fs,
+                project,
+                project_index,
+                _db_dir: db_dir,
+            }
+        }
+
+        /// Waits until at least `count` files of the project are stored in the index.
+        async fn wait_for_indexed_files(&self, count: u64, cx: &mut TestAppContext) {
+            while self.path_count(cx) < count {
+                self.project_index.next_event(cx).await;
+            }
+        }
+
+        fn path_count(&self, cx: &mut TestAppContext) -> u64 {
+            self.project_index
+                .read_with(cx, |index, cx| index.path_count(cx))
+                .unwrap()
+        }
+
+        fn search(
+            &self,
+            query: &str,
+            limit: usize,
+            min_similarity: Option<f32>,
+            cx: &mut TestAppContext,
+        ) -> Task<Result<Vec<SearchResult>>> {
+            cx.update(|cx| {
+                self.project_index
+                    .read(cx)
+                    .search(query.into(), limit, min_similarity, 0., cx)
+            })
+        }
+    }
+
+    /// The paths of search results, which must all be files.
+    fn result_paths(results: &[SearchResult]) -> Vec<Arc<Path>> {
+        results
+            .iter()
+            .map(|result| match &result.source {
+                SearchResultSource::File { path, .. } => path.clone(),
+                SearchResultSource::Document(_) => panic!("expected a file result"),
+            })
+            .collect()
+    }
+
+    #[gpui::test]
+    async fn test_search_history(cx: &mut TestAppContext) {
+        init_test(cx);
+
+        let test = TestProject::new(
+            json!({
+                "needle.md": "garbage in, garbage out",
+                "hay.md": "hay",
+            }),
+            |text| {
+                if text.contains("garbage") {
+                    Ok(Embedding::new(vec![1.0, 0.0]))
+                } else {
+                    Ok(Embedding::new(vec![0.0, 1.0]))
+                }
+            },
+            cx,
+        )
+        .await;
+        test.wait_for_indexed_files(2, cx).await;
+
+        let results = test
+            .search("garbage in, garbage out", 1, None, cx)
+            .await
+            .unwrap();
+        assert_eq!(result_paths(&results), [Arc::from(Path::new("needle.md"))]);
+
+        let worktree_id = test.project.read_with(cx, |project, cx| {
+            project.worktrees().next().unwrap().read(cx).id()
+        });
+        let (history, related_searches, unrelated_searches) = cx
+            .update(|cx| {
+                let project_index = test.project_index.read(cx);
+                futures::future::join3(
+                    project_index.search_history("GARBAGE", 10, cx),
+                    project_index.related_searches(
+                        ProjectPath {
+                            worktree_id,
+                            path: Path::new("needle.md").into(),
+                        },
+                        10,
+                        cx,
+                    ),
+                    project_index.related_searches(
+                        ProjectPath {
+                            worktree_id,
+                            path: Path::new("hay.md").into(),
+                        },
+                        10,
+                        cx,
+                    ),
+                )
+            })
+            .await;
+        assert_eq!(history.unwrap(), ["garbage in, garbage out"]);
+        assert_eq!(related_searches.unwrap(), ["garbage in, garbage out"]);
+        assert!(unrelated_searches.unwrap().is_empty());
+    }
+
+    #[gpui::test]
+    async fn test_search_stream(cx: &mut TestAppContext) {
+        init_test(cx);
+
+        let test = TestProject::new(
+            json!({
+                "a.md": "needle",
+                "b.md": "needle and hay",
+                "c.md": "hay",
+                "d.md": "more hay",
+                "e.md": "needle in hay",
+            }),
+            |text| {
+                if text.contains("needle") {
+                    Ok(Embedding::new(vec![1.0, text.len() as f32 / 100.]))
+                } else {
+                    Ok(Embedding::new(vec![0.0, 1.0]))
+                }
+            },
+            cx,
+        )
+        .await;
+        test.wait_for_indexed_files(5, cx).await;
+
+        let results = test.search("needle", 2, None, cx).await.unwrap();
+        let mut result_stream = cx.update(|cx| {
+            test.project_index
+                .read(cx)
+                .search_stream("needle".into(), 2, None, 0., cx)
+        });
+        let mut batches = Vec::new();
+        while let Some(batch) = result_stream.results.next().await {
+            batches.push(batch);
+        }
+        result_stream.task.await.unwrap();
+
+        // Every batch holds the best results found so far, and the last one holds the
+        // results of the whole search.
+        assert!(batches.iter().all(|batch| batch.len() <= 2));
+        let last_batch = batches.last().expect("should have reported results");
+        assert_eq!(result_paths(last_batch), result_paths(&results));
+        assert_eq!(
+            last_batch
+                .iter()
+                .map(|result| result.score)
+                .collect::<Vec<_>>(),
+            results
+                .iter()
+                .map(|result| result.score)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[gpui::test]
+    async fn test_search_documents(cx: &mut TestAppContext) {
+        init_test(cx);
+
+        let mut test = TestProject::new(
+            json!({ "main.rs": "fn main() {}" }),
+            |text| {
+                if text.contains("deploy") {
+                    Ok(Embedding::new(vec![1.0, 0.0]))
+                } else {
+                    Ok(Embedding::new(vec![0.0, 1.0]))
+                }
+            },
+            cx,
+        )
+        .await;
+
+        let conversation_id = DocumentId::new(DocumentSource::Conversation, "conversations/1");
+        let note_id = DocumentId::new(DocumentSource::ProjectNote, "notes/todo.md");
+        cx.update(|cx| {
+            futures::future::try_join(
+                test.semantic_index.index_document(
+                    conversation_id.clone(),
+                    "How do we deploy to staging?".into(),
+                    &test.project,
+                    cx,
+                ),
+                test.semantic_index.index_document(
+                    note_id.clone(),
+                    "Buy milk".into(),
+                    &test.project,
+                    cx,
+                ),
+            )
+        })
+        .await
+        .unwrap();
+
+        let results = test.search("deploy the app", 1, None, cx).await.unwrap();
+        assert_eq!(results.len(), 1);
+        let SearchResultSource::Document(result_id) = &results[0].source else {
+            panic!("expected a document result");
+        };
+        assert_eq!(result_id, &conversation_id);
+
+        let text = cx
+            .update(|cx| {
+                test.project_index
+                    .read(cx)
+                    .load_document(result_id.clone(), cx)
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            text.as_deref().map(|text| &text[results[0].range.clone()]),
+            Some("How do we deploy to staging?")
+        );
+
+        // Documents are only returned by the searches of the project they were added for.
+        test.fs
+            .insert_tree("/other", json!({ "main.rs": "fn main() {}" }))
+            .await;
+        let other_project = Project::test(test.fs.clone(), ["/other".as_ref()], cx).await;
+        let other_index =
+            cx.update(|cx| test.semantic_index.project_index(other_project.clone(), cx));
+        cx.run_until_parked();
+        let results = cx
+            .update(|cx| {
+                other_index
+                    .read(cx)
+                    .search("deploy the app".into(), 3, None, 0., cx)
+            })
+            .await
+            .unwrap();
+        assert!(results
+            .iter()
+            .all(|result| !matches!(result.source, SearchResultSource::Document(_))));
+
+        cx.update(|cx| {
+            test.semantic_index
+                .remove_document(conversation_id.clone(), cx)
+        })
+        .await
+        .unwrap();
+        let results = test.search("deploy the app", 3, None, cx).await.unwrap();
+        assert!(!results.iter().any(|result| matches!(
+            &result.source,
+            SearchResultSource::Document(id) if *id == conversation_id
+        )));
+    }
+
+    #[gpui::test]
+    async fn test_debounce_rapid_saves(cx: &mut TestAppContext) {
+        init_test(cx);
+        cx.update(|cx| {
+            SettingsStore::update(cx, |store, cx| {
+                store.update_user_settings::<VectorStoreSettings>(cx, |settings| {
+                    settings.reindex_debounce_ms = Some(500);
+                });
+            });
+        });
+
+        let embedded_texts = Arc::new(Mutex::new(Vec::<String>::new()));
+        let test = TestProject::new(
+            json!({ "notes.txt": "version 0" }),
+            {
+                let embedded_texts = embedded_texts.clone();
+                move |text| {
+                    embedded_texts.lock().push(text.to_string());
+                    Ok(Embedding::new(vec![1.0, 0.0]))
+                }
+            },
+            cx,
+        )
+        .await;
+        test.wait_for_indexed_files(1, cx).await;
+        cx.run_until_parked();
+        embedded_texts.lock().clear();
+
+        for version in 1..=3 {
+            test.fs
+                .insert_file("/root/notes.txt", format!("version {version}").into_bytes())
+                .await;
+            cx.run_until_parked();
+        }
+        assert!(
+            embedded_texts.lock().is_empty(),
+            "should wait for saves to settle before reindexing"
+        );
+
+        cx.executor().advance_clock(Duration::from_millis(500));
+        cx.run_until_parked();
+        let embedded_texts = embedded_texts.lock().clone();
+        assert_eq!(embedded_texts.len(), 1, "{embedded_texts:?}");
+        assert!(embedded_texts[0].contains("version 3"));
+    }
+
+    #[gpui::test]
+    async fn test_debounce_max_wait(cx: &mut TestAppContext) {
+        init_test(cx);
+        cx.update(|cx| {
+            SettingsStore::update(cx, |store, cx| {
+                store.update_user_settings::<VectorStoreSettings>(cx, |settings| {
+                    settings.reindex_debounce_ms = Some(500);
+                    settings.reindex_max_wait_ms = Some(1000);
+                });
+            });
+        });
+
+        let embedded_texts = Arc::new(Mutex::new(Vec::<String>::new()));
+        let test = TestProject::new(
+            json!({ "log.txt": "version 0" }),
+            {
+                let embedded_texts = embedded_texts.clone();
+                move |text| {
+                    embedded_texts.lock().push(text.to_string());
+                    Ok(Embedding::new(vec![1.0, 0.0]))
+                }
+            },
+            cx,
+        )
+        .await;
+        test.wait_for_indexed_files(1, cx).await;
+        cx.run_until_parked();
+        embedded_texts.lock().clear();
+
+        // The file changes more often than the debounce, but is still indexed once the
+        // first change has waited for the maximum.
+        for version in 1..=4 {
+            test.fs
+                .insert_file("/root/log.txt", format!("version {version}").into_bytes())
+                .await;
+            cx.run_until_parked();
+            cx.executor().advance_clock(Duration::from_millis(400));
+            cx.run_until_parked();
+        }
+        let embedded_texts = embedded_texts.lock().clone();
+        assert_eq!(embedded_texts.len(), 1, "{embedded_texts:?}");
+        assert!(embedded_texts[0].contains("version 3"));
+    }
+
+    #[gpui::test]
+    async fn test_index_added_worktree(cx: &mut TestAppContext) {
+        init_test(cx);
+
+        let test = TestProject::new(
+            json!({ "hay.txt": "hay" }),
+            |text| {
+                if text.contains("needle") {
+                    Ok(Embedding::new(vec![1.0, 0.0]))
+                } else {
+                    Ok(Embedding::new(vec![0.0, 1.0]))
+                }
+            },
+            cx,
+        )
+        .await;
+        test.fs
+            .insert_tree("/second", json!({ "needle.txt": "needle" }))
+            .await;
+        test.wait_for_indexed_files(1, cx).await;
+
+        let (second_worktree, _) = test
+            .project
+            .update(cx, |project, cx| {
+                project.find_or_create_local_worktree("/second", true, cx)
+            })
+            .await
+            .unwrap();
+        test.wait_for_indexed_files(2, cx).await;
+
+        let results = test.search("needle", 1, None, cx).await.unwrap();
+        let SearchResultSource::File { worktree, path } = &results[0].source else {
+            panic!("expected a file result");
+        };
+        assert_eq!(worktree, &second_worktree);
+        assert_eq!(path.as_ref(), Path::new("needle.txt"));
+    }
+
+    #[gpui::test]
+    async fn test_load_indexed_worktree(cx: &mut TestAppContext) {
+        init_test(cx);
+
+        let embedded_count = Arc::new(AtomicUsize::new(0));
+        let mut test = TestProject::new(
+            json!({ "a.txt": "a", "b.txt": "b" }),
+            {
+                let embedded_count = embedded_count.clone();
+                move |_| {
+                    embedded_count.fetch_add(1, SeqCst);
+                    Ok(Embedding::new(vec![1.0, 0.0]))
+                }
+            },
+            cx,
+        )
+        .await;
+        test.wait_for_indexed_files(2, cx).await;
+        cx.run_until_parked();
+        let embedded_count_after_first_load = embedded_count.load(SeqCst);
+
+        // Opening the worktree again reuses the databases created by the first load.
+        let project = Project::test(test.fs.clone(), ["/root".as_ref()], cx).await;
+        let project_index = cx.update(|cx| test.semantic_index.project_index(project, cx));
+        cx.run_until_parked();
+        assert_eq!(
+            project_index
+                .read_with(cx, |index, cx| index.path_count(cx))
+                .unwrap(),
+            2
+        );
+        assert_eq!(embedded_count.load(SeqCst), embedded_count_after_first_load);
+    }
+
+    #[gpui::test]
+    async fn test_model_migration(cx: &mut TestAppContext) {
+        init_test(cx);
+
+        let test = TestProject::new(
+            json!({ "needle.txt": "needle", "hay.txt": "hay" }),
+            |text| {
+                if text.contains("needle") {
+                    Ok(Embedding::new(vec![1.0, 0.0]))
+                } else {
+                    Ok(Embedding::new(vec![0.0, 1.0]))
+                }
+            },
+            cx,
+        )
+        .await;
+        test.wait_for_indexed_files(2, cx).await;
+        let TestProject {
+            semantic_index,
+            fs,
+            project_index,
+            _db_dir,
+            ..
+        } = test;
+        cx.update(|cx| cx.set_global(semantic_index));
+        let search = |cx: &mut TestAppContext| {
+            cx.update(|cx| {
+                project_index
+                    .read(cx)
+                    .search("straw".into(), 1, None, 0., cx)
+            })
+        };
+        assert_eq!(
+            result_paths(&search(cx).await.unwrap()),
+            [Arc::from(Path::new("hay.txt"))]
+        );
+
+        // The new model ranks the files the other way around, and its embeddings have
+        // another size.
+        let embedded_file_count = Arc::new(AtomicUsize::new(0));
+        let embedding_provider = TestEmbeddingProvider::new(16, {
+            let embedded_file_count = embedded_file_count.clone();
+            move |text| {
+                if text.contains("needle") || text.contains("hay") {
+                    embedded_file_count.fetch_add(1, SeqCst);
+                }
+                if text.contains("hay") {
+                    Ok(Embedding::new(vec![0.0, 1.0, 0.0]))
+                } else {
+                    Ok(Embedding::new(vec![1.0, 0.0, 0.0]))
+                }
+            }
+        })
+        .with_model_metadata(EmbeddingModelMetadata {
+            name: "test/other".into(),
+            dimensions: 3,
+            normalized: true,
+            similarity_metric: SimilarityMetric::Cosine,
+        });
+        cx.update(|cx| {
+            cx.update_global::<SemanticIndex, _>(|semantic_index, cx| {
+                semantic_index.set_embedding_provider(Arc::new(embedding_provider), cx)
+            })
+        });
+
+        // Searches use the previous model until the project is indexed with the new one.
+        assert_eq!(
+            result_paths(&search(cx).await.unwrap()),
+            [Arc::from(Path::new("hay.txt"))]
+        );
+        cx.run_until_parked();
+        assert_eq!(embedded_file_count.load(SeqCst), 2);
+        assert_eq!(
+            project_index
+                .read_with(cx, |index, cx| index.path_count(cx))
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            result_paths(&search(cx).await.unwrap()),
+            [Arc::from(Path::new("needle.txt"))]
+        );
+
+        // The promoted embeddings are found again when the worktree is next opened.
+        let project = Project::test(fs, ["/root".as_ref()], cx).await;
+        let project_index = cx.update(|cx| {
+            cx.update_global::<SemanticIndex, _>(|semantic_index, cx| {
+                semantic_index.project_index(project, cx)
+            })
+        });
+        cx.run_until_parked();
+        assert_eq!(
+            project_index
+                .read_with(cx, |index, cx| index.path_count(cx))
+                .unwrap(),
+            2
+        );
+        assert_eq!(embedded_file_count.load(SeqCst), 2);
+    }
+
+    #[gpui::test]
+    async fn test_index_summary(cx: &mut TestAppContext) {
+        init_test(cx);
+
+        let test = TestProject::new(
+            json!({ "a.txt": "a", "b.txt": "b" }),
+            |_| Ok(Embedding::new(vec![1.0, 0.0])),
+            cx,
+        )
+        .await;
+        let unindexed_project = Project::test(test.fs.clone(), ["/root".as_ref()], cx).await;
+        assert!(!cx.update(|cx| {
+            test.semantic_index
+                .has_index_for_project(&unindexed_project, cx)
+        }));
+        assert_eq!(
+            cx.update(|cx| test.semantic_index.index_summary(&unindexed_project, cx))
+                .unwrap(),
+            IndexSummary::default()
+        );
+
+        while test
+            .project_index
+            .read_with(cx, |index, _| index.last_indexed_at())
+            .is_none()
+        {
+            test.project_index.next_event(cx).await;
+        }
+
+        assert!(cx.update(|cx| test.semantic_index.has_index_for_project(&test.project, cx)));
+        let summary = cx
+            .update(|cx| test.semantic_index.index_summary(&test.project, cx))
+            .unwrap();
+        assert_eq!(summary.file_count, 2);
+        assert_eq!(summary.document_count, 0);
+        assert!(summary.updated_at.is_some());
+    }
+
+    #[gpui::test]
+    async fn test_duplicate_files(cx: &mut TestAppContext) {
+        init_test(cx);
+
+        let embedded_texts = Arc::new(Mutex::new(Vec::new()));
+        let test = TestProject::new(
+            json!({
+                "a.txt": "a needle",
+                "hay.txt": "hay",
+                "vendor": { "a.txt": "a needle" },
+            }),
+            {
+                let embedded_texts = embedded_texts.clone();
+                move |text| {
+                    embedded_texts.lock().push(text.to_string());
+                    if text.contains("needle") {
+                        Ok(Embedding::new(vec![1.0, 0.0]))
+                    } else {
+                        Ok(Embedding::new(vec![0.0, 1.0]))
+                    }
+                }
+            },
+            cx,
+        )
+        .await;
+        test.wait_for_indexed_files(3, cx).await;
+        cx.run_until_parked();
+
+        // The copy isn't embedded, but searches return it along with the file that was.
+        let needle_count = || {
+            embedded_texts
+                .lock()
+                .iter()
+                .filter(|text| text.contains("a needle"))
+                .count()
+        };
+        assert_eq!(needle_count(), 1);
+        let results = test.search("needle", 10, Some(0.5), cx).await.unwrap();
+        let mut paths = result_paths(&results);
+        paths.sort();
+        assert_eq!(
+            paths,
+            [
+                Arc::from(Path::new("a.txt")),
+                Arc::from(Path::new("vendor/a.txt"))
+            ]
+        );
+
+        // A search of a directory finds the copy in it, whichever file holds the
+        // embeddings.
+        let worktree_id = test.project.read_with(cx, |project, cx| {
+            project.worktrees().next().unwrap().read(cx).id()
+        });
+        let results = cx
+            .update(|cx| {
+                test.project_index.read(cx).search_in_directory(
+                    ProjectPath {
+                        worktree_id,
+                        path: Path::new("vendor").into(),
+                    },
+                    "needle".into(),
+                    10,
+                    Some(0.5),
+                    0.,
+                    cx,
+                )
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            result_paths(&results),
+            [Arc::from(Path::new("vendor/a.txt"))]
+        );
+
+        let dump = cx
+            .update(|cx| test.project_index.read(cx).debug_dump(cx))
+            .await
+            .unwrap();
+        let copy = dump.worktrees[0]
+            .files
+            .iter()
+            .find(|file| file.duplicate_of.is_some())
+            .unwrap();
+        let (embedded_path, copy_path) = (copy.duplicate_of.clone().unwrap(), copy.path.clone());
+
+        // When the file holding the embeddings is deleted, its copy is embedded instead.
+        test.fs
+            .remove_file(&Path::new("/root").join(&embedded_path), Default::default())
+            .await
+            .unwrap();
+        cx.executor().advance_clock(Duration::from_secs(1));
+        cx.run_until_parked();
+        assert_eq!(needle_count(), 2);
+        let results = test.search("needle", 10, Some(0.5), cx).await.unwrap();
+        assert_eq!(result_paths(&results), [copy_path]);
+    }
+
+    #[gpui::test]
+    async fn test_search_paginated(cx: &mut TestAppContext) {
+        init_test(cx);
+
+        let embedded_query_count = Arc::new(AtomicUsize::new(0));
+        let test = TestProject::new(
+            json!({
+                "a.txt": "needle a",
+                "b.txt": "needle b",
+                "c.txt": "needle c",
+            }),
+            {
+                let embedded_query_count = embedded_query_count.clone();
+                move |text| {
+                    if text == "needle" {
+                        embedded_query_count.fetch_add(1, SeqCst);
+                    }
+                    Ok(Embedding::new(vec![1.0, 0.0]))
+                }
+            },
+            cx,
+        )
+        .await;
+        test.wait_for_indexed_files(3, cx).await;
+
+        let first_page = cx
+            .update(|cx| {
+                test.project_index
+                    .read(cx)
+                    .search_paginated("needle".into(), 2, None, 0., cx)
+            })
+            .await
+            .unwrap();
+        assert_eq!(first_page.results.len(), 2);
+        assert!(!first_page.truncated);
+        let cursor = first_page.cursor.unwrap();
+        assert_eq!(cursor.offset(), 2);
+
+        // Later pages don't search the index again.
+        let second_page = cursor.next_page();
+        assert_eq!(second_page.results.len(), 1);
+        assert!(second_page.cursor.is_none());
+        assert!(!second_page.truncated);
+        assert_eq!(embedded_query_count.load(SeqCst), 1);
+
+        let mut paths = result_paths(&first_page.results);
+        paths.extend(result_paths(&second_page.results));
+        paths.sort();
+        assert_eq!(
+            paths,
+            ["a.txt", "b.txt", "c.txt"]
+                .into_iter()
+                .map(|path| Arc::from(Path::new(path)))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[gpui::test]
+    async fn test_search_paginated_truncated(cx: &mut TestAppContext) {
+        init_test(cx);
+
+        let file_count = MAX_SEARCH_PAGES + 1;
+        let files = (0..file_count)
+            .map(|ix| (format!("{ix}.txt"), json!(format!("needle {ix}"))))
+            .collect::<serde_json::Map<_, _>>();
+        let test = TestProject::new(
+            serde_json::Value::Object(files),
+            |_| Ok(Embedding::new(vec![1.0, 0.0])),
+            cx,
+        )
+        .await;
+        test.wait_for_indexed_files(file_count as u64, cx).await;
+
+        // The results that don't fit in the pages are reported on the last page.
+        let mut page = cx
+            .update(|cx| {
+                test.project_index
+                    .read(cx)
+                    .search_paginated("needle".into(), 1, None, 0., cx)
+            })
+            .await
+            .unwrap();
+        let mut result_count = page.results.len();
+        while let Some(cursor) = page.cursor {
+            assert!(!page.truncated);
+            page = cursor.next_page();
+            result_count += page.results.len();
+        }
+        assert_eq!(result_count, MAX_SEARCH_PAGES);
+        assert!(page.truncated);
+    }
+
+    #[gpui::test]
+    async fn test_keyword_prefilter(cx: &mut TestAppContext) {
+        init_test(cx);
+        cx.update(|cx| {
+            SettingsStore::update(cx, |store, cx| {
+                store.update_user_settings::<VectorStoreSettings>(cx, |settings| {
+                    settings.keyword_prefilter_min_files = Some(1);
+                });
+            });
+        });
+
+        let test = TestProject::new(
+            json!({
+                "config.rs": "fn parse_config() {}",
+                "main.rs": "fn main() {}",
+            }),
+            |_| Ok(Embedding::new(vec![1.0, 0.0])),
+            cx,
+        )
+        .await;
+        test.wait_for_indexed_files(2, cx).await;
+
+        let results = test
+            .search("where is parse_config called", 2, None, cx)
+            .await
+            .unwrap();
+        assert_eq!(result_paths(&results), [Arc::from(Path::new("config.rs"))]);
+
+        // Conceptual queries and misspelled identifiers score every file.
+        assert_eq!(
+            test.search("configuration", 2, None, cx)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(
+            test.search("parse_confgi", 2, None, cx)
+                .await
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+
+    #[gpui::test]
+    async fn test_search_memory_limit(cx: &mut TestAppContext) {
+        init_test(cx);
+        // Without any memory to spare, files are decoded one at a time.
+        cx.update(|cx| {
+            SettingsStore::update(cx, |store, cx| {
+                store.update_user_settings::<VectorStoreSettings>(cx, |settings| {
+                    settings.search_memory_limit_mb = Some(0);
+                });
+            });
+        });
+
+        let test = TestProject::new(
+            json!({
+                "a.txt": "a needle",
+                "b.txt": "b needle",
+                "c.txt": "c needle",
+                "vendor": { "a.txt": "a needle" },
+            }),
+            |_| Ok(Embedding::new(vec![1.0, 0.0])),
+            cx,
+        )
+        .await;
+        test.wait_for_indexed_files(4, cx).await;
+
+        let results = test.search("needle", 10, None, cx).await.unwrap();
+        let mut paths = result_paths(&results);
+        paths.sort();
+        assert_eq!(
+            paths,
+            [
+                Arc::from(Path::new("a.txt")),
+                Arc::from(Path::new("b.txt")),
+                Arc::from(Path::new("c.txt")),
+                Arc::from(Path::new("vendor/a.txt")),
+            ]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_search_in_directory(cx: &mut TestAppContext) {
+        init_test(cx);
+
+        let test = TestProject::new(
+            json!({
+                "main.rs": "fn main() {}",
+                "src": {
+                    "lib.rs": "mod nested;",
+                    "nested": {
+                        "mod.rs": "fn nested() {}",
+                    },
+                },
+                "srcs": {
+                    "other.rs": "fn other() {}",
+                },
+            }),
+            |_| Ok(Embedding::new(vec![1.0, 0.0])),
+            cx,
+        )
+        .await;
+        let worktree_id = test.project.read_with(cx, |project, cx| {
+            project.worktrees().next().unwrap().read(cx).id()
+        });
+        test.wait_for_indexed_files(4, cx).await;
+
+        let results = cx
+            .update(|cx| {
+                test.project_index.read(cx).search_in_directory(
+                    ProjectPath {
+                        worktree_id,
+                        path: Path::new("src").into(),
+                    },
+                    "function".into(),
+                    10,
+                    None,
+                    0.,
+                    cx,
+                )
+            })
+            .await
+            .unwrap();
+        let mut paths = result_paths(&results);
+        paths.sort();
+        assert_eq!(
+            paths,
+            [
+                Arc::from(Path::new("src/lib.rs")),
+                Arc::from(Path::new("src/nested/mod.rs")),
+            ]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_min_similarity(cx: &mut TestAppContext) {
+        init_test(cx);
+
+        let test = TestProject::new(
+            json!({
+                "needle.txt": "needle",
+                "hay.txt": "hay",
+            }),
+            |text| {
+                if text.contains("needle") {
+                    Ok(Embedding::new(vec![1.0, 0.0]))
+                } else {
+                    Ok(Embedding::new(vec![0.0, 1.0]))
+                }
+            },
+            cx,
+        )
+        .await;
+        test.wait_for_indexed_files(2, cx).await;
+
+        assert_eq!(test.search("needle", 10, None, cx).await.unwrap().len(), 2);
+        let results = test.search("needle", 10, Some(0.5), cx).await.unwrap();
+        assert_eq!(result_paths(&results), [Arc::from(Path::new("needle.txt"))]);
+    }
+
+    #[gpui::test]
+    async fn test_unreachable_provider(cx: &mut TestAppContext) {
+        init_test(cx);
+
+        let offline = Arc::new(AtomicBool::new(true));
+        let test = TestProject::new(
+            json!({
+                "needle.txt": "a needle in a haystack",
+                "hay.txt": "nothing but hay",
+            }),
+            {
+                let offline = offline.clone();
+                move |_| {
+                    if offline.load(SeqCst) {
+                        Err(anyhow!("offline"))
+                    } else {
+                        Ok(Embedding::new(vec![1.0, 0.0]))
+                    }
+                }
+            },
+            cx,
+        )
+        .await;
+        cx.run_until_parked();
+        assert_eq!(test.path_count(cx), 0);
+        assert!(test
+            .project_index
+            .read_with(cx, |index, _| index.embedding_provider_error())
+            .is_some());
+
+        // Files that failed to embed are retried with a delay.
+        offline.store(false, SeqCst);
+        cx.executor().advance_clock(Duration::from_secs(60));
+        cx.run_until_parked();
+        assert_eq!(test.path_count(cx), 2);
+        assert_eq!(
+            test.project_index
+                .read_with(cx, |index, _| index.embedding_provider_error()),
+            None
+        );
+
+        // While the query can't be embedded, indexed files are found by the words they
+        // contain.
+        offline.store(true, SeqCst);
+        let results = test.search("needle", 2, None, cx).await.unwrap();
+        assert_eq!(result_paths(&results), [Arc::from(Path::new("needle.txt"))]);
+        assert_eq!(results[0].relevance, None);
+    }
+
+    #[gpui::test]
+    async fn test_provider_verification(cx: &mut TestAppContext) {
+        init_test(cx);
+
+        let has_api_key = Arc::new(AtomicBool::new(false));
+        let embedded_count = Arc::new(AtomicUsize::new(0));
+        let test = TestProject::new(
+            json!({
+                "a.txt": "a needle in a haystack",
+                "b.txt": "nothing but hay",
+            }),
+            {
+                let has_api_key = has_api_key.clone();
+                let embedded_count = embedded_count.clone();
+                move |_| {
+                    embedded_count.fetch_add(1, SeqCst);
+                    if has_api_key.load(SeqCst) {
+                        Ok(Embedding::new(vec![1.0, 0.0]))
+                    } else {
+                        Err(anyhow!("invalid API key"))
+                    }
+                }
+            },
+            cx,
+        )
+        .await;
+        cx.run_until_parked();
+
+        // Only the verification reached the provider, rather than every file.
+        assert_eq!(embedded_count.load(SeqCst), 1);
+        assert_eq!(
+            test.project_index
+                .read_with(cx, |index, _| index.embedding_provider_error())
+                .as_deref(),
+            Some("invalid API key")
+        );
+        assert!(test
+            .project_index
+            .read_with(cx, |index, cx| index.indexing_errors(cx))
+            .is_empty());
+
+        // Changing the settings verifies the provider again, and the worktree is indexed
+        // once it passes.
+        has_api_key.store(true, SeqCst);
+        cx.update(|cx| {
+            SettingsStore::update(cx, |store, cx| {
+                store.update_user_settings::<VectorStoreSettings>(cx, |_| {});
+            });
+        });
+        cx.run_until_parked();
+        assert_eq!(
+            test.project_index
+                .read_with(cx, |index, _| index.embedding_provider_error()),
+            None
+        );
+        assert_eq!(test.path_count(cx), 2);
+    }
+
+    #[gpui::test]
+    async fn test_embeddings_for_buffer(cx: &mut TestAppContext) {
+        init_test(cx);
+
+        let test = TestProject::new(
+            json!({ "a.txt": "needle in a haystack" }),
+            |_| Ok(Embedding::new(vec![1.0, 0.0])),
+            cx,
+        )
+        .await;
+        test.wait_for_indexed_files(1, cx).await;
+
+        let buffer = test
+            .project
+            .update(cx, |project, cx| {
+                project.open_local_buffer("/root/a.txt", cx)
+            })
+            .await
+            .unwrap();
+        let embeddings_for_buffer = |cx: &mut TestAppContext| {
+            cx.update(|cx| {
+                test.project_index
+                    .read(cx)
+                    .embeddings_for_buffer(&buffer, cx)
+            })
+        };
+        let embeddings = embeddings_for_buffer(cx).await.unwrap();
+        assert_eq!(embeddings.len(), 1);
+        buffer.read_with(cx, |buffer, _| {
+            let range = embeddings[0].range.to_offset(buffer);
+            assert_eq!(range, 0..buffer.len());
+        });
+
+        // Embeddings of text that has since been edited would be misleading.
+        buffer.update(cx, |buffer, cx| buffer.edit([(0..6, "hay")], None, cx));
+        assert!(embeddings_for_buffer(cx).await.unwrap().is_empty());
+    }
+
+    #[gpui::test]
+    async fn test_search_unsaved_buffers(cx: &mut TestAppContext) {
+        init_test(cx);
+
+        let test = TestProject::new(
+            json!({ "a.txt": "needle in a haystack" }),
+            |_| Ok(Embedding::new(vec![1.0, 0.0])),
+            cx,
+        )
+        .await;
+        test.wait_for_indexed_files(1, cx).await;
+
+        let buffer = test
+            .project
+            .update(cx, |project, cx| {
+                project.open_local_buffer("/root/a.txt", cx)
+            })
+            .await
+            .unwrap();
+        buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "the ")], None, cx));
+
+        let results = test.search("needle", 10, None, cx).await.unwrap();
+        // The unsaved text replaces the indexed one rather than adding to it.
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].range, 0.."the needle in a haystack".len());
+
+        // The result's text is the buffer's, which its range refers to.
+        let text = test
+            .project_index
+            .read_with(cx, |index, cx| index.result_text(&results[0], cx))
+            .await
+            .unwrap();
+        assert_eq!(text.as_deref(), Some("the needle in a haystack"));
+    }
+
     #[gpui::test]
     async fn test_embed_files(cx: &mut TestAppContext) {
         cx.executor().allow_parking();
+        init_test(cx);
 
         let provider = Arc::new(TestEmbeddingProvider::new(3, |text| {
             if text.contains('g') {
@@ -1206,37 +4231,62 @@ mod tests {
         chunked_files_tx
             .send_blocking(ChunkedFile {
                 path: Path::new("test1.md").into(),
+                language: None,
                 mtime: None,
                 handle: indexing_entries.insert(ProjectEntryId::from_proto(0)),
                 text: "abcdefghijklmnop".to_string(),
+                digest: Default::default(),
+                generated: false,
                 chunks: [0..4, 4..8, 8..12, 12..16]
                     .into_iter()
                     .map(|range| Chunk {
                         range,
                         digest: Default::default(),
+                        scope_path: None,
+                        item_range: None,
                     })
                     .collect(),
+                previous_chunks: Vec::new(),
+                syntax_error: None,
+                duplicate_of: None,
             })
             .unwrap();
         chunked_files_tx
             .send_blocking(ChunkedFile {
                 path: Path::new("test2.md").into(),
+                language: None,
                 mtime: None,
                 handle: indexing_entries.insert(ProjectEntryId::from_proto(1)),
                 text: "qrstuvwxyz".to_string(),
+                digest: Default::default(),
+                generated: false,
                 chunks: [0..4, 4..8, 8..10]
                     .into_iter()
                     .map(|range| Chunk {
                         range,
                         digest: Default::default(),
+                        scope_path: None,
+                        item_range: None,
                     })
                     .collect(),
+                previous_chunks: Vec::new(),
+                syntax_error: None,
+                duplicate_of: None,
             })
             .unwrap();
         chunked_files_tx.close();
 
-        let embed_files_task =
-            cx.update(|cx| WorktreeIndex::embed_files(provider.clone(), chunked_files_rx, cx));
+        let indexing_errors = Arc::new(IndexingErrors::default());
+        let embed_files_task = cx.update(|cx| {
+            WorktreeIndex::embed_files(
+                provider.clone(),
+                indexing_errors.clone(),
+                Default::default(),
+                Default::default(),
+                chunked_files_rx,
+                cx,
+            )
+        });
         embed_files_task.task.await.unwrap();
 
         let mut embedded_files_rx = embed_files_task.files;
@@ -1247,6 +4297,16 @@ mod tests {
 
         assert_eq!(embedded_files.len(), 1);
         assert_eq!(embedded_files[0].path.as_ref(), Path::new("test2.md"));
+        assert_eq!(
+            indexing_errors.errors(),
+            vec![(
+                Arc::from(Path::new("test1.md")),
+                IndexingError {
+                    kind: IndexingErrorKind::Embed,
+                    message: "failed to compute embeddings".into(),
+                }
+            )]
+        );
         assert_eq!(
             embedded_files[0]
                 .chunks
@@ -1260,4 +4320,177 @@ mod tests {
             ],
         );
     }
+
+    #[gpui::test]
+    async fn test_embed_files_reuses_unchanged_chunks(cx: &mut TestAppContext) {
+        cx.executor().allow_parking();
+        init_test(cx);
+
+        let embedded_texts = Arc::new(Mutex::new(Vec::<String>::new()));
+        let provider = Arc::new(TestEmbeddingProvider::new(3, {
+            let embedded_texts = embedded_texts.clone();
+            move |text| {
+                embedded_texts.lock().push(text.to_string());
+                Ok(Embedding::new(vec![0.0, 1.0]))
+            }
+        }));
+
+        let chunk = |range: Range<usize>, digest: u8| Chunk {
+            range,
+            digest: [digest; 32],
+            scope_path: Some(format!("fn f{digest}")),
+            item_range: None,
+        };
+        let previous_chunks = vec![
+            EmbeddedChunk {
+                chunk: chunk(0..4, 1),
+                embedding: Embedding::new(vec![1.0, 0.0]),
+            },
+            EmbeddedChunk {
+                chunk: chunk(4..8, 2),
+                embedding: Embedding::new(vec![1.0, 0.0]),
+            },
+            EmbeddedChunk {
+                chunk: chunk(8..12, 3),
+                embedding: Embedding::new(vec![1.0, 0.0]),
+            },
+        ];
+
+        let (indexing_progress_tx, _) = channel::unbounded();
+        let indexing_entries = Arc::new(IndexingEntrySet::new(indexing_progress_tx));
+        let (chunked_files_tx, chunked_files_rx) = channel::unbounded::<ChunkedFile>();
+        chunked_files_tx
+            .send_blocking(ChunkedFile {
+                path: Path::new("test.md").into(),
+                language: None,
+                mtime: None,
+                handle: indexing_entries.insert(ProjectEntryId::from_proto(0)),
+                text: "abcdEFGHijklmnop".to_string(),
+                digest: Default::default(),
+                generated: false,
+                // The second chunk was edited, and a fourth one was added.
+                chunks: vec![
+                    chunk(0..4, 1),
+                    chunk(4..8, 4),
+                    chunk(8..12, 3),
+                    chunk(12..16, 5),
+                ],
+                previous_chunks,
+                syntax_error: None,
+                duplicate_of: None,
+            })
+            .unwrap();
+        chunked_files_tx.close();
+
+        let embed_files_task = cx.update(|cx| {
+            WorktreeIndex::embed_files(
+                provider.clone(),
+                Default::default(),
+                Default::default(),
+                Default::default(),
+                chunked_files_rx,
+                cx,
+            )
+        });
+        embed_files_task.task.await.unwrap();
+
+        let mut embedded_files_rx = embed_files_task.files;
+        let (embedded_file, _) = embedded_files_rx.next().await.unwrap();
+        assert_eq!(
+            embedded_file
+                .chunks
+                .iter()
+                .map(|embedded_chunk| embedded_chunk.embedding.clone())
+                .collect::<Vec<_>>(),
+            vec![
+                Embedding::new(vec![1.0, 0.0]),
+                Embedding::new(vec![0.0, 1.0]),
+                Embedding::new(vec![1.0, 0.0]),
+                Embedding::new(vec![0.0, 1.0]),
+            ]
+        );
+
+        let embedded_texts = embedded_texts.lock().clone();
+        assert_eq!(embedded_texts.len(), 2, "{embedded_texts:?}");
+        assert!(embedded_texts[0].contains("EFGH"));
+        assert!(embedded_texts[1].contains("mnop"));
+    }
+
+    #[test]
+    fn test_rerank_for_diversity() {
+        let scored_chunk = |key: &str, embedding: Vec<f32>, score: f32| ScoredChunk {
+            owner: ChunkOwner::Document(DocumentId::new(DocumentSource::ProjectNote, key)),
+            range: 0..1,
+            item_range: None,
+            position_hints: PositionHints::default(),
+            embedding: Embedding::new(embedding),
+            score,
+        };
+        let chunks = [
+            scored_chunk("a", vec![1.0, 0.0], 0.9),
+            scored_chunk("a-duplicate", vec![0.99, 0.14], 0.89),
+            scored_chunk("b", vec![0.0, 1.0], 0.6),
+        ];
+        let candidates = || merge_signature_matches(chunks.iter().collect());
+        let keys = |ranked: Vec<Candidate>| {
+            ranked
+                .into_iter()
+                .map(|candidate| match &candidate.chunk.owner {
+                    ChunkOwner::Document(id) => id.key.to_string(),
+                    ChunkOwner::File { .. } => unreachable!(),
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let ranked = rerank_for_diversity(candidates(), 2, 0., SimilarityMetric::Cosine);
+        assert_eq!(keys(ranked), ["a", "a-duplicate"]);
+
+        let ranked = rerank_for_diversity(candidates(), 2, 0.5, SimilarityMetric::Cosine);
+        assert_eq!(keys(ranked), ["a", "b"]);
+
+        let ranked = rerank_for_diversity(candidates(), 5, 0.5, SimilarityMetric::Cosine);
+        assert_eq!(keys(ranked), ["a", "b", "a-duplicate"]);
+    }
+
+    #[test]
+    fn test_merge_signature_matches() {
+        let owner = ChunkOwner::Document(DocumentId::new(DocumentSource::ProjectNote, "a"));
+        let scored_chunk =
+            |range: Range<usize>, item_range: Option<Range<usize>>, score| ScoredChunk {
+                owner: owner.clone(),
+                range,
+                item_range,
+                position_hints: PositionHints::default(),
+                embedding: Embedding::new(vec![1.0, 0.0]),
+                score,
+            };
+        let chunks = [
+            // The signature of an item whose body spans two chunks, the second of which matched.
+            scored_chunk(0..10, Some(0..100), 0.9),
+            scored_chunk(50..100, None, 0.5),
+            // The signature of an item whose body didn't match.
+            scored_chunk(100..110, Some(100..200), 0.4),
+            scored_chunk(200..300, None, 0.7),
+        ];
+
+        let merged = merge_signature_matches(chunks.iter().collect());
+        assert_eq!(
+            merged
+                .iter()
+                .map(|candidate| (candidate.chunk.range.clone(), candidate.score))
+                .collect::<Vec<_>>(),
+            [(50..100, 0.9), (200..300, 0.7), (100..110, 0.4)]
+        );
+    }
+
+    #[test]
+    fn test_search_chunks_capacity() {
+        let chunk_size = mem::size_of::<(ChunkOwner, EmbeddedChunk)>() + 1536 * 4;
+        assert_eq!(
+            search_chunks_capacity(256 * 1024 * 1024, 1536),
+            256 * 1024 * 1024 / chunk_size
+        );
+        assert_eq!(search_chunks_capacity(10 * chunk_size + 1, 1536), 10);
+        assert_eq!(search_chunks_capacity(0, 1536), 1);
+    }
 }
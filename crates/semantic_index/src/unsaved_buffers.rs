@@ -0,0 +1,189 @@
+use crate::{
+    chunking::{chunk_text, Chunk, ChunkedText},
+    db_key_for_path,
+    generated_files::is_generated_text,
+    keyword_prefilter::FileKeywords,
+    redaction, ChunkOwner, DocumentTemplate, DocumentVariables, EmbeddedChunk, EmbeddedFile,
+    Embedding, ProjectIndex, SearchScope, VectorStoreSettings,
+};
+use gpui::AppContext;
+use heed::types::{SerdeBincode, Str};
+use language::Language;
+use project::WorktreeId;
+use sha2::{Digest, Sha256};
+use std::{borrow::Cow, ops::Range, path::Path, sync::Arc, time::SystemTime};
+use util::ResultExt;
+
+/// The most unsaved buffers a search scores in place of their files. Their chunks are
+/// embedded along with the query, so this bounds how much a search embeds.
+const MAX_UNSAVED_BUFFERS: usize = 16;
+
+/// The text of an open buffer with unsaved changes, whose chunks a search scores in place
+/// of the ones stored for its file, so that results reflect what the user sees.
+pub(crate) struct UnsavedBuffer {
+    pub worktree_id: WorktreeId,
+    pub path: Arc<Path>,
+    mtime: Option<SystemTime>,
+    language: Option<Arc<Language>>,
+    pub text: Arc<str>,
+    db_connection: heed::Env,
+    db: heed::Database<Str, SerdeBincode<EmbeddedFile>>,
+}
+
+/// An unsaved buffer split into chunks, along with the documents to embed for them.
+pub(crate) struct ChunkedUnsavedBuffer {
+    pub worktree_id: WorktreeId,
+    pub text: Arc<str>,
+    file: EmbeddedFile,
+    chunks: Vec<Chunk>,
+    pub documents: Vec<String>,
+}
+
+impl ProjectIndex {
+    /// The open buffers with unsaved changes in the worktrees of this index, restricted
+    /// to the files in `scope` if given.
+    pub(crate) fn unsaved_buffers(
+        &self,
+        scope: Option<&SearchScope>,
+        cx: &AppContext,
+    ) -> Vec<UnsavedBuffer> {
+        let Some(project) = self.project.upgrade() else {
+            return Vec::new();
+        };
+        project
+            .read(cx)
+            .opened_buffers()
+            .into_iter()
+            .filter_map(|buffer| {
+                let buffer = buffer.read(cx);
+                if !buffer.is_dirty() {
+                    return None;
+                }
+                let file = buffer.file()?;
+                let worktree_id = WorktreeId::from_usize(file.worktree_id());
+                if scope.map_or(false, |scope| {
+                    !scope.contains_file(worktree_id, file.path())
+                }) {
+                    return None;
+                }
+                let index = self.worktree_index(worktree_id, cx)?;
+                let index = index.read(cx);
+                Some(UnsavedBuffer {
+                    worktree_id,
+                    path: file.path().clone(),
+                    mtime: file.mtime(),
+                    language: buffer.language().cloned(),
+                    text: buffer.text().into(),
+                    db_connection: index.db_connection.clone(),
+                    db: index.db,
+                })
+            })
+            .take(MAX_UNSAVED_BUFFERS)
+            .collect()
+    }
+}
+
+impl UnsavedBuffer {
+    /// Splits the buffer into chunks like its file would be when indexed. Returns `None`
+    /// for buffers whose file isn't indexed, e.g. because it's excluded.
+    pub fn chunk(
+        self,
+        settings: &VectorStoreSettings,
+        document_template: &DocumentTemplate,
+        redact_secrets: bool,
+    ) -> Option<ChunkedUnsavedBuffer> {
+        if !self.is_indexed() {
+            return None;
+        }
+
+        let language_name = self.language.as_ref().map(|language| language.name());
+        let chunking = settings.chunking_for_language(language_name.as_deref());
+        let ChunkedText {
+            mut chunks,
+            signature_chunks,
+            syntax_error,
+        } = chunk_text(&self.text, self.language.as_ref(), &self.path, &chunking);
+        chunks.extend(signature_chunks);
+
+        let secrets = redact_secrets.then(|| redaction::Secrets::find(&self.path, &self.text));
+        let documents = chunks
+            .iter()
+            .map(|chunk| {
+                let contents = match &secrets {
+                    Some(secrets) => secrets.redact(&self.text, chunk.range.clone()),
+                    None => Cow::Borrowed(&self.text[chunk.range.clone()]),
+                };
+                document_template.render(&DocumentVariables {
+                    path: &self.path,
+                    language: language_name.as_deref(),
+                    scope: chunk.scope_path.as_deref(),
+                    contents: &contents,
+                })
+            })
+            .collect();
+        Some(ChunkedUnsavedBuffer {
+            worktree_id: self.worktree_id,
+            text: self.text.clone(),
+            file: EmbeddedFile {
+                keywords: Vec::new(),
+                path: self.path,
+                mtime: self.mtime,
+                digest: Sha256::digest(self.text.as_bytes()).into(),
+                generated: is_generated_text(&self.text),
+                chunks: Vec::new(),
+                syntax_error,
+                duplicate_of: None,
+            },
+            chunks,
+            documents,
+        })
+    }
+
+    /// The ranges of the chunks the buffer would be split into, without the signature
+    /// chunks that overlap them. Returns `None` for buffers whose file isn't indexed.
+    pub fn chunk_ranges(&self, settings: &VectorStoreSettings) -> Option<Vec<Range<usize>>> {
+        if !self.is_indexed() {
+            return None;
+        }
+        let language_name = self.language.as_ref().map(|language| language.name());
+        let chunking = settings.chunking_for_language(language_name.as_deref());
+        let chunks = chunk_text(&self.text, self.language.as_ref(), &self.path, &chunking).chunks;
+        Some(chunks.into_iter().map(|chunk| chunk.range).collect())
+    }
+
+    /// What the chunks of this buffer belong to when they're scanned during a search.
+    pub fn owner(&self) -> ChunkOwner {
+        ChunkOwner::File {
+            worktree_id: self.worktree_id,
+            path: self.path.clone(),
+            mtime: self.mtime,
+            generated: is_generated_text(&self.text),
+            unsaved_text: Some(self.text.clone()),
+        }
+    }
+
+    fn is_indexed(&self) -> bool {
+        let Some(txn) = self.db_connection.read_txn().log_err() else {
+            return false;
+        };
+        let keywords_db = self.db.remap_data_type::<SerdeBincode<FileKeywords>>();
+        keywords_db
+            .get(&txn, &db_key_for_path(&self.path))
+            .log_err()
+            .flatten()
+            .is_some()
+    }
+}
+
+impl ChunkedUnsavedBuffer {
+    /// Pairs the buffer's chunks with the embeddings of its documents.
+    pub fn embedded(mut self, embeddings: impl IntoIterator<Item = Embedding>) -> EmbeddedFile {
+        self.file.chunks = self
+            .chunks
+            .into_iter()
+            .zip(embeddings)
+            .map(|(chunk, embedding)| EmbeddedChunk { chunk, embedding })
+            .collect();
+        self.file
+    }
+}
@@ -0,0 +1,285 @@
+use crate::DocumentTemplate;
+use anyhow::Result;
+use collections::HashMap;
+use gpui::AppContext;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::{Settings, SettingsSources};
+use std::{sync::Arc, time::Duration};
+
+#[derive(Clone, Deserialize, Debug)]
+pub struct VectorStoreSettings {
+    pub embedding_model: String,
+    pub auto_index: bool,
+    pub auto_index_delay_ms: u64,
+    pub reindex_debounce_ms: u64,
+    pub reindex_max_wait_ms: u64,
+    pub redact_secrets: bool,
+    pub summarize_files: bool,
+    pub search_memory_limit_mb: u64,
+    pub indexing_memory_limit_mb: u64,
+    pub min_embedding_batch_size: usize,
+    pub max_embedding_batch_size: Option<usize>,
+    pub min_relevance: f32,
+    pub recency_boost: f32,
+    pub keyword_prefilter_min_files: u64,
+    pub chunking: ChunkingSettings,
+    pub languages: HashMap<Arc<str>, ChunkingSettingsContent>,
+    pub document_templates: HashMap<String, String>,
+    pub generated_files: GeneratedFiles,
+    pub generated_file_patterns: Vec<String>,
+    pub reconcile_interval_minutes: u64,
+    pub compaction_threshold_mb: u64,
+    pub compaction_threshold_ratio: f32,
+}
+
+/// What searches do with files that were generated by a tool, like protobuf output or
+/// minified JavaScript, so that they don't crowd out the code they were generated from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GeneratedFiles {
+    /// Rank generated files like any other file.
+    Include,
+    /// Only return results from generated files when there aren't enough other results.
+    DownRank,
+    /// Never return results from generated files, which aren't embedded either.
+    Exclude,
+}
+
+/// How files are split into chunks before they're embedded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChunkGranularity {
+    /// Split files along the items in their outline, such as types and functions.
+    Items,
+    /// Split files only where they exceed the maximum chunk size, ignoring their syntax.
+    File,
+    /// Embed the chunks of both `items` and `file`.
+    Both,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct ChunkingSettings {
+    pub min_chunk_size: usize,
+    pub max_chunk_size: usize,
+    pub granularity: ChunkGranularity,
+}
+
+impl Default for ChunkingSettings {
+    fn default() -> Self {
+        Self {
+            min_chunk_size: 1024,
+            max_chunk_size: 8192,
+            granularity: ChunkGranularity::Items,
+        }
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct ChunkingSettingsContent {
+    /// The size in bytes below which a chunk is extended past item boundaries.
+    ///
+    /// Default: 1024
+    pub min_chunk_size: Option<usize>,
+    /// The size in bytes above which a chunk is split.
+    ///
+    /// Default: 8192
+    pub max_chunk_size: Option<usize>,
+    /// Whether to split files along their outline items, only by size, or both.
+    ///
+    /// Default: items
+    pub granularity: Option<ChunkGranularity>,
+}
+
+/// Vector store configuration.
+#[derive(Clone, Default, Serialize, Deserialize, JsonSchema, Debug)]
+pub struct VectorStoreSettingsContent {
+    /// The model hosted by Zed that files are embedded with. When it changes, projects
+    /// are indexed again with the new model in the background, and searches keep using
+    /// the previous model's embeddings until then.
+    ///
+    /// Default: openai/text-embedding-3-small
+    pub embedding_model: Option<String>,
+    /// Whether to index projects when they're opened, rather than waiting for the
+    /// project to be indexed explicitly.
+    ///
+    /// Default: false
+    pub auto_index: Option<bool>,
+    /// How long to wait after a project is opened before indexing it automatically, in
+    /// milliseconds, so that indexing doesn't compete with language servers starting up.
+    ///
+    /// Default: 10000
+    pub auto_index_delay_ms: Option<u64>,
+    /// How long to wait after a file changes before reindexing it, in milliseconds.
+    /// Changes made within this window are indexed together.
+    ///
+    /// Default: 500
+    pub reindex_debounce_ms: Option<u64>,
+    /// The longest a changed file waits to be reindexed while it keeps changing within
+    /// `reindex_debounce_ms`, in milliseconds, e.g. while a tool rewrites it continuously.
+    ///
+    /// Default: 5000
+    pub reindex_max_wait_ms: Option<u64>,
+    /// Whether to mask likely credentials, such as API keys, private keys and the
+    /// values in `.env` files, before sending files to a remote embedding provider.
+    ///
+    /// Default: true
+    pub redact_secrets: Option<bool>,
+    /// Whether to describe each indexed file in a few sentences with a language model,
+    /// which grounds the assistant's answers in what the files are for. Files are sent
+    /// to the model once they're indexed, and again when they change.
+    ///
+    /// Default: false
+    pub summarize_files: Option<bool>,
+    /// How much memory a search may use for the embeddings it has decoded from the index
+    /// but not yet scored, in megabytes. The index is read through a memory map and its
+    /// files are decoded as this allows, so the index can be larger than this.
+    ///
+    /// Default: 256
+    pub search_memory_limit_mb: Option<u64>,
+    /// How much memory indexing may use for files that were read and chunked but are
+    /// waiting to be embedded, in megabytes. Files beyond it are set aside in the
+    /// database until the embedding provider catches up.
+    ///
+    /// Default: 256
+    pub indexing_memory_limit_mb: Option<u64>,
+    /// The fewest texts to send the embedding provider in a request while indexing.
+    /// Requests shrink toward it while the provider is slow or reports being overloaded.
+    ///
+    /// Default: 8
+    pub min_embedding_batch_size: Option<usize>,
+    /// The most texts to send the embedding provider in a request while indexing.
+    /// Requests grow toward it while the provider keeps up. null uses the most the
+    /// provider accepts, which also caps this.
+    ///
+    /// Default: null
+    pub max_embedding_batch_size: Option<usize>,
+    /// The relevance, from 0 to 100, below which search results are omitted. Relevance
+    /// is normalized against the scores of past searches with the same embedding model,
+    /// so it behaves the same across models, and results are kept until enough
+    /// searches were made to normalize it.
+    ///
+    /// Default: 0
+    pub min_relevance: Option<f32>,
+    /// How much to add to the scores of results from files that are open or were
+    /// recently modified, so that among similarly relevant results the code being worked
+    /// on comes first. Files modified a day ago get half of it. 0 disables the boost.
+    ///
+    /// Default: 0.02
+    pub recency_boost: Option<f32>,
+    /// The number of files a worktree's index must have before searches for code
+    /// identifiers, like `parse_config` or `WorktreeIndex`, only score the files that
+    /// contain them. Searches without identifiers always score every file. 0 disables
+    /// this.
+    ///
+    /// Default: 5000
+    pub keyword_prefilter_min_files: Option<u64>,
+    /// How files are split into chunks before they're embedded. Changes apply to files
+    /// as they're reindexed.
+    pub chunking: Option<ChunkingSettingsContent>,
+    /// Chunking settings for individual languages, which override `chunking`.
+    ///
+    /// Default: {}
+    pub languages: Option<HashMap<Arc<str>, ChunkingSettingsContent>>,
+    /// The text embedded for each chunk, by the name of the embedding model, for the
+    /// models whose framing of chunks should differ from their provider's. Templates can
+    /// reference `{path}`, `{language}`, `{scope}` and `{contents}`, and lines referencing
+    /// a variable without a value are left out. Changes reindex the projects that were
+    /// embedded with the model.
+    ///
+    /// Default: {}
+    pub document_templates: Option<HashMap<String, String>>,
+    /// What searches do with generated files, which are recognized by
+    /// `generated_file_patterns` or by a comment like `@generated` at their top.
+    ///
+    /// Default: down_rank
+    pub generated_files: Option<GeneratedFiles>,
+    /// Globs matching the paths of generated files.
+    pub generated_file_patterns: Option<Vec<String>>,
+    /// How often to verify the contents of every indexed file in the background, in
+    /// minutes, which repairs the index when changes to files were missed. 0 disables
+    /// these checks.
+    ///
+    /// Default: 0
+    pub reconcile_interval_minutes: Option<u64>,
+    /// How much space deleted embeddings must leave unused in the database, in
+    /// megabytes, before a compacted copy of it is written in the background, which
+    /// replaces it the next time it's opened. 0 disables compaction.
+    ///
+    /// Default: 256
+    pub compaction_threshold_mb: Option<u64>,
+    /// The share of the database, between 0 and 1, that must be unused too before it's
+    /// compacted.
+    ///
+    /// Default: 0.5
+    pub compaction_threshold_ratio: Option<f32>,
+}
+
+impl VectorStoreSettings {
+    pub fn auto_index_delay(&self) -> Duration {
+        Duration::from_millis(self.auto_index_delay_ms)
+    }
+
+    pub fn reindex_debounce(&self) -> Duration {
+        Duration::from_millis(self.reindex_debounce_ms)
+    }
+
+    pub fn reindex_max_wait(&self) -> Duration {
+        Duration::from_millis(self.reindex_max_wait_ms)
+    }
+
+    pub fn reconcile_interval(&self) -> Option<Duration> {
+        (self.reconcile_interval_minutes > 0)
+            .then(|| Duration::from_secs(self.reconcile_interval_minutes * 60))
+    }
+
+    /// How many bytes of the database must be unused before it's compacted, unless
+    /// compaction is disabled.
+    pub fn compaction_threshold(&self) -> Option<u64> {
+        (self.compaction_threshold_mb > 0)
+            .then(|| self.compaction_threshold_mb.saturating_mul(1024 * 1024))
+    }
+
+    pub fn search_memory_limit(&self) -> usize {
+        (self.search_memory_limit_mb as usize).saturating_mul(1024 * 1024)
+    }
+
+    pub fn indexing_memory_limit(&self) -> usize {
+        (self.indexing_memory_limit_mb as usize).saturating_mul(1024 * 1024)
+    }
+
+    /// The template the settings give for framing chunks embedded with `model`, if any.
+    pub fn document_template(&self, model: &str) -> Option<DocumentTemplate> {
+        self.document_templates
+            .get(model)
+            .map(DocumentTemplate::new)
+    }
+
+    /// The chunking settings for files in the given language.
+    pub fn chunking_for_language(&self, language: Option<&str>) -> ChunkingSettings {
+        let mut settings = self.chunking;
+        if let Some(overrides) = language.and_then(|language| self.languages.get(language)) {
+            if let Some(min_chunk_size) = overrides.min_chunk_size {
+                settings.min_chunk_size = min_chunk_size;
+            }
+            if let Some(max_chunk_size) = overrides.max_chunk_size {
+                settings.max_chunk_size = max_chunk_size;
+            }
+            if let Some(granularity) = overrides.granularity {
+                settings.granularity = granularity;
+            }
+        }
+        settings.max_chunk_size = settings.max_chunk_size.max(1);
+        settings.min_chunk_size = settings.min_chunk_size.min(settings.max_chunk_size);
+        settings
+    }
+}
+
+impl Settings for VectorStoreSettings {
+    const KEY: Option<&'static str> = Some("vector_store");
+    type FileContent = VectorStoreSettingsContent;
+
+    fn load(sources: SettingsSources<Self::FileContent>, _: &mut AppContext) -> Result<Self> {
+        sources.json_merge()
+    }
+}
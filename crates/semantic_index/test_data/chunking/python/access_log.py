@@ -0,0 +1,39 @@
+"""Helpers for reading and summarizing the access log of a web server."""
+
+import re
+from dataclasses import dataclass
+
+LINE_PATTERN = re.compile(r"(\S+) \S+ \S+ \[.*\] (\S+) (\S+) \S+ (\d+)$")
+
+
+@dataclass
+class Request:
+    """A request parsed from one line of an access log."""
+
+    address: str
+    method: str
+    path: str
+    status: int
+
+    def is_error(self):
+        """Whether the server failed to answer the request."""
+        return self.status >= 500
+
+
+def parse_line(line):
+    """Parses a line of the log, returning None for lines in another format."""
+    found = LINE_PATTERN.match(line)
+    if found is None:
+        return None
+    address, method, path, status = found.groups()
+    return Request(address, method, path, int(status))
+
+
+async def count_errors(lines):
+    """Counts the requests that failed for each path."""
+    counts = {}
+    async for line in lines:
+        request = parse_line(line)
+        if request is not None and request.is_error():
+            counts[request.path] = counts.get(request.path, 0) + 1
+    return counts
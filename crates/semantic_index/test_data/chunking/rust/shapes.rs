@@ -0,0 +1,49 @@
+use std::fmt;
+
+/// A point on a plane, in pixels from the top left corner of the canvas.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// The shapes that can be drawn on a canvas.
+pub enum Shape {
+    Circle { center: Point, radius: f32 },
+    Rectangle { origin: Point, width: f32, height: f32 },
+}
+
+impl Shape {
+    /// The area the shape covers, in square pixels.
+    pub fn area(&self) -> f32 {
+        match self {
+            Shape::Circle { radius, .. } => std::f32::consts::PI * radius * radius,
+            Shape::Rectangle { width, height, .. } => width * height,
+        }
+    }
+
+    /// Whether the point lies inside the shape or on its outline.
+    pub fn contains(&self, point: Point) -> bool {
+        match self {
+            Shape::Circle { center, radius } => {
+                let (dx, dy) = (point.x - center.x, point.y - center.y);
+                dx * dx + dy * dy <= radius * radius
+            }
+            Shape::Rectangle { origin, width, height } => {
+                point.x >= origin.x
+                    && point.x <= origin.x + width
+                    && point.y >= origin.y
+                    && point.y <= origin.y + height
+            }
+        }
+    }
+}
+
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Shape::Circle { radius, .. } => write!(f, "circle of radius {radius}"),
+            Shape::Rectangle { width, height, .. } => write!(f, "{width}x{height} rectangle"),
+        }
+    }
+}
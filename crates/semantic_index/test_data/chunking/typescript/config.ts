@@ -0,0 +1,42 @@
+import { readFile } from "fs/promises";
+
+export interface Options {
+  verbose: boolean;
+  retries: number;
+}
+
+/**
+ * Loads the configuration at `path`, retrying when the file is locked by an
+ * editor that is writing it at the same time.
+ */
+export async function loadConfig(path: string, options: Options): Promise<Config> {
+  for (let attempt = 0; attempt < options.retries; attempt++) {
+    try {
+      return Config.parse(await readFile(path, "utf8"));
+    } catch (error) {
+      if (options.verbose) {
+        console.warn(`retrying ${path} after`, error);
+      }
+    }
+  }
+  throw new Error(`failed to load ${path}`);
+}
+
+export class Config {
+  private values = new Map<string, string>();
+
+  static parse(text: string): Config {
+    const config = new Config();
+    for (const line of text.split("\n")) {
+      const [key, value] = line.split("=", 2);
+      if (key && value !== undefined) {
+        config.values.set(key.trim(), value.trim());
+      }
+    }
+    return config;
+  }
+
+  lookup(key: string): string | undefined {
+    return this.values.get(key);
+  }
+}
@@ -0,0 +1,106 @@
+use anyhow::{Context as _, Result};
+use clap::{Parser, Subcommand};
+use semantic_index::{
+    headless::{self, HeadlessIndex},
+    EmbeddingProvider as _, OpenAiEmbeddingModel, OpenAiEmbeddingProvider,
+};
+use std::{path::PathBuf, sync::Arc};
+
+#[derive(Parser, Debug)]
+#[command(name = "zed-index")]
+struct Args {
+    /// The path to the index database. Defaults to the one used by Zed.
+    #[arg(long)]
+    db_path: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Index the files in a directory that changed since it was last indexed
+    Index { dir: PathBuf },
+    /// Search the index of a directory
+    Search {
+        dir: PathBuf,
+        query: String,
+        /// The number of results to print
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+    },
+    /// Write the index of a directory to a single file
+    Export { dir: PathBuf, archive: PathBuf },
+    /// Replace the index of a directory with an exported one
+    Import { dir: PathBuf, archive: PathBuf },
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+
+    let args = Args::parse();
+    let db_path = args
+        .db_path
+        .unwrap_or_else(|| paths::EMBEDDINGS_DIR.join("semantic-index-db.0.mdb"));
+    // Files are chunked and redacted the way the app would, by the user's settings.
+    let user_settings = std::fs::read_to_string(paths::SETTINGS.as_path()).ok();
+    let settings = headless::load_settings(user_settings.as_deref())?;
+    let api_key = std::env::var("OPENAI_API_KEY").context("OPENAI_API_KEY not set")?;
+    let mut embedding_provider = OpenAiEmbeddingProvider::new(
+        http::client(None),
+        OpenAiEmbeddingModel::TextEmbedding3Small,
+        open_ai::OPEN_AI_API_URL.to_string(),
+        api_key,
+    );
+    if let Some(document_template) = embedding_provider
+        .model_metadata()
+        .and_then(|model| settings.document_template(&model.name))
+    {
+        embedding_provider = embedding_provider.with_document_template(document_template);
+    }
+    let embedding_provider = Arc::new(embedding_provider);
+    let index = HeadlessIndex::open(&db_path, embedding_provider, settings)?;
+
+    smol::block_on(async {
+        match args.command {
+            Command::Index { dir } => {
+                let dir = dir.canonicalize().context("failed to canonicalize dir")?;
+                let summary = index.index_directory(&dir).await?;
+                println!(
+                    "indexed {} files ({} unchanged, {} deleted)",
+                    summary.indexed_count, summary.unchanged_count, summary.deleted_count
+                );
+                for (path, error) in &summary.errors {
+                    eprintln!(
+                        "{}: {:?} error: {}",
+                        path.display(),
+                        error.kind,
+                        error.message
+                    );
+                }
+            }
+            Command::Search { dir, query, limit } => {
+                let dir = dir.canonicalize().context("failed to canonicalize dir")?;
+                for result in index.search(&dir, &query, limit).await? {
+                    println!(
+                        "{:.3} {}:{}..{}",
+                        result.score,
+                        result.path.display(),
+                        result.range.start,
+                        result.range.end
+                    );
+                }
+            }
+            Command::Export { dir, archive } => {
+                let dir = dir.canonicalize().context("failed to canonicalize dir")?;
+                let file_count = index.export(&dir, &archive)?;
+                println!("exported {file_count} files to {}", archive.display());
+            }
+            Command::Import { dir, archive } => {
+                let dir = dir.canonicalize().context("failed to canonicalize dir")?;
+                let file_count = index.import(&dir, &archive)?;
+                println!("imported {file_count} files from {}", archive.display());
+            }
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,331 @@
+//! Terminals on a byte stream instead of the PTY of a shell, like a serial device or the
+//! standard input and output of a command such as `docker attach`.
+
+use crate::{shell_marks::MarkedParser, TerminalBackend, TerminalSize, ZedListener};
+use alacritty_terminal::{
+    event::{Event as AlacTermEvent, EventListener},
+    sync::FairMutex,
+    Term,
+};
+use anyhow::{Context as _, Result};
+use std::{
+    cell::RefCell,
+    io::{self, Read, Write},
+    path::Path,
+    process::{Child, Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+const READ_BUFFER_SIZE: usize = 0x10_000;
+/// How long the reading thread waits for output before checking whether the terminal
+/// was closed.
+const CLOSE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The two halves of a duplex byte stream for a terminal to show and type into.
+pub struct ByteStream {
+    pub reader: Box<dyn Read + Send>,
+    pub writer: Box<dyn Write + Send>,
+    /// The process at the other end of the stream, killed when the terminal is closed.
+    pub child: Option<Child>,
+    /// The file descriptor `reader` reads from, which is polled so that the reading
+    /// thread can stop, and close it, when the terminal is closed. A reader without one
+    /// is only dropped once a read returns.
+    #[cfg(unix)]
+    reader_fd: Option<std::os::fd::RawFd>,
+}
+
+impl ByteStream {
+    pub fn new(reader: Box<dyn Read + Send>, writer: Box<dyn Write + Send>) -> Self {
+        Self {
+            reader,
+            writer,
+            child: None,
+            #[cfg(unix)]
+            reader_fd: None,
+        }
+    }
+
+    /// Runs `command` with its standard input and output connected to the stream. What
+    /// it prints to its standard error isn't shown.
+    pub fn spawn(command: &[String]) -> Result<Self> {
+        let (program, args) = command.split_first().context("empty command")?;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("failed to run {program}"))?;
+        let writer = child.stdin.take().context("no stdin for command")?;
+        let reader = child.stdout.take().context("no stdout for command")?;
+        Ok(Self {
+            #[cfg(unix)]
+            reader_fd: Some(std::os::fd::AsRawFd::as_raw_fd(&reader)),
+            reader: Box::new(reader),
+            writer: Box::new(writer),
+            child: Some(child),
+        })
+    }
+
+    /// Opens a serial device, like `/dev/ttyUSB0`, switching it to raw mode at
+    /// `baud_rate`, so that the bytes reach the terminal as the device sent them.
+    #[cfg(unix)]
+    pub fn open_serial_device(path: &Path, baud_rate: u32) -> Result<Self> {
+        use std::os::{fd::AsRawFd, unix::fs::OpenOptionsExt};
+
+        let speed = baud_rate_speed(baud_rate)
+            .with_context(|| format!("unsupported baud rate {baud_rate}"))?;
+        let device = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open(path)
+            .with_context(|| format!("failed to open {path:?}"))?;
+
+        let fd = device.as_raw_fd();
+        // SAFETY: `termios` is only read after `tcgetattr` filled it in, and `fd` is open
+        // for as long as `device` lives.
+        unsafe {
+            let mut termios = std::mem::zeroed::<libc::termios>();
+            if libc::tcgetattr(fd, &mut termios) != 0 {
+                return Err(io::Error::last_os_error())
+                    .with_context(|| format!("{path:?} is not a serial device"));
+            }
+            libc::cfmakeraw(&mut termios);
+            termios.c_cflag |= libc::CLOCAL | libc::CREAD;
+            libc::cfsetispeed(&mut termios, speed);
+            libc::cfsetospeed(&mut termios, speed);
+            if libc::tcsetattr(fd, libc::TCSANOW, &termios) != 0 {
+                return Err(io::Error::last_os_error())
+                    .with_context(|| format!("failed to configure {path:?}"));
+            }
+        }
+
+        let writer = device.try_clone()?;
+        Ok(Self {
+            reader_fd: Some(fd),
+            ..Self::new(Box::new(device), Box::new(writer))
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn open_serial_device(path: &Path, _baud_rate: u32) -> Result<Self> {
+        anyhow::bail!("can't open {path:?}: serial devices are only supported on Unix")
+    }
+
+    /// Starts the threads that move bytes between the stream and `term`: the output is
+    /// parsed into `term` with `parser` on a thread of its own, like Alacritty's event
+    /// loop does with the output of a PTY, and the input is written from another, as
+    /// writing to a slow device may block.
+    pub(crate) fn connect(
+        self,
+        term: Arc<FairMutex<Term<ZedListener>>>,
+        listener: ZedListener,
+        mut parser: MarkedParser,
+    ) -> StreamBackend {
+        let Self {
+            mut reader,
+            mut writer,
+            child,
+            #[cfg(unix)]
+            reader_fd,
+        } = self;
+        let closed = Arc::new(AtomicBool::new(false));
+
+        let reader_closed = closed.clone();
+        thread::spawn(move || {
+            let mut buffer = vec![0; READ_BUFFER_SIZE];
+            loop {
+                #[cfg(unix)]
+                if let Some(fd) = reader_fd {
+                    match wait_until_readable(fd, &reader_closed) {
+                        Ok(true) => {}
+                        Ok(false) => return,
+                        Err(_) => break,
+                    }
+                }
+                let read = match reader.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(read) => read,
+                    Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(_) => break,
+                };
+                // A reader that can't be polled may only return after the terminal is
+                // closed.
+                if reader_closed.load(Ordering::Relaxed) {
+                    return;
+                }
+                parser.advance(&mut term.lock(), &buffer[..read]);
+                listener.send_event(AlacTermEvent::Wakeup);
+            }
+            listener.send_event(AlacTermEvent::Exit);
+        });
+
+        let (input_tx, input_rx) = mpsc::channel::<Vec<u8>>();
+        thread::spawn(move || {
+            for bytes in input_rx {
+                if writer
+                    .write_all(&bytes)
+                    .and_then(|_| writer.flush())
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        StreamBackend {
+            input_tx,
+            closed,
+            child: RefCell::new(child),
+        }
+    }
+}
+
+/// Waits for `fd` to have something to read, or to be hung up, returning false if the
+/// terminal was closed first.
+#[cfg(unix)]
+fn wait_until_readable(fd: std::os::fd::RawFd, closed: &AtomicBool) -> io::Result<bool> {
+    let mut poll_fd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    loop {
+        if closed.load(Ordering::Relaxed) {
+            return Ok(false);
+        }
+        // SAFETY: `poll_fd` is a single valid `pollfd`, and `fd` stays open while the
+        // reader that owns it lives.
+        let ready = unsafe { libc::poll(&mut poll_fd, 1, CLOSE_POLL_INTERVAL.as_millis() as i32) };
+        match ready {
+            0 => continue,
+            ready if ready > 0 => return Ok(true),
+            _ => {
+                let error = io::Error::last_os_error();
+                if error.kind() != io::ErrorKind::Interrupted {
+                    return Err(error);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn baud_rate_speed(baud_rate: u32) -> Option<libc::speed_t> {
+    Some(match baud_rate {
+        1200 => libc::B1200,
+        2400 => libc::B2400,
+        4800 => libc::B4800,
+        9600 => libc::B9600,
+        19200 => libc::B19200,
+        38400 => libc::B38400,
+        57600 => libc::B57600,
+        115200 => libc::B115200,
+        230400 => libc::B230400,
+        _ => return None,
+    })
+}
+
+/// Sends the input of a terminal to the writing half of a [`ByteStream`].
+pub struct StreamBackend {
+    input_tx: mpsc::Sender<Vec<u8>>,
+    closed: Arc<AtomicBool>,
+    child: RefCell<Option<Child>>,
+}
+
+impl TerminalBackend for StreamBackend {
+    fn write(&self, bytes: Vec<u8>) {
+        self.input_tx.send(bytes).ok();
+    }
+
+    // A byte stream has no window size to tell the other end about; programs on a serial
+    // console are usually told with `stty`.
+    fn resize(&self, _: TerminalSize) {}
+
+    fn shutdown(&self) {
+        self.closed.store(true, Ordering::Relaxed);
+        if let Some(mut child) = self.child.borrow_mut().take() {
+            // The process is reaped off the main thread, as it may take a while to exit
+            // after being killed.
+            if child.kill().is_ok() {
+                thread::spawn(move || child.wait().ok());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{output_text, shell_marks::SCROLLBACK_SLACK};
+    use alacritty_terminal::term::Config;
+    use futures::{channel::mpsc::unbounded, StreamExt};
+    use std::io::Cursor;
+
+    const MAX_HISTORY_LINES: usize = 100;
+
+    fn new_term(listener: ZedListener) -> Arc<FairMutex<Term<ZedListener>>> {
+        let config = Config {
+            scrolling_history: MAX_HISTORY_LINES + SCROLLBACK_SLACK,
+            ..Config::default()
+        };
+        Arc::new(FairMutex::new(Term::new(
+            config,
+            &TerminalSize::default(),
+            listener,
+        )))
+    }
+
+    fn new_parser() -> MarkedParser {
+        MarkedParser::new(Default::default(), MAX_HISTORY_LINES)
+    }
+
+    #[test]
+    fn test_stream_output_is_parsed_until_exit() {
+        let (events_tx, mut events_rx) = unbounded();
+        let term = new_term(ZedListener(events_tx.clone()));
+        let stream = ByteStream::new(
+            Box::new(Cursor::new(b"\x1b[1mboot\x1b[0m\r\nlogin: ".to_vec())),
+            Box::new(io::sink()),
+        );
+        let _backend = stream.connect(term.clone(), ZedListener(events_tx), new_parser());
+
+        smol::block_on(async {
+            while let Some(event) = events_rx.next().await {
+                if matches!(event, AlacTermEvent::Exit) {
+                    break;
+                }
+            }
+        });
+        assert_eq!(output_text(&term.lock(), None), "boot\nlogin:");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shutdown_closes_a_silent_stream() {
+        use std::{os::fd::AsRawFd, os::unix::net::UnixStream, time::Duration};
+
+        let (events_tx, _events_rx) = unbounded();
+        let term = new_term(ZedListener(events_tx.clone()));
+        let (ours, mut theirs) = UnixStream::pair().unwrap();
+        let stream = ByteStream {
+            reader_fd: Some(ours.as_raw_fd()),
+            ..ByteStream::new(Box::new(ours), Box::new(io::sink()))
+        };
+        let backend = stream.connect(term, ZedListener(events_tx), new_parser());
+        backend.shutdown();
+
+        // Nothing was ever sent, so the read only returns once the reading thread has
+        // dropped its end.
+        theirs
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(theirs.read(&mut [0; 1]).unwrap(), 0);
+    }
+}
@@ -75,7 +75,8 @@ pub struct ProcessInfo {
 pub struct PtyProcessInfo {
     system: System,
     refresh_kind: ProcessRefreshKind,
-    pid_getter: ProcessIdGetter,
+    /// `None` for terminals that don't run a process of their own, like tmux panes.
+    pid_getter: Option<ProcessIdGetter>,
     pub current: Option<ProcessInfo>,
 }
 
@@ -91,13 +92,29 @@ impl PtyProcessInfo {
         PtyProcessInfo {
             system,
             refresh_kind: process_refresh_kind,
-            pid_getter: ProcessIdGetter::new(pty),
+            pid_getter: Some(ProcessIdGetter::new(pty)),
             current: None,
         }
     }
 
+    /// Info for a terminal without a process to look at, which never has any.
+    pub fn detached() -> PtyProcessInfo {
+        let process_refresh_kind = ProcessRefreshKind::new();
+        PtyProcessInfo {
+            system: System::new(),
+            refresh_kind: process_refresh_kind,
+            pid_getter: None,
+            current: None,
+        }
+    }
+
+    /// Whether there's a process to look at, which there isn't for detached terminals.
+    pub fn has_process(&self) -> bool {
+        self.pid_getter.is_some()
+    }
+
     fn refresh(&mut self) -> Option<&Process> {
-        let pid = self.pid_getter.pid()?;
+        let pid = self.pid_getter.as_ref()?.pid()?;
         if self
             .system
             .refresh_process_specifics(pid, self.refresh_kind)
@@ -0,0 +1,241 @@
+//! Quick select labels the URLs, paths, hashes and UUIDs visible in the terminal with short
+//! tags, so that one can be copied or opened by typing its tag instead of selecting it
+//! with the mouse.
+
+use crate::visible_regex_match_iter;
+use alacritty_terminal::{
+    index::{Boundary, Point as AlacPoint},
+    term::search::RegexSearch,
+    Term,
+};
+use std::ops::RangeInclusive;
+
+/// The letters tags are made of, starting with the home row so that the most common tags
+/// are the easiest to type.
+const TAG_ALPHABET: &str = "asdfghjklqwertyuiopzxcvbnm";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum QuickSelectKind {
+    Url,
+    Path,
+    Uuid,
+    Hash,
+}
+
+/// A token visible in the terminal, tagged with the letters that pick it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QuickSelectHint {
+    pub tag: String,
+    pub kind: QuickSelectKind,
+    pub text: String,
+    pub range: RangeInclusive<AlacPoint>,
+}
+
+/// The hints shown while quick selecting, along with the letters typed so far.
+#[derive(Clone, Debug, Default)]
+pub struct QuickSelectState {
+    pub hints: Vec<QuickSelectHint>,
+    pub typed: String,
+}
+
+impl QuickSelectState {
+    /// Adds a typed letter, returning the hint it completes the tag of, if any. Typing a
+    /// letter that no tag continues with starts over.
+    pub fn push(&mut self, letter: char) -> Option<QuickSelectHint> {
+        self.typed.push(letter.to_ascii_lowercase());
+        if let Some(hint) = self.hints.iter().find(|hint| hint.tag == self.typed) {
+            return Some(hint.clone());
+        }
+        if !self
+            .hints
+            .iter()
+            .any(|hint| hint.tag.starts_with(&self.typed))
+        {
+            self.typed.clear();
+        }
+        None
+    }
+
+    pub fn pop(&mut self) {
+        self.typed.pop();
+    }
+}
+
+/// The regexes matching each kind of hint, in order of precedence: a match overlapping an
+/// earlier one is dropped, e.g. the path part of a URL.
+pub(crate) struct QuickSelectRegexes(Vec<(QuickSelectKind, RegexSearch)>);
+
+impl QuickSelectRegexes {
+    pub fn new(url_regex: &str) -> Self {
+        Self(vec![
+            (QuickSelectKind::Url, RegexSearch::new(url_regex).unwrap()),
+            (
+                QuickSelectKind::Uuid,
+                RegexSearch::new(
+                    r"[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}",
+                )
+                .unwrap(),
+            ),
+            (
+                QuickSelectKind::Path,
+                RegexSearch::new(r"(~|\.{1,2}|[\w.-]+)?(/[\w.-]+)*/[\w.-]*\w(:\d+){0,2}").unwrap(),
+            ),
+            (
+                QuickSelectKind::Hash,
+                RegexSearch::new(r"[0-9a-f]{7,40}").unwrap(),
+            ),
+        ])
+    }
+}
+
+/// Finds the tokens visible in the terminal and tags them, starting from the bottom so
+/// that the latest output gets the first tags.
+pub(crate) fn visible_hints<T>(
+    term: &Term<T>,
+    regexes: &mut QuickSelectRegexes,
+) -> Vec<QuickSelectHint> {
+    let mut matches: Vec<(QuickSelectKind, RangeInclusive<AlacPoint>)> = Vec::new();
+    for (kind, regex) in &mut regexes.0 {
+        for regex_match in visible_regex_match_iter(term, regex) {
+            if matches.iter().any(|(_, existing)| {
+                existing.start() <= regex_match.end() && regex_match.start() <= existing.end()
+            }) {
+                continue;
+            }
+            if *kind == QuickSelectKind::Hash && !is_hash(term, &regex_match) {
+                continue;
+            }
+            matches.push((*kind, regex_match));
+        }
+    }
+    matches.sort_by(|(_, a), (_, b)| b.start().cmp(a.start()));
+
+    let tags = tags(matches.len());
+    matches
+        .into_iter()
+        .zip(tags)
+        .map(|((kind, range), tag)| QuickSelectHint {
+            tag,
+            kind,
+            text: term.bounds_to_string(*range.start(), *range.end()),
+            range,
+        })
+        .collect()
+}
+
+/// Whether a run of hex digits is a whole word with a digit in it, rather than part of a
+/// longer word or an English word like "deadbeef" or "decade".
+fn is_hash<T>(term: &Term<T>, range: &RangeInclusive<AlacPoint>) -> bool {
+    let is_word_char = |point: AlacPoint| {
+        let c = term.grid()[point].c;
+        c.is_alphanumeric() || c == '_'
+    };
+    let before = range.start().sub(term, Boundary::Grid, 1);
+    let after = range.end().add(term, Boundary::Grid, 1);
+    if (before != *range.start() && is_word_char(before))
+        || (after != *range.end() && is_word_char(after))
+    {
+        return false;
+    }
+    term.bounds_to_string(*range.start(), *range.end())
+        .chars()
+        .any(|c| c.is_ascii_digit())
+}
+
+/// Tags for `count` hints. All tags have the same length, so that none is the prefix of
+/// another and typing a tag never has to wait for more letters.
+fn tags(count: usize) -> Vec<String> {
+    let alphabet = TAG_ALPHABET.chars().collect::<Vec<_>>();
+    let mut len = 1;
+    while alphabet.len().pow(len) < count {
+        len += 1;
+    }
+    (0..count)
+        .map(|mut ix| {
+            let mut tag = String::new();
+            for _ in 0..len {
+                tag.insert(0, alphabet[ix % alphabet.len()]);
+                ix /= alphabet.len();
+            }
+            tag
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TerminalSize, URL_REGEX};
+    use alacritty_terminal::{event::VoidListener, term::Config, vte::ansi::Processor};
+
+    #[test]
+    fn test_tags() {
+        assert_eq!(tags(3), ["a", "s", "d"]);
+        let tags = tags(30);
+        assert_eq!(tags[0], "aa");
+        assert_eq!(tags[26], "sa");
+        assert!(tags.iter().all(|tag| tag.len() == 2));
+    }
+
+    #[test]
+    fn test_visible_hints() {
+        let mut term = Term::new(Config::default(), &TerminalSize::default(), VoidListener);
+        let mut processor: Processor = Processor::new();
+        let output = "see https://zed.dev/docs in src/main.rs:12 at 3f9a2c1\r\n\
+            id 123e4567-e89b-12d3-a456-426614174000 but not deadbeef\r\n";
+        for byte in output.as_bytes() {
+            processor.advance(&mut term, *byte);
+        }
+
+        let mut regexes = QuickSelectRegexes::new(URL_REGEX);
+        let hints = visible_hints(&term, &mut regexes)
+            .into_iter()
+            .map(|hint| (hint.tag, hint.kind, hint.text))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            hints,
+            [
+                (
+                    "a".to_string(),
+                    QuickSelectKind::Uuid,
+                    "123e4567-e89b-12d3-a456-426614174000".to_string()
+                ),
+                (
+                    "s".to_string(),
+                    QuickSelectKind::Hash,
+                    "3f9a2c1".to_string()
+                ),
+                (
+                    "d".to_string(),
+                    QuickSelectKind::Path,
+                    "src/main.rs:12".to_string()
+                ),
+                (
+                    "f".to_string(),
+                    QuickSelectKind::Url,
+                    "https://zed.dev/docs".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quick_select_typing() {
+        let hint = |tag: &str| QuickSelectHint {
+            tag: tag.to_string(),
+            kind: QuickSelectKind::Hash,
+            text: tag.to_string(),
+            range: AlacPoint::default()..=AlacPoint::default(),
+        };
+        let mut quick_select = QuickSelectState {
+            hints: vec![hint("aa"), hint("as"), hint("sa")],
+            typed: String::new(),
+        };
+
+        assert_eq!(quick_select.push('a'), None);
+        assert_eq!(quick_select.push('x'), None);
+        assert_eq!(quick_select.typed, "");
+        assert_eq!(quick_select.push('A'), None);
+        assert_eq!(quick_select.push('S'), Some(hint("as")));
+    }
+}
@@ -0,0 +1,159 @@
+//! Shell integration makes the shell mark its prompt, the command line and the output of
+//! each command with `OSC 133` escape sequences, so that the terminal can tell where every
+//! command starts and ends.
+
+use crate::terminal_settings::Shell;
+use anyhow::{Context as _, Result};
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+/// The comment that starts the integration snippet, used to tell whether a shell
+/// configuration file already has it.
+pub const SHELL_INTEGRATION_MARKER: &str = "# Zed shell integration";
+
+const BASH_SNIPPET: &str = r#"# Zed shell integration
+if [ "$ZED_TERM" = "true" ]; then
+    __zed_prompt_command() {
+        printf '\033]133;D;%s\007\033]133;A\007' "$?"
+    }
+    PROMPT_COMMAND="__zed_prompt_command${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+    PS1="$PS1\[\033]133;B\007\]"
+    PS0="${PS0}\033]133;C\007"
+fi
+"#;
+
+const ZSH_SNIPPET: &str = r#"# Zed shell integration
+if [[ "$ZED_TERM" == "true" ]]; then
+    __zed_precmd() { print -n "\e]133;D;$?\a\e]133;A\a" }
+    __zed_preexec() { print -n "\e]133;C\a" }
+    precmd_functions+=(__zed_precmd)
+    preexec_functions+=(__zed_preexec)
+    PS1="$PS1%{"$'\e]133;B\a'"%}"
+fi
+"#;
+
+const FISH_SNIPPET: &str = r#"# Zed shell integration
+if test "$ZED_TERM" = "true"
+    function __zed_prompt --on-event fish_prompt
+        printf '\e]133;D;%s\a\e]133;A\a' $status
+    end
+    function __zed_preexec --on-event fish_preexec
+        printf '\e]133;C\a'
+    end
+    functions --copy fish_prompt __zed_fish_prompt
+    function fish_prompt
+        __zed_fish_prompt
+        printf '\e]133;B\a'
+    end
+end
+"#;
+
+/// A shell for which Zed has an integration snippet.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IntegratedShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl IntegratedShell {
+    /// Determines which shell a terminal with the given settings runs, if Zed can
+    /// integrate with it.
+    pub fn detect(shell: &Shell) -> Option<Self> {
+        match shell {
+            Shell::System => Self::from_program(&std::env::var("SHELL").ok()?),
+            Shell::Program(program) | Shell::WithArguments { program, .. } => {
+                Self::from_program(program)
+            }
+        }
+    }
+
+    fn from_program(program: &str) -> Option<Self> {
+        match Path::new(program).file_name()?.to_str()? {
+            "bash" => Some(Self::Bash),
+            "zsh" => Some(Self::Zsh),
+            "fish" => Some(Self::Fish),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Bash => "bash",
+            Self::Zsh => "zsh",
+            Self::Fish => "fish",
+        }
+    }
+
+    /// The configuration file that interactive instances of the shell read.
+    pub fn config_path(self, home_dir: &Path) -> PathBuf {
+        match self {
+            Self::Bash => home_dir.join(".bashrc"),
+            Self::Zsh => home_dir.join(".zshrc"),
+            Self::Fish => home_dir.join(".config/fish/config.fish"),
+        }
+    }
+
+    pub fn snippet(self) -> &'static str {
+        match self {
+            Self::Bash => BASH_SNIPPET,
+            Self::Zsh => ZSH_SNIPPET,
+            Self::Fish => FISH_SNIPPET,
+        }
+    }
+
+    /// Whether the configuration file at `config_path` already has the integration
+    /// snippet. A missing file has no integration.
+    pub fn is_installed(config_path: &Path) -> bool {
+        fs::read_to_string(config_path)
+            .map_or(false, |config| config.contains(SHELL_INTEGRATION_MARKER))
+    }
+
+    /// Appends the integration snippet to the configuration file at `config_path`,
+    /// creating the file if needed.
+    pub fn install(self, config_path: &Path) -> Result<()> {
+        if let Some(parent) = config_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create directory {parent:?}"))?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(config_path)
+            .with_context(|| format!("failed to open {config_path:?}"))?;
+        write!(file, "\n{}", self.snippet())
+            .with_context(|| format!("failed to write to {config_path:?}"))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_integrated_shell() {
+        assert_eq!(
+            IntegratedShell::detect(&Shell::Program("/bin/zsh".into())),
+            Some(IntegratedShell::Zsh)
+        );
+        assert_eq!(
+            IntegratedShell::detect(&Shell::WithArguments {
+                program: "/usr/local/bin/fish".into(),
+                args: vec!["--login".into()],
+            }),
+            Some(IntegratedShell::Fish)
+        );
+        assert_eq!(IntegratedShell::detect(&Shell::Program("nu".into())), None);
+        for shell in [
+            IntegratedShell::Bash,
+            IntegratedShell::Zsh,
+            IntegratedShell::Fish,
+        ] {
+            assert!(shell.snippet().starts_with(SHELL_INTEGRATION_MARKER));
+        }
+    }
+}
@@ -0,0 +1,512 @@
+//! The `OSC 133` marks that shell integration writes around the prompt, the command line
+//! and the output of each command. Alacritty ignores these sequences, so the output of a
+//! shell is scanned for them as it's parsed, and each mark is placed where the cursor was
+//! when the shell wrote it.
+
+use crate::ZedListener;
+use alacritty_terminal::{
+    event::{Event as AlacTermEvent, EventListener, OnResize, WindowSize},
+    grid::Dimensions,
+    index::{Column, Line, Point as AlacPoint},
+    sync::FairMutex,
+    term::{Term, TermMode},
+    tty::{self, ChildEvent, EventedPty, EventedReadWrite},
+    vte::ansi::Processor,
+};
+use parking_lot::Mutex;
+use polling::{Event as PollingEvent, PollMode, Poller};
+use std::{
+    io::{self, Read},
+    sync::Arc,
+};
+
+const READ_BUFFER_SIZE: usize = 0x10_000;
+/// Alacritty drops the oldest lines of a full scrollback without telling how many, so
+/// grids are created with a scrollback this many lines longer than their limit, and the
+/// output is parsed with [`MarkedParser`], which trims the scrollback back to the limit
+/// and counts the lines it drops. A byte of output scrolls at most a screen of lines.
+pub(crate) const SCROLLBACK_SLACK: usize = 1000;
+/// The most bytes of an `OSC` sequence kept to tell whether it's a mark, which is enough
+/// for any mark and its exit code.
+const MAX_MARK_LENGTH: usize = 32;
+
+/// A mark written by shell integration.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ShellMark {
+    /// `A`: the prompt starts.
+    PromptStart,
+    /// `B`: the prompt ends, and the command line starts.
+    CommandStart,
+    /// `C`: the command was entered, and its output starts.
+    OutputStart,
+    /// `D`: the command finished, with its exit code if the shell reported one.
+    CommandFinished(Option<i32>),
+}
+
+impl ShellMark {
+    /// Parses the parameters of an `OSC` sequence, like `133;D;0`.
+    fn parse(params: &[u8]) -> Option<Self> {
+        let params = std::str::from_utf8(params).ok()?;
+        let mut params = params.split(';');
+        if params.next()? != "133" {
+            return None;
+        }
+        match params.next()? {
+            "A" => Some(Self::PromptStart),
+            "B" => Some(Self::CommandStart),
+            "C" => Some(Self::OutputStart),
+            "D" => Some(Self::CommandFinished(
+                params.next().and_then(|exit_code| exit_code.parse().ok()),
+            )),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+enum ScanState {
+    #[default]
+    Ground,
+    Escape,
+    Osc,
+    /// An escape inside an `OSC` sequence, which ends it, as the first half of `ST` or
+    /// the start of another sequence.
+    OscEscape,
+}
+
+/// Finds the `OSC` sequences that are marks in a stream of output, one byte at a time.
+#[derive(Default)]
+struct MarkScanner {
+    state: ScanState,
+    params: Vec<u8>,
+}
+
+impl MarkScanner {
+    /// Scans the next byte of output, returning the mark it ends, if any.
+    fn advance(&mut self, byte: u8) -> Option<ShellMark> {
+        match (self.state, byte) {
+            (ScanState::Osc, 0x07) | (ScanState::OscEscape, b'\\') => {
+                self.state = ScanState::Ground;
+                return ShellMark::parse(&self.params);
+            }
+            (ScanState::Osc, 0x1b) => self.state = ScanState::OscEscape,
+            // `CAN` and `SUB` cancel the sequence.
+            (ScanState::Osc, 0x18 | 0x1a) => self.state = ScanState::Ground,
+            (ScanState::Osc, byte) => {
+                if self.params.len() < MAX_MARK_LENGTH {
+                    self.params.push(byte);
+                }
+            }
+            (ScanState::Escape | ScanState::OscEscape, b']') => {
+                self.params.clear();
+                self.state = ScanState::Osc;
+            }
+            (_, 0x1b) => self.state = ScanState::Escape,
+            _ => self.state = ScanState::Ground,
+        }
+        None
+    }
+}
+
+/// A command entered at a marked prompt, with the exit code the shell reported for it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct FinishedCommand {
+    pub command: String,
+    pub exit_code: Option<i32>,
+}
+
+/// A prompt that the shell marked, with its lines counted from the first line of output,
+/// including the lines dropped from the scrollback since. Resizing the terminal rewraps
+/// lines without telling where they moved, so a prompt can be off by the lines that
+/// wrapped differently above it.
+struct Prompt {
+    line: usize,
+    /// Where the command line starts.
+    command_start: Option<(usize, Column)>,
+    /// The line the command's output starts on, once the command was entered.
+    output_start: Option<usize>,
+    /// The command entered at the prompt, until it finishes.
+    command: Option<String>,
+}
+
+/// What shell integration told about the prompts and the commands entered at them,
+/// updated as the shell's output is parsed.
+#[derive(Default)]
+pub(crate) struct ShellMarks {
+    /// Whether the shell wrote any marks, i.e. has shell integration.
+    seen: bool,
+    /// The lines dropped from the top of the scrollback, or cleared from it.
+    dropped_lines: usize,
+    /// The last prompt, which is the one the cursor is at unless a command is running.
+    prompt: Option<Prompt>,
+    /// The prompt before the last one, if a command was entered at it.
+    previous_prompt: Option<Prompt>,
+    /// The commands that finished since they were last taken.
+    finished_commands: Vec<FinishedCommand>,
+}
+
+impl ShellMarks {
+    pub fn seen(&self) -> bool {
+        self.seen
+    }
+
+    /// How many lines of output were dropped from the top of the scrollback, or cleared
+    /// from it, which turns a line counted from the top of the scrollback into one
+    /// counted from the first line of output.
+    pub fn dropped_lines(&self) -> usize {
+        self.dropped_lines
+    }
+
+    /// The line the cursor is on, counted from the first line of output.
+    fn cursor_line<T: EventListener>(&self, term: &Term<T>) -> usize {
+        let grid = term.grid();
+        self.dropped_lines + grid.history_size() + grid.cursor.point.line.0 as usize
+    }
+
+    /// The line of the grid that a line counted from the first line of output is on, if
+    /// it's still in the grid.
+    fn grid_line<T: EventListener>(&self, term: &Term<T>, line: usize) -> Option<Line> {
+        if term.mode().contains(TermMode::ALT_SCREEN) {
+            return None;
+        }
+        let line = line.checked_sub(self.dropped_lines)? as i32 - term.grid().history_size() as i32;
+        (line <= term.bottommost_line().0).then_some(Line(line))
+    }
+
+    fn record<T: EventListener>(&mut self, term: &Term<T>, mark: ShellMark) {
+        self.seen = true;
+        let cursor = term.grid().cursor.point;
+        let cursor_line = self.cursor_line(term);
+        match mark {
+            ShellMark::PromptStart => {
+                self.previous_prompt = self
+                    .prompt
+                    .take()
+                    .filter(|prompt| prompt.output_start.is_some());
+                self.prompt = Some(Prompt {
+                    line: cursor_line,
+                    command_start: None,
+                    output_start: None,
+                    command: None,
+                });
+            }
+            ShellMark::CommandStart => {
+                if let Some(prompt) = self.prompt.as_mut() {
+                    prompt.command_start = Some((cursor_line, cursor.column));
+                }
+            }
+            ShellMark::OutputStart => {
+                let Some((line, column)) =
+                    self.prompt.as_ref().and_then(|prompt| prompt.command_start)
+                else {
+                    return;
+                };
+                let command = self
+                    .grid_line(term, line)
+                    .and_then(|line| crate::entered_command(term, AlacPoint::new(line, column)));
+                if let Some(prompt) = self.prompt.as_mut() {
+                    prompt.command = command;
+                    prompt.output_start = Some(cursor_line);
+                }
+            }
+            ShellMark::CommandFinished(exit_code) => {
+                if let Some(command) = self
+                    .prompt
+                    .as_mut()
+                    .and_then(|prompt| prompt.command.take())
+                {
+                    self.finished_commands
+                        .push(FinishedCommand { command, exit_code });
+                }
+            }
+        }
+    }
+
+    /// The prompt that the last command was entered at.
+    fn command_prompt(&self) -> Option<&Prompt> {
+        match &self.prompt {
+            Some(prompt) if prompt.output_start.is_some() => Some(prompt),
+            _ => self.previous_prompt.as_ref(),
+        }
+    }
+
+    /// The line of the grid that the prompt the last command was entered at starts on.
+    pub fn command_prompt_line<T: EventListener>(&self, term: &Term<T>) -> Option<Line> {
+        self.grid_line(term, self.command_prompt()?.line)
+    }
+
+    /// The line of the grid that the output of the last command entered at a prompt
+    /// starts on.
+    pub fn output_start<T: EventListener>(&self, term: &Term<T>) -> Option<Line> {
+        self.grid_line(term, self.command_prompt()?.output_start?)
+    }
+
+    /// Where the command line of the last prompt starts, until a command is entered at it.
+    pub fn command_start<T: EventListener>(&self, term: &Term<T>) -> Option<AlacPoint> {
+        let prompt = self.prompt.as_ref()?;
+        let (line, column) = prompt
+            .command_start
+            .filter(|_| prompt.output_start.is_none())?;
+        Some(AlacPoint::new(self.grid_line(term, line)?, column))
+    }
+
+    /// Clears the terminal like [`crate::clear_above`], counting the cleared lines as
+    /// dropped and forgetting the prompts that were on them.
+    pub fn clear_above<T: EventListener>(&mut self, term: &mut Term<T>, first_line: Line) -> Line {
+        let history_size = term.grid().history_size();
+        let first_kept_line = crate::clear_above(term, first_line);
+        self.dropped_lines += (history_size as i32 + first_kept_line.0) as usize;
+
+        for prompt in [&mut self.prompt, &mut self.previous_prompt] {
+            if prompt
+                .as_ref()
+                .map_or(false, |prompt| prompt.line < self.dropped_lines)
+            {
+                *prompt = None;
+            }
+        }
+        first_kept_line
+    }
+
+    /// Takes the commands that finished since this was last called.
+    pub fn take_finished_commands(&mut self) -> Vec<FinishedCommand> {
+        std::mem::take(&mut self.finished_commands)
+    }
+}
+
+/// Parses the output of a shell into its terminal's grid, recording the marks in it.
+pub(crate) struct MarkedParser {
+    parser: Processor,
+    scanner: MarkScanner,
+    marks: Arc<Mutex<ShellMarks>>,
+    /// The most lines the scrollback keeps, which is [`SCROLLBACK_SLACK`] lines fewer than
+    /// the grid was created with.
+    max_history_lines: usize,
+}
+
+impl MarkedParser {
+    pub fn new(marks: Arc<Mutex<ShellMarks>>, max_history_lines: usize) -> Self {
+        Self {
+            parser: Processor::new(),
+            scanner: MarkScanner::default(),
+            marks,
+            max_history_lines,
+        }
+    }
+
+    pub fn advance<T: EventListener>(&mut self, term: &mut Term<T>, bytes: &[u8]) {
+        for byte in bytes {
+            // The alternate screen has a scrollback of its own, which is always empty.
+            let was_primary = !term.mode().contains(TermMode::ALT_SCREEN);
+            let history_size = term.grid().history_size();
+            self.parser.advance(term, *byte);
+            let mark = self.scanner.advance(*byte);
+
+            let mut dropped_lines = 0;
+            if was_primary && !term.mode().contains(TermMode::ALT_SCREEN) {
+                // Clearing the scrollback drops its lines too.
+                dropped_lines = history_size.saturating_sub(term.grid().history_size());
+                let excess_lines = term
+                    .grid()
+                    .history_size()
+                    .saturating_sub(self.max_history_lines);
+                if excess_lines > 0 {
+                    term.grid_mut().update_history(self.max_history_lines);
+                    term.grid_mut()
+                        .update_history(self.max_history_lines + SCROLLBACK_SLACK);
+                    dropped_lines += excess_lines;
+                }
+            }
+            if dropped_lines > 0 || mark.is_some() {
+                let mut marks = self.marks.lock();
+                marks.dropped_lines += dropped_lines;
+                if let Some(mark) = mark.filter(|_| !term.mode().contains(TermMode::ALT_SCREEN)) {
+                    marks.record(term, mark);
+                }
+            }
+        }
+    }
+}
+
+/// The PTY of a shell, whose output is parsed when Alacritty's event loop reads it rather
+/// than by the event loop itself, so that the marks in it are found with the cursor where
+/// the shell wrote them.
+pub(crate) struct MarkingPty {
+    pty: tty::Pty,
+    parser: MarkedParser,
+    term: Arc<FairMutex<Term<ZedListener>>>,
+    listener: ZedListener,
+    buffer: Box<[u8]>,
+}
+
+impl MarkingPty {
+    pub fn new(
+        pty: tty::Pty,
+        parser: MarkedParser,
+        term: Arc<FairMutex<Term<ZedListener>>>,
+        listener: ZedListener,
+    ) -> Self {
+        Self {
+            pty,
+            parser,
+            term,
+            listener,
+            buffer: vec![0; READ_BUFFER_SIZE].into_boxed_slice(),
+        }
+    }
+}
+
+impl Read for MarkingPty {
+    /// Reads and parses what the shell wrote, and tells the event loop that nothing was
+    /// read, so that it has nothing left to parse.
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        let read = self.pty.reader().read(&mut self.buffer)?;
+        if read > 0 {
+            // The event loop holds the lock's lease while it reads, so waiting for the
+            // lease to take the lock would wait for itself.
+            let mut term = self.term.lock_unfair();
+            self.parser.advance(&mut *term, &self.buffer[..read]);
+            drop(term);
+            self.listener.send_event(AlacTermEvent::Wakeup);
+        }
+        Ok(0)
+    }
+}
+
+impl EventedReadWrite for MarkingPty {
+    type Reader = Self;
+    type Writer = <tty::Pty as EventedReadWrite>::Writer;
+
+    unsafe fn register(
+        &mut self,
+        poller: &Arc<Poller>,
+        interest: PollingEvent,
+        mode: PollMode,
+    ) -> io::Result<()> {
+        self.pty.register(poller, interest, mode)
+    }
+
+    fn reregister(
+        &mut self,
+        poller: &Arc<Poller>,
+        interest: PollingEvent,
+        mode: PollMode,
+    ) -> io::Result<()> {
+        self.pty.reregister(poller, interest, mode)
+    }
+
+    fn deregister(&mut self, poller: &Arc<Poller>) -> io::Result<()> {
+        self.pty.deregister(poller)
+    }
+
+    fn reader(&mut self) -> &mut Self {
+        self
+    }
+
+    fn writer(&mut self) -> &mut Self::Writer {
+        self.pty.writer()
+    }
+}
+
+impl EventedPty for MarkingPty {
+    fn next_child_event(&mut self) -> Option<ChildEvent> {
+        self.pty.next_child_event()
+    }
+}
+
+impl OnResize for MarkingPty {
+    fn on_resize(&mut self, window_size: WindowSize) {
+        self.pty.on_resize(window_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TerminalSize;
+    use alacritty_terminal::{event::VoidListener, term::Config};
+
+    fn parse(
+        max_history_lines: usize,
+        output: &str,
+    ) -> (Term<VoidListener>, Arc<Mutex<ShellMarks>>) {
+        let config = Config {
+            scrolling_history: max_history_lines + SCROLLBACK_SLACK,
+            ..Config::default()
+        };
+        let mut term = Term::new(config, &TerminalSize::default(), VoidListener);
+        let marks = Arc::new(Mutex::new(ShellMarks::default()));
+        MarkedParser::new(marks.clone(), max_history_lines).advance(&mut term, output.as_bytes());
+        (term, marks)
+    }
+
+    #[test]
+    fn test_scanner_finds_marks() {
+        let mut scanner = MarkScanner::default();
+        let marks =
+            b"\x1b]0;title\x07\x1b]133;A\x07$ \x1b]133;B\x1b\\\x1b]133;D;127\x1b\\\x1b]133;D\x07"
+                .iter()
+                .filter_map(|byte| scanner.advance(*byte))
+                .collect::<Vec<_>>();
+        assert_eq!(
+            marks,
+            [
+                ShellMark::PromptStart,
+                ShellMark::CommandStart,
+                ShellMark::CommandFinished(Some(127)),
+                ShellMark::CommandFinished(None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_commands_are_recorded_with_their_exit_codes() {
+        let (term, marks) = parse(
+            100,
+            "\x1b]133;A\x07~ $ \x1b]133;B\x07ls missing\r\n\x1b]133;C\x07\
+             ls: missing: No such file\r\n\x1b]133;D;2\x07\x1b]133;A\x07~ $ \x1b]133;B\x07ls",
+        );
+        let mut marks = marks.lock();
+
+        assert_eq!(
+            marks.take_finished_commands(),
+            [FinishedCommand {
+                command: "ls missing".to_string(),
+                exit_code: Some(2),
+            }]
+        );
+        assert_eq!(marks.command_prompt_line(&term), Some(Line(0)));
+        assert_eq!(marks.output_start(&term), Some(Line(1)));
+        assert_eq!(
+            marks.command_start(&term),
+            Some(AlacPoint::new(Line(2), Column(4)))
+        );
+    }
+
+    #[test]
+    fn test_marks_move_up_as_a_full_scrollback_drops_lines() {
+        // The screen has 6 lines and the scrollback keeps 1, so every line of output from
+        // the 7th on drops one, and the prompt ends up where it was written.
+        let (term, marks) = parse(
+            1,
+            "1\r\n2\r\n3\r\n4\r\n5\r\n6\r\n7\r\n\x1b]133;A\x07$ \x1b]133;B\x07ls\r\n\x1b]133;C\x07\
+             a\r\n\x1b]133;D;0\x07\x1b]133;A\x07$ \x1b]133;B\x07",
+        );
+        let mut marks = marks.lock();
+
+        assert_eq!(term.grid().history_size(), 1);
+        assert_eq!(
+            marks.take_finished_commands(),
+            [FinishedCommand {
+                command: "ls".to_string(),
+                exit_code: Some(0),
+            }]
+        );
+        assert_eq!(marks.command_prompt_line(&term), Some(Line(3)));
+        assert_eq!(marks.output_start(&term), Some(Line(4)));
+        assert_eq!(
+            marks.command_start(&term),
+            Some(AlacPoint::new(Line(5), Column(2)))
+        );
+    }
+}
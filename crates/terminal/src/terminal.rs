@@ -1,9 +1,14 @@
+pub mod byte_stream;
 pub mod mappings;
 
 pub use alacritty_terminal;
 
 mod pty_info;
+pub mod quick_select;
+pub mod shell_integration;
+mod shell_marks;
 pub mod terminal_settings;
+pub mod tmux;
 
 use alacritty_terminal::{
     event::{Event as AlacTermEvent, EventListener, Notify, WindowSize},
@@ -13,15 +18,16 @@ use alacritty_terminal::{
     selection::{Selection, SelectionRange, SelectionType},
     sync::FairMutex,
     term::{
-        cell::Cell,
+        cell::{Cell, Flags},
         search::{Match, RegexIter, RegexSearch},
-        Config, RenderableCursor, TermMode,
+        Config, RenderableCursor, TermDamage, TermMode,
     },
     tty::{self, setup_env},
     vte::ansi::{ClearMode, Handler, NamedPrivateMode, PrivateMode, Rgb},
     Term,
 };
 use anyhow::{bail, Result};
+use byte_stream::ByteStream;
 
 use futures::{
     channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender},
@@ -35,19 +41,24 @@ use mappings::mouse::{
 
 use collections::{HashMap, VecDeque};
 use futures::StreamExt;
+use parking_lot::Mutex;
 use pty_info::PtyProcessInfo;
+use quick_select::{QuickSelectHint, QuickSelectRegexes, QuickSelectState};
 use serde::{Deserialize, Serialize};
 use settings::Settings;
+use shell_marks::{MarkedParser, MarkingPty, ShellMarks};
 use smol::channel::{Receiver, Sender};
 use task::TaskId;
-use terminal_settings::{AlternateScroll, Shell, TerminalBlink, TerminalSettings};
+use terminal_settings::{AlternateScroll, PasteSettings, Shell, TerminalBlink, TerminalSettings};
 use theme::{ActiveTheme, Theme};
 use util::truncate_and_trailoff;
 
 use std::{
     cmp::{self, min},
+    collections::BTreeMap,
     fmt::Display,
-    ops::{Deref, Index, RangeInclusive},
+    mem,
+    ops::{Deref, Index, Range, RangeInclusive},
     path::PathBuf,
     sync::Arc,
     time::Duration,
@@ -64,7 +75,25 @@ use crate::mappings::{colors::to_alac_rgb, keys::to_esc_str};
 
 actions!(
     terminal,
-    [Clear, Copy, Paste, ShowCharacterPalette, SearchTest,]
+    [
+        Clear,
+        Copy,
+        Paste,
+        ShowCharacterPalette,
+        SearchTest,
+        ShowMemoryUsage,
+        CopyEnvironment,
+        ClearToPreviousPrompt,
+        RenameTerminal,
+        QuickSelect,
+        ShowCommandHistory,
+        ScrollLineUp,
+        ScrollLineDown,
+        ScrollPageUp,
+        ScrollPageDown,
+        ScrollToTop,
+        ScrollToBottom,
+    ]
 );
 
 ///Scrolling is unbearably sluggish by default. Alacritty supports a configurable
@@ -75,6 +104,7 @@ const SCROLL_MULTIPLIER: f32 = 4.;
 #[cfg(not(target_os = "macos"))]
 const SCROLL_MULTIPLIER: f32 = 1.;
 const MAX_SEARCH_LINES: usize = 100;
+const URL_REGEX: &str = r#"(ipfs:|ipns:|magnet:|mailto:|gemini://|gopher://|https://|http://|news:|file://|git://|ssh:|ftp://)[^\u{0000}-\u{001F}\u{007F}-\u{009F}<>"\s{-}\^⟨⟩`]+"#;
 const DEBUG_TERMINAL_WIDTH: Pixels = px(500.);
 const DEBUG_TERMINAL_HEIGHT: Pixels = px(30.);
 const DEBUG_CELL_WIDTH: Pixels = px(5.);
@@ -88,10 +118,22 @@ pub enum Event {
     CloseTerminal,
     Bell,
     Wakeup,
+    /// The font size changed the number of columns or lines, reflowing the terminal's
+    /// content.
+    GridReflowed,
     BlinkChanged,
     SelectionsChanged,
     NewNavigationTarget(Option<MaybeNavigationTarget>),
     Open(MaybeNavigationTarget),
+    /// A command entered at a prompt marked by shell integration finished, with the exit
+    /// code the shell reported for it.
+    CommandFinished {
+        command: String,
+        exit_code: Option<i32>,
+    },
+    /// The first command was entered at a prompt that shell integration didn't mark.
+    /// Emitted at most once, and only for terminals running a shell Zed spawned.
+    ShellIntegrationMissing,
 }
 
 #[derive(Clone, Debug)]
@@ -118,11 +160,14 @@ enum InternalEvent {
     ColorRequest(usize, Arc<dyn Fn(Rgb) -> String + Sync + Send + 'static>),
     Resize(TerminalSize),
     Clear,
+    ClearToPreviousPrompt,
     // FocusNextMatch,
     Scroll(AlacScroll),
+    ScrollToLine(usize),
     ScrollToAlacPoint(AlacPoint),
     SetSelection(Option<(Selection, AlacPoint)>),
     UpdateSelection(Point<Pixels>),
+    SelectMatches(Vec<RangeInclusive<AlacPoint>>),
     // Adjusted mouse position, should open
     FindHyperlink(Point<Pixels>, bool),
     Copy,
@@ -138,6 +183,28 @@ impl EventListener for ZedListener {
     }
 }
 
+/// Where the input of a terminal and the changes of its size go: the PTY of a shell Zed
+/// spawned, or a program relaying them to a terminal it runs, like tmux in control mode.
+pub trait TerminalBackend {
+    fn write(&self, bytes: Vec<u8>);
+    fn resize(&self, size: TerminalSize);
+    fn shutdown(&self);
+}
+
+impl TerminalBackend for Notifier {
+    fn write(&self, bytes: Vec<u8>) {
+        self.notify(bytes);
+    }
+
+    fn resize(&self, size: TerminalSize) {
+        self.0.send(Msg::Resize(size.into())).ok();
+    }
+
+    fn shutdown(&self) {
+        self.0.send(Msg::Shutdown).ok();
+    }
+}
+
 pub fn init(cx: &mut AppContext) {
     TerminalSettings::register(cx);
 }
@@ -306,13 +373,18 @@ impl TerminalBuilder {
         alternate_scroll: AlternateScroll,
         max_scroll_history_lines: Option<usize>,
         window: AnyWindowHandle,
-        completion_tx: Sender<()>,
+        completion_tx: Sender<TaskCompletion>,
     ) -> Result<TerminalBuilder> {
         // TODO: Properly set the current locale,
         env.entry("LC_ALL".to_string())
             .or_insert_with(|| "en_US.UTF-8".to_string());
 
         env.insert("ZED_TERM".to_string(), "true".to_string());
+        // Identify the terminal to programs that enable features based on it, and advertise
+        // 24-bit colors even when `TERM` names a terminfo entry that doesn't mention them.
+        env.insert("TERM_PROGRAM".to_string(), "zed".to_string());
+        env.entry("COLORTERM".to_string())
+            .or_insert_with(|| "truecolor".to_string());
 
         let pty_options = {
             let alac_shell = match shell.clone() {
@@ -335,6 +407,7 @@ impl TerminalBuilder {
 
         // Setup Alacritty's env
         setup_env();
+        let spawn_env = spawn_environment(std::env::vars(), pty_options.env.clone());
 
         let scrolling_history = if task.is_some() {
             // Tasks like `cargo build --all` may produce a lot of output, ergo allow maximum scrolling.
@@ -346,33 +419,17 @@ impl TerminalBuilder {
                 .unwrap_or(DEFAULT_SCROLL_HISTORY_LINES)
                 .min(MAX_SCROLL_HISTORY_LINES)
         };
-        let config = Config {
-            scrolling_history,
-            ..Config::default()
-        };
 
         //Spawn a task so the Alacritty EventLoop can communicate with us in a view context
         //TODO: Remove with a bounded sender which can be dispatched on &self
         let (events_tx, events_rx) = unbounded();
-        //Set up the terminal...
-        let mut term = Term::new(
-            config,
-            &TerminalSize::default(),
+        let term = new_term(
+            scrolling_history,
+            blink_settings,
+            alternate_scroll,
             ZedListener(events_tx.clone()),
         );
 
-        //Start off blinking if we need to
-        if let Some(TerminalBlink::On) = blink_settings {
-            term.set_private_mode(PrivateMode::Named(NamedPrivateMode::BlinkingCursor));
-        }
-
-        //Alacritty defaults to alternate scrolling being on, so we just need to turn it off.
-        if let AlternateScroll::Off = alternate_scroll {
-            term.unset_private_mode(PrivateMode::Named(NamedPrivateMode::AlternateScroll));
-        }
-
-        let term = Arc::new(FairMutex::new(term));
-
         //Setup the pty...
         let pty = match tty::new(
             &pty_options,
@@ -390,6 +447,13 @@ impl TerminalBuilder {
         };
 
         let pty_info = PtyProcessInfo::new(&pty);
+        let shell_marks = Arc::new(Mutex::new(ShellMarks::default()));
+        let pty = MarkingPty::new(
+            pty,
+            MarkedParser::new(shell_marks.clone(), scrolling_history),
+            term.clone(),
+            ZedListener(events_tx.clone()),
+        );
 
         //And connect them together
         let event_loop = EventLoop::new(
@@ -404,30 +468,16 @@ impl TerminalBuilder {
         let pty_tx = event_loop.channel();
         let _io_thread = event_loop.spawn(); // DANGER
 
-        let url_regex = RegexSearch::new(r#"(ipfs:|ipns:|magnet:|mailto:|gemini://|gopher://|https://|http://|news:|file://|git://|ssh:|ftp://)[^\u{0000}-\u{001F}\u{007F}-\u{009F}<>"\s{-}\^⟨⟩`]+"#).unwrap();
-        let word_regex = RegexSearch::new(r#"[\$\+\w.\[\]:/\\@\-~]+"#).unwrap();
-
-        let terminal = Terminal {
-            task,
-            pty_tx: Notifier(pty_tx),
-            completion_tx,
+        let terminal = Terminal::new(
+            Box::new(Notifier(pty_tx)),
             term,
-            events: VecDeque::with_capacity(10), //Should never get this high.
-            last_content: Default::default(),
-            last_mouse: None,
-            matches: Vec::new(),
-            selection_head: None,
+            shell_marks,
             pty_info,
-            breadcrumb_text: String::new(),
-            scroll_px: px(0.),
-            last_mouse_position: None,
-            next_link_id: 0,
-            selection_phase: SelectionPhase::Ended,
-            secondary_pressed: false,
-            hovered_word: false,
-            url_regex,
-            word_regex,
-        };
+            task,
+            scrolling_history,
+            completion_tx,
+            spawn_env,
+        );
 
         Ok(TerminalBuilder {
             terminal,
@@ -435,6 +485,87 @@ impl TerminalBuilder {
         })
     }
 
+    /// Builds a terminal for a program Zed doesn't spawn, like a tmux pane, whose output
+    /// is given to it with [`Terminal::write_output`] and whose input goes to `backend`.
+    pub fn new_detached(
+        backend: Box<dyn TerminalBackend>,
+        blink_settings: Option<TerminalBlink>,
+        alternate_scroll: AlternateScroll,
+        max_scroll_history_lines: Option<usize>,
+    ) -> TerminalBuilder {
+        let mut builder = Self::build_detached(
+            blink_settings,
+            alternate_scroll,
+            max_scroll_history_lines,
+            |_, _, _| backend,
+        );
+        let shell_marks = builder.terminal.shell_marks.clone();
+        builder.terminal.output_parser = Some(MarkedParser::new(
+            shell_marks,
+            builder.terminal.max_scroll_history_lines,
+        ));
+        builder
+    }
+
+    /// Builds a terminal on a byte stream, like a serial device, which reads its output
+    /// from the stream and writes its input to it.
+    pub fn new_for_stream(
+        stream: ByteStream,
+        blink_settings: Option<TerminalBlink>,
+        alternate_scroll: AlternateScroll,
+        max_scroll_history_lines: Option<usize>,
+    ) -> TerminalBuilder {
+        Self::build_detached(
+            blink_settings,
+            alternate_scroll,
+            max_scroll_history_lines,
+            |term, listener, parser| Box::new(stream.connect(term, listener, parser)),
+        )
+    }
+
+    fn build_detached(
+        blink_settings: Option<TerminalBlink>,
+        alternate_scroll: AlternateScroll,
+        max_scroll_history_lines: Option<usize>,
+        backend: impl FnOnce(
+            Arc<FairMutex<Term<ZedListener>>>,
+            ZedListener,
+            MarkedParser,
+        ) -> Box<dyn TerminalBackend>,
+    ) -> TerminalBuilder {
+        let scrolling_history = max_scroll_history_lines
+            .unwrap_or(DEFAULT_SCROLL_HISTORY_LINES)
+            .min(MAX_SCROLL_HISTORY_LINES);
+        let (events_tx, events_rx) = unbounded();
+        let term = new_term(
+            scrolling_history,
+            blink_settings,
+            alternate_scroll,
+            ZedListener(events_tx.clone()),
+        );
+        let shell_marks = Arc::new(Mutex::new(ShellMarks::default()));
+        let parser = MarkedParser::new(shell_marks.clone(), scrolling_history);
+        let backend = backend(term.clone(), ZedListener(events_tx), parser);
+        // Detached terminals don't run tasks, so nothing waits for their completion.
+        let (completion_tx, _) = smol::channel::unbounded();
+
+        let terminal = Terminal::new(
+            backend,
+            term,
+            shell_marks,
+            PtyProcessInfo::detached(),
+            None,
+            scrolling_history,
+            completion_tx,
+            Vec::new(),
+        );
+
+        TerminalBuilder {
+            terminal,
+            events_rx,
+        }
+    }
+
     pub fn subscribe(mut self, cx: &mut ModelContext<Terminal>) -> Terminal {
         //Event loop
         cx.spawn(|terminal, mut cx| async move {
@@ -498,6 +629,34 @@ impl TerminalBuilder {
     }
 }
 
+/// Creates the grid of a terminal, keeping up to `scrolling_history` lines of scrollback
+/// once its output is parsed with a [`MarkedParser`].
+fn new_term(
+    scrolling_history: usize,
+    blink_settings: Option<TerminalBlink>,
+    alternate_scroll: AlternateScroll,
+    listener: ZedListener,
+) -> Arc<FairMutex<Term<ZedListener>>> {
+    let config = Config {
+        scrolling_history: scrolling_history + shell_marks::SCROLLBACK_SLACK,
+        ..Config::default()
+    };
+    //Set up the terminal...
+    let mut term = Term::new(config, &TerminalSize::default(), listener);
+
+    //Start off blinking if we need to
+    if let Some(TerminalBlink::On) = blink_settings {
+        term.set_private_mode(PrivateMode::Named(NamedPrivateMode::BlinkingCursor));
+    }
+
+    //Alacritty defaults to alternate scrolling being on, so we just need to turn it off.
+    if let AlternateScroll::Off = alternate_scroll {
+        term.unset_private_mode(PrivateMode::Named(NamedPrivateMode::AlternateScroll));
+    }
+
+    Arc::new(FairMutex::new(term))
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct IndexedCell {
     pub point: AlacPoint,
@@ -513,16 +672,49 @@ impl Deref for IndexedCell {
     }
 }
 
+const ZERO_WIDTH_JOINER: char = '\u{200d}';
+
+/// Returns the text of the grapheme cluster starting at the first of `cells`, and how
+/// many cells it spans.
+///
+/// Combining characters and variation selectors are stored on the cell of the character
+/// they modify, but characters joined with a zero width joiner (as in family or
+/// profession emoji) each get their own cells, so they're merged back into one cluster.
+pub fn grapheme_cluster<'a>(cells: impl IntoIterator<Item = &'a Cell>) -> (String, usize) {
+    let mut text = String::new();
+    let mut cell_count = 0;
+    for cell in cells {
+        if cell_count > 0 {
+            if cell.flags.contains(Flags::WIDE_CHAR_SPACER) {
+                cell_count += 1;
+                continue;
+            }
+            if !text.ends_with(ZERO_WIDTH_JOINER) {
+                break;
+            }
+        }
+        text.push(cell.c);
+        text.extend(cell.zerowidth().into_iter().flatten());
+        cell_count += 1;
+    }
+    (text, cell_count)
+}
+
 // TODO: Un-pub
 #[derive(Clone)]
 pub struct TerminalContent {
     pub cells: Vec<IndexedCell>,
     pub mode: TermMode,
     pub display_offset: usize,
+    /// How many lines of scrollback are above the screen.
+    pub history_size: usize,
     pub selection_text: Option<String>,
     pub selection: Option<SelectionRange>,
     pub cursor: RenderableCursor,
-    pub cursor_char: char,
+    /// The text of the grapheme cluster under the cursor.
+    pub cursor_text: String,
+    /// The lines of the viewport that continue a line soft-wrapped above them.
+    pub wrapped_lines: Vec<Line>,
     pub size: TerminalSize,
     pub last_hovered_word: Option<HoveredWord>,
 }
@@ -540,13 +732,15 @@ impl Default for TerminalContent {
             cells: Default::default(),
             mode: Default::default(),
             display_offset: Default::default(),
+            history_size: Default::default(),
             selection_text: Default::default(),
             selection: Default::default(),
             cursor: RenderableCursor {
                 shape: alacritty_terminal::vte::ansi::CursorShape::Block,
                 point: AlacPoint::new(Line(0), Column(0)),
             },
-            cursor_char: Default::default(),
+            cursor_text: Default::default(),
+            wrapped_lines: Default::default(),
             size: Default::default(),
             last_hovered_word: None,
         }
@@ -560,18 +754,31 @@ pub enum SelectionPhase {
 }
 
 pub struct Terminal {
-    pty_tx: Notifier,
-    completion_tx: Sender<()>,
+    backend: Box<dyn TerminalBackend>,
+    /// Parses the output given to a detached terminal, which has no event loop of
+    /// Alacritty's reading it from a PTY.
+    output_parser: Option<MarkedParser>,
+    completion_tx: Sender<TaskCompletion>,
     term: Arc<FairMutex<Term<ZedListener>>>,
+    /// The prompts and commands that shell integration marked in the output.
+    shell_marks: Arc<Mutex<ShellMarks>>,
     events: VecDeque<InternalEvent>,
     /// This is only used for mouse mode cell change detection
     last_mouse: Option<(AlacPoint, AlacDirection)>,
     /// This is only used for terminal hovered word checking
     last_mouse_position: Option<Point<Pixels>>,
     pub matches: Vec<RangeInclusive<AlacPoint>>,
+    /// The search matches selected together, which are copied one per line. Alacritty has
+    /// a single selection, which is set to the last of them.
+    selected_matches: Vec<RangeInclusive<AlacPoint>>,
     pub last_content: TerminalContent,
+    /// Whether the grid changed since `last_content` was taken from it.
+    content_outdated: bool,
     pub selection_head: Option<AlacPoint>,
     pub breadcrumb_text: String,
+    /// A title the user gave the terminal, shown instead of the titles the running
+    /// programs set until it's cleared.
+    title_override: Option<String>,
     pub pty_info: PtyProcessInfo,
     scroll_px: Pixels,
     next_link_id: usize,
@@ -580,7 +787,66 @@ pub struct Terminal {
     hovered_word: bool,
     url_regex: RegexSearch,
     word_regex: RegexSearch,
+    quick_select_regexes: QuickSelectRegexes,
+    quick_select: Option<QuickSelectState>,
+    /// The environment the shell was spawned with, sorted by variable name.
+    spawn_env: Vec<(String, String)>,
     task: Option<TaskState>,
+    max_scroll_history_lines: usize,
+    focused: bool,
+    /// The line on which a command was last entered, counted from the top of the
+    /// scrollback, which is taken for the prompt's line when the shell doesn't mark its
+    /// prompts. Once the scrollback is full, old lines are dropped without moving it, so
+    /// it drifts down.
+    prompt_line: Option<usize>,
+    /// Where the cursor was when the first key of the command being typed at the prompt
+    /// was sent, with the line counted like `prompt_line`, which is taken for the start
+    /// of the command line when the shell doesn't mark it.
+    command_start: Option<(usize, Column)>,
+    task_completion: Option<TaskCompletion>,
+    /// Where the terminal was scrolled to and what was selected in it when its tab was
+    /// hidden, restored the next time it's shown.
+    saved_viewport: Option<SavedViewport>,
+    shell_integration_check: ShellIntegrationCheck,
+}
+
+/// Whether the shell marks its prompts, which is checked when the first command is
+/// entered, as the shell has printed its first prompt by then.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ShellIntegrationCheck {
+    Pending,
+    /// No prompt was marked, which is told with the next wakeup.
+    Missing,
+    Done,
+}
+
+/// An estimate of the memory held by the grid of a terminal, most of which is usually
+/// its scrollback history.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerminalMemoryUsage {
+    /// The lines currently kept in the scrollback history.
+    pub history_lines: usize,
+    /// The most lines the scrollback history can grow to.
+    pub max_history_lines: usize,
+    pub screen_lines: usize,
+    pub columns: usize,
+    /// The size of the cells of the history and the screen, excluding the extra data of
+    /// cells with hyperlinks or zero-width characters.
+    pub grid_bytes: usize,
+}
+
+impl TerminalMemoryUsage {
+    fn new(history_lines: usize, max_history_lines: usize, size: &impl Dimensions) -> Self {
+        let screen_lines = size.screen_lines();
+        let columns = size.columns();
+        Self {
+            history_lines,
+            max_history_lines,
+            screen_lines,
+            columns,
+            grid_bytes: (history_lines + screen_lines) * columns * mem::size_of::<Cell>(),
+        }
+    }
 }
 
 pub struct TaskState {
@@ -589,7 +855,24 @@ pub struct TaskState {
     pub label: String,
     pub command_label: String,
     pub status: TaskStatus,
-    pub completion_rx: Receiver<()>,
+    pub completion_rx: Receiver<TaskCompletion>,
+}
+
+/// How a task that ran in a terminal finished, for callers to act on the outcome, e.g.
+/// to reveal the diagnostics on failure or close the terminal on success.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskCompletion {
+    /// The exit code of the task's process, or `None` if the terminal shut down before
+    /// the process reported one.
+    pub exit_code: Option<i32>,
+    /// The last lines the task printed.
+    pub output_tail: String,
+}
+
+impl TaskCompletion {
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
 }
 
 /// A status of the current terminal tab's task.
@@ -619,8 +902,62 @@ impl TaskStatus {
 }
 
 impl Terminal {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        backend: Box<dyn TerminalBackend>,
+        term: Arc<FairMutex<Term<ZedListener>>>,
+        shell_marks: Arc<Mutex<ShellMarks>>,
+        pty_info: PtyProcessInfo,
+        task: Option<TaskState>,
+        max_scroll_history_lines: usize,
+        completion_tx: Sender<TaskCompletion>,
+        spawn_env: Vec<(String, String)>,
+    ) -> Self {
+        let url_regex = RegexSearch::new(URL_REGEX).unwrap();
+        let word_regex = RegexSearch::new(r#"[\$\+\w.\[\]:/\\@\-~]+"#).unwrap();
+
+        Terminal {
+            task,
+            max_scroll_history_lines,
+            focused: false,
+            prompt_line: None,
+            command_start: None,
+            task_completion: None,
+            backend,
+            output_parser: None,
+            completion_tx,
+            term,
+            shell_marks,
+            events: VecDeque::with_capacity(10), //Should never get this high.
+            last_content: Default::default(),
+            content_outdated: true,
+            last_mouse: None,
+            matches: Vec::new(),
+            selected_matches: Vec::new(),
+            selection_head: None,
+            pty_info,
+            breadcrumb_text: String::new(),
+            title_override: None,
+            scroll_px: px(0.),
+            last_mouse_position: None,
+            next_link_id: 0,
+            selection_phase: SelectionPhase::Ended,
+            secondary_pressed: false,
+            hovered_word: false,
+            url_regex,
+            word_regex,
+            quick_select_regexes: QuickSelectRegexes::new(URL_REGEX),
+            quick_select: None,
+            spawn_env,
+            saved_viewport: None,
+            shell_integration_check: ShellIntegrationCheck::Pending,
+        }
+    }
+
     fn process_event(&mut self, event: &AlacTermEvent, cx: &mut ModelContext<Self>) {
         match event {
+            // Titles pushed and popped with XTWINOPS are kept by Alacritty, which sends the
+            // popped title, or a reset if there was none, as if it was just set.
             AlacTermEvent::Title(title) => {
                 self.breadcrumb_text = title.to_string();
                 cx.emit(Event::BreadcrumbsChanged);
@@ -637,6 +974,8 @@ impl Terminal {
                     .map(|ci| ci.text().to_string())
                     .unwrap_or_else(|| "".to_string()),
             )),
+            // Answers to the queries Alacritty handles itself, like the DA1/DA2 device
+            // attribute queries that report the terminal's identity and features.
             AlacTermEvent::PtyWrite(out) => self.write_to_pty(out.clone()),
             AlacTermEvent::TextAreaSizeRequest(format) => {
                 self.write_to_pty(format(self.last_content.size.into()))
@@ -652,8 +991,24 @@ impl Terminal {
                 //NOOP, Handled in render
             }
             AlacTermEvent::Wakeup => {
+                // The snapshot that frames are rendered from is taken here, as the event
+                // loop batches wakeups, rather than when rendering.
+                let term = self.term.clone();
+                self.update_content(&mut term.lock());
                 cx.emit(Event::Wakeup);
 
+                let finished_commands = self.shell_marks.lock().take_finished_commands();
+                for finished in finished_commands {
+                    cx.emit(Event::CommandFinished {
+                        command: finished.command,
+                        exit_code: finished.exit_code,
+                    });
+                }
+                if self.shell_integration_check == ShellIntegrationCheck::Missing {
+                    self.shell_integration_check = ShellIntegrationCheck::Done;
+                    cx.emit(Event::ShellIntegrationMissing);
+                }
+
                 if self.pty_info.has_changed() {
                     cx.emit(Event::TitleChanged);
                 }
@@ -694,41 +1049,42 @@ impl Terminal {
                 new_size.size.height = cmp::max(new_size.line_height, new_size.height());
                 new_size.size.width = cmp::max(new_size.cell_width, new_size.width());
 
-                self.last_content.size = new_size;
+                let old_size = mem::replace(&mut self.last_content.size, new_size);
 
-                self.pty_tx.0.send(Msg::Resize(new_size.into())).ok();
+                self.backend.resize(new_size);
 
-                term.resize(new_size);
-            }
-            InternalEvent::Clear => {
-                // Clear back buffer
-                term.clear_screen(ClearMode::Saved);
-
-                let cursor = term.grid().cursor.point;
-
-                // Clear the lines above
-                term.grid_mut().reset_region(..cursor.line);
+                resize_term(term, new_size, &mut self.selection_head);
 
-                // Copy the current line up
-                let line = term.grid()[cursor.line][..Column(term.grid().columns())]
-                    .iter()
-                    .cloned()
-                    .enumerate()
-                    .collect::<Vec<(usize, Cell)>>();
-
-                for (i, cell) in line {
-                    term.grid_mut()[Line(0)][Column(i)] = cell;
+                // The first resize replaces a placeholder size, which isn't worth reporting.
+                let font_size_changed = old_size != TerminalSize::default()
+                    && (old_size.cell_width != new_size.cell_width
+                        || old_size.line_height != new_size.line_height);
+                let grid_changed = old_size.num_columns() != new_size.num_columns()
+                    || old_size.num_lines() != new_size.num_lines();
+                if font_size_changed && grid_changed {
+                    cx.emit(Event::GridReflowed);
                 }
+            }
+            InternalEvent::Clear => {
+                let cursor_line = term.grid().cursor.point.line;
+                self.shell_marks.lock().clear_above(term, cursor_line);
+                self.prompt_line = None;
 
-                // Reset the cursor
-                term.grid_mut().cursor.point =
-                    AlacPoint::new(Line(0), term.grid_mut().cursor.point.column);
-                let new_cursor = term.grid().cursor.point;
-
-                // Clear the lines below the new cursor
-                if (new_cursor.line.0 as usize) < term.screen_lines() - 1 {
-                    term.grid_mut().reset_region((new_cursor.line + 1)..);
+                cx.emit(Event::Wakeup);
+            }
+            InternalEvent::ClearToPreviousPrompt => {
+                let cursor_line = term.grid().cursor.point.line;
+                let mut shell_marks = self.shell_marks.lock();
+                let prompt_line = if shell_marks.seen() {
+                    shell_marks.command_prompt_line(term)
+                } else {
+                    self.prompt_line
+                        .map(|line| absolute_to_grid_line(term, line))
                 }
+                .filter(|line| *line <= cursor_line)
+                .unwrap_or(cursor_line);
+                let first_kept_line = shell_marks.clear_above(term, prompt_line);
+                self.prompt_line = (first_kept_line == prompt_line).then_some(0);
 
                 cx.emit(Event::Wakeup);
             }
@@ -736,7 +1092,12 @@ impl Terminal {
                 term.scroll_display(*scroll);
                 self.refresh_hovered_word();
             }
+            InternalEvent::ScrollToLine(line) => {
+                scroll_to_line(term, *line);
+                self.refresh_hovered_word();
+            }
             InternalEvent::SetSelection(selection) => {
+                self.selected_matches.clear();
                 term.selection = selection.as_ref().map(|(sel, _)| sel.clone());
 
                 #[cfg(target_os = "linux")]
@@ -751,6 +1112,7 @@ impl Terminal {
             }
             InternalEvent::UpdateSelection(position) => {
                 if let Some(mut selection) = term.selection.take() {
+                    self.selected_matches.clear();
                     let (point, side) = grid_point_and_side(
                         *position,
                         self.last_content.size,
@@ -769,10 +1131,31 @@ impl Terminal {
                     cx.emit(Event::SelectionsChanged)
                 }
             }
+            InternalEvent::SelectMatches(matches) => {
+                // The last match is selected, so that it's where the selection continues
+                // when it's extended.
+                if let Some(last_match) = matches.last() {
+                    term.selection = Some(make_selection(last_match));
+                    self.selection_head = Some(*last_match.end());
+                }
+                self.selected_matches = matches.clone();
+                cx.emit(Event::SelectionsChanged)
+            }
 
             InternalEvent::Copy => {
-                if let Some(txt) = term.selection_to_string() {
-                    cx.write_to_clipboard(ClipboardItem::new(txt))
+                let text = if self.selected_matches.is_empty() {
+                    term.selection_to_string()
+                } else {
+                    Some(
+                        self.selected_matches
+                            .iter()
+                            .map(|range| term.bounds_to_string(*range.start(), *range.end()))
+                            .collect::<Vec<_>>()
+                            .join("\n"),
+                    )
+                };
+                if let Some(text) = text {
+                    cx.write_to_clipboard(ClipboardItem::new(text))
                 }
             }
             InternalEvent::ScrollToAlacPoint(point) => {
@@ -946,6 +1329,7 @@ impl Terminal {
         }
     }
 
+    /// Selects all of the given search matches, so that they're copied together.
     pub fn select_matches(&mut self, matches: &[RangeInclusive<AlacPoint>]) {
         let matches_to_select = self
             .matches
@@ -953,14 +1337,25 @@ impl Terminal {
             .filter(|self_match| matches.contains(self_match))
             .cloned()
             .collect::<Vec<_>>();
-        for match_to_select in matches_to_select {
-            self.set_selection(Some((
-                make_selection(&match_to_select),
-                *match_to_select.end(),
-            )));
+        if !matches_to_select.is_empty() {
+            self.events
+                .push_back(InternalEvent::SelectMatches(matches_to_select));
         }
     }
 
+    /// Forgets the search matches, e.g. when the search is dismissed, along with the
+    /// ones that were selected.
+    pub fn clear_matches(&mut self) {
+        self.matches.clear();
+        self.selected_matches.clear();
+    }
+
+    /// The search matches selected by [`Terminal::select_matches`], until the selection
+    /// changes in any way or the matches are cleared.
+    pub fn selected_matches(&self) -> &[RangeInclusive<AlacPoint>] {
+        &self.selected_matches
+    }
+
     pub fn select_all(&mut self) {
         let term = self.term.lock();
         let start = AlacPoint::new(term.topmost_line(), Column(0));
@@ -969,6 +1364,40 @@ impl Terminal {
         self.set_selection(Some((make_selection(&(start..=end)), end)));
     }
 
+    /// Tags the tokens visible in the terminal, until a tag is typed or quick select is
+    /// cancelled.
+    pub fn start_quick_select(&mut self) {
+        let term = self.term.lock();
+        let hints = quick_select::visible_hints(&term, &mut self.quick_select_regexes);
+        drop(term);
+        self.quick_select = Some(QuickSelectState {
+            hints,
+            typed: String::new(),
+        });
+    }
+
+    pub fn quick_select(&self) -> Option<&QuickSelectState> {
+        self.quick_select.as_ref()
+    }
+
+    pub fn cancel_quick_select(&mut self) {
+        self.quick_select = None;
+    }
+
+    /// Types a letter of a tag. Once a whole tag is typed, quick select ends and the hint
+    /// it tags is returned.
+    pub fn quick_select_letter(&mut self, letter: char) -> Option<QuickSelectHint> {
+        let hint = self.quick_select.as_mut()?.push(letter)?;
+        self.quick_select = None;
+        Some(hint)
+    }
+
+    pub fn quick_select_backspace(&mut self) {
+        if let Some(quick_select) = &mut self.quick_select {
+            quick_select.pop();
+        }
+    }
+
     fn set_selection(&mut self, selection: Option<(Selection, AlacPoint)>) {
         self.events
             .push_back(InternalEvent::SetSelection(selection));
@@ -982,6 +1411,38 @@ impl Terminal {
         self.events.push_back(InternalEvent::Clear)
     }
 
+    /// Scrolls the display through the scrollback without sending any input.
+    pub fn scroll(&mut self, scroll: AlacScroll) {
+        self.events.push_back(InternalEvent::Scroll(scroll));
+    }
+
+    /// Scrolls the display so that `line` is at the top of the viewport, or as close to it
+    /// as the scrollback allows. Lines are counted from the oldest one in the scrollback,
+    /// as in [`Terminal::viewport`].
+    pub fn scroll_to_line(&mut self, line: usize) {
+        self.events.push_back(InternalEvent::ScrollToLine(line));
+    }
+
+    /// Scrolls the display by `pages` screens, towards the top of the scrollback when
+    /// positive and towards the bottom when negative.
+    pub fn scroll_by_pages(&mut self, pages: i32) {
+        let lines = pages.saturating_mul(self.last_content.size.num_lines() as i32);
+        self.events
+            .push_back(InternalEvent::Scroll(AlacScroll::Delta(lines)));
+    }
+
+    /// The lines currently shown in the viewport, counted from the oldest one in the
+    /// scrollback.
+    pub fn viewport(&self) -> Range<usize> {
+        viewport(&self.term.lock())
+    }
+
+    /// Clears everything above the last command entered at a prompt, keeping the command,
+    /// its output and the current line.
+    pub fn clear_to_previous_prompt(&mut self) {
+        self.events.push_back(InternalEvent::ClearToPreviousPrompt)
+    }
+
     ///Resize the terminal and the PTY.
     pub fn set_size(&mut self, new_size: TerminalSize) {
         if self.last_content.size != new_size {
@@ -991,14 +1452,42 @@ impl Terminal {
 
     ///Write the Input payload to the tty.
     fn write_to_pty(&self, input: String) {
-        self.pty_tx.notify(input.into_bytes());
+        self.backend.write(input.into_bytes());
     }
 
     fn write_bytes_to_pty(&self, input: Vec<u8>) {
-        self.pty_tx.notify(input);
+        self.backend.write(input);
     }
 
+    /// Writes `input` to the pty as if it was typed, scrolling to the bottom and clearing
+    /// the selection. Use [`Terminal::paste`] for text that shouldn't be interpreted as
+    /// keystrokes, e.g. a command sent from an editor.
     pub fn input(&mut self, input: String) {
+        if !self.last_content.mode.contains(TermMode::ALT_SCREEN) {
+            let term = self.term.lock();
+            let grid = term.grid();
+            let cursor_line = grid.history_size() + grid.cursor.point.line.0 as usize;
+            if input.contains('\r') {
+                self.prompt_line = Some(cursor_line);
+                self.command_start = None;
+                if self.shell_integration_check == ShellIntegrationCheck::Pending {
+                    self.shell_integration_check = if self.shell_marks.lock().seen()
+                        || self.task.is_some()
+                        || self.is_detached()
+                    {
+                        ShellIntegrationCheck::Done
+                    } else {
+                        ShellIntegrationCheck::Missing
+                    };
+                }
+            } else if input == "\x03" {
+                // Ctrl-C discards the command line.
+                self.command_start = None;
+            } else if self.command_start.is_none() && self.task.is_none() {
+                self.command_start = Some((cursor_line, grid.cursor.point.column));
+            }
+        }
+
         self.events
             .push_back(InternalEvent::Scroll(AlacScroll::Bottom));
         self.events.push_back(InternalEvent::SetSelection(None));
@@ -1006,6 +1495,38 @@ impl Terminal {
         self.write_to_pty(input);
     }
 
+    /// Whether something was typed at the prompt and not entered yet. Unless the shell
+    /// marks where its command line starts, only input sent through Zed is tracked, so
+    /// text a shell inserted on its own, e.g. from its history, is missed until a key is
+    /// sent after it.
+    pub fn has_pending_input(&self) -> bool {
+        if self.last_content.mode.contains(TermMode::ALT_SCREEN) {
+            return false;
+        }
+        let term = self.term.lock();
+        let shell_marks = self.shell_marks.lock();
+        let command_start = if shell_marks.seen() {
+            shell_marks.command_start(&term)
+        } else {
+            self.command_start
+                .map(|(line, column)| AlacPoint::new(absolute_to_grid_line(&term, line), column))
+        };
+        command_start.map_or(false, |start| entered_command(&term, start).is_some())
+    }
+
+    /// Clears what was typed at the prompt and not entered yet, so that text sent next
+    /// isn't appended to it. Moves the cursor to the end of the line and deletes back to
+    /// its start, like ctrl-e ctrl-u would.
+    pub fn clear_pending_input(&mut self) {
+        if self.has_pending_input() {
+            self.input("\x05\x15".to_string());
+        }
+    }
+
+    pub fn input_str(&mut self, input: &str) {
+        self.input(input.to_string());
+    }
+
     pub fn input_bytes(&mut self, input: Vec<u8>) {
         self.events
             .push_back(InternalEvent::Scroll(AlacScroll::Bottom));
@@ -1014,6 +1535,32 @@ impl Terminal {
         self.write_bytes_to_pty(input);
     }
 
+    /// Whether the terminal shows a program Zed doesn't spawn, like a tmux pane or a
+    /// serial device, rather than a shell of its own. Such terminals aren't restored when
+    /// Zed restarts.
+    pub fn is_detached(&self) -> bool {
+        !self.pty_info.has_process()
+    }
+
+    /// Shows output of the program of a terminal built with
+    /// [`TerminalBuilder::new_detached`]. Does nothing for terminals that read their
+    /// output themselves, from a PTY or a byte stream.
+    pub fn write_output(&mut self, bytes: &[u8], cx: &mut ModelContext<Self>) {
+        let Some(parser) = self.output_parser.as_mut() else {
+            return;
+        };
+        parser.advance(&mut self.term.lock(), bytes);
+        self.process_event(&AlacTermEvent::Wakeup, cx);
+    }
+
+    /// Tells a detached terminal that its program is gone, closing it like a shell that
+    /// exited.
+    pub fn output_closed(&mut self, cx: &mut ModelContext<Self>) {
+        if self.is_detached() {
+            self.process_event(&AlacTermEvent::Exit, cx);
+        }
+    }
+
     pub fn try_keystroke(&mut self, keystroke: &Keystroke, alt_is_meta: bool) -> bool {
         let esc = to_esc_str(keystroke, &self.last_content.mode, alt_is_meta);
         if let Some(esc) = esc {
@@ -1033,18 +1580,23 @@ impl Terminal {
         changed
     }
 
-    ///Paste text into the terminal
+    /// Writes `text` to the pty as a paste, wrapped in bracketed paste markers if the
+    /// running program asked for them, so that it isn't run line by line.
     pub fn paste(&mut self, text: &str) {
-        let paste_text = if self.last_content.mode.contains(TermMode::BRACKETED_PASTE) {
-            format!("{}{}{}", "\x1b[200~", text.replace('\x1b', ""), "\x1b[201~")
-        } else {
-            text.replace("\r\n", "\r").replace('\n', "\r")
-        };
-
-        self.input(paste_text);
+        self.input(paste_text(text, self.last_content.mode));
     }
 
+    /// Processes the pending events and brings [`Terminal::last_content`] up to date.
+    ///
+    /// Frames are rendered from [`Terminal::last_content`], a copy of the viewport that's
+    /// taken whenever the PTY reader wakes the terminal up, so rendering doesn't contend
+    /// with the reader for the grid's lock. The lock is only taken here to process the
+    /// events queued by input, like scrolling, selecting or resizing.
     pub fn sync(&mut self, cx: &mut ModelContext<Self>) {
+        if self.events.is_empty() && !self.content_outdated && self.saved_viewport.is_none() {
+            return;
+        }
+
         let term = self.term.clone();
         let mut terminal = term.lock_unfair();
         //Note that the ordering of events matters for event processing
@@ -1052,47 +1604,78 @@ impl Terminal {
             self.process_terminal_event(&e, &mut terminal, cx)
         }
 
-        self.last_content = Self::make_content(&terminal, &self.last_content);
+        // The terminal is only synced when it's shown, after the resize to its new bounds.
+        if let Some(saved_viewport) = self.saved_viewport.take() {
+            let dropped_lines = self.shell_marks.lock().dropped_lines();
+            if let Some(selection_head) =
+                restore_viewport(&mut terminal, &saved_viewport, dropped_lines)
+            {
+                self.selection_head = Some(selection_head);
+            }
+        }
+
+        self.update_content(&mut terminal);
     }
 
-    fn make_content(term: &Term<ZedListener>, last_content: &TerminalContent) -> TerminalContent {
+    fn update_content(&mut self, term: &mut Term<ZedListener>) {
+        let mut cells = mem::take(&mut self.last_content.cells);
+        update_cells(&mut cells, self.last_content.display_offset, term);
+        self.last_content = Self::make_content(term, cells, &self.last_content);
+        self.content_outdated = false;
+    }
+
+    fn make_content(
+        term: &Term<ZedListener>,
+        cells: Vec<IndexedCell>,
+        last_content: &TerminalContent,
+    ) -> TerminalContent {
         let content = term.renderable_content();
         TerminalContent {
-            cells: content
-                .display_iter
-                //TODO: Add this once there's a way to retain empty lines
-                // .filter(|ic| {
-                //     !ic.flags.contains(Flags::HIDDEN)
-                //         && !(ic.bg == Named(NamedColor::Background)
-                //             && ic.c == ' '
-                //             && !ic.flags.contains(Flags::INVERSE))
-                // })
-                .map(|ic| IndexedCell {
-                    point: ic.point,
-                    cell: ic.cell.clone(),
-                })
-                .collect::<Vec<IndexedCell>>(),
+            cells,
             mode: content.mode,
             display_offset: content.display_offset,
+            history_size: term.grid().history_size(),
             selection_text: term.selection_to_string(),
             selection: content.selection,
             cursor: content.cursor,
-            cursor_char: term.grid()[content.cursor.point].c,
+            cursor_text: cursor_text(term, content.cursor.point),
+            wrapped_lines: wrapped_lines(term),
             size: last_content.size,
             last_hovered_word: last_content.last_hovered_word.clone(),
         }
     }
 
-    pub fn focus_in(&self) {
-        if self.last_content.mode.contains(TermMode::FOCUS_IN_OUT) {
-            self.write_to_pty("\x1b[I".to_string());
+    /// Remembers the scroll position and the selection of a terminal whose tab is being
+    /// hidden, to put them back when it's next shown, in case a resize or a reflow of its
+    /// lines in the meantime moved them.
+    pub fn save_viewport(&mut self) {
+        let term = self.term.lock();
+        let dropped_lines = self.shell_marks.lock().dropped_lines();
+        self.saved_viewport = save_viewport(&term, self.selection_head, dropped_lines);
+    }
+
+    pub fn focus_in(&mut self) {
+        if !self.focused {
+            self.focused = true;
+            self.report_focus();
         }
     }
 
     pub fn focus_out(&mut self) {
         self.last_mouse_position = None;
-        if self.last_content.mode.contains(TermMode::FOCUS_IN_OUT) {
-            self.write_to_pty("\x1b[O".to_string());
+        if self.focused {
+            self.focused = false;
+            self.report_focus();
+        }
+    }
+
+    /// Tells the running program that the terminal gained or lost focus, if it enabled
+    /// focus reporting (`CSI ? 1004 h`). The mode is read from the terminal rather than
+    /// the last synced content, which may be older than the program's request.
+    fn report_focus(&self) {
+        if self.term.lock().mode().contains(TermMode::FOCUS_IN_OUT) {
+            let report = if self.focused { "\x1b[I" } else { "\x1b[O" };
+            self.write_to_pty(report.to_string());
         }
     }
 
@@ -1129,7 +1712,7 @@ impl Terminal {
 
             if self.mouse_changed(point, side) {
                 if let Some(bytes) = mouse_moved_report(point, e, self.last_content.mode) {
-                    self.pty_tx.notify(bytes);
+                    self.backend.write(bytes);
                 }
             }
         } else if self.secondary_pressed {
@@ -1208,7 +1791,7 @@ impl Terminal {
             if let Some(bytes) =
                 mouse_button_report(point, e.button, e.modifiers, true, self.last_content.mode)
             {
-                self.pty_tx.notify(bytes);
+                self.backend.write(bytes);
             }
         } else {
             match e.button {
@@ -1239,8 +1822,7 @@ impl Terminal {
                 #[cfg(target_os = "linux")]
                 MouseButton::Middle => {
                     if let Some(item) = _cx.read_from_primary() {
-                        let text = item.text().to_string();
-                        self.input(text);
+                        self.paste(item.text());
                     }
                 }
                 _ => {}
@@ -1267,7 +1849,7 @@ impl Terminal {
             if let Some(bytes) =
                 mouse_button_report(point, e.button, e.modifiers, false, self.last_content.mode)
             {
-                self.pty_tx.notify(bytes);
+                self.backend.write(bytes);
             }
         } else {
             if e.button == MouseButton::Left && setting.copy_on_select {
@@ -1305,7 +1887,7 @@ impl Terminal {
                 if let Some(scrolls) = scroll_report(point, scroll_lines, e, self.last_content.mode)
                 {
                     for scroll in scrolls {
-                        self.pty_tx.notify(scroll);
+                        self.backend.write(scroll);
                     }
                 };
             } else if self
@@ -1314,7 +1896,7 @@ impl Terminal {
                 .contains(TermMode::ALT_SCREEN | TermMode::ALTERNATE_SCROLL)
                 && !e.shift
             {
-                self.pty_tx.notify(alt_scroll(scroll_lines))
+                self.backend.write(alt_scroll(scroll_lines))
             } else {
                 if scroll_lines != 0 {
                     let scroll = AlacScroll::Delta(scroll_lines);
@@ -1369,8 +1951,31 @@ impl Terminal {
         })
     }
 
+    pub fn title_override(&self) -> Option<&str> {
+        self.title_override.as_deref()
+    }
+
+    /// Pins the title of the terminal, or unpins it when the title is blank.
+    pub fn set_title_override(&mut self, title: Option<String>, cx: &mut ModelContext<Self>) {
+        let title = title
+            .map(|title| title.trim().to_string())
+            .filter(|title| !title.is_empty());
+        if title != self.title_override {
+            self.title_override = title;
+            cx.emit(Event::TitleChanged);
+            cx.emit(Event::BreadcrumbsChanged);
+        }
+    }
+
     pub fn title(&self, truncate: bool) -> String {
         const MAX_CHARS: usize = 25;
+        if let Some(title_override) = &self.title_override {
+            return if truncate {
+                truncate_and_trailoff(title_override, MAX_CHARS)
+            } else {
+                title_override.clone()
+            };
+        }
         match &self.task {
             Some(task_state) => {
                 if truncate {
@@ -1422,16 +2027,52 @@ impl Terminal {
         self.task.as_ref()
     }
 
-    pub fn wait_for_completed_task(&self, cx: &mut AppContext) -> Task<()> {
+    /// The environment the shell was spawned with as `NAME=value` lines, including the
+    /// variables Zed added, e.g. to see why `PATH` differs from another terminal's.
+    pub fn environment_text(&self) -> String {
+        self.spawn_env
+            .iter()
+            .map(|(name, value)| format!("{name}={value}\n"))
+            .collect()
+    }
+
+    /// Estimates how much memory the grid of this terminal holds.
+    pub fn memory_usage(&self) -> TerminalMemoryUsage {
+        let term = self.term.lock();
+        let grid = term.grid();
+        TerminalMemoryUsage::new(grid.history_size(), self.max_scroll_history_lines, grid)
+    }
+
+    /// The text of the scrollback and the screen, or with `last_command`, of the output
+    /// of the last command entered at the prompt, up to the cursor's line. Falls back to
+    /// the whole scrollback when no command was entered, or its line was cleared.
+    pub fn output_text(&self, last_command: bool) -> String {
+        let term = self.term.lock();
+        let shell_marks = self.shell_marks.lock();
+        let output_start = if !last_command {
+            None
+        } else if shell_marks.seen() {
+            shell_marks.output_start(&term)
+        } else {
+            self.prompt_line
+                .map(|line| Line(absolute_to_grid_line(&term, line).0 + 1))
+        };
+        output_text(&term, output_start)
+    }
+
+    /// Waits for the terminal's task to finish, and resolves to how it finished. Resolves
+    /// to `None` right away when the terminal isn't running a task.
+    pub fn wait_for_completed_task(&self, cx: &mut AppContext) -> Task<Option<TaskCompletion>> {
+        if let Some(completion) = &self.task_completion {
+            return Task::ready(Some(completion.clone()));
+        }
         if let Some(task) = self.task() {
             if task.status == TaskStatus::Running {
                 let mut completion_receiver = task.completion_rx.clone();
-                return cx.spawn(|_| async move {
-                    completion_receiver.next().await;
-                });
+                return cx.spawn(|_| async move { completion_receiver.next().await });
             }
         }
-        Task::ready(())
+        Task::ready(None)
     }
 
     fn register_task_finished(
@@ -1439,7 +2080,6 @@ impl Terminal {
         error_code: Option<i32>,
         cx: &mut ModelContext<'_, Terminal>,
     ) {
-        self.completion_tx.try_send(()).ok();
         let task = match &mut self.task {
             Some(task) => task,
             None => {
@@ -1461,16 +2101,146 @@ impl Terminal {
             }
         };
 
+        let completion = TaskCompletion {
+            exit_code: error_code,
+            output_tail: output_tail(&self.term.lock(), TASK_OUTPUT_TAIL_LINES),
+        };
+        self.completion_tx.try_send(completion.clone()).ok();
+        self.task_completion = Some(completion);
+
         let (task_line, command_line) = task_summary(task, error_code);
         // SAFETY: the invocation happens on non `TaskStatus::Running` tasks, once,
         // after either `AlacTermEvent::Exit` or `AlacTermEvent::ChildExit` events that are spawned
         // when Zed task finishes and no more output is made.
         // After the task summary is output once, no more text is appended to the terminal.
         unsafe { append_text_to_term(&mut self.term.lock(), &[&task_line, &command_line]) };
+        self.content_outdated = true;
     }
 }
 
 const TASK_DELIMITER: &str = "⏵ ";
+/// How many of the last lines a task printed are kept in its [`TaskCompletion`].
+const TASK_OUTPUT_TAIL_LINES: usize = 20;
+
+/// Brings `cells`, a snapshot of the viewport taken when its display offset was
+/// `last_display_offset`, up to date with `term`. Only the cells Alacritty reports as
+/// damaged since the last snapshot are copied, unless the viewport moved or was resized.
+fn update_cells<T: EventListener>(
+    cells: &mut Vec<IndexedCell>,
+    last_display_offset: usize,
+    term: &mut Term<T>,
+) {
+    let display_offset = term.grid().display_offset();
+    let columns = term.columns();
+    let damaged_lines = match term.damage() {
+        TermDamage::Full => None,
+        TermDamage::Partial(lines) => Some(lines.collect::<Vec<_>>()),
+    };
+    term.reset_damage();
+
+    match damaged_lines {
+        Some(damaged_lines)
+            if display_offset == last_display_offset
+                && cells.len() == term.screen_lines() * columns =>
+        {
+            for bounds in damaged_lines {
+                let row = &term.grid()[Line(bounds.line as i32 - display_offset as i32)];
+                for column in bounds.left..=bounds.right.min(columns - 1) {
+                    cells[bounds.line * columns + column].cell = row[Column(column)].clone();
+                }
+            }
+        }
+        _ => {
+            *cells = term
+                .renderable_content()
+                .display_iter
+                .map(|ic| IndexedCell {
+                    point: ic.point,
+                    cell: ic.cell.clone(),
+                })
+                .collect();
+        }
+    }
+}
+
+/// The text of the grapheme cluster at `point`.
+fn cursor_text<T: EventListener>(term: &Term<T>, point: AlacPoint) -> String {
+    // Indexing the grid outside of it panics, which would take the whole app down.
+    if point.line < term.topmost_line()
+        || point.line > term.bottommost_line()
+        || point.column.0 >= term.columns()
+    {
+        return String::new();
+    }
+    let row = &term.grid()[point.line];
+    grapheme_cluster((point.column.0..term.columns()).map(|column| &row[Column(column)])).0
+}
+
+/// The lines of the viewport whose previous line soft-wraps onto them.
+fn wrapped_lines<T: EventListener>(term: &Term<T>) -> Vec<Line> {
+    let grid = term.grid();
+    let top_line = Line(-(grid.display_offset() as i32));
+    let last_column = term.last_column();
+    (top_line.0..top_line.0 + term.screen_lines() as i32)
+        .map(Line)
+        .filter(|line| {
+            let previous_line = Line(line.0 - 1);
+            previous_line >= term.topmost_line()
+                && grid[previous_line][last_column]
+                    .flags
+                    .contains(Flags::WRAPLINE)
+        })
+        .collect()
+}
+
+/// The text of up to `line_count` lines up to the cursor's line, with trailing blank
+/// lines removed.
+fn output_tail<T: EventListener>(term: &Term<T>, line_count: usize) -> String {
+    let cursor_line = term.grid().cursor.point.line;
+    let first_line = (cursor_line - line_count.saturating_sub(1) as i32).max(term.topmost_line());
+    let text = term.bounds_to_string(
+        AlacPoint::new(first_line, Column(0)),
+        AlacPoint::new(cursor_line, term.last_column()),
+    );
+    text.trim_end().to_string()
+}
+
+/// The text of the lines from `output_start` to the cursor's line, or if it isn't given or
+/// is no longer in the grid, of all the lines up to the last non-blank one.
+fn output_text<T: EventListener>(term: &Term<T>, output_start: Option<Line>) -> String {
+    let cursor_line = term.grid().cursor.point.line;
+    let range = match output_start {
+        Some(output_start)
+            if output_start >= term.topmost_line() && output_start <= cursor_line =>
+        {
+            output_start..=cursor_line
+        }
+        _ => term.topmost_line()..=term.bottommost_line(),
+    };
+    let text = term.bounds_to_string(
+        AlacPoint::new(*range.start(), Column(0)),
+        AlacPoint::new(*range.end(), term.last_column()),
+    );
+    text.trim_end().to_string()
+}
+
+/// The command typed at the prompt, from the point at which it started to the end of the
+/// cursor's line, including the lines it wrapped onto.
+fn entered_command<T: EventListener>(term: &Term<T>, start: AlacPoint) -> Option<String> {
+    let cursor_line = term.grid().cursor.point.line;
+    if start.line < term.topmost_line() || start.line > cursor_line {
+        return None;
+    }
+    let command = term.bounds_to_string(start, AlacPoint::new(cursor_line, term.last_column()));
+    let command = command.trim();
+    (!command.is_empty()).then(|| command.to_string())
+}
+
+/// The line of the grid that a line counted from the top of the scrollback is on.
+fn absolute_to_grid_line<T: EventListener>(term: &Term<T>, line: usize) -> Line {
+    Line(line as i32 - term.grid().history_size() as i32)
+}
+
 fn task_summary(task: &TaskState, error_code: Option<i32>) -> (String, String) {
     let escaped_full_label = task.full_label.replace("\r\n", "\r").replace('\n', "\r");
     let task_line = match error_code {
@@ -1489,6 +2259,302 @@ fn task_summary(task: &TaskState, error_code: Option<i32>) -> (String, String) {
     (task_line, command_line)
 }
 
+/// The environment a shell is spawned with: the variables it inherits from Zed, overridden
+/// by the ones passed to it, sorted by name.
+fn spawn_environment(
+    inherited: impl IntoIterator<Item = (String, String)>,
+    passed: impl IntoIterator<Item = (String, String)>,
+) -> Vec<(String, String)> {
+    let env = inherited
+        .into_iter()
+        .chain(passed)
+        .collect::<BTreeMap<_, _>>();
+    env.into_iter().collect()
+}
+
+/// The lines shown on the screen, counted from the oldest one in the scrollback.
+fn viewport<T: EventListener>(term: &Term<T>) -> Range<usize> {
+    let grid = term.grid();
+    let start = grid.history_size() - grid.display_offset();
+    start..start + grid.screen_lines()
+}
+
+/// A scroll position and a selection, with lines counted from the first line of output,
+/// including the lines a full scrollback dropped since, so that they stay put as output
+/// is added below them.
+#[derive(Debug, Clone, PartialEq)]
+struct SavedViewport {
+    /// The line at the top of the viewport, or `None` when scrolled to the bottom, which
+    /// follows the output.
+    top_line: Option<usize>,
+    selection: Option<SavedSelection>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct SavedSelection {
+    start: (usize, Column),
+    end: (usize, Column),
+    head: Option<(usize, Column)>,
+    /// The selected text, to check the lines weren't cleared or rewritten before the
+    /// selection is put back.
+    text: String,
+}
+
+/// Saves the viewport of `term`, from which `dropped_lines` lines of output were dropped.
+fn save_viewport<T: EventListener>(
+    term: &Term<T>,
+    selection_head: Option<AlacPoint>,
+    dropped_lines: usize,
+) -> Option<SavedViewport> {
+    // The alternate screen has no scrollback, and its programs redraw it on resize.
+    if term.mode().contains(TermMode::ALT_SCREEN) {
+        return None;
+    }
+    let history_size = term.grid().history_size() as i32;
+    let absolute = |point: AlacPoint| {
+        let line = dropped_lines + (history_size + point.line.0) as usize;
+        (line, point.column)
+    };
+
+    let top_line = (term.grid().display_offset() > 0).then(|| dropped_lines + viewport(term).start);
+    let selection = term
+        .selection
+        .as_ref()
+        .and_then(|selection| selection.to_range(term))
+        .filter(|range| !range.is_block)
+        .map(|range| SavedSelection {
+            start: absolute(range.start),
+            end: absolute(range.end),
+            head: selection_head.map(absolute),
+            text: term.bounds_to_string(range.start, range.end),
+        });
+    Some(SavedViewport {
+        top_line,
+        selection,
+    })
+}
+
+/// Scrolls back to a saved position and selects the saved text again if the selection
+/// was lost, returning the head of the restored selection. `dropped_lines` is how many
+/// lines of output were dropped from `term` in all, so a position whose lines have been
+/// dropped since it was saved scrolls to the top, and its selection isn't put back.
+fn restore_viewport<T: EventListener>(
+    term: &mut Term<T>,
+    saved: &SavedViewport,
+    dropped_lines: usize,
+) -> Option<AlacPoint> {
+    if term.mode().contains(TermMode::ALT_SCREEN) {
+        return None;
+    }
+    if let Some(top_line) = saved.top_line {
+        scroll_to_line(term, top_line.saturating_sub(dropped_lines));
+    }
+
+    let saved_selection = saved
+        .selection
+        .as_ref()
+        .filter(|_| term.selection.is_none())?;
+    let history_size = term.grid().history_size() as i32;
+    let topmost_line = term.topmost_line();
+    let point = |(line, column): (usize, Column)| {
+        let line = Line(line.checked_sub(dropped_lines)? as i32 - history_size);
+        (line >= topmost_line).then(|| AlacPoint::new(line, column))
+    };
+    let (start, end) = (point(saved_selection.start)?, point(saved_selection.end)?);
+    if term.bounds_to_string(start, end) != saved_selection.text {
+        return None;
+    }
+    term.selection = Some(make_selection(&(start..=end)));
+    saved_selection.head.and_then(point)
+}
+
+fn scroll_to_line<T: EventListener>(term: &mut Term<T>, line: usize) {
+    let grid = term.grid();
+    let display_offset = grid.history_size().saturating_sub(line);
+    let delta = display_offset as i32 - grid.display_offset() as i32;
+    term.scroll_display(AlacScroll::Delta(delta));
+}
+
+/// Clears the scrollback and the screen, except for the lines from `first_line` to the
+/// cursor's line, which are moved to the top of the screen. Returns the first line that
+/// was kept, as no more lines than fit on the screen are.
+fn clear_above<T: EventListener>(term: &mut Term<T>, first_line: Line) -> Line {
+    let cursor = term.grid().cursor.point;
+    let columns = term.grid().columns();
+    let first_line = Line(
+        first_line
+            .0
+            .max(cursor.line.0 + 1 - term.screen_lines() as i32)
+            .min(cursor.line.0),
+    );
+    let kept_lines = (first_line.0..=cursor.line.0)
+        .map(|line| term.grid()[Line(line)][..Column(columns)].to_vec())
+        .collect::<Vec<Vec<Cell>>>();
+
+    // Clear back buffer
+    term.clear_screen(ClearMode::Saved);
+    term.grid_mut().reset_region(..);
+
+    for (line, cells) in kept_lines.iter().enumerate() {
+        for (column, cell) in cells.iter().enumerate() {
+            term.grid_mut()[Line(line as i32)][Column(column)] = cell.clone();
+        }
+    }
+
+    // Reset the cursor
+    term.grid_mut().cursor.point = AlacPoint::new(Line(kept_lines.len() as i32 - 1), cursor.column);
+    first_line
+}
+
+/// Resizes the terminal's grid. When the number of columns changes, Alacritty rewraps the
+/// lines of the primary screen and its scrollback but clears the selection, so the
+/// selection is moved to where its text was rewrapped instead.
+fn resize_term<T: EventListener>(
+    term: &mut Term<T>,
+    new_size: TerminalSize,
+    selection_head: &mut Option<AlacPoint>,
+) {
+    let reflows =
+        term.columns() != new_size.columns() && !term.mode().contains(TermMode::ALT_SCREEN);
+    let unwrapped_selection = term
+        .selection
+        .as_ref()
+        .filter(|_| reflows)
+        .and_then(|selection| selection.to_range(term))
+        .filter(|range| !range.is_block)
+        .map(|range| {
+            (
+                unwrap_point(term, range.start),
+                unwrap_point(term, range.end),
+            )
+        });
+    let unwrapped_head = selection_head
+        .filter(|_| unwrapped_selection.is_some())
+        .map(|head| unwrap_point(term, head));
+
+    term.resize(new_size);
+
+    if let Some((start, end)) = unwrapped_selection {
+        if let (Some(start), Some(end)) = (wrap_point(term, start), wrap_point(term, end)) {
+            term.selection = Some(make_selection(&(start..=end)));
+            *selection_head = unwrapped_head.and_then(|head| wrap_point(term, head));
+        }
+    }
+}
+
+/// Where a point is in the terminal's content regardless of how its lines are wrapped.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct UnwrappedPoint {
+    /// How many logical lines the point's line is below the cursor's, which is negative
+    /// for lines above it. Rewrapping lines preserves the content around the cursor.
+    lines_from_cursor: i32,
+    /// The point's column in its logical line.
+    column: usize,
+}
+
+fn unwrap_point<T>(term: &Term<T>, point: AlacPoint) -> UnwrappedPoint {
+    let line_start = term.line_search_left(point).line;
+    let column = (point.line.0 - line_start.0) as usize * term.columns() + point.column.0;
+
+    let cursor_line_start = term.line_search_left(term.grid().cursor.point).line;
+    let (mut line, end_line) = if line_start <= cursor_line_start {
+        (line_start, cursor_line_start)
+    } else {
+        (cursor_line_start, line_start)
+    };
+    let mut line_count = 0;
+    while line < end_line {
+        line = term.line_search_right(AlacPoint::new(line, Column(0))).line + 1;
+        line_count += 1;
+    }
+
+    UnwrappedPoint {
+        lines_from_cursor: if line_start <= cursor_line_start {
+            -line_count
+        } else {
+            line_count
+        },
+        column,
+    }
+}
+
+/// Finds an unwrapped point in the terminal's current lines, if its line still exists.
+fn wrap_point<T>(term: &Term<T>, point: UnwrappedPoint) -> Option<AlacPoint> {
+    let mut line_start = term.line_search_left(term.grid().cursor.point).line;
+    for _ in 0..point.lines_from_cursor.unsigned_abs() {
+        if point.lines_from_cursor < 0 {
+            if line_start <= term.topmost_line() {
+                return None;
+            }
+            line_start = term
+                .line_search_left(AlacPoint::new(line_start - 1, Column(0)))
+                .line;
+        } else {
+            line_start = term
+                .line_search_right(AlacPoint::new(line_start, Column(0)))
+                .line
+                + 1;
+            if line_start > term.bottommost_line() {
+                return None;
+            }
+        }
+    }
+
+    let columns = term.columns();
+    let line_end = term.line_search_right(AlacPoint::new(line_start, Column(0)));
+    let point = AlacPoint::new(
+        Line(line_start.0 + (point.column / columns) as i32),
+        Column(point.column % columns),
+    );
+    Some(point.min(line_end))
+}
+
+/// Changes text pasted from the clipboard according to the `paste` settings.
+pub fn transform_pasted_text(text: &str, settings: PasteSettings) -> String {
+    let mut text = if settings.convert_crlf {
+        text.replace("\r\n", "\n")
+    } else {
+        text.to_string()
+    };
+
+    if settings.trim_indentation && text.trim_end().contains('\n') {
+        let indent_len = |line: &str| line.len() - line.trim_start_matches([' ', '\t']).len();
+        let common_indent_len = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(indent_len)
+            .min()
+            .unwrap_or(0);
+        text = text
+            .split_inclusive('\n')
+            .map(|line| &line[indent_len(line).min(common_indent_len)..])
+            .collect();
+    }
+
+    if settings.strip_trailing_newline {
+        let line_len = text
+            .strip_suffix("\r\n")
+            .or_else(|| text.strip_suffix('\n'))
+            .filter(|line| !line.contains('\n'))
+            .map(str::len);
+        if let Some(line_len) = line_len {
+            text.truncate(line_len);
+        }
+    }
+    text
+}
+
+/// Converts pasted text to what's written to the pty. With bracketed paste, escape
+/// characters are removed so the text can't end the paste early. Without it, newlines
+/// are sent as carriage returns, like the Enter key.
+fn paste_text(text: &str, mode: TermMode) -> String {
+    if mode.contains(TermMode::BRACKETED_PASTE) {
+        format!("{}{}{}", "\x1b[200~", text.replace('\x1b', ""), "\x1b[201~")
+    } else {
+        text.replace("\r\n", "\r").replace('\n', "\r")
+    }
+}
+
 /// Appends a stringified task summary to the terminal, after its output.
 ///
 /// SAFETY: This function should only be called after terminal's PTY is no longer alive.
@@ -1528,7 +2594,7 @@ unsafe fn append_text_to_term(term: &mut Term<ZedListener>, text_lines: &[&str])
 
 impl Drop for Terminal {
     fn drop(&mut self) {
-        self.pty_tx.0.send(Msg::Shutdown).ok();
+        self.backend.shutdown();
     }
 }
 
@@ -1617,9 +2683,10 @@ pub fn get_color_at_index(index: usize, theme: &Theme) -> Hsla {
             let step = (u8::MAX as f32 / 24.).floor() as u8; // Split the RGB grayscale values into 24 chunks
             rgba_color(i * step, i * step, i * step) // Map the ANSI-grayscale components to the RGB-grayscale
         }
-        // For compatibility with the alacritty::Colors interface
+        // For compatibility with the alacritty::Colors interface. These are also the
+        // answers to OSC 10/11/12 queries, so they must match what the terminal paints.
         256 => colors.text,
-        257 => colors.background,
+        257 => colors.terminal_background,
         258 => theme.players().local().cursor,
         259 => colors.terminal_ansi_dim_black,
         260 => colors.terminal_ansi_dim_red,
@@ -1668,16 +2735,86 @@ pub fn rgba_color(r: u8, g: u8, b: u8) -> Hsla {
 #[cfg(test)]
 mod tests {
     use alacritty_terminal::{
+        event::VoidListener,
+        grid::{Dimensions, Scroll as AlacScroll},
         index::{Column, Line, Point as AlacPoint},
-        term::cell::Cell,
+        term::{cell::Cell, Config, Term, TermMode},
+        vte::ansi::Processor,
+    };
+    use gpui::{
+        point, px, size, Context as _, Modifiers, MouseButton, MouseDownEvent, Pixels,
+        TestAppContext,
     };
-    use gpui::{point, size, Pixels};
+    use parking_lot::Mutex;
     use rand::{distributions::Alphanumeric, rngs::ThreadRng, thread_rng, Rng};
+    use std::sync::Arc;
 
     use crate::{
-        content_index_for_mouse, rgb_for_index, IndexedCell, TerminalContent, TerminalSize,
+        clear_above, content_index_for_mouse, cursor_text, entered_command, grapheme_cluster,
+        make_selection, output_tail, output_text, paste_text, resize_term, restore_viewport,
+        rgb_for_index, save_viewport, scroll_to_line,
+        shell_marks::{MarkedParser, ShellMarks, SCROLLBACK_SLACK},
+        spawn_environment,
+        terminal_settings::{AlternateScroll, PasteSettings},
+        transform_pasted_text, update_cells, viewport, wrapped_lines, IndexedCell, TerminalBackend,
+        TerminalBuilder, TerminalContent, TerminalMemoryUsage, TerminalSize,
     };
 
+    struct NullBackend;
+
+    impl TerminalBackend for NullBackend {
+        fn write(&self, _: Vec<u8>) {}
+        fn resize(&self, _: TerminalSize) {}
+        fn shutdown(&self) {}
+    }
+
+    #[gpui::test]
+    fn test_selection_changes_clear_selected_matches(cx: &mut TestAppContext) {
+        let terminal = cx.new_model(|cx| {
+            TerminalBuilder::new_detached(Box::new(NullBackend), None, AlternateScroll::On, None)
+                .subscribe(cx)
+        });
+        let first = AlacPoint::new(Line(0), Column(0))..=AlacPoint::new(Line(0), Column(2));
+        let second = AlacPoint::new(Line(0), Column(8))..=AlacPoint::new(Line(0), Column(10));
+        terminal.update(cx, |terminal, cx| {
+            terminal.write_output(b"foo bar foo", cx);
+            terminal.matches = vec![first.clone(), second.clone()];
+
+            terminal.select_matches(&[first.clone(), second.clone()]);
+            terminal.sync(cx);
+            assert_eq!(terminal.selected_matches(), [first.clone(), second.clone()]);
+
+            // A click starts a new selection.
+            terminal.mouse_down(
+                &MouseDownEvent {
+                    button: MouseButton::Left,
+                    position: point(px(0.), px(0.)),
+                    modifiers: Modifiers::default(),
+                    click_count: 1,
+                    first_mouse: false,
+                },
+                point(px(0.), px(0.)),
+                cx,
+            );
+            terminal.sync(cx);
+            assert!(terminal.selected_matches().is_empty());
+
+            // Typing clears the selection.
+            terminal.select_matches(&[first.clone(), second.clone()]);
+            terminal.sync(cx);
+            assert_eq!(terminal.selected_matches().len(), 2);
+            terminal.input("x".to_string());
+            terminal.sync(cx);
+            assert!(terminal.selected_matches().is_empty());
+
+            // Dismissing the search forgets the matches.
+            terminal.select_matches(&[first.clone(), second.clone()]);
+            terminal.sync(cx);
+            terminal.clear_matches();
+            assert!(terminal.selected_matches().is_empty());
+        });
+    }
+
     #[test]
     fn test_rgb_for_index() {
         // Test every possible value in the color cube.
@@ -1687,6 +2824,404 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_synchronized_updates_are_applied_at_once() {
+        // Alacritty's parser holds back output between the start and the end of a
+        // synchronized update (DEC mode 2026), so a frame never shows half of a redraw.
+        let mut term = Term::new(Config::default(), &TerminalSize::default(), VoidListener);
+        let mut processor: Processor = Processor::new();
+        let mut advance = |term: &mut Term<VoidListener>, bytes: &[u8]| {
+            for byte in bytes {
+                processor.advance(term, *byte);
+            }
+        };
+
+        advance(&mut term, b"\x1b[?2026hredraw");
+        assert_eq!(term.grid()[Line(0)][Column(0)].c, ' ');
+        advance(&mut term, b"\x1b[?2026l");
+        assert_eq!(term.grid()[Line(0)][Column(0)].c, 'r');
+    }
+
+    #[test]
+    fn test_clear_above() {
+        let mut term = Term::new(Config::default(), &TerminalSize::default(), VoidListener);
+        let mut processor: Processor = Processor::new();
+        for byte in b"one\r\ntwo\r\nthree" {
+            processor.advance(&mut term, *byte);
+        }
+
+        assert_eq!(clear_above(&mut term, Line(1)), Line(1));
+        assert_eq!(term.grid()[Line(0)][Column(0)].c, 't');
+        assert_eq!(term.grid()[Line(0)][Column(1)].c, 'w');
+        assert_eq!(term.grid()[Line(1)][Column(0)].c, 't');
+        assert_eq!(term.grid()[Line(1)][Column(1)].c, 'h');
+        assert_eq!(term.grid().cursor.point, AlacPoint::new(Line(1), Column(5)));
+        assert_eq!(term.grid()[Line(2)][Column(0)].c, ' ');
+    }
+
+    #[test]
+    fn test_grapheme_cluster() {
+        let mut term = Term::new(Config::default(), &TerminalSize::default(), VoidListener);
+        let mut processor: Processor = Processor::new();
+        for byte in "e\u{301}\u{1f468}\u{200d}\u{1f469}\u{2764}\u{fe0f}!".as_bytes() {
+            processor.advance(&mut term, *byte);
+        }
+
+        let row = &term.grid()[Line(0)];
+        let cluster_at = |column: usize| {
+            grapheme_cluster((column..term.columns()).map(|column| &row[Column(column)]))
+        };
+        assert_eq!(cluster_at(0), ("e\u{301}".to_string(), 1));
+        assert_eq!(cluster_at(1), ("\u{1f468}\u{200d}\u{1f469}".to_string(), 4));
+        assert_eq!(cluster_at(5), ("\u{2764}\u{fe0f}".to_string(), 1));
+        assert_eq!(cluster_at(6), ("!".to_string(), 1));
+
+        assert_eq!(cursor_text(&term, AlacPoint::new(Line(0), Column(6))), "!");
+        let below_grid = Line(term.screen_lines() as i32);
+        assert_eq!(
+            cursor_text(&term, AlacPoint::new(below_grid, Column(0))),
+            ""
+        );
+        let past_last_column = Column(term.columns());
+        assert_eq!(
+            cursor_text(&term, AlacPoint::new(Line(0), past_last_column)),
+            ""
+        );
+    }
+
+    #[test]
+    fn test_scroll_to_line() {
+        let mut term = Term::new(Config::default(), &TerminalSize::default(), VoidListener);
+        let mut processor: Processor = Processor::new();
+        for line in 0..100 {
+            for byte in format!("{line}\r\n").as_bytes() {
+                processor.advance(&mut term, *byte);
+            }
+        }
+
+        let screen_lines = term.screen_lines();
+        let history_size = term.grid().history_size();
+        assert_eq!(viewport(&term), history_size..history_size + screen_lines);
+
+        scroll_to_line(&mut term, 10);
+        assert_eq!(viewport(&term), 10..10 + screen_lines);
+        assert_eq!(term.grid()[Line(0)][Column(0)].c, '1');
+        assert_eq!(term.grid()[Line(0)][Column(1)].c, '0');
+
+        scroll_to_line(&mut term, 0);
+        assert_eq!(viewport(&term), 0..screen_lines);
+
+        // Lines past the end of the scrollback scroll to the bottom.
+        scroll_to_line(&mut term, history_size + 10);
+        assert_eq!(viewport(&term), history_size..history_size + screen_lines);
+    }
+
+    #[test]
+    fn test_restore_viewport() {
+        let mut term = Term::new(Config::default(), &TerminalSize::default(), VoidListener);
+        let mut processor: Processor = Processor::new();
+        let mut advance = |term: &mut Term<VoidListener>, text: String| {
+            for byte in text.as_bytes() {
+                processor.advance(term, *byte);
+            }
+        };
+        for line in 0..100 {
+            advance(&mut term, format!("line {line}\r\n"));
+        }
+        scroll_to_line(&mut term, 10);
+        let history_size = term.grid().history_size() as i32;
+        let start = AlacPoint::new(Line(11 - history_size), Column(0));
+        let end = AlacPoint::new(Line(12 - history_size), Column(6));
+        term.selection = Some(make_selection(&(start..=end)));
+        let saved = save_viewport(&term, Some(end), 0).unwrap();
+        assert_eq!(saved.top_line, Some(10));
+
+        // Output added while hidden, then the viewport and the selection were reset.
+        advance(&mut term, "more\r\n".to_string());
+        term.scroll_display(AlacScroll::Bottom);
+        term.selection = None;
+
+        let head = restore_viewport(&mut term, &saved, 0).unwrap();
+        assert_eq!(viewport(&term).start, 10);
+        assert_eq!(
+            term.selection_to_string().as_deref(),
+            Some("line 11\nline 12")
+        );
+        assert_eq!(term.bounds_to_string(head, head), "2");
+
+        // A selection in a scrollback that was cleared isn't put back.
+        term.selection = None;
+        term.grid_mut().clear_history();
+        assert_eq!(restore_viewport(&mut term, &saved, 0), None);
+        assert_eq!(term.selection, None);
+
+        // Scrolled to the bottom, the terminal keeps following the output.
+        term.scroll_display(AlacScroll::Bottom);
+        let saved = save_viewport(&term, None, 0).unwrap();
+        assert_eq!(saved.top_line, None);
+        advance(&mut term, "newest\r\n".to_string());
+        restore_viewport(&mut term, &saved, 0);
+        assert_eq!(term.grid().display_offset(), 0);
+    }
+
+    #[test]
+    fn test_restore_viewport_in_full_scrollback() {
+        let max_history_lines = 50;
+        let config = Config {
+            scrolling_history: max_history_lines + SCROLLBACK_SLACK,
+            ..Config::default()
+        };
+        let mut term = Term::new(config, &TerminalSize::default(), VoidListener);
+        let marks = Arc::new(Mutex::new(ShellMarks::default()));
+        let mut parser = MarkedParser::new(marks.clone(), max_history_lines);
+        let output = (0..100)
+            .map(|line| format!("line {line}\r\n"))
+            .collect::<String>();
+        parser.advance(&mut term, output.as_bytes());
+        assert_eq!(term.grid().history_size(), max_history_lines);
+
+        let top_line = |term: &Term<VoidListener>| {
+            let top = Line(-(term.grid().display_offset() as i32));
+            (
+                AlacPoint::new(top, Column(0)),
+                AlacPoint::new(top, Column(6)),
+            )
+        };
+        scroll_to_line(&mut term, 10);
+        let (start, end) = top_line(&term);
+        let saved_text = term.bounds_to_string(start, end);
+        term.selection = Some(make_selection(&(start..=end)));
+        let saved = save_viewport(&term, None, marks.lock().dropped_lines()).unwrap();
+
+        // The output added while hidden drops the oldest lines of the full scrollback.
+        parser.advance(&mut term, b"more\r\nand more\r\n");
+        term.scroll_display(AlacScroll::Bottom);
+        term.selection = None;
+
+        restore_viewport(&mut term, &saved, marks.lock().dropped_lines());
+        let (start, end) = top_line(&term);
+        assert_eq!(term.bounds_to_string(start, end), saved_text);
+        assert_eq!(term.selection_to_string(), Some(saved_text));
+
+        // Once the saved lines are dropped too, the selection isn't put back.
+        parser.advance(&mut term, "\r\n".repeat(max_history_lines).as_bytes());
+        term.scroll_display(AlacScroll::Bottom);
+        term.selection = None;
+        restore_viewport(&mut term, &saved, marks.lock().dropped_lines());
+        assert_eq!(term.selection, None);
+        assert_eq!(viewport(&term).start, 0);
+    }
+
+    #[test]
+    fn test_output_tail() {
+        let mut term = Term::new(Config::default(), &TerminalSize::default(), VoidListener);
+        let mut processor: Processor = Processor::new();
+        for byte in b"one\r\ntwo\r\nthree\r\n\r\n" {
+            processor.advance(&mut term, *byte);
+        }
+
+        assert_eq!(output_tail(&term, 4), "two\nthree");
+        assert_eq!(output_tail(&term, 100), "one\ntwo\nthree");
+    }
+
+    #[test]
+    fn test_output_text() {
+        let mut term = Term::new(Config::default(), &TerminalSize::default(), VoidListener);
+        let mut processor: Processor = Processor::new();
+        for byte in b"$ ls\r\na.txt\r\n$ cat a.txt\r\nhello\r\n" {
+            processor.advance(&mut term, *byte);
+        }
+
+        assert_eq!(output_text(&term, Some(Line(3))), "hello");
+        assert_eq!(output_text(&term, None), "$ ls\na.txt\n$ cat a.txt\nhello");
+        assert_eq!(
+            output_text(&term, Some(Line(5))),
+            "$ ls\na.txt\n$ cat a.txt\nhello"
+        );
+    }
+
+    #[test]
+    fn test_wrapped_lines() {
+        let mut term = Term::new(Config::default(), &TerminalSize::default(), VoidListener);
+        let mut processor: Processor = Processor::new();
+        let long_line = "x".repeat(term.columns() * 2 + 1);
+        for byte in format!("short\r\n{long_line}\r\nshort").as_bytes() {
+            processor.advance(&mut term, *byte);
+        }
+
+        assert_eq!(wrapped_lines(&term), vec![Line(2), Line(3)]);
+        // Wrapped lines are copied as one logical line.
+        assert_eq!(
+            output_text(&term, None),
+            format!("short\n{long_line}\nshort")
+        );
+    }
+
+    #[test]
+    fn test_update_cells() {
+        let mut term = Term::new(Config::default(), &TerminalSize::default(), VoidListener);
+        let mut processor: Processor = Processor::new();
+        let full_snapshot = |term: &Term<VoidListener>| {
+            term.renderable_content()
+                .display_iter
+                .map(|ic| (ic.point, ic.cell.c))
+                .collect::<Vec<_>>()
+        };
+        let snapshot =
+            |cells: &[IndexedCell]| cells.iter().map(|ic| (ic.point, ic.c)).collect::<Vec<_>>();
+
+        let mut cells = Vec::new();
+        for byte in b"first\r\nsecond" {
+            processor.advance(&mut term, *byte);
+        }
+        update_cells(&mut cells, 0, &mut term);
+        assert_eq!(snapshot(&cells), full_snapshot(&term));
+
+        // The lines written to since the last snapshot are copied into it.
+        for byte in b"\r\nthird\x1b[1;1Hfist" {
+            processor.advance(&mut term, *byte);
+        }
+        update_cells(&mut cells, 0, &mut term);
+        assert_eq!(snapshot(&cells), full_snapshot(&term));
+        assert_eq!(cells[1].c, 'i');
+        assert_eq!(cells[3].c, 't');
+
+        // When the viewport moves, the whole snapshot is taken again.
+        for _ in 0..term.screen_lines() {
+            processor.advance(&mut term, b'\n');
+        }
+        term.scroll_display(AlacScroll::Delta(2));
+        update_cells(&mut cells, 0, &mut term);
+        assert_eq!(snapshot(&cells), full_snapshot(&term));
+    }
+
+    #[test]
+    fn test_entered_command() {
+        let mut term = Term::new(Config::default(), &TerminalSize::default(), VoidListener);
+        let mut processor: Processor = Processor::new();
+        for byte in b"output\r\n~/zed $ cargo  test -p terminal " {
+            processor.advance(&mut term, *byte);
+        }
+
+        assert_eq!(
+            entered_command(&term, AlacPoint::new(Line(1), Column(8))).as_deref(),
+            Some("cargo  test -p terminal")
+        );
+        assert_eq!(
+            entered_command(&term, AlacPoint::new(Line(2), Column(0))),
+            None
+        );
+        assert_eq!(
+            entered_command(&term, AlacPoint::new(Line(1), Column(40))),
+            None
+        );
+    }
+
+    #[test]
+    fn test_resize_reflows_selection() {
+        let narrow = TerminalSize::new(px(10.), px(10.), size(px(100.), px(50.)));
+        let wide = TerminalSize::new(px(10.), px(10.), size(px(200.), px(50.)));
+        let mut term = Term::new(Config::default(), &narrow, VoidListener);
+        let mut processor: Processor = Processor::new();
+        for byte in b"0123456789abcdefghij\r\n$ " {
+            processor.advance(&mut term, *byte);
+        }
+        let mut selection_head = Some(AlacPoint::new(Line(1), Column(4)));
+        term.selection = Some(make_selection(
+            &(AlacPoint::new(Line(1), Column(0))..=AlacPoint::new(Line(1), Column(4))),
+        ));
+
+        resize_term(&mut term, wide, &mut selection_head);
+        assert_eq!(term.selection_to_string().as_deref(), Some("abcde"));
+        let selection_end = term
+            .selection
+            .as_ref()
+            .unwrap()
+            .to_range(&term)
+            .unwrap()
+            .end;
+        assert_eq!(selection_head, Some(selection_end));
+
+        resize_term(&mut term, narrow, &mut selection_head);
+        assert_eq!(term.selection_to_string().as_deref(), Some("abcde"));
+        let selection_end = term
+            .selection
+            .as_ref()
+            .unwrap()
+            .to_range(&term)
+            .unwrap()
+            .end;
+        assert_eq!(selection_head, Some(selection_end));
+    }
+
+    #[test]
+    fn test_paste_text() {
+        assert_eq!(paste_text("ls\r\npwd\n", TermMode::empty()), "ls\rpwd\r");
+        assert_eq!(
+            paste_text("ls\n\x1b[201~rm -rf /\n", TermMode::BRACKETED_PASTE),
+            "\x1b[200~ls\n[201~rm -rf /\n\x1b[201~"
+        );
+    }
+
+    #[test]
+    fn test_spawn_environment() {
+        let env = |vars: &[(&str, &str)]| {
+            vars.iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            spawn_environment(
+                env(&[("PATH", "/usr/bin"), ("HOME", "/home/user")]),
+                env(&[("ZED_TERM", "true"), ("PATH", "/opt/bin:/usr/bin")]),
+            ),
+            env(&[
+                ("HOME", "/home/user"),
+                ("PATH", "/opt/bin:/usr/bin"),
+                ("ZED_TERM", "true"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_transform_pasted_text() {
+        let settings = PasteSettings {
+            strip_trailing_newline: true,
+            convert_crlf: true,
+            trim_indentation: true,
+        };
+        assert_eq!(transform_pasted_text("ls -la\r\n", settings), "ls -la");
+        assert_eq!(transform_pasted_text("ls\npwd\n", settings), "ls\npwd\n");
+        assert_eq!(
+            transform_pasted_text("    if x:\r\n\r\n        y()\r\n", settings),
+            "if x:\n\n    y()\n"
+        );
+        assert_eq!(transform_pasted_text("    x = 1\n", settings), "    x = 1");
+
+        let settings = PasteSettings {
+            strip_trailing_newline: false,
+            convert_crlf: false,
+            trim_indentation: false,
+        };
+        assert_eq!(
+            transform_pasted_text("  ls\r\n  pwd\r\n", settings),
+            "  ls\r\n  pwd\r\n"
+        );
+    }
+
+    #[test]
+    fn test_memory_usage() {
+        let size = TerminalSize::new(
+            Pixels::from(10.),
+            Pixels::from(5.),
+            size(Pixels::from(400.), Pixels::from(240.)),
+        );
+        let usage = TerminalMemoryUsage::new(1_000, 10_000, &size);
+        assert_eq!(usage.screen_lines, 24);
+        assert_eq!(usage.columns, 80);
+        assert_eq!(usage.grid_bytes, 1_024 * 80 * std::mem::size_of::<Cell>());
+    }
+
     #[test]
     fn test_mouse_to_cell_test() {
         let mut rng = thread_rng();
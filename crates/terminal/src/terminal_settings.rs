@@ -26,6 +26,8 @@ pub struct Toolbar {
 #[derive(Deserialize)]
 pub struct TerminalSettings {
     pub shell: Shell,
+    pub login_shell: bool,
+    pub shell_args: Vec<String>,
     pub working_directory: WorkingDirectory,
     pub font_size: Option<Pixels>,
     pub font_family: Option<String>,
@@ -42,8 +44,15 @@ pub struct TerminalSettings {
     pub default_width: Pixels,
     pub default_height: Pixels,
     pub detect_venv: VenvSettings,
+    pub activation_script: Option<String>,
     pub max_scroll_history_lines: Option<usize>,
     pub toolbar: Toolbar,
+    pub paste: PasteSettings,
+    pub alert_on_bell: AlertOnBell,
+    pub background_opacity: f32,
+    pub show_wrap_indicator: bool,
+    pub clear_line_before_sending: bool,
+    pub tmux_command: Vec<String>,
 }
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
@@ -96,6 +105,16 @@ pub struct TerminalSettingsContent {
     ///
     /// Default: system
     pub shell: Option<Shell>,
+    /// Whether to start the shell as a login shell, passing it `-l`, so that it reads
+    /// the profile files that often add to `PATH`. Has no effect on Windows.
+    ///
+    /// Default: false
+    pub login_shell: Option<bool>,
+    /// Arguments passed to the shell before those given by the `shell` setting. They
+    /// apply to the `system` shell as well, which takes no arguments of its own.
+    ///
+    /// Default: []
+    pub shell_args: Option<Vec<String>>,
     /// What working directory to use when launching the terminal
     ///
     /// Default: current_project_directory
@@ -161,6 +180,12 @@ pub struct TerminalSettingsContent {
     ///
     /// Default: on
     pub detect_venv: Option<VenvSettings>,
+    /// A command to run in each new terminal once its shell started, to set up the
+    /// project's environment, e.g. `source .venv/bin/activate` or `nvm use`. Best set
+    /// in the project's settings. Not run in terminals that run tasks.
+    ///
+    /// Default: null
+    pub activation_script: Option<String>,
     /// The maximum number of lines to keep in the scrollback history.
     /// Maximum allowed value is 100_000, all values above that will be treated as 100_000.
     /// 0 disables the scrolling.
@@ -171,6 +196,68 @@ pub struct TerminalSettingsContent {
     pub max_scroll_history_lines: Option<usize>,
     /// Toolbar related settings
     pub toolbar: Option<ToolbarContent>,
+    /// How text pasted from the clipboard is changed before it's sent to the terminal
+    pub paste: Option<PasteSettingsContent>,
+    /// How a terminal alerts the user when a program running in it rings the bell.
+    /// Each terminal can override this from its context menu.
+    ///
+    /// Default: visual
+    pub alert_on_bell: Option<AlertOnBell>,
+    /// The opacity of the terminal's background, between 0 (transparent) and 1
+    /// (opaque), letting what's behind the terminal show through. To blur it as
+    /// well, set the theme's `window_background_appearance` to `blurred` on
+    /// platforms that support it.
+    ///
+    /// Default: 1.0
+    pub background_opacity: Option<f32>,
+    /// Whether to show an indicator in the margin next to lines that continue a
+    /// line too long to fit in the terminal.
+    ///
+    /// Default: false
+    pub show_wrap_indicator: Option<bool>,
+    /// Whether to clear what's typed at the prompt before Zed sends a command to the
+    /// terminal, e.g. from the command history, so that the command isn't appended
+    /// to half-typed text. The line is cleared with ctrl-e ctrl-u, which most shells
+    /// understand.
+    ///
+    /// Default: true
+    pub clear_line_before_sending: Option<bool>,
+    /// The command that `terminal_panel: attach tmux session` runs to start tmux in
+    /// control mode, showing each window of the session in a tab of the terminal panel,
+    /// with its panes split inside it.
+    /// Run it through ssh, e.g. `["ssh", "host", "tmux", "-C", "attach"]`, for a
+    /// session on another machine.
+    ///
+    /// Default: ["tmux", "-C", "new-session", "-A", "-s", "zed"]
+    pub tmux_command: Option<Vec<String>>,
+}
+
+impl TerminalSettings {
+    /// The shell that terminals are opened with, with the `login_shell` and `shell_args`
+    /// settings applied to it.
+    pub fn shell_with_args(&self) -> Shell {
+        let login_arg = (self.login_shell && cfg!(unix)).then(|| "-l".to_string());
+        let extra_args = login_arg
+            .into_iter()
+            .chain(self.shell_args.iter().cloned())
+            .collect::<Vec<_>>();
+        if extra_args.is_empty() {
+            return self.shell.clone();
+        }
+
+        let (program, mut args) = match &self.shell {
+            // Alacritty looks the system shell up itself, so it can't be given arguments.
+            Shell::System => match std::env::var("SHELL") {
+                Ok(program) => (program, Vec::new()),
+                Err(_) => return Shell::System,
+            },
+            Shell::Program(program) => (program.clone(), Vec::new()),
+            Shell::WithArguments { program, args } => (program.clone(), args.clone()),
+        };
+        // Options have to precede operands like a script or the command of `-c`.
+        args.splice(0..0, extra_args);
+        Shell::WithArguments { program, args }
+    }
 }
 
 impl settings::Settings for TerminalSettings {
@@ -256,6 +343,21 @@ pub enum TerminalBlink {
     On,
 }
 
+#[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertOnBell {
+    /// Ignore the bell.
+    None,
+    /// Show a bell in the terminal's tab until it receives input.
+    #[default]
+    Visual,
+    /// Play a sound, in addition to showing a bell in the terminal's tab.
+    Audible,
+    /// Show a notification in the workspace, in addition to showing a bell in the
+    /// terminal's tab.
+    Notify,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum Shell {
@@ -291,6 +393,32 @@ pub enum WorkingDirectory {
     Always { directory: String },
 }
 
+#[derive(Copy, Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct PasteSettings {
+    pub strip_trailing_newline: bool,
+    pub convert_crlf: bool,
+    pub trim_indentation: bool,
+}
+
+// Paste related settings
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+pub struct PasteSettingsContent {
+    /// Whether to remove the newline ending text that's pasted on a single line, so
+    /// that a pasted command can be edited before it's run.
+    ///
+    /// Default: false
+    pub strip_trailing_newline: Option<bool>,
+    /// Whether to convert Windows line endings (CRLF) to newlines (LF).
+    ///
+    /// Default: true
+    pub convert_crlf: Option<bool>,
+    /// Whether to remove the indentation shared by all the lines of text pasted on
+    /// multiple lines, e.g. for pasting indented code into a REPL.
+    ///
+    /// Default: false
+    pub trim_indentation: Option<bool>,
+}
+
 // Toolbar related settings
 #[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 pub struct ToolbarContent {
@@ -0,0 +1,717 @@
+//! Tmux's control mode (`tmux -C`), in which tmux sends a client notifications about the
+//! windows and panes of a session and the output of each pane as lines of text, instead of
+//! drawing the session itself. Each pane is shown in a detached terminal of Zed's, so a
+//! tmux session, e.g. one on a remote machine reached over ssh, is used like local tabs
+//! and splits.
+
+use crate::{
+    terminal_settings::TerminalSettings, Terminal, TerminalBackend, TerminalBuilder, TerminalSize,
+};
+use anyhow::{Context as _, Result};
+use collections::{BTreeMap, HashMap, VecDeque};
+use futures::{
+    channel::mpsc::{unbounded, UnboundedSender},
+    StreamExt,
+};
+use gpui::{AppContext, Context, EventEmitter, Model, ModelContext, Task};
+use parking_lot::Mutex;
+use settings::Settings;
+use smol::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    process::{Child, Command, Stdio},
+};
+use std::{fmt, sync::Arc};
+
+/// The most bytes sent to a pane by one `send-keys` command, keeping command lines short.
+const MAX_SEND_KEYS_BYTES: usize = 256;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PaneId(pub u32);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WindowId(pub u32);
+
+impl PaneId {
+    fn parse(text: &str) -> Option<Self> {
+        text.strip_prefix('%')?.parse().ok().map(Self)
+    }
+}
+
+impl WindowId {
+    fn parse(text: &str) -> Option<Self> {
+        text.strip_prefix('@')?.parse().ok().map(Self)
+    }
+}
+
+impl fmt::Display for PaneId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl fmt::Display for WindowId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "@{}", self.0)
+    }
+}
+
+/// A line, or a block of lines answering a command, that tmux sends in control mode.
+/// Notifications a client doesn't need to act on are skipped by the parser.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Notification {
+    Output {
+        pane: PaneId,
+        data: Vec<u8>,
+    },
+    WindowAdd(WindowId),
+    WindowClose(WindowId),
+    LayoutChange {
+        window: WindowId,
+        layout: Layout,
+    },
+    SessionChanged,
+    /// The output of a command, in the order the commands were sent.
+    Reply {
+        success: bool,
+        lines: Vec<String>,
+    },
+    Exit(Option<String>),
+}
+
+/// Splits what tmux sends in control mode into [`Notification`]s, one line at a time.
+#[derive(Default)]
+pub struct ControlModeParser {
+    /// The lines of the reply being received, between `%begin` and `%end` or `%error`.
+    reply: Option<Vec<String>>,
+}
+
+impl ControlModeParser {
+    pub fn parse_line(&mut self, line: &[u8]) -> Option<Notification> {
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        if let Some(reply) = self.reply.as_mut() {
+            let success = if line.starts_with(b"%end") {
+                true
+            } else if line.starts_with(b"%error") {
+                false
+            } else {
+                reply.push(String::from_utf8_lossy(line).into_owned());
+                return None;
+            };
+            let lines = self.reply.take().unwrap_or_default();
+            return Some(Notification::Reply { success, lines });
+        }
+
+        // Pane output is escaped, but may contain UTF-8, so it's split off before the rest
+        // of the line is read as text.
+        if let Some(rest) = line.strip_prefix(b"%output ") {
+            let separator = rest.iter().position(|byte| *byte == b' ')?;
+            let pane = PaneId::parse(std::str::from_utf8(&rest[..separator]).ok()?)?;
+            let data = unescape_output(&rest[separator + 1..]);
+            return Some(Notification::Output { pane, data });
+        }
+
+        let line = String::from_utf8_lossy(line);
+        let mut words = line.split(' ');
+        match words.next()? {
+            "%begin" => {
+                self.reply = Some(Vec::new());
+                None
+            }
+            "%window-add" => Some(Notification::WindowAdd(WindowId::parse(words.next()?)?)),
+            "%window-close" | "%unlinked-window-close" => {
+                Some(Notification::WindowClose(WindowId::parse(words.next()?)?))
+            }
+            "%layout-change" => {
+                let window = WindowId::parse(words.next()?)?;
+                let layout = Layout::parse(words.next()?)?;
+                Some(Notification::LayoutChange { window, layout })
+            }
+            "%session-changed" => Some(Notification::SessionChanged),
+            "%exit" => {
+                let reason = words.collect::<Vec<_>>().join(" ");
+                Some(Notification::Exit((!reason.is_empty()).then_some(reason)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Decodes the output of a pane, in which tmux escapes the control characters and
+/// backslashes as `\ooo` octal codes.
+fn unescape_output(escaped: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(escaped.len());
+    let mut index = 0;
+    while index < escaped.len() {
+        let code = escaped
+            .get(index + 1..index + 4)
+            .filter(|_| escaped[index] == b'\\')
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match code {
+            Some(byte) => {
+                output.push(byte);
+                index += 4;
+            }
+            None => {
+                output.push(escaped[index]);
+                index += 1;
+            }
+        }
+    }
+    output
+}
+
+/// How the panes of a tmux window are arranged, as tmux describes it in a layout string
+/// like `b25d,80x24,0,0{40x24,0,0,1,39x24,41,0,2}`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Layout {
+    pub columns: u32,
+    pub lines: u32,
+    pub kind: LayoutKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LayoutKind {
+    Pane(PaneId),
+    /// Cells side by side, written in `{}`.
+    Columns(Vec<Layout>),
+    /// Cells one above another, written in `[]`.
+    Rows(Vec<Layout>),
+}
+
+impl Layout {
+    pub fn parse(text: &str) -> Option<Self> {
+        // The layout starts with a checksum, which tmux checks itself.
+        let (_, cells) = text.split_once(',')?;
+        let mut rest = cells;
+        let layout = Self::parse_cell(&mut rest)?;
+        rest.is_empty().then_some(layout)
+    }
+
+    fn parse_cell(text: &mut &str) -> Option<Self> {
+        let columns = take_number(text)?;
+        *text = text.strip_prefix('x')?;
+        let lines = take_number(text)?;
+        *text = text.strip_prefix(',')?;
+        take_number(text)?;
+        *text = text.strip_prefix(',')?;
+        take_number(text)?;
+
+        let kind = if let Some(rest) = text.strip_prefix('{') {
+            *text = rest;
+            LayoutKind::Columns(Self::parse_children(text, '}')?)
+        } else if let Some(rest) = text.strip_prefix('[') {
+            *text = rest;
+            LayoutKind::Rows(Self::parse_children(text, ']')?)
+        } else {
+            *text = text.strip_prefix(',')?;
+            LayoutKind::Pane(PaneId(take_number(text)?))
+        };
+        Some(Layout {
+            columns,
+            lines,
+            kind,
+        })
+    }
+
+    fn parse_children(text: &mut &str, end: char) -> Option<Vec<Self>> {
+        let mut children = vec![Self::parse_cell(text)?];
+        loop {
+            if let Some(rest) = text.strip_prefix(end) {
+                *text = rest;
+                return Some(children);
+            }
+            *text = text.strip_prefix(',')?;
+            children.push(Self::parse_cell(text)?);
+        }
+    }
+
+    /// The panes of the layout, from left to right and top to bottom.
+    pub fn panes(&self) -> Vec<PaneId> {
+        match &self.kind {
+            LayoutKind::Pane(pane) => vec![*pane],
+            LayoutKind::Columns(children) | LayoutKind::Rows(children) => {
+                children.iter().flat_map(Layout::panes).collect()
+            }
+        }
+    }
+}
+
+fn take_number(text: &mut &str) -> Option<u32> {
+    let end = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let number = text[..end].parse().ok()?;
+    *text = &text[end..];
+    Some(number)
+}
+
+/// What to do with the reply to a command sent to tmux.
+enum PendingReply {
+    Ignore,
+    /// Lines of `@window layout`, listing all windows of the session.
+    Windows,
+    /// The screen of a pane that was just found, to show before its new output.
+    Capture(PaneId),
+}
+
+/// Sends command lines to tmux, remembering what to do with their replies, which come
+/// back in the same order.
+#[derive(Clone)]
+struct CommandSender {
+    tx: UnboundedSender<String>,
+    pending: Arc<Mutex<VecDeque<PendingReply>>>,
+}
+
+impl CommandSender {
+    fn send(&self, command: String, reply: PendingReply) {
+        let mut pending = self.pending.lock();
+        if self.tx.unbounded_send(command).is_ok() {
+            pending.push_back(reply);
+        }
+    }
+
+    fn next_reply(&self) -> Option<PendingReply> {
+        self.pending.lock().pop_front()
+    }
+}
+
+/// Relays the input of the terminal of a pane to tmux.
+struct TmuxPaneBackend {
+    pane: PaneId,
+    commands: CommandSender,
+}
+
+impl TerminalBackend for TmuxPaneBackend {
+    fn write(&self, bytes: Vec<u8>) {
+        for chunk in bytes.chunks(MAX_SEND_KEYS_BYTES) {
+            self.commands
+                .send(send_keys_command(self.pane, chunk), PendingReply::Ignore);
+        }
+    }
+
+    // Tmux decides the size of each pane from the size of the client, which is set with
+    // [`TmuxSession::resize_client`], and the layout of its window. Resizing panes one by
+    // one would have them fight over the size of the window they share.
+    fn resize(&self, _: TerminalSize) {}
+
+    // Closing the terminal of a pane leaves the pane running in tmux, as detaching would.
+    fn shutdown(&self) {}
+}
+
+fn send_keys_command(pane: PaneId, bytes: &[u8]) -> String {
+    let mut command = format!("send-keys -H -t {pane}");
+    for byte in bytes {
+        command.push_str(&format!(" {byte:02x}"));
+    }
+    command
+}
+
+pub enum TmuxEvent {
+    /// A window was found in the session. Its layout and the terminals of its panes are
+    /// available from the session.
+    WindowAdded(WindowId),
+    /// The layout of a window changed, e.g. because a pane was split or closed.
+    LayoutChanged(WindowId),
+    WindowClosed(WindowId),
+    /// Tmux exited or detached the client, for the reason it gave if any, and the
+    /// terminals of the panes were closed.
+    Exited {
+        reason: Option<String>,
+    },
+}
+
+/// A pane of the attached session, shown in a terminal.
+struct Pane {
+    terminal: Model<Terminal>,
+    /// The output received while the pane's screen is being captured, which is held back
+    /// so that it isn't shown before the screen it was written to.
+    held_output: Option<Vec<u8>>,
+}
+
+/// A tmux client in control mode, keeping a terminal for each pane of the attached
+/// session.
+pub struct TmuxSession {
+    commands: CommandSender,
+    parser: ControlModeParser,
+    windows: BTreeMap<WindowId, Layout>,
+    panes: HashMap<PaneId, Pane>,
+    /// The size last requested with `refresh-client`, in columns and lines.
+    client_size: Option<(usize, usize)>,
+    /// The last lines tmux wrote to its standard error, which explain why it exited when
+    /// it didn't say so itself, e.g. because ssh failed to connect.
+    stderr: VecDeque<String>,
+    exited: bool,
+    _child: Child,
+    _io_tasks: [Task<Result<()>>; 3],
+}
+
+impl EventEmitter<TmuxEvent> for TmuxSession {}
+
+impl TmuxSession {
+    /// Runs `command`, which starts tmux in control mode, e.g. `tmux -C attach`, possibly
+    /// through ssh.
+    pub fn spawn(command: &[String], cx: &mut AppContext) -> Result<Model<Self>> {
+        let (program, args) = command.split_first().context("empty tmux command")?;
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("failed to run {program}"))?;
+        let mut stdin = child.stdin.take().context("no stdin for tmux")?;
+        let stdout = child.stdout.take().context("no stdout for tmux")?;
+        let stderr = child.stderr.take().context("no stderr for tmux")?;
+
+        Ok(cx.new_model(|cx| {
+            let (commands_tx, mut commands_rx) = unbounded::<String>();
+            let writer = cx.background_executor().spawn(async move {
+                while let Some(command) = commands_rx.next().await {
+                    stdin.write_all(command.as_bytes()).await?;
+                    stdin.write_all(b"\n").await?;
+                    stdin.flush().await?;
+                }
+                anyhow::Ok(())
+            });
+            let stderr_reader = cx.spawn(|this, mut cx| async move {
+                let mut lines = BufReader::new(stderr).lines();
+                while let Some(Ok(line)) = lines.next().await {
+                    this.update(&mut cx, |this, _| this.push_stderr_line(line))?;
+                }
+                anyhow::Ok(())
+            });
+            let reader = cx.spawn(|this, mut cx| async move {
+                let mut stdout = BufReader::new(stdout);
+                let mut line = Vec::new();
+                loop {
+                    line.clear();
+                    if stdout.read_until(b'\n', &mut line).await.unwrap_or(0) == 0 {
+                        break;
+                    }
+                    this.update(&mut cx, |this, cx| this.handle_line(&line, cx))?;
+                }
+                this.update(&mut cx, |this, cx| this.exit(None, cx))?;
+                anyhow::Ok(())
+            });
+
+            let commands = CommandSender {
+                tx: commands_tx,
+                pending: Default::default(),
+            };
+            // The command tmux was started with is answered first.
+            commands.pending.lock().push_back(PendingReply::Ignore);
+            commands.send(list_windows_command(), PendingReply::Windows);
+
+            TmuxSession {
+                commands,
+                parser: ControlModeParser::default(),
+                windows: BTreeMap::default(),
+                panes: HashMap::default(),
+                client_size: None,
+                stderr: VecDeque::new(),
+                exited: false,
+                _child: child,
+                _io_tasks: [writer, stderr_reader, reader],
+            }
+        }))
+    }
+
+    /// The windows of the session, in the order of their ids.
+    pub fn windows(&self) -> impl Iterator<Item = WindowId> + '_ {
+        self.windows.keys().copied()
+    }
+
+    /// How the panes of `window` are arranged, if it's still open.
+    pub fn window_layout(&self, window: WindowId) -> Option<&Layout> {
+        self.windows.get(&window)
+    }
+
+    /// The terminal showing `pane`, if it's still open.
+    pub fn pane_terminal(&self, pane: PaneId) -> Option<Model<Terminal>> {
+        Some(self.panes.get(&pane)?.terminal.clone())
+    }
+
+    /// Sets the size of the client, which all windows of the session share, to the
+    /// space a window is shown in. Tmux then divides it among the window's panes.
+    pub fn resize_client(&mut self, columns: usize, lines: usize) {
+        if columns == 0 || lines == 0 || self.client_size == Some((columns, lines)) {
+            return;
+        }
+        self.client_size = Some((columns, lines));
+        self.commands.send(
+            format!("refresh-client -C {columns}x{lines}"),
+            PendingReply::Ignore,
+        );
+    }
+
+    fn push_stderr_line(&mut self, line: String) {
+        const MAX_STDERR_LINES: usize = 20;
+        if self.stderr.len() == MAX_STDERR_LINES {
+            self.stderr.pop_front();
+        }
+        self.stderr.push_back(line);
+    }
+
+    fn handle_line(&mut self, line: &[u8], cx: &mut ModelContext<Self>) {
+        let Some(notification) = self.parser.parse_line(line) else {
+            return;
+        };
+        match notification {
+            Notification::Output { pane, data } => {
+                let Some(pane) = self.panes.get_mut(&pane) else {
+                    return;
+                };
+                match &mut pane.held_output {
+                    Some(held_output) => held_output.extend(data),
+                    None => pane
+                        .terminal
+                        .update(cx, |terminal, cx| terminal.write_output(&data, cx)),
+                }
+            }
+            Notification::WindowAdd(_) | Notification::SessionChanged => {
+                self.commands
+                    .send(list_windows_command(), PendingReply::Windows);
+            }
+            Notification::WindowClose(window) => self.set_window_layout(window, None, cx),
+            Notification::LayoutChange { window, layout } => {
+                self.set_window_layout(window, Some(layout), cx)
+            }
+            Notification::Reply { success, lines } => match self.commands.next_reply() {
+                Some(PendingReply::Windows) if success => self.set_windows(&lines, cx),
+                Some(PendingReply::Capture(pane)) => {
+                    let Some(pane) = self.panes.get_mut(&pane) else {
+                        return;
+                    };
+                    let held_output = pane.held_output.take().unwrap_or_default();
+                    pane.terminal.update(cx, |terminal, cx| {
+                        if success {
+                            // The output received before the reply was written to the
+                            // screen before it was captured, so it's already shown.
+                            terminal.write_output(lines.join("\r\n").as_bytes(), cx);
+                        } else {
+                            terminal.write_output(&held_output, cx);
+                        }
+                    });
+                }
+                _ => {}
+            },
+            Notification::Exit(reason) => self.exit(reason, cx),
+        }
+    }
+
+    /// Replaces the windows with those listed by `list-windows`.
+    fn set_windows(&mut self, lines: &[String], cx: &mut ModelContext<Self>) {
+        let listed = lines
+            .iter()
+            .filter_map(|line| {
+                let (window, layout) = line.split_once(' ')?;
+                Some((WindowId::parse(window)?, Layout::parse(layout)?))
+            })
+            .collect::<BTreeMap<_, _>>();
+        let closed = self
+            .windows
+            .keys()
+            .filter(|window| !listed.contains_key(window))
+            .copied()
+            .collect::<Vec<_>>();
+        for window in closed {
+            self.set_window_layout(window, None, cx);
+        }
+        for (window, layout) in listed {
+            self.set_window_layout(window, Some(layout), cx);
+        }
+    }
+
+    /// Opens terminals for the panes new to `window` and closes those of the panes it
+    /// lost, closing the window when it has no layout.
+    fn set_window_layout(
+        &mut self,
+        window: WindowId,
+        layout: Option<Layout>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        let panes = layout.as_ref().map(Layout::panes).unwrap_or_default();
+        let old_layout = match layout {
+            Some(layout) => {
+                if self.windows.get(&window) == Some(&layout) {
+                    return;
+                }
+                self.windows.insert(window, layout)
+            }
+            None => {
+                let Some(old_layout) = self.windows.remove(&window) else {
+                    return;
+                };
+                Some(old_layout)
+            }
+        };
+        let old_panes = old_layout.as_ref().map(Layout::panes).unwrap_or_default();
+
+        for pane in &old_panes {
+            if !panes.contains(pane) {
+                self.close_pane(*pane, cx);
+            }
+        }
+        for pane in &panes {
+            if !self.panes.contains_key(pane) {
+                self.open_pane(*pane, cx);
+            }
+        }
+
+        cx.emit(match (old_layout, panes.is_empty()) {
+            (_, true) => TmuxEvent::WindowClosed(window),
+            (None, false) => TmuxEvent::WindowAdded(window),
+            (Some(_), false) => TmuxEvent::LayoutChanged(window),
+        });
+    }
+
+    fn open_pane(&mut self, pane: PaneId, cx: &mut ModelContext<Self>) {
+        let settings = TerminalSettings::get_global(cx);
+        let builder = TerminalBuilder::new_detached(
+            Box::new(TmuxPaneBackend {
+                pane,
+                commands: self.commands.clone(),
+            }),
+            Some(settings.blinking),
+            settings.alternate_scroll,
+            settings.max_scroll_history_lines,
+        );
+        let terminal = cx.new_model(|cx| builder.subscribe(cx));
+        self.panes.insert(
+            pane,
+            Pane {
+                terminal,
+                held_output: Some(Vec::new()),
+            },
+        );
+        self.commands.send(
+            format!("capture-pane -p -e -t {pane}"),
+            PendingReply::Capture(pane),
+        );
+    }
+
+    fn close_pane(&mut self, pane: PaneId, cx: &mut ModelContext<Self>) {
+        if let Some(pane) = self.panes.remove(&pane) {
+            pane.terminal
+                .update(cx, |terminal, cx| terminal.output_closed(cx));
+        }
+    }
+
+    fn exit(&mut self, reason: Option<String>, cx: &mut ModelContext<Self>) {
+        if self.exited {
+            return;
+        }
+        self.exited = true;
+        for window in self.windows.keys().copied().collect::<Vec<_>>() {
+            self.set_window_layout(window, None, cx);
+        }
+        for pane in self.panes.keys().copied().collect::<Vec<_>>() {
+            self.close_pane(pane, cx);
+        }
+        // When tmux couldn't attach, e.g. because there's no session or ssh failed, the
+        // reason is only written to its standard error.
+        let reason = reason.or_else(|| {
+            let stderr = self.stderr.drain(..).collect::<Vec<_>>().join("\n");
+            (!stderr.trim().is_empty()).then_some(stderr)
+        });
+        cx.emit(TmuxEvent::Exited { reason });
+    }
+}
+
+fn list_windows_command() -> String {
+    "list-windows -F \"#{window_id} #{window_layout}\"".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_lines(parser: &mut ControlModeParser, lines: &[&str]) -> Vec<Notification> {
+        lines
+            .iter()
+            .filter_map(|line| parser.parse_line(line.as_bytes()))
+            .collect()
+    }
+
+    #[test]
+    fn test_output_is_unescaped() {
+        let mut parser = ControlModeParser::default();
+        assert_eq!(
+            parse_lines(&mut parser, &["%output %3 ls\\015\\012a\\\\b ü\n"]),
+            vec![Notification::Output {
+                pane: PaneId(3),
+                data: "ls\r\na\\b ü".as_bytes().to_vec(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_replies_collect_their_lines() {
+        let mut parser = ControlModeParser::default();
+        assert_eq!(
+            parse_lines(
+                &mut parser,
+                &[
+                    "%begin 1700000000 12 1",
+                    "@1 b25d,80x24,0,0,1",
+                    "%output %1 ignored inside a reply",
+                    "%end 1700000000 12 1",
+                    "%begin 1700000000 13 1",
+                    "no such pane",
+                    "%error 1700000000 13 1",
+                    "%window-add @2",
+                    "%sessions-changed",
+                    "%exit detached",
+                ]
+            ),
+            vec![
+                Notification::Reply {
+                    success: true,
+                    lines: vec![
+                        "@1 b25d,80x24,0,0,1".to_string(),
+                        "%output %1 ignored inside a reply".to_string(),
+                    ],
+                },
+                Notification::Reply {
+                    success: false,
+                    lines: vec!["no such pane".to_string()],
+                },
+                Notification::WindowAdd(WindowId(2)),
+                Notification::Exit(Some("detached".to_string())),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_layout_parsing() {
+        let layout =
+            Layout::parse("b25d,80x24,0,0{40x24,0,0,1,39x24,41,0[39x12,41,0,2,39x11,41,13,5]}")
+                .unwrap();
+        assert_eq!(layout.columns, 80);
+        assert_eq!(layout.lines, 24);
+        assert_eq!(layout.panes(), vec![PaneId(1), PaneId(2), PaneId(5)]);
+        let LayoutKind::Columns(columns) = &layout.kind else {
+            panic!("expected columns, got {:?}", layout.kind);
+        };
+        assert!(matches!(&columns[1].kind, LayoutKind::Rows(rows) if rows.len() == 2));
+
+        assert_eq!(
+            Layout::parse("c0de,120x40,0,0,7").map(|layout| layout.panes()),
+            Some(vec![PaneId(7)])
+        );
+        assert_eq!(Layout::parse("c0de,120x40,0,0{"), None);
+        assert_eq!(Layout::parse("c0de,120x40,0,0,7,"), None);
+    }
+
+    #[test]
+    fn test_send_keys_command() {
+        assert_eq!(
+            send_keys_command(PaneId(4), b"ls\r"),
+            "send-keys -H -t %4 6c 73 0d"
+        );
+    }
+}
@@ -0,0 +1,297 @@
+use crate::{persistence::TERMINAL_DB, TerminalView};
+use chrono::{DateTime, Local};
+use fuzzy::{match_strings, StringMatch, StringMatchCandidate};
+use gpui::{
+    AppContext, DismissEvent, EventEmitter, FocusableView, Render, Task, View, ViewContext,
+    VisualContext, WeakView,
+};
+use picker::{Picker, PickerDelegate};
+use settings::Settings;
+use std::{sync::Arc, time::SystemTime};
+use terminal::{terminal_settings::TerminalSettings, ShowCommandHistory};
+use ui::{
+    prelude::*,
+    utils::{format_distance_from_now, DateTimeType},
+    HighlightedLabel, ListItem, ListItemSpacing,
+};
+use util::ResultExt;
+use workspace::{ModalView, WorkspaceId};
+
+/// The most commands kept in the history of a project.
+const MAX_COMMANDS: i64 = 1000;
+
+struct HistoryEntry {
+    command: String,
+    timestamp: Option<DateTime<Local>>,
+    /// The exit code of the last run of the command, if the shell reported one.
+    exit_code: Option<i32>,
+}
+
+/// Adds a command that finished in one of the project's terminals to its history, or moves
+/// it to the top of the history if it's already there.
+pub(crate) fn record_command(
+    workspace_id: WorkspaceId,
+    command: String,
+    exit_code: Option<i32>,
+    cx: &AppContext,
+) {
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs() as i64);
+    cx.background_executor()
+        .spawn(async move {
+            TERMINAL_DB
+                .save_command(workspace_id, command, timestamp, exit_code)
+                .await
+                .log_err();
+            TERMINAL_DB
+                .delete_old_commands(workspace_id, MAX_COMMANDS)
+                .await
+                .log_err();
+        })
+        .detach();
+}
+
+impl TerminalView {
+    pub(crate) fn show_command_history(
+        &mut self,
+        _: &ShowCommandHistory,
+        cx: &mut ViewContext<Self>,
+    ) {
+        let Some(workspace_id) = self.workspace_id else {
+            return;
+        };
+        let terminal_view = cx.view().downgrade();
+        self.workspace
+            .update(cx, |workspace, cx| {
+                workspace.toggle_modal(cx, |cx| {
+                    let delegate = CommandHistoryDelegate::new(
+                        cx.view().downgrade(),
+                        terminal_view,
+                        workspace_id,
+                    );
+                    CommandHistory::new(delegate, cx)
+                })
+            })
+            .ok();
+    }
+}
+
+/// A picker to search the commands entered in the project's terminals and run one again
+/// in the terminal it was opened from.
+pub struct CommandHistory {
+    picker: View<Picker<CommandHistoryDelegate>>,
+}
+
+impl CommandHistory {
+    fn new(delegate: CommandHistoryDelegate, cx: &mut ViewContext<Self>) -> Self {
+        let workspace_id = delegate.workspace_id;
+        let picker = cx.new_view(|cx| Picker::uniform_list(delegate, cx));
+        cx.spawn(|this, mut cx| async move {
+            let commands = cx
+                .background_executor()
+                .spawn(async move { TERMINAL_DB.get_commands(workspace_id) })
+                .await
+                .log_err()
+                .unwrap_or_default();
+            this.update(&mut cx, |this, cx| {
+                this.picker.update(cx, |picker, cx| {
+                    picker.delegate.history = commands
+                        .into_iter()
+                        .map(|(command, timestamp, exit_code)| HistoryEntry {
+                            command,
+                            timestamp: DateTime::from_timestamp(timestamp, 0)
+                                .map(|timestamp| timestamp.with_timezone(&Local)),
+                            exit_code,
+                        })
+                        .collect();
+                    picker.refresh(cx);
+                })
+            })
+            .log_err();
+        })
+        .detach();
+        Self { picker }
+    }
+}
+
+impl FocusableView for CommandHistory {
+    fn focus_handle(&self, cx: &AppContext) -> gpui::FocusHandle {
+        self.picker.focus_handle(cx)
+    }
+}
+
+impl EventEmitter<DismissEvent> for CommandHistory {}
+impl ModalView for CommandHistory {}
+
+impl Render for CommandHistory {
+    fn render(&mut self, _cx: &mut ViewContext<Self>) -> impl IntoElement {
+        v_flex().w(rems(34.)).child(self.picker.clone())
+    }
+}
+
+pub struct CommandHistoryDelegate {
+    view: WeakView<CommandHistory>,
+    terminal_view: WeakView<TerminalView>,
+    workspace_id: WorkspaceId,
+    /// The project's commands, most recently entered first.
+    history: Vec<HistoryEntry>,
+    matches: Vec<StringMatch>,
+    selected_index: usize,
+}
+
+impl CommandHistoryDelegate {
+    fn new(
+        view: WeakView<CommandHistory>,
+        terminal_view: WeakView<TerminalView>,
+        workspace_id: WorkspaceId,
+    ) -> Self {
+        Self {
+            view,
+            terminal_view,
+            workspace_id,
+            history: Vec::new(),
+            matches: Vec::new(),
+            selected_index: 0,
+        }
+    }
+}
+
+impl PickerDelegate for CommandHistoryDelegate {
+    type ListItem = ListItem;
+
+    fn placeholder_text(&self, _cx: &mut WindowContext) -> Arc<str> {
+        "Search command history...".into()
+    }
+
+    fn match_count(&self) -> usize {
+        self.matches.len()
+    }
+
+    fn selected_index(&self) -> usize {
+        self.selected_index
+    }
+
+    fn set_selected_index(&mut self, ix: usize, _: &mut ViewContext<Picker<Self>>) {
+        self.selected_index = ix;
+    }
+
+    fn update_matches(&mut self, query: String, cx: &mut ViewContext<Picker<Self>>) -> Task<()> {
+        let background = cx.background_executor().clone();
+        let candidates = self
+            .history
+            .iter()
+            .enumerate()
+            .map(|(id, entry)| StringMatchCandidate {
+                id,
+                char_bag: entry.command.as_str().into(),
+                string: entry.command.clone(),
+            })
+            .collect::<Vec<_>>();
+
+        cx.spawn(|this, mut cx| async move {
+            let matches = if query.is_empty() {
+                candidates
+                    .into_iter()
+                    .map(|candidate| StringMatch {
+                        candidate_id: candidate.id,
+                        string: candidate.string,
+                        positions: Vec::new(),
+                        score: 0.0,
+                    })
+                    .collect()
+            } else {
+                match_strings(
+                    &candidates,
+                    &query,
+                    false,
+                    100,
+                    &Default::default(),
+                    background,
+                )
+                .await
+            };
+
+            this.update(&mut cx, |this, _| {
+                this.delegate.matches = matches;
+                this.delegate.selected_index = 0;
+            })
+            .log_err();
+        })
+    }
+
+    /// Runs the selected command in the terminal, or with the secondary modifier, only
+    /// types it at the prompt so that it can be edited first.
+    fn confirm(&mut self, secondary: bool, cx: &mut ViewContext<Picker<Self>>) {
+        if let Some(command) = self
+            .matches
+            .get(self.selected_index)
+            .map(|command| command.string.clone())
+        {
+            if let Some(terminal_view) = self.terminal_view.upgrade() {
+                let clear_line = TerminalSettings::get_global(cx).clear_line_before_sending;
+                terminal_view.update(cx, |terminal_view, cx| {
+                    terminal_view.terminal().update(cx, |terminal, _| {
+                        if clear_line {
+                            terminal.clear_pending_input();
+                        }
+                        if secondary {
+                            terminal.paste(&command);
+                        } else {
+                            terminal.input(format!("{command}\r"));
+                        }
+                    });
+                    cx.focus_self();
+                });
+            }
+        }
+
+        self.dismissed(cx);
+    }
+
+    fn dismissed(&mut self, cx: &mut ViewContext<Picker<Self>>) {
+        self.view
+            .update(cx, |_, cx| cx.emit(DismissEvent))
+            .log_err();
+    }
+
+    fn render_match(
+        &self,
+        ix: usize,
+        selected: bool,
+        _cx: &mut ViewContext<Picker<Self>>,
+    ) -> Option<Self::ListItem> {
+        let command_match = &self.matches[ix];
+        let entry = &self.history[command_match.candidate_id];
+
+        Some(
+            ListItem::new(ix)
+                .inset(true)
+                .spacing(ListItemSpacing::Sparse)
+                .selected(selected)
+                .child(HighlightedLabel::new(
+                    command_match.string.clone(),
+                    command_match.positions.clone(),
+                ))
+                .end_slot(
+                    h_flex()
+                        .gap_2()
+                        .children(entry.exit_code.filter(|code| *code != 0).map(|exit_code| {
+                            Label::new(format!("exit {exit_code}"))
+                                .size(LabelSize::Small)
+                                .color(Color::Error)
+                        }))
+                        .children(entry.timestamp.map(|timestamp| {
+                            Label::new(format_distance_from_now(
+                                DateTimeType::Local(timestamp),
+                                false,
+                                true,
+                                false,
+                            ))
+                            .size(LabelSize::Small)
+                            .color(Color::Muted)
+                        })),
+                ),
+        )
+    }
+}
@@ -34,6 +34,19 @@ define_connection! {
             DROP TABLE terminals;
 
             ALTER TABLE terminals2 RENAME TO terminals;
+        ),
+        sql!(
+            CREATE TABLE terminal_command_history (
+                workspace_id INTEGER,
+                command TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                PRIMARY KEY(workspace_id, command),
+                FOREIGN KEY(workspace_id) REFERENCES workspaces(workspace_id)
+                ON DELETE CASCADE
+            ) STRICT;
+        ),
+        sql!(
+            ALTER TABLE terminal_command_history ADD COLUMN exit_code INTEGER;
         )];
 }
 
@@ -68,4 +81,40 @@ impl TerminalDb {
             WHERE item_id = ? AND workspace_id = ?
         }
     }
+
+    query! {
+        pub async fn save_command(
+            workspace_id: WorkspaceId,
+            command: String,
+            timestamp: i64,
+            exit_code: Option<i32>
+        ) -> Result<()> {
+            INSERT OR REPLACE INTO terminal_command_history(workspace_id, command, timestamp, exit_code)
+            VALUES (?, ?, ?, ?)
+        }
+    }
+
+    query! {
+        pub async fn delete_old_commands(
+            workspace_id: WorkspaceId,
+            kept_count: i64
+        ) -> Result<()> {
+            DELETE FROM terminal_command_history
+            WHERE workspace_id = ?1 AND command NOT IN (
+                SELECT command FROM terminal_command_history
+                WHERE workspace_id = ?1
+                ORDER BY timestamp DESC
+                LIMIT ?2
+            )
+        }
+    }
+
+    query! {
+        pub fn get_commands(workspace_id: WorkspaceId) -> Result<Vec<(String, i64, Option<i32>)>> {
+            SELECT command, timestamp, exit_code
+            FROM terminal_command_history
+            WHERE workspace_id = ?
+            ORDER BY timestamp DESC
+        }
+    }
 }
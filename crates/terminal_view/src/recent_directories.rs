@@ -0,0 +1,198 @@
+use crate::terminal_panel::{NewTerminalIn, TerminalPanel};
+use fuzzy::{match_strings, StringMatch, StringMatchCandidate};
+use gpui::{
+    AppContext, DismissEvent, EventEmitter, FocusableView, Render, Task, View, ViewContext,
+    VisualContext, WeakView,
+};
+use picker::{Picker, PickerDelegate};
+use std::{path::PathBuf, sync::Arc};
+use ui::{prelude::*, HighlightedLabel, ListItem, ListItemSpacing};
+use util::ResultExt;
+use workspace::{ModalView, OpenTerminal, Workspace};
+
+/// A picker of the directories the shells of the project's terminals were recently in, to
+/// open a new terminal in one of them.
+pub struct RecentDirectories {
+    picker: View<Picker<RecentDirectoriesDelegate>>,
+}
+
+impl RecentDirectories {
+    pub(crate) fn toggle(
+        workspace: &mut Workspace,
+        _: &NewTerminalIn,
+        cx: &mut ViewContext<Workspace>,
+    ) {
+        let directories = workspace
+            .project()
+            .read(cx)
+            .recent_terminal_directories()
+            .map(|directory| directory.to_path_buf())
+            .collect::<Vec<_>>();
+        let workspace_handle = cx.view().downgrade();
+        workspace.toggle_modal(cx, |cx| {
+            let delegate = RecentDirectoriesDelegate::new(
+                cx.view().downgrade(),
+                workspace_handle,
+                directories,
+            );
+            Self {
+                picker: cx.new_view(|cx| Picker::uniform_list(delegate, cx)),
+            }
+        });
+    }
+}
+
+impl FocusableView for RecentDirectories {
+    fn focus_handle(&self, cx: &AppContext) -> gpui::FocusHandle {
+        self.picker.focus_handle(cx)
+    }
+}
+
+impl EventEmitter<DismissEvent> for RecentDirectories {}
+impl ModalView for RecentDirectories {}
+
+impl Render for RecentDirectories {
+    fn render(&mut self, _cx: &mut ViewContext<Self>) -> impl IntoElement {
+        v_flex().w(rems(34.)).child(self.picker.clone())
+    }
+}
+
+pub struct RecentDirectoriesDelegate {
+    view: WeakView<RecentDirectories>,
+    workspace: WeakView<Workspace>,
+    /// Most recently entered first.
+    directories: Vec<PathBuf>,
+    matches: Vec<StringMatch>,
+    selected_index: usize,
+}
+
+impl RecentDirectoriesDelegate {
+    fn new(
+        view: WeakView<RecentDirectories>,
+        workspace: WeakView<Workspace>,
+        directories: Vec<PathBuf>,
+    ) -> Self {
+        Self {
+            view,
+            workspace,
+            directories,
+            matches: Vec::new(),
+            selected_index: 0,
+        }
+    }
+}
+
+impl PickerDelegate for RecentDirectoriesDelegate {
+    type ListItem = ListItem;
+
+    fn placeholder_text(&self, _cx: &mut WindowContext) -> Arc<str> {
+        "Open a new terminal in...".into()
+    }
+
+    fn match_count(&self) -> usize {
+        self.matches.len()
+    }
+
+    fn selected_index(&self) -> usize {
+        self.selected_index
+    }
+
+    fn set_selected_index(&mut self, ix: usize, _: &mut ViewContext<Picker<Self>>) {
+        self.selected_index = ix;
+    }
+
+    fn update_matches(&mut self, query: String, cx: &mut ViewContext<Picker<Self>>) -> Task<()> {
+        let background = cx.background_executor().clone();
+        let candidates = self
+            .directories
+            .iter()
+            .enumerate()
+            .map(|(id, directory)| {
+                let directory = directory.to_string_lossy().into_owned();
+                StringMatchCandidate {
+                    id,
+                    char_bag: directory.as_str().into(),
+                    string: directory,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        cx.spawn(|this, mut cx| async move {
+            let matches = if query.is_empty() {
+                candidates
+                    .into_iter()
+                    .map(|candidate| StringMatch {
+                        candidate_id: candidate.id,
+                        string: candidate.string,
+                        positions: Vec::new(),
+                        score: 0.0,
+                    })
+                    .collect()
+            } else {
+                match_strings(
+                    &candidates,
+                    &query,
+                    false,
+                    100,
+                    &Default::default(),
+                    background,
+                )
+                .await
+            };
+
+            this.update(&mut cx, |this, _| {
+                this.delegate.matches = matches;
+                this.delegate.selected_index = 0;
+            })
+            .log_err();
+        })
+    }
+
+    fn confirm(&mut self, _: bool, cx: &mut ViewContext<Picker<Self>>) {
+        if let Some(directory) = self
+            .matches
+            .get(self.selected_index)
+            .map(|directory_match| self.directories[directory_match.candidate_id].clone())
+        {
+            self.workspace
+                .update(cx, |workspace, cx| {
+                    TerminalPanel::open_terminal(
+                        workspace,
+                        &OpenTerminal {
+                            working_directory: directory,
+                        },
+                        cx,
+                    )
+                })
+                .log_err();
+        }
+
+        self.dismissed(cx);
+    }
+
+    fn dismissed(&mut self, cx: &mut ViewContext<Picker<Self>>) {
+        self.view
+            .update(cx, |_, cx| cx.emit(DismissEvent))
+            .log_err();
+    }
+
+    fn render_match(
+        &self,
+        ix: usize,
+        selected: bool,
+        _cx: &mut ViewContext<Picker<Self>>,
+    ) -> Option<Self::ListItem> {
+        let directory_match = &self.matches[ix];
+
+        Some(
+            ListItem::new(ix)
+                .inset(true)
+                .spacing(ListItemSpacing::Sparse)
+                .selected(selected)
+                .child(HighlightedLabel::new(
+                    directory_match.string.clone(),
+                    directory_match.positions.clone(),
+                )),
+        )
+    }
+}
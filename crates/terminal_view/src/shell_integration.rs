@@ -0,0 +1,79 @@
+use db::kvp::KEY_VALUE_STORE;
+use dirs::home_dir;
+use gpui::{VisualContext, WeakView, WindowContext};
+use settings::Settings;
+use terminal::{shell_integration::IntegratedShell, terminal_settings::TerminalSettings};
+use util::ResultExt;
+use workspace::{
+    notifications::{simple_message_notification::MessageNotification, NotificationId},
+    Workspace,
+};
+
+fn shell_integration_key(shell: IntegratedShell) -> String {
+    format!("{}_shell_integration_suggest", shell.name())
+}
+
+/// Offers to add Zed's shell integration to the configuration of the user's shell, the
+/// first time a command is entered in a terminal whose shell didn't mark its prompt.
+pub(crate) fn suggest(workspace: WeakView<Workspace>, cx: &mut WindowContext) {
+    let Some(shell) = IntegratedShell::detect(&TerminalSettings::get_global(cx).shell) else {
+        return;
+    };
+    let Some(home_dir) = home_dir() else {
+        return;
+    };
+    let key = shell_integration_key(shell);
+    let Ok(None) = KEY_VALUE_STORE.read_kvp(&key) else {
+        return;
+    };
+
+    let config_path = shell.config_path(&home_dir);
+    cx.spawn(|mut cx| async move {
+        let is_installed = cx
+            .background_executor()
+            .spawn({
+                let config_path = config_path.clone();
+                async move { IntegratedShell::is_installed(&config_path) }
+            })
+            .await;
+        if is_installed {
+            return;
+        }
+
+        // Only ever ask once, whatever the answer.
+        KEY_VALUE_STORE
+            .write_kvp(key, "suggested".to_string())
+            .await
+            .log_err();
+
+        struct ShellIntegrationNotification;
+
+        workspace
+            .update(&mut cx, |workspace, cx| {
+                workspace.show_notification(
+                    NotificationId::unique::<ShellIntegrationNotification>(),
+                    cx,
+                    |cx| {
+                        cx.new_view(move |_cx| {
+                            MessageNotification::new(format!(
+                                "Add Zed's {} integration to {}? It lets the terminal tell \
+                                where each command and its output start.",
+                                shell.name(),
+                                config_path.display()
+                            ))
+                            .with_click_message("Add")
+                            .on_click(move |cx| {
+                                let config_path = config_path.clone();
+                                cx.background_executor()
+                                    .spawn(async move { shell.install(&config_path) })
+                                    .detach_and_log_err(cx);
+                            })
+                            .with_secondary_click_message("Not Now")
+                        })
+                    },
+                );
+            })
+            .log_err();
+    })
+    .detach();
+}
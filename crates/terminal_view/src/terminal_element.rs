@@ -1,9 +1,9 @@
 use editor::{CursorLayout, HighlightedRange, HighlightedRangeLine};
 use gpui::{
-    div, fill, point, px, relative, AnyElement, Bounds, DispatchPhase, Element, ElementId,
+    div, fill, point, px, relative, size, AnyElement, Bounds, DispatchPhase, Element, ElementId,
     FocusHandle, Font, FontStyle, FontWeight, GlobalElementId, HighlightStyle, Hitbox, Hsla,
     InputHandler, InteractiveElement, Interactivity, IntoElement, LayoutId, Model, ModelContext,
-    ModifiersChangedEvent, MouseButton, MouseMoveEvent, Pixels, Point, ShapedLine,
+    ModifiersChangedEvent, MouseButton, MouseMoveEvent, PaintQuad, Pixels, Point, ShapedLine,
     StatefulInteractiveElement, StrikethroughStyle, Styled, TextRun, TextStyle, UnderlineStyle,
     WeakView, WhiteSpace, WindowContext, WindowTextSystem,
 };
@@ -20,16 +20,23 @@ use terminal::{
             CursorShape as AlacCursorShape, NamedColor,
         },
     },
+    grapheme_cluster,
     terminal_settings::TerminalSettings,
     HoveredWord, IndexedCell, Terminal, TerminalContent, TerminalSize,
 };
 use theme::{ActiveTheme, Theme, ThemeSettings};
 use ui::Tooltip;
+use util::ResultExt;
 use workspace::Workspace;
 
 use std::mem;
 use std::{fmt::Debug, ops::RangeInclusive};
 
+/// The width of the indicator of the viewport's position in the scrollback.
+const SCROLL_INDICATOR_WIDTH: Pixels = px(6.);
+/// The indicator is kept tall enough to be seen in a long scrollback.
+const SCROLL_INDICATOR_MIN_HEIGHT: Pixels = px(12.);
+
 /// The information generated during layout that is necessary for painting.
 pub struct LayoutState {
     hitbox: Hitbox,
@@ -44,6 +51,10 @@ pub struct LayoutState {
     hyperlink_tooltip: Option<AnyElement>,
     gutter: Pixels,
     last_hovered_word: Option<HoveredWord>,
+    quick_select_tags: Vec<LayoutQuickSelectTag>,
+    wrap_indicator: Option<ShapedLine>,
+    wrapped_lines: Vec<i32>,
+    scroll_indicator: Option<PaintQuad>,
 }
 
 /// Helper struct for converting data between Alacritty's cursor points, and displayed cursor points.
@@ -142,6 +153,27 @@ impl LayoutRect {
     }
 }
 
+/// The tag of a quick select hint, painted over the start of the hint.
+struct LayoutQuickSelectTag {
+    point: AlacPoint<i32, i32>,
+    text: ShapedLine,
+    background_color: Hsla,
+}
+
+impl LayoutQuickSelectTag {
+    fn paint(&self, origin: Point<Pixels>, layout: &LayoutState, cx: &mut WindowContext) {
+        let position = point(
+            (origin.x + self.point.column as f32 * layout.dimensions.cell_width).floor(),
+            origin.y + self.point.line as f32 * layout.dimensions.line_height,
+        );
+        let size = point(self.text.width.ceil(), layout.dimensions.line_height).into();
+        cx.paint_quad(fill(Bounds::new(position, size), self.background_color));
+        self.text
+            .paint(position, layout.dimensions.line_height, cx)
+            .ok();
+    }
+}
+
 /// The GPUI element that paints the terminal.
 /// We need to keep a reference to the view for mouse events, do we need it for any other terminal stuff, or can we move that to connection?
 pub struct TerminalElement {
@@ -203,7 +235,11 @@ impl TerminalElement {
 
         let linegroups = grid.into_iter().group_by(|i| i.point.line);
         for (line_index, (_, line)) in linegroups.into_iter().enumerate() {
-            for cell in line {
+            let line = line.collect::<Vec<_>>();
+            let mut line_cells = Vec::new();
+            let mut shaping_failed = false;
+            let mut cluster_end = 0;
+            for (ix, cell) in line.iter().copied().enumerate() {
                 let mut fg = cell.fg;
                 let mut bg = cell.bg;
                 if cell.flags.contains(Flags::INVERSE) {
@@ -268,27 +304,48 @@ impl TerminalElement {
 
                 //Layout current cell text
                 {
-                    if !is_blank(&cell) {
-                        let cell_text = cell.c.to_string();
-                        let cell_style =
+                    // Cells that are part of the grapheme cluster of a previous cell only
+                    // have their background painted.
+                    if !shaping_failed && ix >= cluster_end && !is_blank(&cell) {
+                        let (cell_text, cell_count) =
+                            grapheme_cluster(line[ix..].iter().map(|cell| &cell.cell));
+                        cluster_end = ix + cell_count;
+                        let mut cell_style =
                             TerminalElement::cell_style(&cell, fg, theme, text_style, hyperlink);
+                        cell_style.len = cell_text.len();
 
-                        let layout_cell = text_system
+                        match text_system
                             .shape_line(
                                 cell_text.into(),
                                 text_style.font_size.to_pixels(cx.rem_size()),
                                 &[cell_style],
                             )
-                            .unwrap();
-
-                        cells.push(LayoutCell::new(
-                            AlacPoint::new(line_index as i32, cell.point.column.0 as i32),
-                            layout_cell,
-                        ))
+                            .log_err()
+                        {
+                            Some(layout_cell) => line_cells.push(LayoutCell::new(
+                                AlacPoint::new(line_index as i32, cell.point.column.0 as i32),
+                                layout_cell,
+                            )),
+                            None => shaping_failed = true,
+                        }
                     };
                 }
             }
 
+            // A line whose text can't be shaped is replaced as a whole, rather than
+            // rendered with gaps, and shouldn't bring down the terminal.
+            if shaping_failed {
+                cells.extend(Self::layout_placeholder_line(
+                    line_index,
+                    text_style,
+                    text_system,
+                    theme,
+                    cx,
+                ));
+            } else {
+                cells.append(&mut line_cells);
+            }
+
             if cur_rect.is_some() {
                 rects.push(cur_rect.take().unwrap());
             }
@@ -296,6 +353,60 @@ impl TerminalElement {
         (cells, rects)
     }
 
+    /// Shapes the text shown in place of a line that failed to be shaped.
+    fn layout_placeholder_line(
+        line_index: usize,
+        text_style: &TextStyle,
+        text_system: &WindowTextSystem,
+        theme: &Theme,
+        cx: &WindowContext<'_>,
+    ) -> Option<LayoutCell> {
+        let text = "\u{FFFD}";
+        let layout = text_system
+            .shape_line(
+                text.into(),
+                text_style.font_size.to_pixels(cx.rem_size()),
+                &[TextRun {
+                    len: text.len(),
+                    font: text_style.font(),
+                    color: theme.colors().text_muted,
+                    background_color: None,
+                    underline: None,
+                    strikethrough: None,
+                }],
+            )
+            .log_err()?;
+        Some(LayoutCell::new(
+            AlacPoint::new(line_index as i32, 0),
+            layout,
+        ))
+    }
+
+    /// Computes where the indicator of the viewport's position in the scrollback is
+    /// drawn, along the right edge of `bounds`. It's only shown while the terminal is
+    /// scrolled up from the bottom.
+    fn scroll_indicator_bounds(
+        bounds: Bounds<Pixels>,
+        display_offset: usize,
+        history_size: usize,
+        screen_lines: usize,
+    ) -> Option<Bounds<Pixels>> {
+        if display_offset == 0 || history_size == 0 {
+            return None;
+        }
+
+        let total_lines = (history_size + screen_lines) as f32;
+        let height = (bounds.size.height * (screen_lines as f32 / total_lines))
+            .max(SCROLL_INDICATOR_MIN_HEIGHT)
+            .min(bounds.size.height);
+        let top_line = history_size - display_offset.min(history_size);
+        let top = (bounds.size.height - height) * (top_line as f32 / history_size as f32);
+        Some(Bounds::new(
+            point(bounds.right() - SCROLL_INDICATOR_WIDTH, bounds.top() + top),
+            size(SCROLL_INDICATOR_WIDTH, height),
+        ))
+    }
+
     /// Computes the cursor position and expected block width, may return a zero width if x_for_index returns
     /// the same position for sequential indexes. Use em_width instead
     fn shape_cursor(
@@ -357,27 +468,11 @@ impl TerminalElement {
                 thickness: Pixels::from(1.0),
             });
 
-        let weight = if flags.intersects(Flags::BOLD) {
-            FontWeight::BOLD
-        } else {
-            FontWeight::NORMAL
-        };
-
-        let style = if flags.intersects(Flags::ITALIC) {
-            FontStyle::Italic
-        } else {
-            FontStyle::Normal
-        };
-
         let mut result = TextRun {
             len: indexed.c.len_utf8(),
             color: fg,
             background_color: None,
-            font: Font {
-                weight,
-                style,
-                ..text_style.font()
-            },
+            font: Self::cell_font(flags, text_style),
             underline,
             strikethrough,
         };
@@ -397,6 +492,55 @@ impl TerminalElement {
         result
     }
 
+    /// Converts the bold and italic flags of a cell to a font.
+    fn cell_font(flags: Flags, text_style: &TextStyle) -> Font {
+        let weight = if flags.intersects(Flags::BOLD) {
+            FontWeight::BOLD
+        } else {
+            FontWeight::NORMAL
+        };
+
+        let style = if flags.intersects(Flags::ITALIC) {
+            FontStyle::Italic
+        } else {
+            FontStyle::Normal
+        };
+
+        Font {
+            weight,
+            style,
+            ..text_style.font()
+        }
+    }
+
+    /// Computes the colors of a block cursor and of the character drawn on top of it.
+    /// Like other terminals, the cursor swaps the foreground and background of the cell
+    /// under it, after applying inverse video, so the character stays readable. Cells
+    /// with the default colors keep the theme's cursor color instead.
+    fn cursor_colors(cell: Option<&IndexedCell>, theme: &Theme) -> (Hsla, Hsla) {
+        let default_colors = (
+            theme.players().local().cursor,
+            theme.colors().terminal_background,
+        );
+        let Some(cell) = cell else {
+            return default_colors;
+        };
+
+        let mut fg = cell.fg;
+        let mut bg = cell.bg;
+        if cell.flags.contains(Flags::INVERSE) {
+            mem::swap(&mut fg, &mut bg);
+        }
+
+        if matches!(fg, Named(NamedColor::Foreground))
+            && matches!(bg, Named(NamedColor::Background))
+        {
+            default_colors
+        } else {
+            (convert_color(&fg, theme), convert_color(&bg, theme))
+        }
+    }
+
     fn generic_button_handler<E>(
         connection: Model<Terminal>,
         origin: Point<Pixels>,
@@ -594,6 +738,8 @@ impl Element for TerminalElement {
                 let font_weight = terminal_settings.font_weight.unwrap_or_default();
 
                 let line_height = terminal_settings.line_height.value();
+                let background_opacity = terminal_settings.background_opacity.clamp(0., 1.);
+                let show_wrap_indicator = terminal_settings.show_wrap_indicator;
                 let font_size = terminal_settings.font_size;
 
                 let font_size =
@@ -660,8 +806,11 @@ impl Element for TerminalElement {
                 };
 
                 let search_matches = self.terminal.read(cx).matches.clone();
+                let selected_matches = self.terminal.read(cx).selected_matches().to_vec();
+                let quick_select = self.terminal.read(cx).quick_select().cloned();
 
-                let background_color = theme.colors().terminal_background;
+                let mut background_color = theme.colors().terminal_background;
+                background_color.fade_out(1. - background_opacity);
 
                 let last_hovered_word = self.terminal.update(cx, |terminal, cx| {
                     terminal.set_size(dimensions);
@@ -690,9 +839,11 @@ impl Element for TerminalElement {
                     cells,
                     mode,
                     display_offset,
-                    cursor_char,
+                    cursor_text,
                     selection,
                     cursor,
+                    wrapped_lines,
+                    history_size,
                     ..
                 } = &self.terminal.read(cx).last_content;
 
@@ -701,11 +852,39 @@ impl Element for TerminalElement {
                 for search_match in search_matches {
                     relative_highlighted_ranges.push((search_match, match_color))
                 }
-                if let Some(selection) = selection {
+                if selected_matches.is_empty() {
+                    if let Some(selection) = selection {
+                        relative_highlighted_ranges
+                            .push((selection.start..=selection.end, player_color.selection));
+                    }
+                } else {
+                    for selected_match in selected_matches {
+                        relative_highlighted_ranges.push((selected_match, player_color.selection));
+                    }
+                }
+                // Only the hints whose tag starts with the letters typed so far are shown.
+                let quick_select_hints = quick_select
+                    .iter()
+                    .flat_map(|quick_select| {
+                        quick_select
+                            .hints
+                            .iter()
+                            .filter(|hint| hint.tag.starts_with(&quick_select.typed))
+                    })
+                    .collect::<Vec<_>>();
+                for hint in &quick_select_hints {
                     relative_highlighted_ranges
-                        .push((selection.start..=selection.end, player_color.selection));
+                        .push((hint.range.clone(), theme.status().info_background));
                 }
 
+                let cursor_cell = cells.iter().find(|cell| cell.point == cursor.point);
+                let (cursor_color, cursor_text_color) =
+                    TerminalElement::cursor_colors(cursor_cell, &theme);
+                let cursor_font = match cursor_cell {
+                    Some(cell) => TerminalElement::cell_font(cell.flags, &text_style),
+                    None => text_style.font(),
+                };
+
                 // then have that representation be converted to the appropriate highlight data structure
 
                 let (cells, rects) = TerminalElement::layout_grid(
@@ -718,6 +897,37 @@ impl Element for TerminalElement {
                     cx,
                 );
 
+                let tag_font = Font {
+                    weight: FontWeight::BOLD,
+                    ..text_style.font()
+                };
+                let quick_select_tags = quick_select_hints
+                    .iter()
+                    .filter_map(|hint| {
+                        let point = DisplayCursor::from(*hint.range.start(), *display_offset);
+                        let text = cx
+                            .text_system()
+                            .shape_line(
+                                hint.tag.clone().into(),
+                                text_style.font_size.to_pixels(cx.rem_size()),
+                                &[TextRun {
+                                    len: hint.tag.len(),
+                                    font: tag_font.clone(),
+                                    color: theme.colors().editor_background,
+                                    background_color: None,
+                                    underline: None,
+                                    strikethrough: None,
+                                }],
+                            )
+                            .log_err()?;
+                        Some(LayoutQuickSelectTag {
+                            point: AlacPoint::new(point.line(), point.col() as i32),
+                            text,
+                            background_color: theme.colors().text_accent,
+                        })
+                    })
+                    .collect();
+
                 // Layout cursor. Rectangle is used for IME, so we should lay it out even
                 // if we don't end up showing it.
                 let cursor = if let AlacCursorShape::Hidden = cursor.shape {
@@ -725,49 +935,96 @@ impl Element for TerminalElement {
                 } else {
                     let cursor_point = DisplayCursor::from(cursor.point, *display_offset);
                     let cursor_text = {
-                        let str_trxt = cursor_char.to_string();
-                        let len = str_trxt.len();
+                        let len = cursor_text.len();
                         cx.text_system()
                             .shape_line(
-                                str_trxt.into(),
+                                cursor_text.clone().into(),
                                 text_style.font_size.to_pixels(cx.rem_size()),
                                 &[TextRun {
                                     len,
-                                    font: text_style.font(),
-                                    color: theme.colors().terminal_background,
+                                    font: cursor_font,
+                                    color: cursor_text_color,
                                     background_color: None,
                                     underline: Default::default(),
                                     strikethrough: None,
                                 }],
                             )
-                            .unwrap()
+                            .log_err()
                     };
 
                     let focused = self.focused;
-                    TerminalElement::shape_cursor(cursor_point, dimensions, &cursor_text).map(
-                        move |(cursor_position, block_width)| {
-                            let (shape, text) = match cursor.shape {
-                                AlacCursorShape::Block if !focused => (CursorShape::Hollow, None),
-                                AlacCursorShape::Block => (CursorShape::Block, Some(cursor_text)),
-                                AlacCursorShape::Underline => (CursorShape::Underscore, None),
-                                AlacCursorShape::Beam => (CursorShape::Bar, None),
-                                AlacCursorShape::HollowBlock => (CursorShape::Hollow, None),
-                                //This case is handled in the if wrapping the whole cursor layout
-                                AlacCursorShape::Hidden => unreachable!(),
-                            };
-
-                            CursorLayout::new(
-                                cursor_position,
-                                block_width,
-                                dimensions.line_height,
-                                theme.players().local().cursor,
-                                shape,
-                                text,
+                    cursor_text.and_then(|cursor_text| {
+                        TerminalElement::shape_cursor(cursor_point, dimensions, &cursor_text).map(
+                            move |(cursor_position, block_width)| {
+                                let (shape, text) = match cursor.shape {
+                                    AlacCursorShape::Block if !focused => {
+                                        (CursorShape::Hollow, None)
+                                    }
+                                    AlacCursorShape::Block => {
+                                        (CursorShape::Block, Some(cursor_text))
+                                    }
+                                    AlacCursorShape::Underline => (CursorShape::Underscore, None),
+                                    AlacCursorShape::Beam => (CursorShape::Bar, None),
+                                    AlacCursorShape::HollowBlock => (CursorShape::Hollow, None),
+                                    //This case is handled in the if wrapping the whole cursor layout
+                                    AlacCursorShape::Hidden => unreachable!(),
+                                };
+
+                                CursorLayout::new(
+                                    cursor_position,
+                                    block_width,
+                                    dimensions.line_height,
+                                    match shape {
+                                        CursorShape::Block => cursor_color,
+                                        _ => theme.players().local().cursor,
+                                    },
+                                    shape,
+                                    text,
+                                )
+                            },
+                        )
+                    })
+                };
+
+                // Continuations of soft-wrapped lines are marked in the gutter.
+                let (wrap_indicator, wrapped_lines) = if show_wrap_indicator {
+                    let wrapped_lines = wrapped_lines
+                        .iter()
+                        .map(|line| line.0 + *display_offset as i32)
+                        .collect::<Vec<_>>();
+                    let text = "↪";
+                    let wrap_indicator = (!wrapped_lines.is_empty()).then(|| {
+                        cx.text_system()
+                            .shape_line(
+                                text.into(),
+                                text_style.font_size.to_pixels(cx.rem_size()),
+                                &[TextRun {
+                                    len: text.len(),
+                                    font: text_style.font(),
+                                    color: theme.colors().text_muted,
+                                    background_color: None,
+                                    underline: Default::default(),
+                                    strikethrough: None,
+                                }],
                             )
-                        },
-                    )
+                            .log_err()
+                    });
+                    (wrap_indicator.flatten(), wrapped_lines)
+                } else {
+                    (None, Vec::new())
                 };
 
+                let scroll_indicator = TerminalElement::scroll_indicator_bounds(
+                    bounds,
+                    *display_offset,
+                    *history_size,
+                    dimensions.num_lines(),
+                )
+                .map(|indicator_bounds| {
+                    fill(indicator_bounds, theme.colors().scrollbar_thumb_background)
+                        .corner_radii(SCROLL_INDICATOR_WIDTH / 2.)
+                });
+
                 LayoutState {
                     hitbox,
                     cells,
@@ -781,6 +1038,10 @@ impl Element for TerminalElement {
                     hyperlink_tooltip,
                     gutter,
                     last_hovered_word,
+                    quick_select_tags,
+                    wrap_indicator,
+                    wrapped_lines,
+                    scroll_indicator,
                 }
             })
     }
@@ -859,6 +1120,26 @@ impl Element for TerminalElement {
                     cell.paint(origin, &layout, bounds, cx);
                 }
 
+                for tag in &layout.quick_select_tags {
+                    tag.paint(origin, &layout, cx);
+                }
+
+                if let Some(wrap_indicator) = &layout.wrap_indicator {
+                    for line in &layout.wrapped_lines {
+                        let position = point(
+                            bounds.origin.x,
+                            origin.y + *line as f32 * layout.dimensions.line_height,
+                        );
+                        wrap_indicator
+                            .paint(position, layout.dimensions.line_height, cx)
+                            .ok();
+                    }
+                }
+
+                if let Some(scroll_indicator) = layout.scroll_indicator.take() {
+                    cx.paint_quad(scroll_indicator);
+                }
+
                 if self.cursor_visible {
                     if let Some(mut cursor) = cursor {
                         cursor.paint(origin, cx);
@@ -920,7 +1201,7 @@ impl InputHandler for TerminalInputHandler {
         cx: &mut WindowContext,
     ) {
         self.terminal.update(cx, |terminal, _| {
-            terminal.input(text.into());
+            terminal.input_str(text);
         });
 
         self.workspace
@@ -956,6 +1237,10 @@ fn is_blank(cell: &IndexedCell) -> bool {
         return false;
     }
 
+    if cell.zerowidth().is_some() {
+        return false;
+    }
+
     if cell.bg != AnsiColor::Named(NamedColor::Background) {
         return false;
     }
@@ -1063,7 +1348,7 @@ pub fn convert_color(fg: &terminal::alacritty_terminal::vte::ansi::Color, theme:
             NamedColor::BrightCyan => colors.terminal_ansi_bright_cyan,
             NamedColor::BrightWhite => colors.terminal_ansi_bright_white,
             NamedColor::Foreground => colors.text,
-            NamedColor::Background => colors.background,
+            NamedColor::Background => colors.terminal_background,
             NamedColor::Cursor => theme.players().local().cursor,
             NamedColor::DimBlack => colors.terminal_ansi_dim_black,
             NamedColor::DimRed => colors.terminal_ansi_dim_red,
@@ -1,23 +1,28 @@
 use std::{ops::ControlFlow, path::PathBuf, sync::Arc};
 
-use crate::TerminalView;
+use crate::{
+    recent_directories::RecentDirectories, tmux_window_view::TmuxWindowView, TerminalView,
+};
 use collections::{HashMap, HashSet};
 use db::kvp::KEY_VALUE_STORE;
 use futures::future::join_all;
 use gpui::{
-    actions, Action, AppContext, AsyncWindowContext, DismissEvent, Entity, EventEmitter,
-    ExternalPaths, FocusHandle, FocusableView, IntoElement, Model, ParentElement, Pixels, Render,
-    Styled, Subscription, Task, View, ViewContext, VisualContext, WeakView, WindowContext,
+    actions, impl_actions, Action, AppContext, AsyncWindowContext, Context, DismissEvent, Entity,
+    EventEmitter, ExternalPaths, FocusHandle, FocusableView, IntoElement, Model, ParentElement,
+    Pixels, Render, Styled, Subscription, Task, View, ViewContext, VisualContext, WeakView,
+    WindowContext,
 };
 use itertools::Itertools;
 use project::{Fs, ProjectEntryId};
 use search::{buffer_search::DivRegistrar, BufferSearchBar};
 use serde::{Deserialize, Serialize};
-use settings::Settings;
+use settings::{Settings, SettingsStore};
 use task::{RevealStrategy, SpawnInTerminal, TaskId, TerminalWorkDir};
 use terminal::{
+    byte_stream::ByteStream,
     terminal_settings::{Shell, TerminalDockPosition, TerminalSettings},
-    Terminal,
+    tmux::{TmuxEvent, TmuxSession},
+    Clear, Terminal, TerminalBuilder,
 };
 use ui::{
     h_flex, ButtonCommon, Clickable, ContextMenu, FluentBuilder, IconButton, IconSize, Selectable,
@@ -27,22 +32,60 @@ use util::{ResultExt, TryFutureExt};
 use workspace::{
     dock::{DockPosition, Panel, PanelEvent},
     item::Item,
+    notifications::{NotificationId, NotifyResultExt},
     pane,
     ui::IconName,
-    DraggedTab, NewTerminal, Pane, ToggleZoom, Workspace,
+    DraggedTab, NewTerminal, Pane, Toast, ToggleZoom, Workspace,
 };
 
 use anyhow::Result;
 
 const TERMINAL_PANEL_KEY: &str = "TerminalPanel";
+/// The most terminals remembered by `FocusPreviousTerminal`.
+const MAX_FOCUS_HISTORY: usize = 32;
+
+actions!(
+    terminal_panel,
+    [
+        ToggleFocus,
+        FocusPreviousTerminal,
+        NewTerminalIn,
+        AttachTmuxSession,
+    ]
+);
+
+/// Opens a terminal on a serial device, like the console of a development board, instead
+/// of a shell.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct OpenSerialTerminal {
+    pub path: PathBuf,
+    #[serde(default = "default_baud_rate")]
+    pub baud_rate: u32,
+}
+
+fn default_baud_rate() -> u32 {
+    115200
+}
+
+/// Opens a terminal on the standard input and output of a command, like `docker attach`,
+/// instead of a shell.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct OpenCommandTerminal {
+    pub command: Vec<String>,
+}
 
-actions!(terminal_panel, [ToggleFocus]);
+impl_actions!(terminal_panel, [OpenSerialTerminal, OpenCommandTerminal]);
 
 pub fn init(cx: &mut AppContext) {
     cx.observe_new_views(
         |workspace: &mut Workspace, _: &mut ViewContext<Workspace>| {
             workspace.register_action(TerminalPanel::new_terminal);
             workspace.register_action(TerminalPanel::open_terminal);
+            workspace.register_action(TerminalPanel::focus_previous_terminal);
+            workspace.register_action(TerminalPanel::attach_tmux_session);
+            workspace.register_action(TerminalPanel::open_serial_terminal);
+            workspace.register_action(TerminalPanel::open_command_terminal);
+            workspace.register_action(RecentDirectories::toggle);
             workspace.register_action(|workspace, _: &ToggleFocus, cx| {
                 if workspace
                     .panel::<TerminalPanel>(cx)
@@ -68,6 +111,14 @@ pub struct TerminalPanel {
     _subscriptions: Vec<Subscription>,
     deferred_tasks: HashMap<TaskId, Task<()>>,
     enabled: bool,
+    /// The terminals that had focus, in the workspace or in this panel, most recent last.
+    focus_history: Vec<WeakView<TerminalView>>,
+    position: DockPosition,
+    /// The terminal that had focus when the panel was asked to move to another dock, which
+    /// gets it back once the move has happened.
+    focus_after_move: Option<WeakView<TerminalView>>,
+    /// The tmux sessions attached in control mode, whose panes are shown in this panel.
+    tmux_sessions: Vec<(Model<TmuxSession>, Subscription)>,
 }
 
 impl TerminalPanel {
@@ -123,6 +174,21 @@ impl TerminalPanel {
                     .when_some(pane.new_item_menu.as_ref(), |el, new_item_menu| {
                         el.child(Pane::render_menu_overlay(new_item_menu))
                     })
+                    .child(
+                        IconButton::new("clear", IconName::Trash)
+                            .icon_size(IconSize::Small)
+                            .on_click(cx.listener(|pane, _, cx| {
+                                if let Some(terminal_view) = pane
+                                    .active_item()
+                                    .and_then(|item| item.downcast::<TerminalView>())
+                                {
+                                    terminal_view.update(cx, |terminal_view, cx| {
+                                        terminal_view.clear(&Clear, cx)
+                                    });
+                                }
+                            }))
+                            .tooltip(|cx| Tooltip::for_action("Clear", &Clear, cx)),
+                    )
                     .child({
                         let zoomed = pane.is_zoomed();
                         IconButton::new("toggle_zoom", IconName::Maximize)
@@ -196,6 +262,7 @@ impl TerminalPanel {
         let subscriptions = vec![
             cx.observe(&pane, |_, _, cx| cx.notify()),
             cx.subscribe(&pane, Self::handle_pane_event),
+            cx.observe_global::<SettingsStore>(Self::settings_changed),
         ];
         let project = workspace.project().read(cx);
         let enabled = project.is_local() || project.supports_remote_terminal(cx);
@@ -210,6 +277,10 @@ impl TerminalPanel {
             deferred_tasks: HashMap::default(),
             _subscriptions: subscriptions,
             enabled,
+            focus_history: Vec::new(),
+            position: Self::dock_position(cx),
+            focus_after_move: None,
+            tmux_sessions: Vec::new(),
         };
         this
     }
@@ -319,6 +390,7 @@ impl TerminalPanel {
                     let pane = self.pane.clone();
                     workspace.update(cx, |workspace, cx| item.added_to_pane(workspace, pane, cx))
                 }
+                self.serialize(cx);
             }
 
             _ => {}
@@ -463,6 +535,144 @@ impl TerminalPanel {
             .detach_and_log_err(cx);
     }
 
+    /// Attaches to tmux in control mode with the `tmux_command` setting, opening a tab
+    /// for each window of the session with its panes split inside it.
+    fn attach_tmux_session(
+        workspace: &mut Workspace,
+        _: &AttachTmuxSession,
+        cx: &mut ViewContext<Workspace>,
+    ) {
+        let Some(terminal_panel) = workspace.panel::<Self>(cx) else {
+            return;
+        };
+
+        let command = TerminalSettings::get_global(cx).tmux_command.clone();
+        let result = terminal_panel.update(cx, |terminal_panel, cx| {
+            let session = TmuxSession::spawn(&command, cx)?;
+            let subscription = cx.subscribe(&session, Self::handle_tmux_event);
+            terminal_panel.tmux_sessions.push((session, subscription));
+            anyhow::Ok(())
+        });
+        if result.notify_err(workspace, cx).is_some() {
+            workspace.focus_panel::<Self>(cx);
+        }
+    }
+
+    fn handle_tmux_event(
+        &mut self,
+        session: Model<TmuxSession>,
+        event: &TmuxEvent,
+        cx: &mut ViewContext<Self>,
+    ) {
+        match event {
+            TmuxEvent::WindowAdded(window) => {
+                let Some(workspace) = self.workspace.upgrade() else {
+                    return;
+                };
+                let workspace_id = workspace.read(cx).database_id();
+                let window_view = Box::new(cx.new_view(|cx| {
+                    TmuxWindowView::new(
+                        session.clone(),
+                        *window,
+                        self.workspace.clone(),
+                        workspace_id,
+                        cx,
+                    )
+                }));
+                self.pane.update(cx, |pane, cx| {
+                    let focus = pane.has_focus(cx);
+                    pane.add_item(window_view, true, focus, None, cx);
+                });
+            }
+            TmuxEvent::Exited { reason } => {
+                self.tmux_sessions
+                    .retain(|(attached, _)| attached != &session);
+                if let Some(reason) = reason.clone() {
+                    struct TmuxExited;
+                    self.workspace
+                        .update(cx, |workspace, cx| {
+                            workspace.show_toast(
+                                Toast::new(
+                                    NotificationId::unique::<TmuxExited>(),
+                                    format!("tmux exited: {reason}"),
+                                ),
+                                cx,
+                            )
+                        })
+                        .ok();
+                }
+            }
+            TmuxEvent::LayoutChanged(_) | TmuxEvent::WindowClosed(_) => {}
+        }
+    }
+
+    fn open_serial_terminal(
+        workspace: &mut Workspace,
+        action: &OpenSerialTerminal,
+        cx: &mut ViewContext<Workspace>,
+    ) {
+        let stream = ByteStream::open_serial_device(&action.path, action.baud_rate);
+        let title = action.path.to_string_lossy().into_owned();
+        Self::open_stream_terminal(workspace, stream, title, cx);
+    }
+
+    fn open_command_terminal(
+        workspace: &mut Workspace,
+        action: &OpenCommandTerminal,
+        cx: &mut ViewContext<Workspace>,
+    ) {
+        let stream = ByteStream::spawn(&action.command);
+        let title = action.command.join(" ");
+        Self::open_stream_terminal(workspace, stream, title, cx);
+    }
+
+    fn open_stream_terminal(
+        workspace: &mut Workspace,
+        stream: Result<ByteStream>,
+        title: String,
+        cx: &mut ViewContext<Workspace>,
+    ) {
+        let Some(terminal_panel) = workspace.panel::<Self>(cx) else {
+            return;
+        };
+        let Some(stream) = stream.notify_err(workspace, cx) else {
+            return;
+        };
+
+        let settings = TerminalSettings::get_global(cx);
+        let builder = TerminalBuilder::new_for_stream(
+            stream,
+            Some(settings.blinking),
+            settings.alternate_scroll,
+            settings.max_scroll_history_lines,
+        );
+        let terminal = cx.new_model(|cx| {
+            let mut terminal = builder.subscribe(cx);
+            terminal.set_title_override(Some(title), cx);
+            terminal
+        });
+        terminal_panel.update(cx, |terminal_panel, cx| {
+            terminal_panel.add_detached_terminal(terminal, cx)
+        });
+        workspace.focus_panel::<Self>(cx);
+    }
+
+    /// Adds a tab for a terminal that doesn't run a shell, which Zed didn't spawn.
+    fn add_detached_terminal(&mut self, terminal: Model<Terminal>, cx: &mut ViewContext<Self>) {
+        let Some(workspace) = self.workspace.upgrade() else {
+            return;
+        };
+        let workspace_id = workspace.read(cx).database_id();
+        let terminal_view =
+            Box::new(cx.new_view(|cx| {
+                TerminalView::new(terminal, self.workspace.clone(), workspace_id, cx)
+            }));
+        self.pane.update(cx, |pane, cx| {
+            let focus = pane.has_focus(cx);
+            pane.add_item(terminal_view, true, focus, None, cx);
+        });
+    }
+
     fn terminals_for_task(
         &self,
         label: &str,
@@ -484,6 +694,78 @@ impl TerminalPanel {
             .collect()
     }
 
+    /// Remembers that `terminal_view` got focus, for `FocusPreviousTerminal`.
+    pub(crate) fn record_focus(&mut self, terminal_view: WeakView<TerminalView>) {
+        self.focus_history
+            .retain(|entry| entry.upgrade().is_some() && *entry != terminal_view);
+        self.focus_history.push(terminal_view);
+        if self.focus_history.len() > MAX_FOCUS_HISTORY {
+            self.focus_history.remove(0);
+        }
+    }
+
+    /// The most recently focused terminal that is still open and doesn't have focus now.
+    fn previous_terminal(&self, cx: &WindowContext) -> Option<View<TerminalView>> {
+        self.focus_history
+            .iter()
+            .rev()
+            .filter_map(|entry| entry.upgrade())
+            .find(|terminal_view| !terminal_view.focus_handle(cx).contains_focused(cx))
+    }
+
+    /// Focuses the terminal that had focus before the current one, wherever it is now:
+    /// in a pane of the workspace or in this panel.
+    fn focus_previous_terminal(
+        workspace: &mut Workspace,
+        _: &FocusPreviousTerminal,
+        cx: &mut ViewContext<Workspace>,
+    ) {
+        let Some(terminal_panel) = workspace.panel::<Self>(cx) else {
+            return;
+        };
+        let Some(terminal_view) = terminal_panel.read(cx).previous_terminal(cx) else {
+            return;
+        };
+        if workspace.activate_item(&terminal_view, cx) {
+            return;
+        }
+
+        let panel_pane = terminal_panel.read(cx).pane.clone();
+        let Some(item_index) = panel_pane.read(cx).index_for_item(&terminal_view) else {
+            return;
+        };
+        workspace.open_panel::<Self>(cx);
+        terminal_panel.update(cx, |terminal_panel, cx| {
+            terminal_panel.activate_terminal_view(item_index, cx)
+        });
+    }
+
+    fn dock_position(cx: &WindowContext) -> DockPosition {
+        match TerminalSettings::get_global(cx).dock {
+            TerminalDockPosition::Left => DockPosition::Left,
+            TerminalDockPosition::Bottom => DockPosition::Bottom,
+            TerminalDockPosition::Right => DockPosition::Right,
+        }
+    }
+
+    /// Moving the panel to another dock renders it anew, which loses the focus of its
+    /// terminal, so the terminal that had it is focused again after the move.
+    fn settings_changed(&mut self, cx: &mut ViewContext<Self>) {
+        let position = Self::dock_position(cx);
+        if position == self.position {
+            return;
+        }
+        self.position = position;
+        if let Some(terminal_view) = self
+            .focus_after_move
+            .take()
+            .and_then(|terminal_view| terminal_view.upgrade())
+        {
+            // Focus once the dock has moved the panel.
+            cx.defer(move |_, cx| terminal_view.focus_handle(cx).focus(cx));
+        }
+    }
+
     fn activate_terminal_view(&self, item_index: usize, cx: &mut WindowContext) {
         self.pane.update(cx, |pane, cx| {
             pane.activate_item(item_index, true, true, cx)
@@ -562,7 +844,8 @@ impl TerminalPanel {
             .items()
             .filter_map(|item| {
                 let terminal_view = item.act_as::<TerminalView>(cx)?;
-                if terminal_view.read(cx).terminal().read(cx).task().is_some() {
+                let terminal = terminal_view.read(cx).terminal().read(cx);
+                if terminal.task().is_some() || terminal.is_detached() {
                     None
                 } else {
                     let id = item.item_id().as_u64();
@@ -660,7 +943,7 @@ async fn wait_for_terminals_tasks(
             })
             .ok()
     });
-    let _: Vec<()> = join_all(pending_tasks).await;
+    let _: Vec<_> = join_all(pending_tasks).await;
 }
 
 fn add_paths_to_terminal(pane: &mut Pane, paths: &[PathBuf], cx: &mut ViewContext<'_, Pane>) {
@@ -707,11 +990,7 @@ impl FocusableView for TerminalPanel {
 
 impl Panel for TerminalPanel {
     fn position(&self, cx: &WindowContext) -> DockPosition {
-        match TerminalSettings::get_global(cx).dock {
-            TerminalDockPosition::Left => DockPosition::Left,
-            TerminalDockPosition::Bottom => DockPosition::Bottom,
-            TerminalDockPosition::Right => DockPosition::Right,
-        }
+        Self::dock_position(cx)
     }
 
     fn position_is_valid(&self, _: DockPosition) -> bool {
@@ -719,6 +998,13 @@ impl Panel for TerminalPanel {
     }
 
     fn set_position(&mut self, position: DockPosition, cx: &mut ViewContext<Self>) {
+        self.focus_after_move = self
+            .pane
+            .read(cx)
+            .active_item()
+            .and_then(|item| item.downcast::<TerminalView>())
+            .filter(|terminal_view| terminal_view.focus_handle(cx).contains_focused(cx))
+            .map(|terminal_view| terminal_view.downgrade());
         settings::update_settings_file::<TerminalSettings>(self.fs.clone(), cx, move |settings| {
             let dock = match position {
                 DockPosition::Left => TerminalDockPosition::Left,
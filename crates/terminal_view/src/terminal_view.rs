@@ -1,37 +1,51 @@
+mod command_history;
 mod persistence;
+mod recent_directories;
+mod shell_integration;
 pub mod terminal_element;
 pub mod terminal_panel;
+mod tmux_window_view;
 
+use audio::{Audio, Sound};
 use collections::HashSet;
-use editor::{scroll::Autoscroll, Editor};
+use editor::{scroll::Autoscroll, Editor, EditorEvent, MultiBuffer};
+use file_icons::FileIcons;
 use futures::{stream::FuturesUnordered, StreamExt};
 use gpui::{
-    anchored, deferred, div, impl_actions, AnyElement, AppContext, DismissEvent, EventEmitter,
-    FocusHandle, FocusableView, KeyContext, KeyDownEvent, Keystroke, Model, MouseButton,
-    MouseDownEvent, Pixels, Render, Styled, Subscription, Task, View, VisualContext, WeakView,
+    anchored, deferred, div, impl_actions, AnyElement, AppContext, ClipboardItem, DismissEvent,
+    EventEmitter, FocusHandle, FocusableView, KeyContext, KeyDownEvent, Keystroke, Model,
+    MouseButton, MouseDownEvent, Pixels, Render, Styled, Subscription, Task, View, VisualContext,
+    WeakView,
 };
-use language::Bias;
+use human_bytes::human_bytes;
+use language::{Bias, Buffer, Capability};
 use persistence::TERMINAL_DB;
 use project::{search::SearchQuery, Fs, LocalWorktree, Metadata, Project};
 use settings::SettingsStore;
 use task::TerminalWorkDir;
 use terminal::{
     alacritty_terminal::{
+        grid::Scroll as AlacScroll,
         index::Point,
         term::{search::RegexSearch, TermMode},
     },
-    terminal_settings::{TerminalBlink, TerminalSettings, WorkingDirectory},
-    Clear, Copy, Event, MaybeNavigationTarget, Paste, ShowCharacterPalette, TaskStatus, Terminal,
+    quick_select::QuickSelectKind,
+    terminal_settings::{AlertOnBell, TerminalBlink, TerminalSettings, WorkingDirectory},
+    transform_pasted_text, Clear, ClearToPreviousPrompt, Copy, CopyEnvironment, Event,
+    MaybeNavigationTarget, Paste, PathLikeTarget, QuickSelect, RenameTerminal, ScrollLineDown,
+    ScrollLineUp, ScrollPageDown, ScrollPageUp, ScrollToBottom, ScrollToTop, ShowCharacterPalette,
+    ShowMemoryUsage, TaskStatus, Terminal,
 };
 use terminal_element::TerminalElement;
+use terminal_panel::TerminalPanel;
 use ui::{h_flex, prelude::*, ContextMenu, Icon, IconName, Label, Tooltip};
 use util::{paths::PathLikeWithPosition, ResultExt};
 use workspace::{
     item::{BreadcrumbText, Item, ItemEvent, TabContentParams},
-    notifications::NotifyResultExt,
+    notifications::{NotificationId, NotifyResultExt},
     register_deserializable_item,
     searchable::{SearchEvent, SearchOptions, SearchableItem, SearchableItemHandle},
-    CloseActiveItem, NewCenterTerminal, OpenVisible, Pane, ToolbarItemLocation, Workspace,
+    CloseActiveItem, NewCenterTerminal, OpenVisible, Pane, Toast, ToolbarItemLocation, Workspace,
     WorkspaceId,
 };
 
@@ -53,6 +67,8 @@ const REGEX_SPECIAL_CHARS: &[char] = &[
 ];
 
 const CURSOR_BLINK_INTERVAL: Duration = Duration::from_millis(500);
+/// How long the terminal's dimensions are shown after a font size change reflowed it.
+const GRID_SIZE_NOTICE_DURATION: Duration = Duration::from_millis(1500);
 
 ///Event to transmit the scroll from the element to the view
 #[derive(Clone, Debug, PartialEq)]
@@ -64,7 +80,17 @@ pub struct SendText(String);
 #[derive(Clone, Debug, Default, Deserialize, PartialEq)]
 pub struct SendKeystroke(String);
 
-impl_actions!(terminal, [SendText, SendKeystroke]);
+/// Opens the terminal's output in a read-only editor, to search and copy it with the
+/// editor's tools.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+pub struct OpenOutputInEditor {
+    /// Only open the output of the last command entered at the prompt, rather than the
+    /// whole scrollback.
+    #[serde(default)]
+    pub last_command: bool,
+}
+
+impl_actions!(terminal, [SendText, SendKeystroke, OpenOutputInEditor]);
 
 pub fn init(cx: &mut AppContext) {
     terminal_panel::init(cx);
@@ -74,10 +100,14 @@ pub fn init(cx: &mut AppContext) {
 
     cx.observe_new_views(|workspace: &mut Workspace, _| {
         workspace.register_action(TerminalView::deploy);
+        workspace.register_action(TerminalView::show_memory_usage);
     })
     .detach();
 }
 
+/// Identifies the notifications shown when a terminal's bell rings.
+struct TerminalBell;
+
 ///A terminal view, maintains the PTY's file handles and communicates with the terminal
 pub struct TerminalView {
     terminal: Model<Terminal>,
@@ -85,6 +115,8 @@ pub struct TerminalView {
     focus_handle: FocusHandle,
     //Currently using iTerm bell, show bell emoji in tab until input is received
     has_bell: bool,
+    /// Overrides the `alert_on_bell` setting for this terminal.
+    alert_on_bell: Option<AlertOnBell>,
     context_menu: Option<(View<ContextMenu>, gpui::Point<Pixels>, Subscription)>,
     blink_state: bool,
     blinking_on: bool,
@@ -93,6 +125,10 @@ pub struct TerminalView {
     can_navigate_to_selected_word: bool,
     workspace_id: Option<WorkspaceId>,
     show_title: bool,
+    grid_size_notice: Option<SharedString>,
+    grid_size_notice_epoch: usize,
+    /// The editor shown in the tab while the terminal is being renamed.
+    rename_editor: Option<(View<Editor>, Subscription)>,
     _subscriptions: Vec<Subscription>,
     _terminal_subscriptions: Vec<Subscription>,
 }
@@ -139,6 +175,42 @@ impl TerminalView {
         }
     }
 
+    /// Reports how much memory the scrollback of each of the project's terminals holds.
+    fn show_memory_usage(
+        workspace: &mut Workspace,
+        _: &ShowMemoryUsage,
+        cx: &mut ViewContext<Workspace>,
+    ) {
+        let mut lines = Vec::new();
+        let mut total_bytes = 0;
+        for terminal in workspace.project().read(cx).local_terminal_handles() {
+            let Some(terminal) = terminal.upgrade() else {
+                continue;
+            };
+            let terminal = terminal.read(cx);
+            let usage = terminal.memory_usage();
+            total_bytes += usage.grid_bytes;
+            lines.push(format!(
+                "{}: {}/{} lines of history, {}",
+                terminal.title(true),
+                usage.history_lines,
+                usage.max_history_lines,
+                human_bytes(usage.grid_bytes as f64),
+            ));
+        }
+
+        let message = if lines.is_empty() {
+            "No terminals are open".to_string()
+        } else {
+            lines.push(format!("Total: {}", human_bytes(total_bytes as f64)));
+            lines.join("\n")
+        };
+        workspace.show_toast(
+            Toast::new(NotificationId::unique::<ShowMemoryUsage>(), message),
+            cx,
+        );
+    }
+
     pub fn new(
         terminal: Model<Terminal>,
         workspace: WeakView<Workspace>,
@@ -160,6 +232,7 @@ impl TerminalView {
             terminal,
             workspace: workspace_handle,
             has_bell: false,
+            alert_on_bell: None,
             focus_handle,
             context_menu: None,
             blink_state: true,
@@ -169,9 +242,13 @@ impl TerminalView {
             can_navigate_to_selected_word: false,
             workspace_id,
             show_title: TerminalSettings::get_global(cx).toolbar.title,
+            grid_size_notice: None,
+            grid_size_notice_epoch: 0,
+            rename_editor: None,
             _subscriptions: vec![
                 focus_in,
                 focus_out,
+                cx.observe_window_activation(Self::window_activation_changed),
                 cx.observe_global::<SettingsStore>(Self::settings_changed),
             ],
             _terminal_subscriptions: terminal_subscriptions,
@@ -191,14 +268,76 @@ impl TerminalView {
         cx.emit(Event::Wakeup);
     }
 
+    /// How this terminal alerts the user when the bell rings.
+    pub fn alert_on_bell(&self, cx: &AppContext) -> AlertOnBell {
+        self.alert_on_bell
+            .unwrap_or_else(|| TerminalSettings::get_global(cx).alert_on_bell)
+    }
+
+    pub fn set_alert_on_bell(&mut self, alert_on_bell: AlertOnBell, cx: &mut ViewContext<Self>) {
+        self.alert_on_bell = Some(alert_on_bell);
+        if alert_on_bell == AlertOnBell::None {
+            self.clear_bell(cx);
+        }
+    }
+
+    fn ring_bell(&mut self, cx: &mut ViewContext<Self>) {
+        let alert_on_bell = self.alert_on_bell(cx);
+        if alert_on_bell == AlertOnBell::None {
+            return;
+        }
+
+        self.has_bell = true;
+        cx.emit(Event::Wakeup);
+        match alert_on_bell {
+            AlertOnBell::Audible => Audio::play_sound(Sound::TerminalBell, cx),
+            AlertOnBell::Notify => {
+                let message = format!("Bell in {}", self.terminal.read(cx).title(true));
+                let id =
+                    NotificationId::identified::<TerminalBell>(cx.entity_id().as_u64() as usize);
+                self.workspace
+                    .update(cx, |workspace, cx| {
+                        workspace.show_toast(Toast::new(id, message), cx)
+                    })
+                    .ok();
+            }
+            AlertOnBell::None | AlertOnBell::Visual => {}
+        }
+    }
+
     pub fn deploy_context_menu(
         &mut self,
         position: gpui::Point<Pixels>,
         cx: &mut ViewContext<Self>,
     ) {
+        let alert_on_bell = self.alert_on_bell(cx);
+        let this = cx.view().downgrade();
         let context_menu = ContextMenu::build(cx, |menu, _| {
-            menu.action("Clear", Box::new(Clear))
+            let menu = menu
+                .action("Clear", Box::new(Clear))
+                .action("Clear to Previous Prompt", Box::new(ClearToPreviousPrompt))
+                .action(
+                    "Open Output in Editor",
+                    Box::new(OpenOutputInEditor::default()),
+                )
+                .action("Rename", Box::new(RenameTerminal))
                 .action("Close", Box::new(CloseActiveItem { save_intent: None }))
+                .separator()
+                .header("Alert on Bell");
+            [
+                ("None", AlertOnBell::None),
+                ("Visual", AlertOnBell::Visual),
+                ("Audible", AlertOnBell::Audible),
+                ("Notification", AlertOnBell::Notify),
+            ]
+            .into_iter()
+            .fold(menu, |menu, (label, mode)| {
+                let this = this.clone();
+                menu.toggleable_entry(label, alert_on_bell == mode, None, move |cx| {
+                    this.update(cx, |this, cx| this.set_alert_on_bell(mode, cx))
+                        .ok();
+                })
+            })
         });
 
         cx.focus_view(&context_menu);
@@ -251,6 +390,135 @@ impl TerminalView {
         cx.notify();
     }
 
+    fn scroll(&mut self, scroll: AlacScroll, cx: &mut ViewContext<Self>) {
+        self.terminal.update(cx, |term, _| term.scroll(scroll));
+        cx.notify();
+    }
+
+    fn scroll_line_up(&mut self, _: &ScrollLineUp, cx: &mut ViewContext<Self>) {
+        self.scroll(AlacScroll::Delta(1), cx);
+    }
+
+    fn scroll_line_down(&mut self, _: &ScrollLineDown, cx: &mut ViewContext<Self>) {
+        self.scroll(AlacScroll::Delta(-1), cx);
+    }
+
+    fn scroll_page_up(&mut self, _: &ScrollPageUp, cx: &mut ViewContext<Self>) {
+        self.scroll(AlacScroll::PageUp, cx);
+    }
+
+    fn scroll_page_down(&mut self, _: &ScrollPageDown, cx: &mut ViewContext<Self>) {
+        self.scroll(AlacScroll::PageDown, cx);
+    }
+
+    fn scroll_to_top(&mut self, _: &ScrollToTop, cx: &mut ViewContext<Self>) {
+        self.scroll(AlacScroll::Top, cx);
+    }
+
+    fn scroll_to_bottom(&mut self, _: &ScrollToBottom, cx: &mut ViewContext<Self>) {
+        self.scroll(AlacScroll::Bottom, cx);
+    }
+
+    /// Shows an editor in the tab to pin the terminal's title. Confirming a blank title
+    /// unpins it, and moving the focus away cancels the rename.
+    fn rename_terminal(&mut self, _: &RenameTerminal, cx: &mut ViewContext<Self>) {
+        let title = self.terminal.read(cx).title(false);
+        let editor = cx.new_view(|cx| {
+            let mut editor = Editor::single_line(cx);
+            editor.set_text(title, cx);
+            editor.select_all(&editor::actions::SelectAll, cx);
+            editor
+        });
+        let subscription = cx.subscribe(&editor, |this, _, event: &EditorEvent, cx| {
+            if let EditorEvent::Blurred = event {
+                this.rename_editor = None;
+                cx.emit(ItemEvent::UpdateTab);
+            }
+        });
+        cx.focus_view(&editor);
+        self.rename_editor = Some((editor, subscription));
+        cx.emit(ItemEvent::UpdateTab);
+    }
+
+    fn quick_select(&mut self, _: &QuickSelect, cx: &mut ViewContext<Self>) {
+        self.terminal.update(cx, |terminal, _| {
+            if terminal.quick_select().is_some() {
+                terminal.cancel_quick_select();
+            } else {
+                terminal.start_quick_select();
+            }
+        });
+        cx.notify();
+    }
+
+    /// Handles a key typed while quick selecting. Typing a tag copies the hint it tags, or
+    /// opens it when typed with shift.
+    fn quick_select_key_down(&mut self, keystroke: &Keystroke, cx: &mut ViewContext<Self>) {
+        let modifiers = keystroke.modifiers;
+        if modifiers.control || modifiers.alt || modifiers.platform || modifiers.function {
+            return;
+        }
+        if keystroke.key == "backspace" {
+            self.terminal
+                .update(cx, |terminal, _| terminal.quick_select_backspace());
+            cx.notify();
+            return;
+        }
+        let mut chars = keystroke.key.chars();
+        let (Some(letter), None) = (chars.next(), chars.next()) else {
+            return;
+        };
+        if !letter.is_ascii_alphabetic() {
+            return;
+        }
+
+        let hint = self
+            .terminal
+            .update(cx, |terminal, _| terminal.quick_select_letter(letter));
+        cx.notify();
+        let Some(hint) = hint else {
+            return;
+        };
+        match hint.kind {
+            QuickSelectKind::Url if modifiers.shift => cx.open_url(&hint.text),
+            QuickSelectKind::Path if modifiers.shift => {
+                let target = PathLikeTarget {
+                    maybe_path: hint.text,
+                    terminal_dir: self.terminal.read(cx).get_cwd(),
+                };
+                open_path_like_target(&self.workspace, &target, cx);
+            }
+            _ => cx.write_to_clipboard(ClipboardItem::new(hint.text)),
+        }
+    }
+
+    fn open_output_in_editor(&mut self, action: &OpenOutputInEditor, cx: &mut ViewContext<Self>) {
+        let terminal = self.terminal.read(cx);
+        let text = terminal.output_text(action.last_command);
+        let title = format!("{} Output", terminal.title(true));
+        self.workspace
+            .update(cx, |workspace, cx| {
+                let project = workspace.project().clone();
+                let buffer = cx.new_model(|cx| {
+                    let mut buffer = Buffer::local(text, cx);
+                    buffer.set_capability(Capability::ReadOnly, cx);
+                    buffer
+                });
+                let buffer =
+                    cx.new_model(|cx| MultiBuffer::singleton(buffer, cx).with_title(title));
+                let editor =
+                    cx.new_view(|cx| Editor::for_multibuffer(buffer, Some(project), true, cx));
+                workspace.add_item_to_active_pane(Box::new(editor), None, cx);
+            })
+            .ok();
+    }
+
+    fn clear_to_previous_prompt(&mut self, _: &ClearToPreviousPrompt, cx: &mut ViewContext<Self>) {
+        self.terminal
+            .update(cx, |term, _| term.clear_to_previous_prompt());
+        cx.notify();
+    }
+
     pub fn should_show_cursor(&self, focused: bool, cx: &mut gpui::ViewContext<Self>) -> bool {
         //Don't blink the cursor when not focused, blinking is disabled, or paused
         if !focused
@@ -274,6 +542,29 @@ impl TerminalView {
         }
     }
 
+    /// Briefly shows the terminal's columns and lines, so that it's clear how a font size
+    /// change affected the programs running in it.
+    fn show_grid_size_notice(&mut self, cx: &mut ViewContext<Self>) {
+        let size = self.terminal.read(cx).last_content.size;
+        self.grid_size_notice =
+            Some(format!("{} × {}", size.num_columns(), size.num_lines()).into());
+        self.grid_size_notice_epoch += 1;
+        cx.notify();
+
+        let epoch = self.grid_size_notice_epoch;
+        cx.spawn(|this, mut cx| async move {
+            Timer::after(GRID_SIZE_NOTICE_DURATION).await;
+            this.update(&mut cx, |this, cx| {
+                if this.grid_size_notice_epoch == epoch {
+                    this.grid_size_notice = None;
+                    cx.notify();
+                }
+            })
+            .ok();
+        })
+        .detach();
+    }
+
     fn blink_cursors(&mut self, epoch: usize, cx: &mut ViewContext<Self>) {
         if epoch == self.blink_epoch && !self.blinking_paused {
             self.blink_state = !self.blink_state;
@@ -318,6 +609,12 @@ impl TerminalView {
         }
     }
 
+    /// Copies the environment the terminal's shell was spawned with, for debugging.
+    fn copy_environment(&mut self, _: &CopyEnvironment, cx: &mut ViewContext<Self>) {
+        let text = self.terminal.read(cx).environment_text();
+        cx.write_to_clipboard(ClipboardItem::new(text));
+    }
+
     ///Attempt to paste the clipboard into the terminal
     fn copy(&mut self, _: &Copy, cx: &mut ViewContext<Self>) {
         self.terminal.update(cx, |term, _| term.copy());
@@ -327,19 +624,41 @@ impl TerminalView {
     ///Attempt to paste the clipboard into the terminal
     fn paste(&mut self, _: &Paste, cx: &mut ViewContext<Self>) {
         if let Some(item) = cx.read_from_clipboard() {
+            let paste_settings = TerminalSettings::get_global(cx).paste;
+            let text = transform_pasted_text(item.text(), paste_settings);
             self.terminal
-                .update(cx, |terminal, _cx| terminal.paste(item.text()));
+                .update(cx, |terminal, _cx| terminal.paste(&text));
+        }
+    }
+
+    /// Keys bound to input for the terminal, like escape or enter, end quick select
+    /// instead of reaching the running program. Returns whether quick select was active.
+    fn cancel_quick_select(&mut self, cx: &mut ViewContext<Self>) -> bool {
+        let was_active = self.terminal.update(cx, |terminal, _| {
+            let was_active = terminal.quick_select().is_some();
+            terminal.cancel_quick_select();
+            was_active
+        });
+        if was_active {
+            cx.notify();
         }
+        was_active
     }
 
     fn send_text(&mut self, text: &SendText, cx: &mut ViewContext<Self>) {
+        if self.cancel_quick_select(cx) {
+            return;
+        }
         self.clear_bell(cx);
         self.terminal.update(cx, |term, _| {
-            term.input(text.0.to_string());
+            term.input_str(&text.0);
         });
     }
 
     fn send_keystroke(&mut self, text: &SendKeystroke, cx: &mut ViewContext<Self>) {
+        if self.cancel_quick_select(cx) {
+            return;
+        }
         if let Some(keystroke) = Keystroke::parse(&text.0).log_err() {
             self.clear_bell(cx);
             self.terminal.update(cx, |term, cx| {
@@ -445,13 +764,12 @@ fn subscribe_for_terminal_events(
                 cx.emit(SearchEvent::MatchesInvalidated);
             }
 
-            Event::Bell => {
-                this.has_bell = true;
-                cx.emit(Event::Wakeup);
-            }
+            Event::Bell => this.ring_bell(cx),
 
             Event::BlinkChanged => this.blinking_on = !this.blinking_on,
 
+            Event::GridReflowed => this.show_grid_size_notice(cx),
+
             Event::TitleChanged => {
                 cx.emit(ItemEvent::UpdateTab);
                 let terminal = this.terminal().read(cx);
@@ -502,94 +820,17 @@ fn subscribe_for_terminal_events(
                     if !this.can_navigate_to_selected_word {
                         return;
                     }
-                    let task_workspace = workspace.clone();
-                    let Some(fs) = workspace
-                        .update(cx, |workspace, cx| {
-                            workspace.project().read(cx).fs().clone()
-                        })
-                        .ok()
-                    else {
-                        return;
-                    };
-
-                    let path_like_target = path_like_target.clone();
-                    cx.spawn(|terminal_view, mut cx| async move {
-                        let valid_files_to_open = terminal_view
-                            .update(&mut cx, |_, cx| {
-                                possible_open_targets(
-                                    fs,
-                                    &task_workspace,
-                                    &path_like_target.terminal_dir,
-                                    &path_like_target.maybe_path,
-                                    cx,
-                                )
-                            })?
-                            .await;
-                        let paths_to_open = valid_files_to_open
-                            .iter()
-                            .map(|(p, _)| p.path_like.clone())
-                            .collect();
-                        let opened_items = task_workspace
-                            .update(&mut cx, |workspace, cx| {
-                                workspace.open_paths(
-                                    paths_to_open,
-                                    OpenVisible::OnlyDirectories,
-                                    None,
-                                    cx,
-                                )
-                            })
-                            .context("workspace update")?
-                            .await;
-
-                        let mut has_dirs = false;
-                        for ((path, metadata), opened_item) in valid_files_to_open
-                            .into_iter()
-                            .zip(opened_items.into_iter())
-                        {
-                            if metadata.is_dir {
-                                has_dirs = true;
-                            } else if let Some(Ok(opened_item)) = opened_item {
-                                if let Some(row) = path.row {
-                                    let col = path.column.unwrap_or(0);
-                                    if let Some(active_editor) = opened_item.downcast::<Editor>() {
-                                        active_editor
-                                            .downgrade()
-                                            .update(&mut cx, |editor, cx| {
-                                                let snapshot = editor.snapshot(cx).display_snapshot;
-                                                let point = snapshot.buffer_snapshot.clip_point(
-                                                    language::Point::new(
-                                                        row.saturating_sub(1),
-                                                        col.saturating_sub(1),
-                                                    ),
-                                                    Bias::Left,
-                                                );
-                                                editor.change_selections(
-                                                    Some(Autoscroll::center()),
-                                                    cx,
-                                                    |s| s.select_ranges([point..point]),
-                                                );
-                                            })
-                                            .log_err();
-                                    }
-                                }
-                            }
-                        }
-
-                        if has_dirs {
-                            task_workspace.update(&mut cx, |workspace, cx| {
-                                workspace.project().update(cx, |_, cx| {
-                                    cx.emit(project::Event::ActivateProjectPanel);
-                                })
-                            })?;
-                        }
-
-                        anyhow::Ok(())
-                    })
-                    .detach_and_log_err(cx)
+                    open_path_like_target(&workspace, path_like_target, cx);
                 }
             },
             Event::BreadcrumbsChanged => cx.emit(ItemEvent::UpdateBreadcrumbs),
             Event::CloseTerminal => cx.emit(ItemEvent::CloseItem),
+            Event::CommandFinished { command, exit_code } => {
+                if let Some(workspace_id) = this.workspace_id {
+                    command_history::record_command(workspace_id, command.clone(), *exit_code, cx);
+                }
+            }
+            Event::ShellIntegrationMissing => shell_integration::suggest(workspace.clone(), cx),
             Event::SelectionsChanged => cx.emit(SearchEvent::ActiveMatchChanged),
         });
     vec![terminal_subscription, terminal_events_subscription]
@@ -630,6 +871,92 @@ fn possible_open_paths_metadata(
     })
 }
 
+/// Opens the files or directories a path printed in the terminal may refer to, at the
+/// row and column it mentions.
+fn open_path_like_target(
+    workspace: &WeakView<Workspace>,
+    path_like_target: &PathLikeTarget,
+    cx: &mut ViewContext<TerminalView>,
+) {
+    let task_workspace = workspace.clone();
+    let Some(fs) = workspace
+        .update(cx, |workspace, cx| {
+            workspace.project().read(cx).fs().clone()
+        })
+        .ok()
+    else {
+        return;
+    };
+
+    let path_like_target = path_like_target.clone();
+    cx.spawn(|terminal_view, mut cx| async move {
+        let valid_files_to_open = terminal_view
+            .update(&mut cx, |_, cx| {
+                possible_open_targets(
+                    fs,
+                    &task_workspace,
+                    &path_like_target.terminal_dir,
+                    &path_like_target.maybe_path,
+                    cx,
+                )
+            })?
+            .await;
+        let paths_to_open = valid_files_to_open
+            .iter()
+            .map(|(p, _)| p.path_like.clone())
+            .collect();
+        let opened_items = task_workspace
+            .update(&mut cx, |workspace, cx| {
+                workspace.open_paths(paths_to_open, OpenVisible::OnlyDirectories, None, cx)
+            })
+            .context("workspace update")?
+            .await;
+
+        let mut has_dirs = false;
+        for ((path, metadata), opened_item) in valid_files_to_open
+            .into_iter()
+            .zip(opened_items.into_iter())
+        {
+            if metadata.is_dir {
+                has_dirs = true;
+            } else if let Some(Ok(opened_item)) = opened_item {
+                if let Some(row) = path.row {
+                    let col = path.column.unwrap_or(0);
+                    if let Some(active_editor) = opened_item.downcast::<Editor>() {
+                        active_editor
+                            .downgrade()
+                            .update(&mut cx, |editor, cx| {
+                                let snapshot = editor.snapshot(cx).display_snapshot;
+                                let point = snapshot.buffer_snapshot.clip_point(
+                                    language::Point::new(
+                                        row.saturating_sub(1),
+                                        col.saturating_sub(1),
+                                    ),
+                                    Bias::Left,
+                                );
+                                editor.change_selections(Some(Autoscroll::center()), cx, |s| {
+                                    s.select_ranges([point..point])
+                                });
+                            })
+                            .log_err();
+                    }
+                }
+            }
+        }
+
+        if has_dirs {
+            task_workspace.update(&mut cx, |workspace, cx| {
+                workspace.project().update(cx, |_, cx| {
+                    cx.emit(project::Event::ActivateProjectPanel);
+                })
+            })?;
+        }
+
+        anyhow::Ok(())
+    })
+    .detach_and_log_err(cx)
+}
+
 fn possible_open_targets(
     fs: Arc<dyn Fs>,
     workspace: &WeakView<Workspace>,
@@ -702,6 +1029,11 @@ pub fn regex_search_for_query(query: &project::search::SearchQuery) -> Option<Re
 
 impl TerminalView {
     fn key_down(&mut self, event: &KeyDownEvent, cx: &mut ViewContext<Self>) {
+        if self.terminal.read(cx).quick_select().is_some() {
+            self.quick_select_key_down(&event.keystroke, cx);
+            cx.stop_propagation();
+            return;
+        }
         self.clear_bell(cx);
         self.pause_cursor_blinking(cx);
 
@@ -714,8 +1046,20 @@ impl TerminalView {
     }
 
     fn focus_in(&mut self, cx: &mut ViewContext<Self>) {
-        self.terminal.read(cx).focus_in();
+        self.terminal.update(cx, |terminal, _| {
+            terminal.focus_in();
+        });
         self.blink_cursors(self.blink_epoch, cx);
+        if let Some(terminal_panel) = self
+            .workspace
+            .upgrade()
+            .and_then(|workspace| workspace.read(cx).panel::<TerminalPanel>(cx))
+        {
+            let terminal_view = cx.view().downgrade();
+            terminal_panel.update(cx, |terminal_panel, _| {
+                terminal_panel.record_focus(terminal_view)
+            });
+        }
         cx.notify();
     }
 
@@ -725,6 +1069,22 @@ impl TerminalView {
         });
         cx.notify();
     }
+
+    /// Switching to another application moves the focus out of the terminal as far as the
+    /// running program is concerned, e.g. for vim's autosave when focus is lost.
+    fn window_activation_changed(&mut self, cx: &mut ViewContext<Self>) {
+        if !self.focus_handle.contains_focused(cx) {
+            return;
+        }
+        let active = cx.is_window_active();
+        self.terminal.update(cx, |terminal, _| {
+            if active {
+                terminal.focus_in();
+            } else {
+                terminal.focus_out();
+            }
+        });
+    }
 }
 
 impl Render for TerminalView {
@@ -741,8 +1101,20 @@ impl Render for TerminalView {
             .on_action(cx.listener(TerminalView::send_text))
             .on_action(cx.listener(TerminalView::send_keystroke))
             .on_action(cx.listener(TerminalView::copy))
+            .on_action(cx.listener(TerminalView::copy_environment))
             .on_action(cx.listener(TerminalView::paste))
             .on_action(cx.listener(TerminalView::clear))
+            .on_action(cx.listener(TerminalView::scroll_line_up))
+            .on_action(cx.listener(TerminalView::scroll_line_down))
+            .on_action(cx.listener(TerminalView::scroll_page_up))
+            .on_action(cx.listener(TerminalView::scroll_page_down))
+            .on_action(cx.listener(TerminalView::scroll_to_top))
+            .on_action(cx.listener(TerminalView::scroll_to_bottom))
+            .on_action(cx.listener(TerminalView::clear_to_previous_prompt))
+            .on_action(cx.listener(TerminalView::rename_terminal))
+            .on_action(cx.listener(TerminalView::quick_select))
+            .on_action(cx.listener(TerminalView::show_command_history))
+            .on_action(cx.listener(TerminalView::open_output_in_editor))
             .on_action(cx.listener(TerminalView::show_character_palette))
             .on_action(cx.listener(TerminalView::select_all))
             .on_key_down(cx.listener(Self::key_down))
@@ -766,6 +1138,19 @@ impl Render for TerminalView {
                     self.can_navigate_to_selected_word,
                 )),
             )
+            .children(self.grid_size_notice.clone().map(|notice| {
+                div()
+                    .absolute()
+                    .top_2()
+                    .right_2()
+                    .px_2()
+                    .py_1()
+                    .rounded_md()
+                    .border_1()
+                    .border_color(cx.theme().colors().border)
+                    .bg(cx.theme().colors().elevated_surface_background)
+                    .child(Label::new(notice).size(LabelSize::Small))
+            }))
             .children(self.context_menu.as_ref().map(|(menu, position, _)| {
                 deferred(
                     anchored()
@@ -791,8 +1176,12 @@ impl Item for TerminalView {
 
         let (icon, icon_color, rerun_btn) = match terminal.task() {
             Some(terminal_task) => match &terminal_task.status {
-                TaskStatus::Unknown => (IconName::ExclamationTriangle, Color::Warning, None),
-                TaskStatus::Running => (IconName::Play, Color::Disabled, None),
+                TaskStatus::Unknown => (
+                    Icon::new(IconName::ExclamationTriangle),
+                    Color::Warning,
+                    None,
+                ),
+                TaskStatus::Running => (Icon::new(IconName::Play), Color::Disabled, None),
                 TaskStatus::Completed { success } => {
                     let task_id = terminal_task.id.clone();
                     let rerun_btn = IconButton::new("rerun-icon", IconName::Rerun)
@@ -809,13 +1198,21 @@ impl Item for TerminalView {
                         });
 
                     if *success {
-                        (IconName::Check, Color::Success, Some(rerun_btn))
+                        (Icon::new(IconName::Check), Color::Success, Some(rerun_btn))
                     } else {
-                        (IconName::XCircle, Color::Error, Some(rerun_btn))
+                        (Icon::new(IconName::XCircle), Color::Error, Some(rerun_btn))
                     }
                 }
             },
-            None => (IconName::Terminal, Color::Muted, None),
+            None => {
+                let icon = terminal
+                    .pty_info
+                    .current
+                    .as_ref()
+                    .and_then(|process| program_icon(&process.name, cx))
+                    .unwrap_or_else(|| Icon::new(IconName::Terminal));
+                (icon, Color::Muted, None)
+            }
         };
 
         h_flex()
@@ -829,7 +1226,7 @@ impl Item for TerminalView {
                             .when(rerun_btn.is_some(), |this| {
                                 this.hover(|style| style.invisible().w_0())
                             })
-                            .child(Icon::new(icon).color(icon_color)),
+                            .child(icon.color(icon_color)),
                     )
                     .when_some(rerun_btn, |this, rerun_btn| {
                         this.child(
@@ -840,11 +1237,46 @@ impl Item for TerminalView {
                         )
                     }),
             )
-            .child(Label::new(title).color(if params.selected {
-                Color::Default
-            } else {
-                Color::Muted
-            }))
+            .child(match &self.rename_editor {
+                Some((editor, _)) => {
+                    let terminal = self.terminal.clone();
+                    let focus_handle = self.focus_handle.clone();
+                    div()
+                        .w(rems(10.))
+                        .child(editor.clone())
+                        .on_action({
+                            let editor = editor.clone();
+                            let focus_handle = focus_handle.clone();
+                            move |_: &menu::Confirm, cx| {
+                                let title = editor.read(cx).text(cx);
+                                terminal.update(cx, |terminal, cx| {
+                                    terminal.set_title_override(Some(title), cx)
+                                });
+                                cx.focus(&focus_handle);
+                            }
+                        })
+                        .on_action(move |_: &editor::actions::Cancel, cx| {
+                            cx.focus(&focus_handle);
+                        })
+                        .into_any_element()
+                }
+                None => {
+                    let focus_handle = self.focus_handle.clone();
+                    div()
+                        .id("terminal-title")
+                        .child(Label::new(title).color(if params.selected {
+                            Color::Default
+                        } else {
+                            Color::Muted
+                        }))
+                        .on_click(move |event, cx| {
+                            if event.up.click_count == 2 {
+                                focus_handle.dispatch_action(&RenameTerminal, cx);
+                            }
+                        })
+                        .into_any_element()
+                }
+            })
             .into_any()
     }
 
@@ -896,7 +1328,13 @@ impl Item for TerminalView {
 
     fn breadcrumbs(&self, _: &theme::Theme, cx: &AppContext) -> Option<Vec<BreadcrumbText>> {
         Some(vec![BreadcrumbText {
-            text: self.terminal().read(cx).breadcrumb_text.clone(),
+            text: {
+                let terminal = self.terminal().read(cx);
+                terminal
+                    .title_override()
+                    .unwrap_or(&terminal.breadcrumb_text)
+                    .to_string()
+            },
             highlights: None,
             font: None,
         }])
@@ -958,6 +1396,11 @@ impl Item for TerminalView {
         }
     }
 
+    fn deactivated(&mut self, cx: &mut ViewContext<Self>) {
+        self.terminal
+            .update(cx, |terminal, _| terminal.save_viewport());
+    }
+
     fn to_item_events(event: &Self::Event, mut f: impl FnMut(ItemEvent)) {
         f(*event)
     }
@@ -978,7 +1421,7 @@ impl SearchableItem for TerminalView {
 
     /// Clear stored matches
     fn clear_matches(&mut self, cx: &mut ViewContext<Self>) {
-        self.terminal().update(cx, |term, _| term.matches.clear())
+        self.terminal().update(cx, |term, _| term.clear_matches())
     }
 
     /// Store matches returned from find_matches somewhere for rendering
@@ -987,13 +1430,15 @@ impl SearchableItem for TerminalView {
             .update(cx, |term, _| term.matches = matches.to_vec())
     }
 
-    /// Returns the selection content to pre-load into this search
+    /// Returns the selection content to pre-load into this search, unless it spans several
+    /// lines, which makes for a query that rarely matches anything
     fn query_suggestion(&mut self, cx: &mut ViewContext<Self>) -> String {
         self.terminal()
             .read(cx)
             .last_content
             .selection_text
             .clone()
+            .filter(|text| !text.trim_end().contains('\n'))
             .unwrap_or_default()
     }
 
@@ -1137,6 +1582,48 @@ fn get_path_from_wt(wt: &LocalWorktree) -> Option<PathBuf> {
         .map(|_| wt.abs_path().to_path_buf())
 }
 
+/// The icon for a terminal tab whose foreground process is `process_name`, for
+/// programs that are common enough to recognize.
+fn program_icon(process_name: &str, cx: &AppContext) -> Option<Icon> {
+    let program = program_name(process_name);
+    if matches!(program, "ssh" | "mosh" | "et") {
+        return Some(Icon::new(IconName::Server));
+    }
+    let icon_path = cx
+        .try_global::<FileIcons>()?
+        .get_type_icon(program_file_type(program)?)?;
+    Some(Icon::from_path(icon_path))
+}
+
+/// The name of a program without an `.exe` extension or a version suffix, so that
+/// `python3.12` and `python.exe` are both `python`.
+fn program_name(process_name: &str) -> &str {
+    let program = process_name.strip_suffix(".exe").unwrap_or(process_name);
+    let unversioned = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    if unversioned.is_empty() {
+        program
+    } else {
+        unversioned
+    }
+}
+
+/// The file type whose icon represents a program, as named in the file type icons.
+fn program_file_type(program: &str) -> Option<&'static str> {
+    Some(match program {
+        "node" | "npm" | "npx" | "yarn" | "pnpm" => "javascript",
+        "deno" | "tsc" | "ts-node" | "tsx" => "typescript",
+        "bun" | "bunx" => "bun",
+        "cargo" | "rustc" | "rustup" => "rust",
+        "docker" | "docker-compose" | "podman" => "docker",
+        "python" | "pip" | "uv" | "poetry" => "python",
+        "go" => "go",
+        "ruby" | "irb" | "bundle" | "rails" => "ruby",
+        "elixir" | "iex" | "mix" => "elixir",
+        "git" => "vcs",
+        _ => return None,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1145,6 +1632,16 @@ mod tests {
     use std::path::Path;
     use workspace::AppState;
 
+    #[test]
+    fn test_program_file_type() {
+        assert_eq!(program_name("python3.12"), "python");
+        assert_eq!(program_name("node.exe"), "node");
+        assert_eq!(program_name("7z"), "7z");
+        assert_eq!(program_file_type(program_name("cargo")), Some("rust"));
+        assert_eq!(program_file_type(program_name("pip3")), Some("python"));
+        assert_eq!(program_file_type(program_name("zsh")), None);
+    }
+
     // Working directory calculation tests
 
     // No Worktrees in project -> home_dir()
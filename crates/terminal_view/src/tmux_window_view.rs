@@ -0,0 +1,211 @@
+//! A tab showing a window of a tmux session attached in control mode, with its panes
+//! split the way tmux lays them out.
+
+use crate::TerminalView;
+use collections::HashMap;
+use gpui::{
+    canvas, relative, AnyElement, AppContext, EventEmitter, FocusHandle, FocusableView, Model,
+    Render, Subscription, View, WeakView,
+};
+use terminal::{
+    tmux::{Layout, LayoutKind, PaneId, TmuxEvent, TmuxSession, WindowId},
+    TerminalSize,
+};
+use ui::{h_flex, prelude::*, v_flex, Icon, IconName, Label};
+use workspace::{
+    item::{Item, ItemEvent, TabContentParams},
+    Workspace, WorkspaceId,
+};
+
+pub struct TmuxWindowView {
+    session: Model<TmuxSession>,
+    window: WindowId,
+    panes: HashMap<PaneId, (View<TerminalView>, Subscription)>,
+    /// The pane that had focus last, which gets it back when the tab is focused.
+    active_pane: Option<PaneId>,
+    workspace: WeakView<Workspace>,
+    workspace_id: Option<WorkspaceId>,
+    focus_handle: FocusHandle,
+    _subscription: Subscription,
+}
+
+impl TmuxWindowView {
+    pub fn new(
+        session: Model<TmuxSession>,
+        window: WindowId,
+        workspace: WeakView<Workspace>,
+        workspace_id: Option<WorkspaceId>,
+        cx: &mut ViewContext<Self>,
+    ) -> Self {
+        let subscription = cx.subscribe(&session, move |this, _, event, cx| match event {
+            TmuxEvent::WindowAdded(changed) | TmuxEvent::LayoutChanged(changed)
+                if *changed == window =>
+            {
+                this.update_panes(cx)
+            }
+            TmuxEvent::WindowClosed(closed) if *closed == window => cx.emit(ItemEvent::CloseItem),
+            TmuxEvent::Exited { .. } => cx.emit(ItemEvent::CloseItem),
+            _ => {}
+        });
+        let mut this = Self {
+            session,
+            window,
+            panes: HashMap::default(),
+            active_pane: None,
+            workspace,
+            workspace_id,
+            focus_handle: cx.focus_handle(),
+            _subscription: subscription,
+        };
+        this.update_panes(cx);
+        this
+    }
+
+    /// Shows the panes of the window's current layout, keeping the views of the panes
+    /// that were already shown.
+    fn update_panes(&mut self, cx: &mut ViewContext<Self>) {
+        let session = self.session.read(cx);
+        let panes = session
+            .window_layout(self.window)
+            .map(Layout::panes)
+            .unwrap_or_default();
+        let new_terminals = panes
+            .iter()
+            .filter(|pane| !self.panes.contains_key(pane))
+            .filter_map(|pane| Some((*pane, session.pane_terminal(*pane)?)))
+            .collect::<Vec<_>>();
+
+        self.panes.retain(|pane, _| panes.contains(pane));
+        for (pane, terminal) in new_terminals {
+            let terminal_view = cx.new_view(|cx| {
+                TerminalView::new(terminal, self.workspace.clone(), self.workspace_id, cx)
+            });
+            let subscription = cx.on_focus(&terminal_view.focus_handle(cx), move |this, _| {
+                this.active_pane = Some(pane);
+            });
+            self.panes.insert(pane, (terminal_view, subscription));
+        }
+        if self
+            .active_pane
+            .map_or(false, |pane| !self.panes.contains_key(&pane))
+        {
+            self.active_pane = None;
+        }
+        cx.notify();
+    }
+
+    /// The view of the pane that had focus last, or else of the window's first pane.
+    fn active_terminal_view(&self, cx: &AppContext) -> Option<&View<TerminalView>> {
+        let pane = self.active_pane.or_else(|| {
+            let layout = self.session.read(cx).window_layout(self.window)?;
+            layout.panes().first().copied()
+        })?;
+        self.panes.get(&pane).map(|(view, _)| view)
+    }
+
+    fn render_layout(&self, layout: &Layout, cx: &ViewContext<Self>) -> AnyElement {
+        let border_color = cx.theme().colors().border;
+        match &layout.kind {
+            LayoutKind::Pane(pane) => div()
+                .size_full()
+                .children(self.panes.get(pane).map(|(view, _)| view.clone()))
+                .into_any_element(),
+            // Each cell takes the share of its parent that it has in tmux, so that panes
+            // have about the size tmux gave them.
+            LayoutKind::Columns(cells) => h_flex()
+                .size_full()
+                .children(cells.iter().enumerate().map(|(ix, cell)| {
+                    div()
+                        .h_full()
+                        .w(relative(cell.columns as f32 / layout.columns.max(1) as f32))
+                        .when(ix > 0, |cell| cell.border_l_1().border_color(border_color))
+                        .child(self.render_layout(cell, cx))
+                }))
+                .into_any_element(),
+            LayoutKind::Rows(cells) => v_flex()
+                .size_full()
+                .children(cells.iter().enumerate().map(|(ix, cell)| {
+                    div()
+                        .w_full()
+                        .h(relative(cell.lines as f32 / layout.lines.max(1) as f32))
+                        .when(ix > 0, |cell| cell.border_t_1().border_color(border_color))
+                        .child(self.render_layout(cell, cx))
+                }))
+                .into_any_element(),
+        }
+    }
+}
+
+impl EventEmitter<ItemEvent> for TmuxWindowView {}
+
+impl FocusableView for TmuxWindowView {
+    fn focus_handle(&self, cx: &AppContext) -> FocusHandle {
+        match self.active_terminal_view(cx) {
+            Some(view) => view.focus_handle(cx),
+            None => self.focus_handle.clone(),
+        }
+    }
+}
+
+impl Render for TmuxWindowView {
+    fn render(&mut self, cx: &mut ViewContext<Self>) -> impl IntoElement {
+        let layout = self.session.read(cx).window_layout(self.window).cloned();
+        // The client is sized to the space the window is shown in, measured in the cells
+        // of its panes' terminals, which tmux then divides among the panes.
+        let cell_size = self
+            .panes
+            .values()
+            .map(|(view, _)| view.read(cx).terminal().read(cx).last_content.size)
+            .find(|size| *size != TerminalSize::default());
+        let session = self.session.clone();
+
+        div()
+            .size_full()
+            .relative()
+            .track_focus(&self.focus_handle)
+            .child(
+                canvas(
+                    move |bounds, cx| {
+                        if let Some(cell_size) = cell_size {
+                            let size = TerminalSize::new(
+                                cell_size.line_height,
+                                cell_size.cell_width,
+                                bounds.size,
+                            );
+                            session.update(cx, |session, _| {
+                                session.resize_client(size.num_columns(), size.num_lines())
+                            });
+                        }
+                    },
+                    |_, _, _| {},
+                )
+                .absolute()
+                .size_full(),
+            )
+            .children(layout.map(|layout| self.render_layout(&layout, cx)))
+    }
+}
+
+impl Item for TmuxWindowView {
+    type Event = ItemEvent;
+
+    fn tab_content(&self, params: TabContentParams, cx: &WindowContext) -> AnyElement {
+        let title = self
+            .active_terminal_view(cx)
+            .map(|view| view.read(cx).terminal().read(cx).title(true))
+            .unwrap_or_else(|| format!("tmux {}", self.window));
+        h_flex()
+            .gap_2()
+            .child(Icon::new(IconName::Terminal).color(Color::Muted))
+            .child(Label::new(title).color(if params.selected {
+                Color::Default
+            } else {
+                Color::Muted
+            }))
+            .into_any()
+    }
+
+    fn to_item_events(event: &Self::Event, mut f: impl FnMut(ItemEvent)) {
+        f(*event)
+    }
+}
@@ -200,6 +200,7 @@ impl_actions!(
         Reload,
         Save,
         SaveAll,
+        SemanticSearchInDirectory,
         SwapPaneInDirection,
         SendKeystrokes,
     ]
@@ -253,6 +254,13 @@ pub struct OpenTerminal {
     pub working_directory: PathBuf,
 }
 
+#[derive(Debug, Default, Clone, Deserialize, PartialEq)]
+pub struct SemanticSearchInDirectory {
+    /// The directory's path in the project, starting with the name of its worktree when
+    /// the project has several.
+    pub directory: PathBuf,
+}
+
 #[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct WorkspaceId(i64);
 